@@ -0,0 +1,87 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use image::{DynamicImage, ImageBuffer, Rgb};
+
+use ascii_art_cli::ascii_converter::{brightness_to_char, convert_to_ascii};
+use ascii_art_cli::edge_detector::detect_and_convert;
+use ascii_art_cli::image_loader::{preprocess_image, ProcessedImage};
+use ascii_art_cli::renderer::render_to_string;
+use ascii_art_cli::{ColorDepth, RenderOptions};
+
+/// Output widths benchmarked for every full-image stage
+const WIDTHS: [u32; 3] = [80, 160, 320];
+
+/// A synthetic gradient with enough structure to produce edges
+fn source_image() -> DynamicImage {
+    DynamicImage::ImageRgb8(ImageBuffer::from_fn(1024, 768, |x, y| {
+        let stripe = if (x / 32 + y / 32) % 2 == 0 { 64 } else { 0 };
+        Rgb([
+            (x % 256) as u8,
+            (y % 256) as u8,
+            ((x + y) % 192) as u8 + stripe,
+        ])
+    }))
+}
+
+fn processed(width: u32) -> ProcessedImage {
+    preprocess_image(source_image(), width).expect("preprocessing succeeds")
+}
+
+fn bench_brightness_mapping(c: &mut Criterion) {
+    c.bench_function("brightness_to_char/all_levels", |b| {
+        b.iter(|| {
+            for luma in 0..=255u8 {
+                black_box(brightness_to_char(black_box(luma)));
+            }
+        })
+    });
+}
+
+fn bench_conversion(c: &mut Criterion) {
+    let mut group = c.benchmark_group("convert_to_ascii");
+    for width in WIDTHS {
+        let image = processed(width);
+        group.bench_with_input(BenchmarkId::from_parameter(width), &image, |b, image| {
+            b.iter(|| convert_to_ascii(image).expect("conversion succeeds"))
+        });
+    }
+    group.finish();
+}
+
+fn bench_edge_detection(c: &mut Criterion) {
+    let mut group = c.benchmark_group("detect_and_convert");
+    for width in WIDTHS {
+        let image = processed(width);
+        group.bench_with_input(BenchmarkId::from_parameter(width), &image, |b, image| {
+            b.iter(|| detect_and_convert(image).expect("edge detection succeeds"))
+        });
+    }
+    group.finish();
+}
+
+fn bench_rendering(c: &mut Criterion) {
+    let mut group = c.benchmark_group("render_to_string");
+    for width in WIDTHS {
+        let ascii = convert_to_ascii(&processed(width)).expect("conversion succeeds");
+        for depth in [ColorDepth::Ansi16, ColorDepth::TrueColor] {
+            let options = RenderOptions {
+                color_depth: depth,
+                ..RenderOptions::default()
+            };
+            group.bench_with_input(
+                BenchmarkId::new(format!("{depth:?}"), width),
+                &ascii,
+                |b, ascii| b.iter(|| render_to_string(ascii, &options)),
+            );
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_brightness_mapping,
+    bench_conversion,
+    bench_edge_detection,
+    bench_rendering
+);
+criterion_main!(benches);
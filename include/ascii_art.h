@@ -0,0 +1,64 @@
+/*
+ * C interface to the ascii-art-cli converter.
+ *
+ * Build the shared library with:
+ *
+ *     cargo rustc --lib --crate-type cdylib --release --features ffi
+ *
+ * and link against target/release/libascii_art_cli.so (.dylib on macOS,
+ * ascii_art_cli.dll on Windows).
+ */
+#ifndef ASCII_ART_H
+#define ASCII_ART_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Values of AsciiArtOptions.mode */
+#define ASCII_ART_MODE_STANDARD 0u
+#define ASCII_ART_MODE_EDGE 1u
+
+/* Values of AsciiArtOptions.color_depth */
+#define ASCII_ART_COLOR_16 0u
+#define ASCII_ART_COLOR_256 1u
+#define ASCII_ART_COLOR_TRUECOLOR 2u
+#define ASCII_ART_COLOR_MONO 3u
+#define ASCII_ART_COLOR_8 4u
+
+/* Conversion options; a zeroed struct selects the defaults. */
+typedef struct AsciiArtOptions {
+    uint32_t width;       /* output width in characters, or 0 for 80 */
+    uint32_t mode;        /* ASCII_ART_MODE_* */
+    uint32_t color_depth; /* ASCII_ART_COLOR_* */
+    float char_aspect;    /* cell height-to-width ratio, or 0 for 2.0 */
+    uint8_t dither;       /* nonzero to dither the character ramp */
+    const char *charset;  /* UTF-8 ramp from dark to light, or NULL */
+} AsciiArtOptions;
+
+/*
+ * Convert width * height packed 8-bit RGB pixels (rows top to bottom, no
+ * padding) to ASCII art. `options` may be NULL for the defaults.
+ *
+ * Returns NUL-terminated UTF-8 text to release with ascii_art_free(), or NULL
+ * on failure, in which case ascii_art_last_error() describes the problem.
+ */
+char *ascii_art_convert(const uint8_t *pixels, uint32_t width, uint32_t height,
+                        const AsciiArtOptions *options);
+
+/* Release text returned by ascii_art_convert(); NULL is ignored. */
+void ascii_art_free(char *text);
+
+/*
+ * Why the last conversion on this thread failed, or NULL. Valid until the
+ * next failed conversion on the same thread; do not free it.
+ */
+const char *ascii_art_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* ASCII_ART_H */
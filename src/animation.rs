@@ -0,0 +1,1264 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use image::codecs::gif::GifDecoder;
+use image::{AnimationDecoder, DynamicImage, ImageDecoder, ImageFormat};
+use serde_json::json;
+
+use crate::ascii_converter::AsciiImage;
+use crate::builder::AsciiArtBuilder;
+use crate::error::AsciiArtError;
+use crate::grid::{self, GridDocument};
+use crate::image_loader::{
+    load_image_with_limits, map_image_error, map_io_error, open_reader, DecodeLimits,
+    ImageLoaderError,
+};
+use crate::output::TextExport;
+use crate::renderer::{title_sequence, RESTORE_TITLE, SAVE_TITLE};
+
+/// Delay used for GIF frames that ask for (almost) none, as browsers do
+pub const DEFAULT_FRAME_DELAY: Duration = Duration::from_millis(100);
+
+/// Shortest GIF frame delay honored before [`DEFAULT_FRAME_DELAY`] is used
+const MIN_FRAME_DELAY: Duration = Duration::from_millis(20);
+
+/// A decoded frame and how long it stays on screen
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub image: DynamicImage,
+    pub delay: Duration,
+    /// The file or stream the frame was read from, when it was read
+    pub source: Option<Arc<str>>,
+}
+
+/// A converted frame, ready to write to a terminal
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenderedFrame {
+    /// ANSI-colored art, one line per row
+    pub text: String,
+    /// The converted cells, for consumers that draw the art themselves
+    pub image: AsciiImage,
+    pub delay: Duration,
+}
+
+#[cfg(test)]
+impl RenderedFrame {
+    /// A red `#` beside a black `.`, drawn as `text`, for tests
+    pub(crate) fn two_cells(text: &str) -> Self {
+        let cells = vec![
+            crate::ascii_converter::Cell::opaque('#', [255, 0, 0], 76),
+            crate::ascii_converter::Cell::opaque('.', [0, 0, 0], 0),
+        ];
+        Self {
+            text: text.to_string(),
+            image: AsciiImage::new(2, 1, cells).expect("valid grid"),
+            delay: Duration::ZERO,
+        }
+    }
+}
+
+/// How a frame is encoded for consumers that draw it themselves
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum FrameFormat {
+    /// The ANSI-colored art, as printed in a terminal
+    #[default]
+    Ansi,
+    /// A JSON object with the lines of text and the color of every cell
+    Json,
+}
+
+impl FromStr for FrameFormat {
+    type Err = AsciiArtError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "ansi" => Ok(FrameFormat::Ansi),
+            "json" => Ok(FrameFormat::Json),
+            unknown => Err(AsciiArtError::InvalidArgument(format!(
+                "Unknown frame format '{}'. Use 'ansi' or 'json'.",
+                unknown
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for FrameFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameFormat::Ansi => write!(f, "ansi"),
+            FrameFormat::Json => write!(f, "json"),
+        }
+    }
+}
+
+/// Frames to take from an animation: indexes from `start` up to, but not
+/// including, `end`, counting from 0
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FrameRange {
+    pub start: usize,
+    /// Through the last frame when `None`
+    pub end: Option<usize>,
+}
+
+impl FromStr for FrameRange {
+    type Err = AsciiArtError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            AsciiArtError::InvalidArgument(format!(
+                "Invalid frame range '{}'. Use START..END, START.., or ..END, counting from 0.",
+                value
+            ))
+        };
+        let (start, end) = value.split_once("..").ok_or_else(invalid)?;
+        let start = match start.trim() {
+            "" => 0,
+            start => start.parse().map_err(|_| invalid())?,
+        };
+        let end = match end.trim() {
+            "" => None,
+            end => Some(end.parse().map_err(|_| invalid())?),
+        };
+        if end.is_some_and(|end| end <= start) {
+            return Err(AsciiArtError::InvalidArgument(format!(
+                "Frame range '{}' is empty; END is exclusive, so frame N alone is N..N+1.",
+                value
+            )));
+        }
+        Ok(Self { start, end })
+    }
+}
+
+impl fmt::Display for FrameRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.end {
+            Some(end) => write!(f, "{}..{end}", self.start),
+            None => write!(f, "{}..", self.start),
+        }
+    }
+}
+
+/// Frames of an input, decoded one at a time
+///
+/// Animated GIFs yield each frame composited onto the full canvas; any other
+/// image yields itself once, shown for the still delay given to
+/// [`open_frames`]. Raw video from [`open_raw_frames`] yields frames as they
+/// arrive. [`FrameReader::select`] narrows the frames yielded.
+pub struct FrameReader {
+    path: Arc<str>,
+    source: FrameSource,
+    /// Index of the next frame the source decodes
+    index: usize,
+    range: FrameRange,
+    step: usize,
+}
+
+enum FrameSource {
+    Animated(image::Frames<'static>),
+    Still(Option<Frame>),
+    Raw {
+        reader: Box<dyn Read>,
+        video: RawVideo,
+        delay: Duration,
+    },
+}
+
+/// How the pixels of raw video frames are laid out, named as ffmpeg's
+/// `-pix_fmt` names them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RawPixelFormat {
+    /// Red, green, and blue bytes per pixel
+    #[default]
+    Rgb24,
+    /// Red, green, blue, and alpha bytes per pixel
+    Rgba,
+    /// One brightness byte per pixel
+    Gray,
+}
+
+impl RawPixelFormat {
+    fn bytes_per_pixel(self) -> usize {
+        match self {
+            RawPixelFormat::Rgb24 => 3,
+            RawPixelFormat::Rgba => 4,
+            RawPixelFormat::Gray => 1,
+        }
+    }
+}
+
+impl FromStr for RawPixelFormat {
+    type Err = AsciiArtError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "rgb24" => Ok(RawPixelFormat::Rgb24),
+            "rgba" => Ok(RawPixelFormat::Rgba),
+            "gray" => Ok(RawPixelFormat::Gray),
+            unknown => Err(AsciiArtError::InvalidArgument(format!(
+                "Unknown pixel format '{}'. Use 'rgb24', 'rgba', or 'gray'.",
+                unknown
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for RawPixelFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RawPixelFormat::Rgb24 => write!(f, "rgb24"),
+            RawPixelFormat::Rgba => write!(f, "rgba"),
+            RawPixelFormat::Gray => write!(f, "gray"),
+        }
+    }
+}
+
+/// Size and pixel layout of headerless video frames, written as
+/// `WIDTHxHEIGHT:FORMAT` (`640x360:rgb24`); the format defaults to `rgb24`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawVideo {
+    pub width: u32,
+    pub height: u32,
+    pub format: RawPixelFormat,
+}
+
+impl RawVideo {
+    /// Bytes in one frame
+    fn frame_len(&self) -> usize {
+        self.width as usize * self.height as usize * self.format.bytes_per_pixel()
+    }
+}
+
+impl FromStr for RawVideo {
+    type Err = AsciiArtError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (size, format) = match value.split_once(':') {
+            Some((size, format)) => (size, format.parse()?),
+            None => (value, RawPixelFormat::default()),
+        };
+        let dimensions = size
+            .split_once('x')
+            .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)));
+        match dimensions {
+            Some((width, height)) if width > 0 && height > 0 => Ok(Self {
+                width,
+                height,
+                format,
+            }),
+            _ => Err(AsciiArtError::InvalidArgument(format!(
+                "Invalid raw video size '{}'. Use WIDTHxHEIGHT:FORMAT, such as 640x360:rgb24.",
+                value
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for RawVideo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}:{}", self.width, self.height, self.format)
+    }
+}
+
+/// Open an image or animation for frame-by-frame decoding
+pub fn open_frames(
+    path: &str,
+    limits: &DecodeLimits,
+    still_delay: Duration,
+) -> Result<FrameReader, ImageLoaderError> {
+    let reader = open_reader(path)?;
+    let source = if reader.format() == Some(ImageFormat::Gif) {
+        let file = File::open(path).map_err(|err| map_io_error(err, path))?;
+        let mut decoder =
+            GifDecoder::new(BufReader::new(file)).map_err(|err| map_image_error(err, path))?;
+        let (width, height) = decoder.dimensions();
+        limits.check(path, width, height)?;
+        decoder
+            .set_limits(limits.to_decoder_limits())
+            .map_err(|err| map_image_error(err, path))?;
+        FrameSource::Animated(decoder.into_frames())
+    } else {
+        let image = load_image_with_limits(path, limits)?;
+        FrameSource::Still(Some(Frame {
+            image,
+            delay: still_delay,
+            source: Some(path.into()),
+        }))
+    };
+    Ok(FrameReader {
+        path: path.into(),
+        source,
+        index: 0,
+        range: FrameRange::default(),
+        step: 1,
+    })
+}
+
+/// Read headerless video frames of `video`'s size and layout from `reader`,
+/// one after another, each shown for `delay`
+///
+/// This is what `ffmpeg -f rawvideo` writes. Nothing is buffered beyond the
+/// frame being read, so the frames can come from a pipe as they are produced;
+/// `name` stands for the stream in error messages.
+pub fn open_raw_frames(
+    reader: impl Read + 'static,
+    name: &str,
+    video: RawVideo,
+    limits: &DecodeLimits,
+    delay: Duration,
+) -> Result<FrameReader, ImageLoaderError> {
+    limits.check(name, video.width, video.height)?;
+    Ok(FrameReader {
+        path: name.into(),
+        source: FrameSource::Raw {
+            reader: Box::new(reader),
+            video,
+            delay,
+        },
+        index: 0,
+        range: FrameRange::default(),
+        step: 1,
+    })
+}
+
+/// Fill `buf` from `reader`, stopping early only at the end of the stream,
+/// and return how many bytes were read
+fn read_full(reader: &mut dyn Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(filled)
+}
+
+/// The next frame of a raw video stream, or `None` at its end
+fn read_raw_frame(
+    reader: &mut dyn Read,
+    video: RawVideo,
+    delay: Duration,
+    name: &Arc<str>,
+) -> Option<Result<Frame, ImageLoaderError>> {
+    let mut buf = vec![0; video.frame_len()];
+    let filled = match read_full(reader, &mut buf) {
+        Ok(0) => return None,
+        Ok(filled) => filled,
+        Err(err) => return Some(Err(map_io_error(err, name))),
+    };
+    if filled < buf.len() {
+        return Some(Err(ImageLoaderError::DecodeFailed(format!(
+            "\"{name}\" ended partway through a {video} frame ({filled} of {} bytes).",
+            buf.len()
+        ))));
+    }
+    let (width, height) = (video.width, video.height);
+    let image = match video.format {
+        RawPixelFormat::Rgb24 => image::RgbImage::from_raw(width, height, buf).map(Into::into),
+        RawPixelFormat::Rgba => image::RgbaImage::from_raw(width, height, buf).map(Into::into),
+        RawPixelFormat::Gray => image::GrayImage::from_raw(width, height, buf).map(Into::into),
+    };
+    // The buffer is exactly one frame long
+    image.map(|image| {
+        Ok(Frame {
+            image,
+            delay,
+            source: Some(Arc::clone(name)),
+        })
+    })
+}
+
+impl FrameReader {
+    /// Yield only the frames in `range`, and of those every `step`th
+    /// starting with the first
+    ///
+    /// Each frame yielded stays on screen for the frames skipped after it as
+    /// well, so a stride keeps the animation's length. Frames before the
+    /// range are still decoded, since GIF frames build on the ones before
+    /// them, but decoding stops at the end of the range.
+    pub fn select(mut self, range: FrameRange, step: usize) -> Self {
+        self.range = range;
+        self.step = step.max(1);
+        self
+    }
+
+    /// Decode the next frame of the source, whether or not it is selected
+    fn decode_next(&mut self) -> Option<Result<Frame, ImageLoaderError>> {
+        if self.range.end.is_some_and(|end| self.index >= end) {
+            return None;
+        }
+        self.index += 1;
+        match &mut self.source {
+            FrameSource::Animated(frames) => Some(
+                frames
+                    .next()?
+                    .map(|frame| {
+                        let (numerator, denominator) = frame.delay().numer_denom_ms();
+                        let delay = Duration::from_secs_f64(
+                            f64::from(numerator) / f64::from(denominator.max(1)) / 1000.0,
+                        );
+                        Frame {
+                            image: DynamicImage::ImageRgba8(frame.into_buffer()),
+                            delay: gif_delay(delay),
+                            source: Some(self.path.clone()),
+                        }
+                    })
+                    .map_err(|err| map_image_error(err, &self.path)),
+            ),
+            FrameSource::Still(frame) => frame.take().map(Ok),
+            FrameSource::Raw {
+                reader,
+                video,
+                delay,
+            } => read_raw_frame(reader.as_mut(), *video, *delay, &self.path),
+        }
+    }
+}
+
+impl Iterator for FrameReader {
+    type Item = Result<Frame, ImageLoaderError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.index < self.range.start {
+            if let Err(err) = self.decode_next()? {
+                return Some(Err(err));
+            }
+        }
+        let mut frame = match self.decode_next()? {
+            Ok(frame) => frame,
+            Err(err) => return Some(Err(err)),
+        };
+        for _ in 1..self.step {
+            match self.decode_next() {
+                Some(Ok(skipped)) => frame.delay += skipped.delay,
+                Some(Err(err)) => return Some(Err(err)),
+                None => break,
+            }
+        }
+        Some(Ok(frame))
+    }
+}
+
+fn gif_delay(delay: Duration) -> Duration {
+    if delay < MIN_FRAME_DELAY {
+        DEFAULT_FRAME_DELAY
+    } else {
+        delay
+    }
+}
+
+/// Convert every frame with `builder`, dropping the decoded pixels
+pub fn render_frames(
+    frames: impl IntoIterator<Item = Result<Frame, ImageLoaderError>>,
+    builder: &AsciiArtBuilder,
+) -> Result<Vec<RenderedFrame>, AsciiArtError> {
+    frames
+        .into_iter()
+        .map(|frame| {
+            let frame = frame?;
+            let art = builder.generate(&frame.image)?;
+            Ok(RenderedFrame {
+                text: art.to_ansi_string(),
+                image: art.image().clone(),
+                delay: frame.delay,
+            })
+        })
+        .collect()
+}
+
+/// File format of each frame written by [`export_frames`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum FrameFile {
+    /// Plain text without escape sequences
+    Txt,
+    /// ANSI-colored text, as printed in a terminal
+    #[default]
+    Ans,
+    /// A picture of the art drawn with the built-in 8x8 font
+    Png,
+    /// A grid document of the cells, which can be shown again later
+    Aag,
+}
+
+impl FrameFile {
+    pub fn extension(self) -> &'static str {
+        match self {
+            FrameFile::Txt => "txt",
+            FrameFile::Ans => "ans",
+            FrameFile::Png => "png",
+            FrameFile::Aag => grid::EXTENSION,
+        }
+    }
+}
+
+/// Digits frame numbers in exported file names are padded to
+const FRAME_NUMBER_DIGITS: usize = 4;
+
+/// Convert every frame with `builder` and write each to its own file in
+/// `dir`, creating it if needed, and return how many were written
+///
+/// Files are named `frame_0000.ans` and so on, numbered from 0 in the order
+/// written, so tools such as ffmpeg can read them back as a sequence. Text
+/// frames are written with the line endings and encoding of `text`.
+pub fn export_frames(
+    frames: impl IntoIterator<Item = Result<Frame, ImageLoaderError>>,
+    builder: &AsciiArtBuilder,
+    dir: &Path,
+    format: FrameFile,
+    text: TextExport,
+) -> Result<usize, AsciiArtError> {
+    std::fs::create_dir_all(dir)?;
+    let mut written = 0;
+    for frame in frames {
+        let art = builder.generate(&frame?.image)?;
+        let name = format!(
+            "frame_{written:0width$}.{}",
+            format.extension(),
+            width = FRAME_NUMBER_DIGITS
+        );
+        let path = dir.join(name);
+        match format {
+            FrameFile::Txt => std::fs::write(&path, text.encode(&art.to_text()))?,
+            FrameFile::Ans => std::fs::write(&path, text.encode(&art.to_ansi_string()))?,
+            FrameFile::Png => art.to_image().save(&path).map_err(|err| match err {
+                image::ImageError::IoError(err) => AsciiArtError::Render(err),
+                err => {
+                    AsciiArtError::InvalidArgument(format!("Cannot save {}: {err}", path.display()))
+                }
+            })?,
+            FrameFile::Aag => GridDocument::new(art.image().clone()).save(&path)?,
+        }
+        written += 1;
+    }
+    Ok(written)
+}
+
+/// Frame timing chosen on the command line instead of the source's
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Timing {
+    /// Show every frame for `1 / fps` seconds, ignoring the source's delays
+    pub fps: Option<f64>,
+    /// Playback speed multiplier, applied after `fps`
+    pub speed: f64,
+}
+
+impl Default for Timing {
+    fn default() -> Self {
+        Self {
+            fps: None,
+            speed: 1.0,
+        }
+    }
+}
+
+/// Slowest `--fps` and `--speed`; slower still, frames would stay up for
+/// longer than a [`Duration`] can say
+const MIN_FPS: f64 = 0.001;
+const MIN_SPEED: f64 = 0.001;
+
+impl Timing {
+    /// Timing from `--fps` and `--speed`, which must be at least 0.001
+    pub fn new(fps: Option<f64>, speed: f64) -> Result<Self, AsciiArtError> {
+        if let Some(fps) = fps.filter(|fps| !(fps.is_finite() && *fps >= MIN_FPS)) {
+            return Err(AsciiArtError::InvalidArgument(format!(
+                "Frame rate must be at least {MIN_FPS} frames per second, got {fps}."
+            )));
+        }
+        if !(speed.is_finite() && speed >= MIN_SPEED) {
+            return Err(AsciiArtError::InvalidArgument(format!(
+                "Speed must be a factor of at least {MIN_SPEED}, got {speed}."
+            )));
+        }
+        Ok(Self { fps, speed })
+    }
+
+    /// How long a frame the source shows for `delay` stays on screen
+    pub fn delay(&self, delay: Duration) -> Duration {
+        let delay = self
+            .fps
+            .map_or(delay, |fps| Duration::from_secs_f64(1.0 / fps));
+        delay.div_f64(self.speed)
+    }
+}
+
+/// Call `send` with each frame in turn, `passes` times or until it fails
+///
+/// Frames are paced against a monotonic deadline; a client that falls behind
+/// resumes from the current frame rather than receiving a burst of stale ones.
+pub fn pace<F>(frames: &[RenderedFrame], passes: Option<u32>, mut send: F) -> io::Result<()>
+where
+    F: FnMut(&RenderedFrame) -> io::Result<()>,
+{
+    let mut deadline = Instant::now();
+    let mut pass = 0;
+    while !frames.is_empty() && passes.is_none_or(|passes| pass < passes) {
+        for frame in frames {
+            send(frame)?;
+            wait_until(&mut deadline, frame.delay);
+        }
+        pass += 1;
+    }
+    Ok(())
+}
+
+/// Advance `deadline` by `delay` and sleep until it passes, or restart the
+/// clock from now if it already has
+fn wait_until(deadline: &mut Instant, delay: Duration) {
+    *deadline += delay;
+    let now = Instant::now();
+    match deadline.checked_duration_since(now) {
+        Some(wait) => thread::sleep(wait),
+        None => *deadline = now,
+    }
+}
+
+/// How many times playback runs through the frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Loop {
+    Times(u32),
+    Infinite,
+}
+
+impl Loop {
+    /// Whether pass `pass` (counting from 0) is played
+    fn includes(self, pass: u32) -> bool {
+        match self {
+            Loop::Times(times) => pass < times,
+            Loop::Infinite => true,
+        }
+    }
+}
+
+impl FromStr for Loop {
+    type Err = AsciiArtError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "once" => Ok(Loop::Times(1)),
+            "infinite" => Ok(Loop::Infinite),
+            count => match count.parse() {
+                Ok(times) if times > 0 => Ok(Loop::Times(times)),
+                _ => Err(AsciiArtError::InvalidArgument(format!(
+                    "Unknown loop count '{}'. Use a positive number, 'once', or 'infinite'.",
+                    count
+                ))),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Loop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Loop::Times(1) => write!(f, "once"),
+            Loop::Times(times) => write!(f, "{times}"),
+            Loop::Infinite => write!(f, "infinite"),
+        }
+    }
+}
+
+/// How many times an input asks to be played: a GIF's NETSCAPE2.0 loop
+/// count, or once for anything else
+///
+/// The count in the file is the number of repeats after the first pass, as
+/// browsers read it, and 0 means forever. Inputs that can't be read are
+/// reported as played once; opening them for playback reports the error.
+pub fn loop_count(path: &str) -> Loop {
+    let is_gif = open_reader(path).is_ok_and(|reader| reader.format() == Some(ImageFormat::Gif));
+    if !is_gif {
+        return Loop::Times(1);
+    }
+    let repeat = File::open(path).ok().and_then(|file| {
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::Indexed);
+        let mut decoder = options.read_info(BufReader::new(file)).ok()?;
+        // The loop count follows the header, so it is known once the first
+        // frame's description has been read
+        decoder.next_frame_info().ok()?;
+        Some(decoder.repeat())
+    });
+    match repeat {
+        Some(gif::Repeat::Infinite) => Loop::Infinite,
+        Some(gif::Repeat::Finite(repeats)) => Loop::Times(u32::from(repeats) + 1),
+        None => Loop::Times(1),
+    }
+}
+
+/// Longest sleep between checks of the stop flag
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// What happened during [`Player::play`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Playback {
+    /// Frames drawn
+    pub shown: usize,
+    /// Frames skipped because they were already over when decoded
+    pub dropped: usize,
+    /// Time spent converting and writing the frames drawn
+    pub render_time: Duration,
+    /// The slowest frame to convert and write
+    pub slowest: Duration,
+    /// Whether playback ended early because the stop flag was raised
+    pub interrupted: bool,
+}
+
+impl Playback {
+    /// Mean time to convert and write a frame
+    pub fn mean_render_time(&self) -> Duration {
+        self.render_time / self.shown.max(1) as u32
+    }
+}
+
+/// Plays frames in the terminal, converting each just before it is due
+///
+/// Each frame is drawn from the home position with the cursor hidden, and
+/// stays up for its delay under the [`Timing`], measured on a monotonic clock
+/// so conversion time doesn't slow playback down. The cursor and colors are
+/// restored at the end, including when playback is stopped.
+#[derive(Debug, Clone)]
+pub struct Player<'a> {
+    builder: &'a AsciiArtBuilder,
+    timing: Timing,
+    skip_late: bool,
+    loops: Loop,
+    stop: Option<&'a AtomicBool>,
+    titles: bool,
+}
+
+impl<'a> Player<'a> {
+    /// Play frames converted with `builder` once, at the source's timing,
+    /// dropping late frames
+    pub fn new(builder: &'a AsciiArtBuilder) -> Self {
+        Self {
+            builder,
+            timing: Timing::default(),
+            skip_late: true,
+            loops: Loop::Times(1),
+            stop: None,
+            titles: false,
+        }
+    }
+
+    pub fn timing(mut self, timing: Timing) -> Self {
+        self.timing = timing;
+        self
+    }
+
+    /// Drop a frame whose time on screen has already passed by the time it is
+    /// decoded, so playback stays in sync with the source when conversion
+    /// can't keep up; the first frame is always drawn. Otherwise late frames
+    /// are all drawn and playback falls behind.
+    pub fn skip_late(mut self, enabled: bool) -> Self {
+        self.skip_late = enabled;
+        self
+    }
+
+    pub fn loops(mut self, loops: Loop) -> Self {
+        self.loops = loops;
+        self
+    }
+
+    /// Stop between frames, or while waiting for one, once `flag` is set
+    pub fn stop_on(mut self, flag: &'a AtomicBool) -> Self {
+        self.stop = Some(flag);
+        self
+    }
+
+    /// Show the source's file name and the frame number in the terminal's
+    /// title while playing, and put the previous title back at the end
+    pub fn titles(mut self, enabled: bool) -> Self {
+        self.titles = enabled;
+        self
+    }
+
+    /// Play the frames `open` returns on `writer`, calling it again for each
+    /// pass
+    pub fn play<W, F, I>(&self, writer: &mut W, mut open: F) -> Result<Playback, AsciiArtError>
+    where
+        W: Write,
+        F: FnMut() -> Result<I, AsciiArtError>,
+        I: IntoIterator<Item = Result<Frame, ImageLoaderError>>,
+    {
+        if self.titles {
+            writer.write_all(SAVE_TITLE.as_bytes())?;
+        }
+        writer.write_all(b"\x1b[2J\x1b[?25l")?;
+        let mut playback = Playback::default();
+        let result = self.play_passes(writer, &mut open, &mut playback);
+        writer.write_all(b"\x1b[0m\x1b[?25h")?;
+        if self.titles {
+            writer.write_all(RESTORE_TITLE.as_bytes())?;
+        }
+        writer.flush()?;
+        result.map(|()| playback)
+    }
+
+    fn play_passes<W, F, I>(
+        &self,
+        writer: &mut W,
+        open: &mut F,
+        playback: &mut Playback,
+    ) -> Result<(), AsciiArtError>
+    where
+        W: Write,
+        F: FnMut() -> Result<I, AsciiArtError>,
+        I: IntoIterator<Item = Result<Frame, ImageLoaderError>>,
+    {
+        let mut deadline = Instant::now();
+        let mut pass = 0;
+        while self.loops.includes(pass) {
+            let mut frames = 0;
+            // Frames are numbered from 1 within each source, for the title
+            let mut source = None;
+            let mut number = 0;
+            for frame in open()? {
+                if self.stopped() {
+                    playback.interrupted = true;
+                    return Ok(());
+                }
+                let frame = frame?;
+                frames += 1;
+                if frame.source != source {
+                    source = frame.source.clone();
+                    number = 0;
+                }
+                number += 1;
+                let delay = self.timing.delay(frame.delay);
+                let started = Instant::now();
+                if self.skip_late && playback.shown > 0 && started >= deadline + delay {
+                    playback.dropped += 1;
+                    deadline += delay;
+                    continue;
+                }
+                if playback.shown == 0 {
+                    // The clock starts with the first frame, however long a
+                    // live source took to produce it
+                    deadline = started;
+                }
+                let text = self.builder.generate(&frame.image)?.to_ansi_string();
+                if self.titles {
+                    let title = frame_title(source.as_deref(), number);
+                    writer.write_all(title_sequence(&title).as_bytes())?;
+                }
+                writer.write_all(b"\x1b[H")?;
+                writer.write_all(text.as_bytes())?;
+                writer.flush()?;
+                let elapsed = started.elapsed();
+                playback.shown += 1;
+                playback.render_time += elapsed;
+                playback.slowest = playback.slowest.max(elapsed);
+
+                deadline += delay;
+                self.sleep_until(deadline);
+                if !self.skip_late {
+                    deadline = deadline.max(Instant::now());
+                }
+            }
+            if frames == 0 {
+                break;
+            }
+            pass += 1;
+        }
+        playback.interrupted = self.stopped();
+        Ok(())
+    }
+
+    fn stopped(&self) -> bool {
+        self.stop.is_some_and(|flag| flag.load(Ordering::Relaxed))
+    }
+
+    /// Sleep until `deadline`, waking early if the stop flag is raised
+    fn sleep_until(&self, deadline: Instant) {
+        while let Some(wait) = deadline.checked_duration_since(Instant::now()) {
+            if self.stopped() {
+                return;
+            }
+            thread::sleep(wait.min(STOP_POLL_INTERVAL));
+        }
+    }
+}
+
+/// Terminal title for frame `number` of `source`, such as `cat.gif — frame 3`
+fn frame_title(source: Option<&str>, number: usize) -> String {
+    let name = source.map(|source| {
+        Path::new(source)
+            .file_name()
+            .map_or(source.into(), |name| name.to_string_lossy())
+    });
+    match name {
+        Some(name) => format!("{name} \u{2014} frame {number}"),
+        None => format!("frame {number}"),
+    }
+}
+
+/// Encode `frame` as text, such as the body of a WebSocket message
+///
+/// JSON frames look like
+/// `{"colors":[[[255,0,0],[0,0,0]]],"delay_ms":100,"height":1,"lines":["#."],"width":2}`,
+/// with one `[r, g, b]` triple per cell.
+pub fn frame_message(frame: &RenderedFrame, format: FrameFormat) -> String {
+    match format {
+        FrameFormat::Ansi => frame.text.clone(),
+        FrameFormat::Json => {
+            let lines: Vec<String> = frame
+                .image
+                .rows()
+                .map(|row| row.iter().map(|cell| cell.ch).collect())
+                .collect();
+            let colors: Vec<Vec<[u8; 3]>> = frame
+                .image
+                .rows()
+                .map(|row| row.iter().map(|cell| cell.rgb).collect())
+                .collect();
+            json!({
+                "width": frame.image.width(),
+                "height": frame.image.height(),
+                "delay_ms": frame.delay.as_millis() as u64,
+                "lines": lines,
+                "colors": colors,
+            })
+            .to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::codecs::gif::{GifEncoder, Repeat};
+    use image::{Delay, Rgba, RgbaImage};
+
+    fn write_gif(delays_ms: &[u32]) -> tempfile::NamedTempFile {
+        write_gif_repeating(delays_ms, None)
+    }
+
+    fn write_gif_repeating(delays_ms: &[u32], repeat: Option<Repeat>) -> tempfile::NamedTempFile {
+        let file = tempfile::NamedTempFile::with_suffix(".gif").expect("temp file");
+        let mut encoder = GifEncoder::new(file.reopen().expect("reopen"));
+        if let Some(repeat) = repeat {
+            encoder.set_repeat(repeat).expect("set repeat");
+        }
+        for (index, &ms) in delays_ms.iter().enumerate() {
+            let shade = (index * 200) as u8;
+            let image = RgbaImage::from_pixel(8, 8, Rgba([shade, shade, shade, 255]));
+            encoder
+                .encode_frame(image::Frame::from_parts(
+                    image,
+                    0,
+                    0,
+                    Delay::from_numer_denom_ms(ms, 1),
+                ))
+                .expect("encode frame");
+        }
+        drop(encoder);
+        file
+    }
+
+    #[test]
+    fn gif_frames_keep_their_delays() {
+        let gif = write_gif(&[50, 0]);
+        let path = gif.path().to_str().expect("utf8 path");
+        let frames: Vec<Frame> = open_frames(path, &DecodeLimits::default(), Duration::ZERO)
+            .expect("opened")
+            .collect::<Result<_, _>>()
+            .expect("decoded");
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].delay, Duration::from_millis(50));
+        assert_eq!(frames[1].delay, DEFAULT_FRAME_DELAY);
+    }
+
+    #[test]
+    fn still_images_are_one_frame() {
+        let frames: Vec<Frame> = open_frames(
+            "examples/test_image_1.png",
+            &DecodeLimits::default(),
+            Duration::from_secs(2),
+        )
+        .expect("opened")
+        .collect::<Result<_, _>>()
+        .expect("decoded");
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].delay, Duration::from_secs(2));
+    }
+
+    #[test]
+    fn selection_takes_a_range_with_a_stride() {
+        let gif = write_gif(&[20, 30, 40, 50, 60]);
+        let path = gif.path().to_str().expect("utf8 path");
+        let select = |range: &str, step| -> Vec<Duration> {
+            open_frames(path, &DecodeLimits::default(), Duration::ZERO)
+                .expect("opened")
+                .select(range.parse().expect("valid range"), step)
+                .map(|frame| frame.expect("decoded").delay)
+                .collect()
+        };
+        let ms = Duration::from_millis;
+        assert_eq!(select("2..3", 1), [ms(40)]);
+        assert_eq!(select("1..", 1), [ms(30), ms(40), ms(50), ms(60)]);
+        // Skipped frames' time goes to the frame before them
+        assert_eq!(select("..", 2), [ms(50), ms(90), ms(60)]);
+        assert_eq!(select("1..4", 2), [ms(70), ms(50)]);
+        assert!(select("7..", 1).is_empty());
+
+        let still = open_frames("examples/test_image_1.png", &DecodeLimits::default(), ms(5))
+            .expect("opened")
+            .select("1..".parse().expect("valid range"), 1);
+        assert_eq!(still.count(), 0);
+    }
+
+    #[test]
+    fn export_writes_a_numbered_file_per_frame() {
+        let gif = write_gif(&[20, 20, 20]);
+        let path = gif.path().to_str().expect("utf8 path");
+        let dir = tempfile::tempdir().expect("temp dir");
+        let out = dir.path().join("frames");
+        let builder = AsciiArtBuilder::new().width(4);
+        let open = || open_frames(path, &DecodeLimits::default(), Duration::ZERO).expect("opened");
+
+        let utf8 = TextExport::default();
+        let written =
+            export_frames(open(), &builder, &out, FrameFile::Txt, utf8).expect("exported");
+        assert_eq!(written, 3);
+        let text = std::fs::read_to_string(out.join("frame_0002.txt")).expect("third frame");
+        assert!(!text.contains('\x1b'));
+        assert_eq!(
+            text.lines().next().map(|line| line.chars().count()),
+            Some(4)
+        );
+
+        export_frames(open(), &builder, &out, FrameFile::Png, utf8).expect("exported");
+        let image = image::open(out.join("frame_0000.png")).expect("saved png");
+        assert_eq!(image.width(), 4 * crate::font::GLYPH_SIZE);
+        assert!(!out.join("frame_0003.png").exists());
+    }
+
+    #[test]
+    fn raw_video_is_read_frame_by_frame() {
+        let video: RawVideo = "2x1:rgb24".parse().expect("valid size");
+        let bytes = vec![10, 20, 30, 40, 50, 60, 1, 2, 3, 4, 5, 6, 9];
+        let (limits, delay) = (DecodeLimits::default(), Duration::from_millis(40));
+        let open = |bytes: Vec<u8>, video| {
+            open_raw_frames(io::Cursor::new(bytes), "stdin", video, &limits, delay).expect("opened")
+        };
+        let mut frames = open(bytes, video);
+        let first = frames.next().expect("first frame").expect("decoded");
+        assert_eq!(first.image.to_rgb8().into_raw(), [10, 20, 30, 40, 50, 60]);
+        assert_eq!(first.delay, delay);
+        assert!(frames.next().expect("second frame").is_ok());
+        // A trailing partial frame is an error, not a silent end
+        let err = frames.next().expect("partial frame").unwrap_err();
+        assert!(err.to_string().contains("1 of 6 bytes"), "{err}");
+
+        let gray: RawVideo = "1x1:gray".parse().expect("valid size");
+        let mut frames = open(vec![7, 8], gray).select(
+            FrameRange {
+                start: 1,
+                end: None,
+            },
+            1,
+        );
+        let frame = frames.next().expect("frame").expect("decoded");
+        assert_eq!(frame.image.to_luma8().into_raw(), [8]);
+        assert!(frames.next().is_none());
+    }
+
+    #[test]
+    fn raw_video_parses_size_and_format() {
+        let parsed = "640x360:rgba".parse::<RawVideo>().expect("valid size");
+        let expected = (640, 360, RawPixelFormat::Rgba);
+        assert_eq!((parsed.width, parsed.height, parsed.format), expected);
+        let default = "8x4".parse::<RawVideo>().expect("valid size");
+        assert_eq!(default.format, RawPixelFormat::Rgb24);
+        assert_eq!(default.to_string(), "8x4:rgb24");
+        assert!("0x4:rgb24".parse::<RawVideo>().is_err());
+        assert!("8:rgb24".parse::<RawVideo>().is_err());
+        assert!("8x4:yuv420p".parse::<RawVideo>().is_err());
+    }
+
+    #[test]
+    fn frame_range_parses_bounds() {
+        let range = |start, end| FrameRange { start, end };
+        assert_eq!(
+            "42..43".parse::<FrameRange>().ok(),
+            Some(range(42, Some(43)))
+        );
+        assert_eq!("5..".parse::<FrameRange>().ok(), Some(range(5, None)));
+        assert_eq!("..8".parse::<FrameRange>().ok(), Some(range(0, Some(8))));
+        assert!("4..4".parse::<FrameRange>().is_err());
+        assert!("9..3".parse::<FrameRange>().is_err());
+        assert!("4".parse::<FrameRange>().is_err());
+        assert!("a..b".parse::<FrameRange>().is_err());
+        for value in ["42..43", "5.."] {
+            assert_eq!(value.parse::<FrameRange>().unwrap().to_string(), value);
+        }
+    }
+
+    #[test]
+    fn frames_render_to_ansi_text() {
+        let gif = write_gif(&[40, 40]);
+        let path = gif.path().to_str().expect("utf8 path");
+        let reader = open_frames(path, &DecodeLimits::default(), Duration::ZERO).expect("opened");
+        let rendered = render_frames(reader, &AsciiArtBuilder::new().width(4)).expect("rendered");
+        assert_eq!(rendered.len(), 2);
+        assert_ne!(rendered[0].text, rendered[1].text);
+        assert!(rendered.iter().all(|frame| frame.text.contains("\n")));
+    }
+
+    #[test]
+    fn json_frames_list_lines_and_cell_colors() {
+        let frame = RenderedFrame::two_cells("#.\n");
+        let message = frame_message(&frame, FrameFormat::Json);
+        assert_eq!(
+            message,
+            r##"{"colors":[[[255,0,0],[0,0,0]]],"delay_ms":0,"height":1,"lines":["#."],"width":2}"##
+        );
+        assert_eq!(frame_message(&frame, FrameFormat::Ansi), "#.\n");
+    }
+
+    #[test]
+    fn timing_overrides_and_scales_delays() {
+        let source = Duration::from_millis(100);
+        assert_eq!(Timing::default().delay(source), source);
+        let fps = Timing::new(Some(25.0), 1.0).expect("valid timing");
+        assert_eq!(fps.delay(source), Duration::from_millis(40));
+        let fast = Timing::new(None, 2.0).expect("valid timing");
+        assert_eq!(fast.delay(source), Duration::from_millis(50));
+        let both = Timing::new(Some(10.0), 0.5).expect("valid timing");
+        assert_eq!(both.delay(source), Duration::from_millis(200));
+        assert!(Timing::new(Some(0.0), 1.0).is_err());
+        assert!(Timing::new(None, -1.0).is_err());
+        assert!(Timing::new(None, f64::NAN).is_err());
+        assert!(Timing::new(Some(1e-30), 1.0).is_err());
+        assert!(Timing::new(None, 1e-30).is_err());
+    }
+
+    fn open_gif(path: &str) -> impl FnMut() -> Result<FrameReader, AsciiArtError> + '_ {
+        move || Ok(open_frames(path, &DecodeLimits::default(), Duration::ZERO)?)
+    }
+
+    #[test]
+    fn playback_draws_each_frame_from_home_and_restores_the_cursor() {
+        let gif = write_gif(&[20, 20, 20]);
+        let path = gif.path().to_str().expect("utf8 path");
+        let timing = Timing::new(Some(1000.0), 1.0).expect("valid timing");
+        let builder = AsciiArtBuilder::new().width(4);
+        let player = Player::new(&builder).timing(timing).skip_late(false);
+        let mut out = Vec::new();
+        let started = Instant::now();
+        let playback = player.play(&mut out, open_gif(path)).expect("played");
+        assert!(started.elapsed() >= Duration::from_millis(3));
+        assert_eq!((playback.shown, playback.dropped), (3, 0));
+        assert!(!playback.interrupted);
+        let out = String::from_utf8(out).expect("utf8 output");
+        assert!(out.starts_with("\x1b[2J\x1b[?25l"));
+        assert_eq!(out.matches("\x1b[H").count(), 3);
+        assert!(out.ends_with("\x1b[0m\x1b[?25h"));
+
+        let twice = player.loops(Loop::Times(2));
+        let playback = twice.play(&mut Vec::new(), open_gif(path)).expect("played");
+        assert_eq!(playback.shown, 6);
+    }
+
+    #[test]
+    fn late_frames_are_dropped_to_keep_in_sync() {
+        let gif = write_gif(&[20; 6]);
+        let path = gif.path().to_str().expect("utf8 path");
+        let builder = AsciiArtBuilder::new().width(64);
+        // A microsecond per frame: far less than converting one takes
+        let timing = Timing::new(Some(1_000_000.0), 1.0).expect("valid timing");
+        let play_all = |skip_late: bool| {
+            let player = Player::new(&builder).timing(timing).skip_late(skip_late);
+            player
+                .play(&mut Vec::new(), open_gif(path))
+                .expect("played")
+        };
+
+        let skipping = play_all(true);
+        assert_eq!(skipping.shown + skipping.dropped, 6);
+        assert!(skipping.shown >= 1 && skipping.dropped >= 1, "{skipping:?}");
+        assert!(skipping.slowest <= skipping.render_time);
+
+        let every = play_all(false);
+        assert_eq!((every.shown, every.dropped), (6, 0));
+        assert!(every.mean_render_time() <= every.slowest);
+    }
+
+    #[test]
+    fn titles_count_frames_and_restore_the_previous_title() {
+        let gif = write_gif(&[20, 20]);
+        let path = gif.path().to_str().expect("utf8 path");
+        let name = gif.path().file_name().expect("file name").to_string_lossy();
+        let timing = Timing::new(Some(1000.0), 1.0).expect("valid timing");
+        let builder = AsciiArtBuilder::new().width(4);
+        let player = Player::new(&builder)
+            .timing(timing)
+            .skip_late(false)
+            .loops(Loop::Times(2))
+            .titles(true);
+        let mut out = Vec::new();
+        player.play(&mut out, open_gif(path)).expect("played");
+        let out = String::from_utf8(out).expect("utf8 output");
+        assert!(out.starts_with(SAVE_TITLE));
+        assert!(out.ends_with(RESTORE_TITLE));
+        // Numbering starts over with each pass
+        let second = title_sequence(&format!("{name} \u{2014} frame 2"));
+        assert_eq!(out.matches(&second).count(), 2);
+        assert_eq!(frame_title(None, 7), "frame 7");
+    }
+
+    #[test]
+    fn stopping_ends_playback_and_restores_the_cursor() {
+        let gif = write_gif(&[20, 20]);
+        let path = gif.path().to_str().expect("utf8 path");
+        let builder = AsciiArtBuilder::new().width(4);
+        let stop = AtomicBool::new(true);
+        let player = Player::new(&builder).loops(Loop::Infinite).stop_on(&stop);
+        let mut out = Vec::new();
+        let playback = player.play(&mut out, open_gif(path)).expect("played");
+        assert!(playback.interrupted);
+        assert_eq!(playback.shown, 0);
+        assert!(String::from_utf8_lossy(&out).ends_with("\x1b[0m\x1b[?25h"));
+    }
+
+    #[test]
+    fn loop_counts_come_from_the_gif() {
+        let plain = write_gif(&[20]);
+        let forever = write_gif_repeating(&[20], Some(Repeat::Infinite));
+        let thrice = write_gif_repeating(&[20], Some(Repeat::Finite(2)));
+        let count = |file: &tempfile::NamedTempFile| loop_count(file.path().to_str().unwrap());
+        assert_eq!(count(&plain), Loop::Times(1));
+        assert_eq!(count(&forever), Loop::Infinite);
+        assert_eq!(count(&thrice), Loop::Times(3));
+        assert_eq!(loop_count("examples/test_image_1.png"), Loop::Times(1));
+    }
+
+    #[test]
+    fn loop_parses_counts_and_names() {
+        assert_eq!("once".parse::<Loop>().ok(), Some(Loop::Times(1)));
+        assert_eq!("3".parse::<Loop>().ok(), Some(Loop::Times(3)));
+        assert_eq!("infinite".parse::<Loop>().ok(), Some(Loop::Infinite));
+        assert!("0".parse::<Loop>().is_err());
+        assert!("forever".parse::<Loop>().is_err());
+        for value in ["once", "3", "infinite"] {
+            assert_eq!(value.parse::<Loop>().unwrap().to_string(), value);
+        }
+    }
+
+    #[test]
+    fn frame_format_parses_names() {
+        assert_eq!("json".parse::<FrameFormat>().ok(), Some(FrameFormat::Json));
+        assert_eq!(FrameFormat::Ansi.to_string(), "ansi");
+        assert!("xml".parse::<FrameFormat>().is_err());
+    }
+}
@@ -1,10 +1,451 @@
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
 use image::GrayImage;
+use unicode_width::UnicodeWidthChar;
+
+use crate::error::AsciiArtError;
+use crate::font;
+use crate::image_loader::ProcessedImage;
+use crate::parallel::map_rows;
 
-/// Type alias for a 2D grid of ASCII characters
-pub type AsciiGrid = Vec<Vec<char>>;
+/// A single character cell with the color and brightness it was derived from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cell {
+    pub ch: char,
+    pub rgb: [u8; 3],
+    pub luma: u8,
+    /// The pixel was (mostly) transparent: the cell is a blank left to the
+    /// terminal background, with no color of its own
+    pub transparent: bool,
+}
+
+#[cfg(test)]
+impl Cell {
+    /// An opaque cell, as test grids are built from
+    pub(crate) fn opaque(ch: char, rgb: [u8; 3], luma: u8) -> Self {
+        Self {
+            ch,
+            rgb,
+            luma,
+            transparent: false,
+        }
+    }
+}
+
+/// A converted character grid, stored row-major
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsciiImage {
+    width: usize,
+    height: usize,
+    cells: Vec<Cell>,
+}
+
+impl AsciiImage {
+    /// Creates an image from row-major cells
+    ///
+    /// # Returns
+    /// * `Ok(AsciiImage)` - If `cells` holds exactly `width * height` entries
+    /// * `Err(AsciiArtError::EmptyImage)` - If either dimension is zero
+    /// * `Err(AsciiArtError::InvalidArgument)` - If the cell count does not match
+    pub fn new(width: usize, height: usize, cells: Vec<Cell>) -> Result<Self, AsciiArtError> {
+        if width == 0 || height == 0 {
+            return Err(AsciiArtError::EmptyImage);
+        }
+        if cells.len() != width * height {
+            return Err(AsciiArtError::InvalidArgument(format!(
+                "Expected {} cells for a {}x{} grid, got {}.",
+                width * height,
+                width,
+                height,
+                cells.len()
+            )));
+        }
+        Ok(Self {
+            width,
+            height,
+            cells,
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// All cells in row-major order
+    pub fn cells(&self) -> &[Cell] {
+        &self.cells
+    }
+
+    /// Returns the cell at column `x`, row `y`, if it is inside the grid
+    pub fn get(&self, x: usize, y: usize) -> Option<&Cell> {
+        if x < self.width && y < self.height {
+            self.cells.get(y * self.width + x)
+        } else {
+            None
+        }
+    }
+
+    /// Replaces the color of every cell with `f(color)`
+    pub fn map_colors<F: Fn([u8; 3]) -> [u8; 3]>(&mut self, f: F) {
+        for cell in &mut self.cells {
+            cell.rgb = f(cell.rgb);
+        }
+    }
+
+    /// Shows transparent cells as `blank` instead of a space, such as the
+    /// double-width blank of a wide charset
+    pub fn fill_transparent(&mut self, blank: char) {
+        for cell in self.cells.iter_mut().filter(|cell| cell.transparent) {
+            cell.ch = blank;
+        }
+    }
+
+    /// Iterates over the rows of the grid
+    pub fn rows(&self) -> impl Iterator<Item = &[Cell]> {
+        self.cells.chunks_exact(self.width)
+    }
+
+    /// The characters of the grid as plain text, one line per row
+    pub fn to_text(&self) -> String {
+        let mut text = String::with_capacity((self.width + 1) * self.height);
+        for row in self.rows() {
+            text.extend(row.iter().map(|cell| cell.ch));
+            text.push('\n');
+        }
+        text
+    }
+}
+
+/// Ramps shipped with the crate by name, each ordered from dark to light
+pub const BUILTIN_CHARSETS: [(&str, &str); 5] = [
+    ("standard", CHARSET),
+    (
+        "detailed",
+        " .'`^\",:;Il!i><~+_-?][}{1)(|\\/tfjrxnuvczXYUJCLQ0OZmwqpdbkhao*#MW&8%B@$",
+    ),
+    ("simple", " .:#"),
+    ("blocks", " ░▒▓█"),
+    ("braille", " ⠁⠃⠇⡇⣇⣧⣷⣿"),
+];
+
+/// The ramp of one of the [`BUILTIN_CHARSETS`] by name
+pub fn builtin_ramp(name: &str) -> Option<&'static str> {
+    BUILTIN_CHARSETS
+        .iter()
+        .find(|&&(builtin, _)| builtin == name)
+        .map(|&(_, ramp)| ramp)
+}
+
+/// Blank used in place of a space by charsets of double-width characters
+pub const WIDE_BLANK: char = '\u{3000}';
 
 /// Character set ordered by visual density from dark (space) to light (@)
-const CHARSET: &[char] = &[' ', '.', ':', '-', '=', '+', '*', '#', '%', '@'];
+const CHARSET: &str = " .:-=+*#%@";
+
+/// An ordered ramp of characters used to represent brightness levels
+///
+/// The first character represents the darkest brightness and the last the lightest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Charset {
+    chars: Vec<char>,
+}
+
+impl Charset {
+    /// Builds a charset from a ramp string ordered from dark to light
+    ///
+    /// # Returns
+    /// * `Ok(Charset)` - The validated charset
+    /// * `Err(AsciiArtError::EmptyCharset)` - If the ramp is empty
+    /// * `Err(AsciiArtError::InvalidArgument)` - If a character takes no
+    ///   columns, or single- and double-width characters are mixed
+    pub fn new(ramp: &str) -> Result<Self, AsciiArtError> {
+        Self::from_chars(ramp.chars().collect())
+    }
+
+    /// Check that every character takes the same number of terminal columns
+    fn from_chars(chars: Vec<char>) -> Result<Self, AsciiArtError> {
+        let first = *chars.first().ok_or(AsciiArtError::EmptyCharset)?;
+        if let Some(&ch) = chars.iter().find(|ch| ch.width().unwrap_or(0) == 0) {
+            return Err(AsciiArtError::InvalidArgument(format!(
+                "Charset character U+{:04X} takes no columns; zero-width and control \
+                 characters cannot be drawn.",
+                u32::from(ch)
+            )));
+        }
+        if let Some(&ch) = chars.iter().find(|ch| ch.width() != first.width()) {
+            return Err(AsciiArtError::InvalidArgument(format!(
+                "Charset mixes single- and double-width characters ('{first}' and '{ch}'); \
+                 use one kind, such as U+3000 '{WIDE_BLANK}' as a wide blank."
+            )));
+        }
+        Ok(Self { chars })
+    }
+
+    /// One of the [`BUILTIN_CHARSETS`] by name
+    pub fn builtin(name: &str) -> Option<Self> {
+        builtin_ramp(name).and_then(|ramp| Self::new(ramp).ok())
+    }
+
+    /// One of the [`BUILTIN_CHARSETS`] when `value` names one, otherwise a
+    /// ramp of the characters in `value`
+    pub fn named(value: &str) -> Result<Self, AsciiArtError> {
+        Self::builtin(value).map_or_else(|| Self::new(value), Ok)
+    }
+
+    /// Load a charset from a file holding a ramp or a mapping table
+    ///
+    /// See [`parse`](Self::parse) for the format. Errors name the file and,
+    /// for malformed tables, the line and column.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, AsciiArtError> {
+        let path = path.as_ref();
+        let invalid = |reason: String| {
+            AsciiArtError::InvalidArgument(format!(
+                "Invalid charset file \"{}\": {reason}",
+                path.display()
+            ))
+        };
+
+        let source = fs::read_to_string(path).map_err(|err| invalid(err.to_string()))?;
+        Self::parse(&source).map_err(|err| invalid(err.to_string()))
+    }
+
+    /// Parse the contents of a charset file
+    ///
+    /// A file with a single non-blank line is a ramp, ordered from dark to
+    /// light and taken verbatim, leading spaces included. Anything longer is a
+    /// mapping table: one `BRIGHTNESS CHAR` entry per line, where the character
+    /// is used from that brightness (0-255) up to the next entry's. Entries
+    /// must start at 0 and increase; the character may be quoted (`' '`) and
+    /// lines starting with `#` are comments.
+    ///
+    /// ```text
+    /// # brightness  character
+    /// 0    ' '
+    /// 40   .
+    /// 120  +
+    /// 200  @
+    /// ```
+    pub fn parse(source: &str) -> Result<Self, AsciiArtError> {
+        let lines: Vec<&str> = source
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .collect();
+        match lines[..] {
+            [] => Err(AsciiArtError::EmptyCharset),
+            [ramp] => Self::new(ramp),
+            _ => Self::from_table(source),
+        }
+    }
+
+    /// Expand a mapping table into a ramp with one character per brightness
+    fn from_table(source: &str) -> Result<Self, AsciiArtError> {
+        let mut entries: Vec<(u8, char)> = Vec::new();
+        for (index, line) in source.lines().enumerate() {
+            let trimmed = line.trim_start();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            let error = |column: usize, reason: String| {
+                AsciiArtError::InvalidArgument(format!(
+                    "line {}, column {}: {reason}",
+                    index + 1,
+                    column
+                ))
+            };
+            let column_of = |part: &str| line[..line.len() - part.len()].chars().count() + 1;
+
+            let digits = trimmed.split_whitespace().next().unwrap_or_default();
+            let start: u8 = digits.parse().map_err(|_| {
+                error(
+                    column_of(trimmed),
+                    format!("expected a brightness from 0 to 255, found '{digits}'"),
+                )
+            })?;
+            let glyph = trimmed[digits.len()..].trim_start();
+            let value = glyph.trim_end();
+            let ch = match value.chars().collect::<Vec<_>>()[..] {
+                ['\'', ch, '\''] | [ch] => ch,
+                [] => {
+                    return Err(error(
+                        column_of(glyph),
+                        "expected a character after the brightness".to_string(),
+                    ))
+                }
+                _ => {
+                    return Err(error(
+                        column_of(glyph),
+                        format!("expected one character, found '{value}'"),
+                    ))
+                }
+            };
+
+            match entries.last() {
+                None if start != 0 => {
+                    return Err(error(
+                        column_of(trimmed),
+                        format!("the first entry must start at brightness 0, not {start}"),
+                    ))
+                }
+                Some(&(previous, _)) if start <= previous => {
+                    return Err(error(
+                        column_of(trimmed),
+                        format!("brightness {start} must be greater than the previous {previous}"),
+                    ))
+                }
+                _ => entries.push((start, ch)),
+            }
+        }
+
+        let chars = (0..=255u8)
+            .map(|brightness| {
+                entries
+                    .iter()
+                    .rev()
+                    .find(|&&(start, _)| start <= brightness)
+                    .map_or(' ', |&(_, ch)| ch)
+            })
+            .collect();
+        Self::from_chars(chars)
+    }
+
+    /// The characters of the ramp, ordered from dark to light
+    pub fn chars(&self) -> &[char] {
+        &self.chars
+    }
+
+    /// Terminal columns each character takes: 2 for ramps of CJK or emoji
+    /// characters, 1 otherwise
+    pub fn cell_width(&self) -> u32 {
+        self.chars[0].width().unwrap_or(1) as u32
+    }
+
+    /// The character for transparent cells, as wide as the others
+    pub fn blank(&self) -> char {
+        match self.cell_width() {
+            2 => WIDE_BLANK,
+            _ => ' ',
+        }
+    }
+
+    /// The ramp drawn over a gradient from black to white `columns` wide
+    pub fn gradient(&self, columns: u32) -> String {
+        let cells = (columns / self.cell_width()).max(1);
+        (0..cells)
+            .map(|x| {
+                let brightness = match cells {
+                    1 => 0,
+                    _ => x * 255 / (cells - 1),
+                };
+                self.char_for(brightness as u8)
+            })
+            .collect()
+    }
+
+    /// Number of brightness levels the ramp can represent
+    pub fn levels(&self) -> usize {
+        self.chars.len()
+    }
+
+    /// Maps a brightness value (0-255) to a character of this ramp
+    pub fn char_for(&self, brightness: u8) -> char {
+        let last = self.chars.len() - 1;
+        let index = ((brightness as f32 / 255.0) * last as f32).round() as usize;
+        self.chars[index.min(last)]
+    }
+}
+
+impl Charset {
+    /// Measure the ink coverage of every distinct character with the built-in font
+    ///
+    /// Fails if the font has no glyph for some of the characters.
+    pub fn calibrate(&self) -> Result<Calibration, AsciiArtError> {
+        let mut missing = Vec::new();
+        let mut glyphs = Vec::new();
+        for &ch in &self.chars {
+            // Tables repeat characters across brightness levels
+            if glyphs.iter().any(|&(seen, _)| seen == ch) {
+                continue;
+            }
+            match font::ink_coverage(ch) {
+                Some(coverage) => glyphs.push((ch, coverage)),
+                None if !missing.contains(&ch) => missing.push(ch),
+                None => {}
+            }
+        }
+        if !missing.is_empty() {
+            let missing: Vec<String> = missing.iter().map(|ch| format!("'{ch}'")).collect();
+            return Err(AsciiArtError::InvalidArgument(format!(
+                "The built-in font has no glyph for {}; calibration covers ASCII, Latin-1, \
+                 Greek, box drawing, and block characters.",
+                missing.join(", ")
+            )));
+        }
+        // Stable, so characters with the same coverage keep their order
+        glyphs.sort_by(|a, b| a.1.total_cmp(&b.1));
+        Ok(Calibration { glyphs })
+    }
+}
+
+/// The characters of a charset ordered by measured ink coverage
+#[derive(Debug, Clone, PartialEq)]
+pub struct Calibration {
+    /// Each character and the fraction (0-1) of its cell it inks, lightest first
+    pub glyphs: Vec<(char, f32)>,
+}
+
+impl Calibration {
+    /// The characters as a ramp, ordered from the least to the most ink
+    pub fn charset(&self) -> Charset {
+        Charset {
+            chars: self.glyphs.iter().map(|&(ch, _)| ch).collect(),
+        }
+    }
+
+    /// A mapping table for [`Charset::parse`] that spaces the characters by
+    /// their coverage instead of evenly
+    ///
+    /// Coverage is stretched over the full brightness range and each character
+    /// starts halfway between its own level and the previous character's.
+    /// Characters with the same coverage as the one before them are left out.
+    pub fn table(&self) -> String {
+        let (lightest, heaviest) = match (self.glyphs.first(), self.glyphs.last()) {
+            (Some(&(_, lightest)), Some(&(_, heaviest))) => (lightest, heaviest),
+            _ => return String::new(),
+        };
+        let range = (heaviest - lightest).max(f32::EPSILON);
+        let level = |coverage: f32| (coverage - lightest) / range * 255.0;
+
+        let mut table = String::from("# brightness  character\n");
+        let mut previous: Option<(f32, u8)> = None;
+        for &(ch, coverage) in &self.glyphs {
+            let start = match previous {
+                None => 0,
+                Some((last, _)) if coverage == last => continue,
+                Some((last, last_start)) => {
+                    let midpoint = ((level(last) + level(coverage)) / 2.0).ceil() as u8;
+                    midpoint.max(last_start + 1)
+                }
+            };
+            table.push_str(&format!("{start:<4}  '{ch}'\n"));
+            previous = Some((coverage, start));
+        }
+        table
+    }
+}
+
+impl Default for Charset {
+    fn default() -> Self {
+        Self {
+            chars: CHARSET.chars().collect(),
+        }
+    }
+}
 
 /// Maps a brightness value (0-255) to an ASCII character
 ///
@@ -12,46 +453,172 @@ const CHARSET: &[char] = &[' ', '.', ':', '-', '=', '+', '*', '#', '%', '@'];
 /// Brightness 0 maps to ' ' (darkest), 255 maps to '@' (lightest).
 pub fn brightness_to_char(brightness: u8) -> char {
     let index = ((brightness as f32 / 255.0) * 9.0).round() as usize;
-    CHARSET[index.min(9)]
+    char::from(CHARSET.as_bytes()[index.min(9)])
 }
 
-/// Converts a grayscale image to an ASCII character grid
+/// Converts a processed image to an ASCII image
 ///
 /// Each pixel's brightness is mapped to a character, producing a grid
 /// with dimensions matching the input image.
 ///
 /// # Arguments
-/// * `gray` - The grayscale image to convert
+/// * `image` - The resized color and grayscale image to convert
 ///
 /// # Returns
-/// * `Ok(AsciiGrid)` - A 2D vector of characters matching image dimensions
-/// * `Err(String)` - Error message if conversion fails
-pub fn convert_to_ascii(gray: &GrayImage) -> Result<AsciiGrid, String> {
-    let (width, height) = gray.dimensions();
-    
+/// * `Ok(AsciiImage)` - Cells carrying character, color, and brightness
+/// * `Err(AsciiArtError::EmptyImage)` - If the image has no pixels
+pub fn convert_to_ascii(image: &ProcessedImage) -> Result<AsciiImage, AsciiArtError> {
+    convert_with_charset(image, &Charset::default())
+}
+
+/// Converts a processed image to an ASCII image using a custom charset
+///
+/// # Arguments
+/// * `image` - The resized color and grayscale image to convert
+/// * `charset` - The ramp used to map brightness to characters
+///
+/// # Returns
+/// * `Ok(AsciiImage)` - Cells carrying character, color, and brightness
+/// * `Err(AsciiArtError::EmptyImage)` - If the image has no pixels
+pub fn convert_with_charset(
+    image: &ProcessedImage,
+    charset: &Charset,
+) -> Result<AsciiImage, AsciiArtError> {
+    map_cells(image, |_, _, luma| charset.char_for(luma))
+}
+
+/// Builds an ASCII image by choosing a character for every pixel
+///
+/// The color and brightness of each cell are sampled from `image`; `pick` receives
+/// the pixel coordinates and brightness and returns the character to display.
+/// Pixels with less alpha than `image.alpha_threshold` become transparent
+/// spaces without consulting `pick`.
+///
+/// # Returns
+/// * `Ok(AsciiImage)` - Cells carrying character, color, and brightness
+/// * `Err(AsciiArtError::EmptyImage)` - If the image has no pixels
+/// * `Err(AsciiArtError::DimensionMismatch)` - If the color and grayscale images differ in size
+pub fn map_cells<F>(image: &ProcessedImage, pick: F) -> Result<AsciiImage, AsciiArtError>
+where
+    F: Fn(u32, u32, u8) -> char + Sync + Send,
+{
+    map_cell_rows(image, 0..image.gray.height(), pick)
+}
+
+/// Builds an ASCII image covering only the rows in `rows`
+///
+/// This is the streaming counterpart of [`map_cells`]: converting a tall image
+/// strip by strip keeps only one strip of cells in memory at a time. The `y`
+/// passed to `pick` is the row index in the full image.
+pub fn map_cell_rows<F>(
+    image: &ProcessedImage,
+    rows: Range<u32>,
+    pick: F,
+) -> Result<AsciiImage, AsciiArtError>
+where
+    F: Fn(u32, u32, u8) -> char + Sync + Send,
+{
+    let (width, height) = image.gray.dimensions();
+
     if width == 0 || height == 0 {
-        return Err("Image dimensions must be greater than zero.".to_string());
+        return Err(AsciiArtError::EmptyImage);
+    }
+
+    if image.rgb.dimensions() != (width, height) {
+        return Err(AsciiArtError::DimensionMismatch {
+            expected: (width, height),
+            actual: image.rgb.dimensions(),
+        });
     }
+    if let Some(alpha) = image
+        .alpha
+        .as_ref()
+        .filter(|alpha| alpha.dimensions() != (width, height))
+    {
+        return Err(AsciiArtError::DimensionMismatch {
+            expected: (width, height),
+            actual: alpha.dimensions(),
+        });
+    }
+
+    let rows = rows.start.min(height)..rows.end.min(height);
+    let row_len = width as usize;
+    let gray = image.gray.as_raw();
+    let rgb = image.rgb.as_raw();
+    let alpha = image.alpha.as_ref().map(|alpha| alpha.as_raw());
+
+    let strip = map_rows(rows.len(), |offset| {
+        let y = rows.start as usize + offset;
+        let gray_row = &gray[y * row_len..(y + 1) * row_len];
+        let rgb_row = &rgb[y * row_len * 3..(y + 1) * row_len * 3];
+        let alpha_row = alpha.map(|alpha| &alpha[y * row_len..(y + 1) * row_len]);
+        gray_row
+            .iter()
+            .zip(rgb_row.chunks_exact(3))
+            .enumerate()
+            .map(|(x, (&luma, pixel))| {
+                let transparent = alpha_row.is_some_and(|row| row[x] < image.alpha_threshold);
+                Cell {
+                    ch: if transparent {
+                        ' '
+                    } else {
+                        pick(x as u32, y as u32, luma)
+                    },
+                    rgb: [pixel[0], pixel[1], pixel[2]],
+                    luma,
+                    transparent,
+                }
+            })
+            .collect::<Vec<_>>()
+    });
 
-    let mut grid = Vec::with_capacity(height as usize);
-    
-    for y in 0..height {
-        let mut row = Vec::with_capacity(width as usize);
-        for x in 0..width {
-            let pixel = gray.get_pixel(x, y);
-            let brightness = pixel[0];
-            row.push(brightness_to_char(brightness));
+    AsciiImage::new(width as usize, rows.len(), strip.concat())
+}
+
+/// Applies Floyd–Steinberg error diffusion, quantizing brightness to `levels` steps
+///
+/// The returned image only contains the exact brightness of each level, so mapping it
+/// through a charset with the same number of levels is lossless. Diffusing the
+/// quantization error keeps smooth gradients from collapsing into flat bands.
+pub fn dither_gray(gray: &GrayImage, levels: usize) -> GrayImage {
+    let (width, height) = gray.dimensions();
+    let steps = levels.max(2) as f32 - 1.0;
+    let (w, h) = (width as usize, height as usize);
+    let mut buffer: Vec<f32> = gray.pixels().map(|p| p[0] as f32).collect();
+    let mut output = GrayImage::new(width, height);
+
+    for y in 0..h {
+        for x in 0..w {
+            let old = buffer[y * w + x].clamp(0.0, 255.0);
+            let quantized = ((old / 255.0) * steps).round() / steps * 255.0;
+            output.put_pixel(x as u32, y as u32, image::Luma([quantized.round() as u8]));
+
+            let error = old - quantized;
+            let mut spread = |dx: isize, dy: usize, weight: f32| {
+                let nx = x as isize + dx;
+                let ny = y + dy;
+                if nx >= 0 && (nx as usize) < w && ny < h {
+                    buffer[ny * w + nx as usize] += error * weight;
+                }
+            };
+            spread(1, 0, 7.0 / 16.0);
+            spread(-1, 1, 3.0 / 16.0);
+            spread(0, 1, 5.0 / 16.0);
+            spread(1, 1, 1.0 / 16.0);
         }
-        grid.push(row);
     }
-    
-    Ok(grid)
+
+    output
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use image::{GrayImage, ImageBuffer};
+    use image::{DynamicImage, GrayImage, ImageBuffer};
+
+    fn processed(gray: GrayImage) -> ProcessedImage {
+        ProcessedImage::new(DynamicImage::ImageLuma8(gray))
+    }
 
     #[test]
     fn brightness_to_char_boundary_values() {
@@ -74,41 +641,176 @@ mod tests {
     #[test]
     fn convert_to_ascii_dimensions_match() {
         let gray = GrayImage::from_pixel(10, 5, image::Luma([128]));
-        let grid = convert_to_ascii(&gray).expect("conversion succeeds");
-        
-        assert_eq!(grid.len(), 5, "Grid should have 5 rows");
-        assert_eq!(grid[0].len(), 10, "Each row should have 10 characters");
+        let ascii = convert_to_ascii(&processed(gray)).expect("conversion succeeds");
+
+        assert_eq!(ascii.height(), 5, "Grid should have 5 rows");
+        assert_eq!(ascii.width(), 10, "Each row should have 10 characters");
     }
 
     #[test]
     fn convert_to_ascii_fully_black_image() {
         let gray = GrayImage::from_pixel(4, 3, image::Luma([0]));
-        let grid = convert_to_ascii(&gray).expect("conversion succeeds");
-        
-        for row in &grid {
-            for &ch in row {
-                assert_eq!(ch, ' ', "Fully black image should produce all spaces");
-            }
+        let ascii = convert_to_ascii(&processed(gray)).expect("conversion succeeds");
+
+        for cell in ascii.cells() {
+            assert_eq!(cell.ch, ' ', "Fully black image should produce all spaces");
         }
     }
 
     #[test]
     fn convert_to_ascii_fully_white_image() {
         let gray = GrayImage::from_pixel(4, 3, image::Luma([255]));
-        let grid = convert_to_ascii(&gray).expect("conversion succeeds");
-        
-        for row in &grid {
-            for &ch in row {
-                assert_eq!(ch, '@', "Fully white image should produce all '@' characters");
-            }
+        let ascii = convert_to_ascii(&processed(gray)).expect("conversion succeeds");
+
+        for cell in ascii.cells() {
+            assert_eq!(
+                cell.ch, '@',
+                "Fully white image should produce all '@' characters"
+            );
         }
     }
 
     #[test]
     fn convert_to_ascii_rejects_zero_dimensions() {
         let gray = ImageBuffer::new(0, 0);
-        let err = convert_to_ascii(&gray).unwrap_err();
-        assert_eq!(err, "Image dimensions must be greater than zero.");
+        let err = convert_to_ascii(&processed(gray)).unwrap_err();
+        assert!(matches!(err, AsciiArtError::EmptyImage));
+        assert_eq!(
+            err.to_string(),
+            "Image dimensions must be greater than zero."
+        );
+    }
+
+    #[test]
+    fn charset_rejects_empty_ramp() {
+        let err = Charset::new("").unwrap_err();
+        assert!(matches!(err, AsciiArtError::EmptyCharset));
+        assert_eq!(
+            err.to_string(),
+            "Charset must contain at least one character."
+        );
+    }
+
+    #[test]
+    fn charset_checks_character_widths() {
+        assert_eq!(Charset::default().cell_width(), 1);
+        let wide = Charset::new("\u{3000}・口回田").expect("wide charset");
+        assert_eq!((wide.cell_width(), wide.blank()), (2, WIDE_BLANK));
+
+        let err = Charset::new(" .\u{301}#").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Charset character U+0301 takes no columns; zero-width and control characters \
+             cannot be drawn."
+        );
+        assert!(Charset::new(" \t#").is_err());
+        let err = Charset::new(" 口").unwrap_err();
+        assert!(err.to_string().contains("(' ' and '口')"), "{err}");
+    }
+
+    #[test]
+    fn builtin_charsets_draw_gradients() {
+        for (name, ramp) in BUILTIN_CHARSETS {
+            let charset = Charset::builtin(name).expect("valid builtin");
+            assert_eq!(charset.chars().iter().collect::<String>(), ramp);
+        }
+        assert_eq!(Charset::builtin("fancy"), None);
+        assert_eq!(Charset::named("blocks").ok(), Charset::builtin("blocks"));
+        assert_eq!(Charset::named("xy").ok(), Charset::new("xy").ok());
+
+        let blocks = Charset::builtin("blocks").unwrap();
+        assert_eq!(blocks.gradient(10), "  ░░▒▒▓▓██");
+        assert_eq!(blocks.gradient(1), " ");
+        let wide = Charset::new("\u{3000}口").unwrap();
+        assert_eq!(wide.gradient(8), "\u{3000}\u{3000}口口");
+    }
+
+    #[test]
+    fn charset_maps_extremes_to_ramp_ends() {
+        let charset = Charset::new(" .oO").expect("valid charset");
+        assert_eq!(charset.char_for(0), ' ');
+        assert_eq!(charset.char_for(255), 'O');
+        assert_eq!(Charset::new("#").expect("valid").char_for(200), '#');
+    }
+
+    #[test]
+    fn charset_files_hold_a_ramp_or_a_table() {
+        let ramp = Charset::parse(" .oO\n\n").expect("valid ramp");
+        assert_eq!(ramp, Charset::new(" .oO").expect("valid charset"));
+
+        let table =
+            Charset::parse("# brightness char\n0 ' '\n100 .\n\n200 @\n").expect("valid table");
+        assert_eq!(table.levels(), 256);
+        assert_eq!(table.char_for(0), ' ');
+        assert_eq!(table.char_for(99), ' ');
+        assert_eq!(table.char_for(100), '.');
+        assert_eq!(table.char_for(199), '.');
+        assert_eq!(table.char_for(255), '@');
+        assert_eq!(
+            Charset::parse("0 '#'\n10 x").expect("quoted").char_for(5),
+            '#'
+        );
+    }
+
+    #[test]
+    fn charset_table_errors_point_at_the_problem() {
+        let error = |source: &str| Charset::parse(source).unwrap_err().to_string();
+        assert_eq!(
+            error("0 a\n  x b\n"),
+            "line 2, column 3: expected a brightness from 0 to 255, found 'x'"
+        );
+        assert_eq!(
+            error("0 a\n50 bc\n"),
+            "line 2, column 4: expected one character, found 'bc'"
+        );
+        assert_eq!(
+            error("0 a\n50\n"),
+            "line 2, column 3: expected a character after the brightness"
+        );
+        assert_eq!(
+            error("# table\n10 a\n50 b\n"),
+            "line 2, column 1: the first entry must start at brightness 0, not 10"
+        );
+        assert_eq!(
+            error("0 a\n50 b\n50 c\n"),
+            "line 3, column 1: brightness 50 must be greater than the previous 50"
+        );
+        assert!(matches!(
+            Charset::parse("\n  \n"),
+            Err(AsciiArtError::EmptyCharset)
+        ));
+    }
+
+    #[test]
+    fn calibration_orders_and_spaces_by_ink() {
+        let calibration = Charset::new("▓. ░").unwrap().calibrate().unwrap();
+        assert_eq!(calibration.charset(), Charset::new(" .░▓").unwrap());
+        assert!(calibration
+            .glyphs
+            .windows(2)
+            .all(|pair| pair[0].1 <= pair[1].1));
+
+        let table = Charset::parse(&calibration.table()).expect("valid table");
+        assert_eq!(table.char_for(0), ' ');
+        assert_eq!(table.char_for(255), '▓');
+        assert_eq!(calibration.table().lines().nth(1), Some("0     ' '"));
+
+        let err = Charset::new(" .Ж").unwrap().calibrate().unwrap_err();
+        assert!(err.to_string().contains("no glyph for 'Ж'"), "{err}");
+    }
+
+    #[test]
+    fn dither_gray_only_emits_level_values() {
+        let gray = GrayImage::from_fn(8, 8, |x, _| image::Luma([(x * 30) as u8]));
+        let dithered = dither_gray(&gray, 3);
+
+        for pixel in dithered.pixels() {
+            assert!(
+                matches!(pixel[0], 0 | 128 | 255),
+                "unexpected level {}",
+                pixel[0]
+            );
+        }
     }
 
     #[test]
@@ -119,12 +821,69 @@ mod tests {
         gray.put_pixel(1, 0, image::Luma([127]));
         gray.put_pixel(2, 0, image::Luma([255]));
         
-        let grid = convert_to_ascii(&gray).expect("conversion succeeds");
-        
-        assert_eq!(grid.len(), 1);
-        assert_eq!(grid[0].len(), 3);
-        assert_eq!(grid[0][0], ' ');
-        assert_eq!(grid[0][1], '=');
-        assert_eq!(grid[0][2], '@');
+        let ascii = convert_to_ascii(&processed(gray)).expect("conversion succeeds");
+
+        assert_eq!(ascii.height(), 1);
+        assert_eq!(ascii.width(), 3);
+        assert_eq!(ascii.to_text(), " =@\n");
+    }
+
+    #[test]
+    fn convert_to_ascii_records_color_and_luma() {
+        let color = ImageBuffer::from_pixel(2, 1, image::Rgb([200, 40, 10]));
+        let image = ProcessedImage::new(DynamicImage::ImageRgb8(color));
+        let ascii = convert_to_ascii(&image).expect("conversion succeeds");
+
+        let cell = ascii.get(1, 0).expect("cell exists");
+        assert_eq!(cell.rgb, [200, 40, 10]);
+        assert_eq!(cell.luma, image.gray.get_pixel(1, 0)[0]);
+        assert!(ascii.get(2, 0).is_none());
+    }
+
+    #[test]
+    fn map_cells_rejects_mismatched_color_image() {
+        let mut image = processed(GrayImage::from_pixel(4, 3, image::Luma([10])));
+        image.rgb = image::RgbImage::new(2, 2);
+
+        let err = convert_to_ascii(&image).unwrap_err();
+        assert!(matches!(
+            err,
+            AsciiArtError::DimensionMismatch {
+                expected: (4, 3),
+                actual: (2, 2)
+            }
+        ));
+    }
+
+    #[test]
+    fn map_cell_rows_converts_only_requested_strip() {
+        let gray = GrayImage::from_fn(3, 4, |_, y| image::Luma([if y < 2 { 0 } else { 255 }]));
+        let image = processed(gray);
+
+        let strip = map_cell_rows(&image, 1..3, |_, y, _| char::from(b'0' + y as u8))
+            .expect("conversion succeeds");
+        assert_eq!(strip.height(), 2);
+        assert_eq!(strip.to_text(), "111\n222\n");
+    }
+
+    #[test]
+    fn transparent_pixels_become_blank_cells() {
+        let rgba = ImageBuffer::from_fn(3, 1, |x, _| image::Rgba([255, 255, 255, x as u8 * 100]));
+        let mut image = ProcessedImage::new(DynamicImage::ImageRgba8(rgba));
+
+        let ascii = convert_to_ascii(&image).expect("conversion succeeds");
+        assert_eq!(ascii.to_text(), "  @\n");
+        let transparent: Vec<bool> = ascii.cells().iter().map(|cell| cell.transparent).collect();
+        assert_eq!(transparent, [true, true, false]);
+
+        image.alpha_threshold = 0;
+        let opaque = convert_to_ascii(&image).expect("conversion succeeds");
+        assert_eq!(opaque.to_text(), "@@@\n");
+    }
+
+    #[test]
+    fn ascii_image_rejects_mismatched_cell_count() {
+        let err = AsciiImage::new(2, 2, Vec::new()).unwrap_err();
+        assert!(matches!(err, AsciiArtError::InvalidArgument(_)));
     }
 }
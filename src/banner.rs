@@ -0,0 +1,240 @@
+//! Text drawn into an image for the `text` subcommand, so banners go through
+//! the same pipeline as photos
+//!
+//! Text is rasterized into a coverage mask, with the built-in 8x8 font or a
+//! TrueType font (`ttf` feature), and then colored with a gradient; the mask
+//! becomes the alpha channel, so the background is left transparent.
+
+use std::str::FromStr;
+
+use image::{DynamicImage, GrayImage, Luma, Rgba, RgbaImage};
+
+use crate::color::{hsl_to_rgb, parse_color};
+use crate::error::AsciiArtError;
+use crate::font::{self, GLYPH_SIZE};
+
+/// Default height of TrueType text, in pixels
+pub const DEFAULT_FONT_SIZE: f32 = 48.0;
+
+/// Largest height of TrueType text, in pixels
+pub const MAX_FONT_SIZE: f32 = 1024.0;
+
+/// Hues of the `rainbow` gradient, in degrees
+const RAINBOW_HUES: [f32; 6] = [0.0, 40.0, 120.0, 190.0, 240.0, 290.0];
+
+/// Colors blended from left to right across the text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Gradient {
+    stops: Vec<[u8; 3]>,
+}
+
+impl Gradient {
+    /// The color `t` (0-1) of the way across
+    pub fn color_at(&self, t: f32) -> [u8; 3] {
+        let last = self.stops.len() - 1;
+        let position = t.clamp(0.0, 1.0) * last as f32;
+        let index = (position.floor() as usize).min(last.saturating_sub(1));
+        let (from, to) = (self.stops[index], self.stops[(index + 1).min(last)]);
+        let mix = position - index as f32;
+        std::array::from_fn(|c| {
+            (f32::from(from[c]) + (f32::from(to[c]) - f32::from(from[c])) * mix).round() as u8
+        })
+    }
+}
+
+impl Default for Gradient {
+    fn default() -> Self {
+        Self {
+            stops: RAINBOW_HUES
+                .iter()
+                .map(|&hue| hsl_to_rgb([hue, 1.0, 0.5]))
+                .collect(),
+        }
+    }
+}
+
+/// `rainbow`, or one or more comma-separated colors (`#rrggbb` or names)
+impl FromStr for Gradient {
+    type Err = AsciiArtError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if value == "rainbow" {
+            return Ok(Self::default());
+        }
+        let stops = value
+            .split(',')
+            .map(|color| parse_color(color.trim()))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { stops })
+    }
+}
+
+/// Draw `text` with the built-in 8x8 font, one line per line of text
+///
+/// Fails for characters the font has no glyph for.
+pub fn rasterize_builtin(text: &str) -> Result<GrayImage, AsciiArtError> {
+    let lines: Vec<&str> = text.lines().collect();
+    let columns = lines.iter().map(|line| line.chars().count()).max();
+    let columns = columns.unwrap_or(0) as u32;
+    if columns == 0 {
+        return Err(AsciiArtError::InvalidArgument(
+            "The text to draw is empty.".to_string(),
+        ));
+    }
+    let mut mask = GrayImage::new(columns * GLYPH_SIZE, lines.len() as u32 * GLYPH_SIZE);
+    for (row, line) in lines.iter().enumerate() {
+        for (column, ch) in line.chars().enumerate() {
+            let rows = font::glyph(ch).ok_or_else(|| {
+                AsciiArtError::InvalidArgument(format!(
+                    "The built-in font has no glyph for '{ch}' (U+{:04X}); use --font.",
+                    ch as u32
+                ))
+            })?;
+            for (dy, bits) in rows.iter().enumerate() {
+                for dx in (0..GLYPH_SIZE).filter(|dx| bits & (1 << dx) != 0) {
+                    let x = column as u32 * GLYPH_SIZE + dx;
+                    let y = row as u32 * GLYPH_SIZE + dy as u32;
+                    mask.put_pixel(x, y, Luma([255]));
+                }
+            }
+        }
+    }
+    Ok(mask)
+}
+
+/// Draw `text` with a TrueType or OpenType font, `size` pixels high per line
+///
+/// `size` may be at most [`MAX_FONT_SIZE`].
+#[cfg(feature = "ttf")]
+pub fn rasterize_ttf(
+    text: &str,
+    font_data: Vec<u8>,
+    size: f32,
+) -> Result<GrayImage, AsciiArtError> {
+    use rusttype::{point, Font, Scale};
+
+    if !(size.is_finite() && size <= MAX_FONT_SIZE) {
+        return Err(AsciiArtError::InvalidArgument(format!(
+            "Font size must be at most {MAX_FONT_SIZE} pixels, got {size}."
+        )));
+    }
+    let font = Font::try_from_vec(font_data).ok_or_else(|| {
+        AsciiArtError::InvalidArgument("The font file is not a TrueType or OpenType font.".into())
+    })?;
+    let scale = Scale::uniform(size.max(1.0));
+    let metrics = font.v_metrics(scale);
+    let line_height = (metrics.ascent - metrics.descent + metrics.line_gap).ceil();
+    let lines: Vec<Vec<_>> = text
+        .lines()
+        .enumerate()
+        .map(|(row, line)| {
+            let baseline = row as f32 * line_height + metrics.ascent;
+            font.layout(line, scale, point(0.0, baseline)).collect()
+        })
+        .collect();
+    // Cropped to the ink on the right and bottom, so the last line's descent
+    // doesn't leave empty rows
+    let bounds = lines
+        .iter()
+        .flatten()
+        .filter_map(|glyph| glyph.pixel_bounding_box());
+    let (width, height) = bounds.fold((0, 0), |(width, height), bounds| {
+        (width.max(bounds.max.x), height.max(bounds.max.y))
+    });
+    if width <= 0 || height <= 0 {
+        return Err(AsciiArtError::InvalidArgument(
+            "The text to draw is empty.".to_string(),
+        ));
+    }
+
+    let mut mask = GrayImage::new(width as u32, height as u32);
+    for glyph in lines.iter().flatten() {
+        let Some(bounds) = glyph.pixel_bounding_box() else {
+            continue;
+        };
+        glyph.draw(|x, y, coverage| {
+            let (x, y) = (bounds.min.x + x as i32, bounds.min.y + y as i32);
+            if x >= 0 && y >= 0 && (x as u32) < mask.width() && (y as u32) < mask.height() {
+                let pixel = mask.get_pixel_mut(x as u32, y as u32);
+                pixel[0] = pixel[0].max((coverage * 255.0).round() as u8);
+            }
+        });
+    }
+    Ok(mask)
+}
+
+/// Draw `text` with a TrueType or OpenType font, `size` pixels high per line
+///
+/// Fails with [`AsciiArtError::FeatureDisabled`] when built without the
+/// `ttf` feature.
+#[cfg(not(feature = "ttf"))]
+pub fn rasterize_ttf(
+    _text: &str,
+    _font_data: Vec<u8>,
+    _size: f32,
+) -> Result<GrayImage, AsciiArtError> {
+    Err(AsciiArtError::FeatureDisabled {
+        what: "TrueType fonts",
+        feature: "ttf",
+    })
+}
+
+/// Color a coverage mask with `gradient` from its left to its right edge,
+/// keeping the coverage as alpha
+pub fn colorize(mask: &GrayImage, gradient: &Gradient) -> DynamicImage {
+    let span = mask.width().saturating_sub(1).max(1) as f32;
+    let image = RgbaImage::from_fn(mask.width(), mask.height(), |x, y| {
+        let [r, g, b] = gradient.color_at(x as f32 / span);
+        Rgba([r, g, b, mask.get_pixel(x, y)[0]])
+    });
+    DynamicImage::ImageRgba8(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_font_draws_each_line_of_glyphs() {
+        let mask = rasterize_builtin("Hi\n!").expect("drawn");
+        assert_eq!(mask.dimensions(), (16, 16));
+        // The left stem of 'H'
+        assert_eq!(mask.get_pixel(0, 0)[0], 255);
+        // Nothing to the right of '!' on the second line
+        assert!((8..16).all(|x| (8..16).all(|y| mask.get_pixel(x, y)[0] == 0)));
+
+        assert!(rasterize_builtin("").is_err());
+        let missing = rasterize_builtin("日").unwrap_err().to_string();
+        assert!(missing.contains("U+65E5"), "{missing}");
+    }
+
+    #[test]
+    fn gradient_blends_its_stops() {
+        let gradient: Gradient = "#000000, white".parse().expect("valid gradient");
+        assert_eq!(gradient.color_at(0.0), [0, 0, 0]);
+        assert_eq!(gradient.color_at(0.5), [128, 128, 128]);
+        assert_eq!(gradient.color_at(1.0), [255, 255, 255]);
+        let solid: Gradient = "red".parse().expect("valid gradient");
+        assert_eq!(solid.color_at(0.7), [255, 0, 0]);
+        assert_eq!("rainbow".parse::<Gradient>().unwrap(), Gradient::default());
+        assert!("red,nope".parse::<Gradient>().is_err());
+
+        let mask = GrayImage::from_fn(3, 1, |x, _| Luma([if x == 1 { 0 } else { 255 }]));
+        let colored = colorize(&mask, &gradient).to_rgba8();
+        assert_eq!(colored.get_pixel(0, 0).0, [0, 0, 0, 255]);
+        assert_eq!(colored.get_pixel(1, 0).0[3], 0);
+        assert_eq!(colored.get_pixel(2, 0).0, [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn ttf_rejects_data_that_is_not_a_font() {
+        assert!(rasterize_ttf("Hi", b"not a font".to_vec(), 24.0).is_err());
+    }
+
+    #[cfg(feature = "ttf")]
+    #[test]
+    fn ttf_font_size_is_capped() {
+        let err = rasterize_ttf("Hi", Vec::new(), 1e9).unwrap_err();
+        assert!(err.to_string().contains("at most 1024 pixels"), "{err}");
+    }
+}
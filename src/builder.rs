@@ -0,0 +1,1197 @@
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+
+use image::{DynamicImage, GrayImage, RgbImage};
+use tracing::debug;
+
+use crate::ascii_converter::{dither_gray, map_cell_rows, map_cells, AsciiImage, Cell, Charset};
+use crate::color::{
+    darken_for_light_background, srgb_to_oklab, ColorMetric, HslAdjustment, WhiteBalance,
+};
+use crate::edge_detector;
+use crate::error::AsciiArtError;
+use crate::filters::{
+    apply_color_filters, apply_filters, unsharp_mask, Clahe, ColorFilter, Denoise, Filter,
+};
+use crate::image_loader::{
+    composite_over, pixel_art_width, resize_to_grid_with_filter, ChromaKey, ProcessedImage,
+    ResizeFilter, DEFAULT_ALPHA_THRESHOLD, DEFAULT_CHAR_ASPECT,
+};
+use crate::palette::{nearest_color, Palette, Theme};
+use crate::quantize::AdaptivePalette;
+use crate::renderer::{self, ColorDepth, RenderOptions};
+use crate::score::{self, Score};
+use crate::stats::Stats;
+use crate::timings::{time, Timings};
+
+/// Default output width used when none is configured
+const DEFAULT_WIDTH: u32 = 80;
+
+/// Rows converted and written per strip by [`AsciiArtBuilder::write_streaming`]
+pub const DEFAULT_STRIP_ROWS: u32 = 64;
+
+/// Conversion strategy used to pick characters
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Mode {
+    /// Brightness-based character mapping
+    #[default]
+    Standard,
+    /// Canny edge detection contours
+    Edge,
+}
+
+impl FromStr for Mode {
+    type Err = AsciiArtError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "standard" => Ok(Mode::Standard),
+            "edge" => Ok(Mode::Edge),
+            unknown => Err(AsciiArtError::UnknownMode(unknown.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mode::Standard => write!(f, "standard"),
+            Mode::Edge => write!(f, "edge"),
+        }
+    }
+}
+
+/// Brightness of the terminal background the art is shown on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Background {
+    /// Light characters on a dark background
+    #[default]
+    Dark,
+    /// Dense characters mark dark pixels, and pale colors are darkened
+    Light,
+}
+
+impl FromStr for Background {
+    type Err = AsciiArtError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "dark" => Ok(Background::Dark),
+            "light" => Ok(Background::Light),
+            unknown => Err(AsciiArtError::InvalidArgument(format!(
+                "Unknown background '{}'. Use 'dark', 'light', or 'auto'.",
+                unknown
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Background {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Background::Dark => write!(f, "dark"),
+            Background::Light => write!(f, "light"),
+        }
+    }
+}
+
+/// Configures and runs the image-to-ASCII pipeline
+///
+/// ```no_run
+/// use ascii_art_cli::{AsciiArtBuilder, Mode};
+///
+/// let image = image::open("examples/puffin.jpg").unwrap();
+/// let art = AsciiArtBuilder::new()
+///     .width(60)
+///     .mode(Mode::Edge)
+///     .generate(&image)
+///     .unwrap();
+/// println!("{}", art.to_text());
+/// ```
+#[derive(Debug, Clone)]
+pub struct AsciiArtBuilder {
+    width: u32,
+    height: Option<u32>,
+    char_aspect: f32,
+    resize_filter: ResizeFilter,
+    pixel_art: bool,
+    charset: Charset,
+    mode: Mode,
+    render_options: RenderOptions,
+    dithering: bool,
+    filters: Vec<Filter>,
+    color_filters: Vec<ColorFilter>,
+    hsl: HslAdjustment,
+    hsl_brightness: bool,
+    auto_white_balance: bool,
+    clahe: Option<Clahe>,
+    sharpen: f32,
+    denoise: Option<Denoise>,
+    sharpen_color: bool,
+    theme: Option<Theme>,
+    adaptive_palette: Option<AdaptivePalette>,
+    background: Background,
+    background_color: Option<[u8; 3]>,
+    chroma_key: Option<ChromaKey>,
+    alpha_threshold: u8,
+}
+
+impl Default for AsciiArtBuilder {
+    fn default() -> Self {
+        Self {
+            width: DEFAULT_WIDTH,
+            height: None,
+            char_aspect: DEFAULT_CHAR_ASPECT,
+            resize_filter: ResizeFilter::default(),
+            pixel_art: false,
+            charset: Charset::default(),
+            mode: Mode::default(),
+            render_options: RenderOptions::default(),
+            dithering: false,
+            filters: Vec::new(),
+            color_filters: Vec::new(),
+            hsl: HslAdjustment::default(),
+            hsl_brightness: false,
+            auto_white_balance: false,
+            clahe: None,
+            sharpen: 0.0,
+            denoise: None,
+            sharpen_color: false,
+            theme: None,
+            adaptive_palette: None,
+            background: Background::default(),
+            background_color: None,
+            chroma_key: None,
+            alpha_threshold: DEFAULT_ALPHA_THRESHOLD,
+        }
+    }
+}
+
+impl AsciiArtBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Output width in characters
+    pub fn width(mut self, width: u32) -> Self {
+        self.width = width;
+        self
+    }
+
+    /// Output height in characters; derived from the aspect ratio when unset
+    pub fn height(mut self, height: Option<u32>) -> Self {
+        self.height = height;
+        self
+    }
+
+    /// Height-to-width ratio of a character cell, used to derive the height
+    pub fn char_aspect(mut self, char_aspect: f32) -> Self {
+        self.char_aspect = char_aspect;
+        self
+    }
+
+    /// Sampling filter used to shrink the image to the character grid
+    pub fn resize_filter(mut self, resize_filter: ResizeFilter) -> Self {
+        self.resize_filter = resize_filter;
+        self
+    }
+
+    /// Treat the source as pixel art: resize with nearest neighbor at an
+    /// integer scale of its width, and never dither characters or colors
+    pub fn pixel_art(mut self, enabled: bool) -> Self {
+        self.pixel_art = enabled;
+        self
+    }
+
+    /// Brightness ramp used in standard mode
+    pub fn charset(mut self, charset: Charset) -> Self {
+        self.charset = charset;
+        self
+    }
+
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Color depth used when rendering to the terminal
+    pub fn color_depth(mut self, color_depth: ColorDepth) -> Self {
+        self.render_options.color_depth = color_depth;
+        self
+    }
+
+    /// Distance metric used to match cell colors to the terminal palette
+    pub fn color_metric(mut self, color_metric: ColorMetric) -> Self {
+        self.render_options.color_metric = color_metric;
+        self
+    }
+
+    /// Render in shades of gray, keeping each cell's brightness but not its hue
+    pub fn grayscale(mut self, grayscale: bool) -> Self {
+        self.render_options.grayscale = grayscale;
+        self
+    }
+
+    /// Dither cell colors (Floyd–Steinberg) when quantizing to 8, 16, or 256 colors
+    pub fn color_dithering(mut self, enabled: bool) -> Self {
+        self.render_options.color_dither = enabled;
+        self
+    }
+
+    /// Draw 8- and 16-color output with shade glyphs (░▒▓█) that mix a
+    /// foreground and a background color, replacing the character ramp
+    pub fn shading(mut self, enabled: bool) -> Self {
+        self.render_options.shade = enabled;
+        self
+    }
+
+    /// Write as few bytes as possible, for slow links: no trailing blanks,
+    /// and no color reset or repeated color at each line break
+    pub fn compact(mut self, enabled: bool) -> Self {
+        self.render_options.compact = enabled;
+        self
+    }
+
+    /// Extract a palette from each image and snap cell colors to it
+    ///
+    /// Output with an adaptive palette is rendered in truecolor, unless the
+    /// color depth is monochrome.
+    pub fn adaptive_palette(mut self, adaptive_palette: Option<AdaptivePalette>) -> Self {
+        self.adaptive_palette = adaptive_palette;
+        self
+    }
+
+    /// Actual colors of the terminal's palette, used for matching
+    pub fn palette(mut self, palette: Palette) -> Self {
+        self.render_options.palette = palette;
+        self
+    }
+
+    /// Snap every cell color to a built-in color scheme
+    pub fn theme(mut self, theme: Option<Theme>) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    /// Terminal background; light backgrounds invert the ramp and darken pale colors
+    pub fn background(mut self, background: Background) -> Self {
+        self.background = background;
+        self
+    }
+
+    /// Composite transparent regions over this color before conversion,
+    /// instead of leaving them blank
+    pub fn background_color(mut self, color: Option<[u8; 3]>) -> Self {
+        self.background_color = color;
+        self
+    }
+
+    /// Mask out a solid background color before conversion, leaving those
+    /// cells blank (or filled with the background color)
+    pub fn chroma_key(mut self, key: Option<ChromaKey>) -> Self {
+        self.chroma_key = key;
+        self
+    }
+
+    /// Alpha below which pixels become blank cells left to the terminal
+    /// background; 0 treats every pixel as opaque
+    pub fn alpha_threshold(mut self, threshold: u8) -> Self {
+        self.alpha_threshold = threshold;
+        self
+    }
+
+    /// Enable Floyd–Steinberg dithering of brightness in standard mode
+    pub fn dithering(mut self, enabled: bool) -> Self {
+        self.dithering = enabled;
+        self
+    }
+
+    /// Append an adjustment applied to the resized image before conversion
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.filters.push(filter);
+        self
+    }
+
+    /// Append a color transform applied to every cell's color
+    pub fn color_filter(mut self, filter: ColorFilter) -> Self {
+        self.color_filters.push(filter);
+        self
+    }
+
+    /// Hue, saturation, and lightness changes applied to cell colors
+    pub fn hsl(mut self, hsl: HslAdjustment) -> Self {
+        self.hsl = hsl;
+        self
+    }
+
+    /// Apply the [`hsl`](Self::hsl) changes to the image before characters are
+    /// picked, so lightness also changes which characters are used
+    pub fn hsl_brightness(mut self, enabled: bool) -> Self {
+        self.hsl_brightness = enabled;
+        self
+    }
+
+    /// Remove color casts with a gray-world white balance of the cell colors
+    pub fn auto_white_balance(mut self, enabled: bool) -> Self {
+        self.auto_white_balance = enabled;
+        self
+    }
+
+    /// Smooth sensor noise out of the full-resolution image before resizing
+    pub fn denoise(mut self, denoise: Option<Denoise>) -> Self {
+        self.denoise = denoise;
+        self
+    }
+
+    /// Equalize the resized brightness locally (CLAHE), bringing out detail in
+    /// both the bright and the dark parts of an image
+    pub fn clahe(mut self, clahe: Option<Clahe>) -> Self {
+        self.clahe = clahe;
+        self
+    }
+
+    /// Sharpen the resized brightness with an unsharp mask of this strength
+    /// (0 disables it), recovering detail that downscaling softened
+    pub fn sharpen(mut self, amount: f32) -> Self {
+        self.sharpen = amount.max(0.0);
+        self
+    }
+
+    /// Sharpen the cell colors too, not just the brightness that picks characters
+    pub fn sharpen_color(mut self, enabled: bool) -> Self {
+        self.sharpen_color = enabled;
+        self
+    }
+
+    /// Run the pipeline on an image, producing a reusable `AsciiArt`
+    pub fn generate(&self, image: &DynamicImage) -> Result<AsciiArt, AsciiArtError> {
+        let (processed, picker) = self.prepare(image, &mut Timings::default())?;
+        self.convert(&processed, &picker)
+    }
+
+    /// Art from cells converted earlier, such as a grid document's, drawn with
+    /// this builder's render options; no conversion or color setting applies
+    pub fn from_cells(&self, image: AsciiImage) -> AsciiArt {
+        AsciiArt {
+            image,
+            render_options: self.output_options(),
+        }
+    }
+
+    /// Like [`generate`](Self::generate) for the cache's image, reusing the
+    /// stages `cache` holds when the settings they depend on are unchanged
+    ///
+    /// Changing only the charset, dithering, colors, or palette between calls
+    /// re-runs none of the decode, resize, filter, or edge detection work.
+    pub fn generate_cached(&self, cache: &mut StageCache) -> Result<AsciiArt, AsciiArtError> {
+        let image = cache.image;
+        let resize = self.resize_stage();
+        if cache
+            .resized
+            .as_ref()
+            .is_none_or(|(stage, _)| *stage != resize)
+        {
+            let resized = self.resize(image, &mut Timings::default())?;
+            cache.resized = Some((resize, resized));
+            cache.processed = None;
+        }
+        let process = self.process_stage();
+        if cache
+            .processed
+            .as_ref()
+            .is_none_or(|(stage, _)| *stage != process)
+        {
+            let (_, resized) = cache.resized.as_ref().expect("resized above");
+            let processed = self.process(self.apply_filters(resized.clone()));
+            cache.processed = Some((process, processed));
+            cache.edges = None;
+        }
+        let StageCache {
+            processed, edges, ..
+        } = cache;
+        let (_, processed) = processed.as_ref().expect("processed above");
+        let picker = match self.mode {
+            Mode::Edge => {
+                let edges = match edges {
+                    Some(edges) => edges,
+                    None => edges.insert(edge_map(processed)?),
+                };
+                CharPicker::Edges(edges.clone())
+            }
+            Mode::Standard => self.char_picker(processed)?,
+        };
+        self.convert(processed, &picker)
+    }
+
+    /// Pick the characters and colors of every cell
+    fn convert(
+        &self,
+        processed: &ProcessedImage,
+        picker: &CharPicker<'_>,
+    ) -> Result<AsciiArt, AsciiArtError> {
+        let mut image = map_cells(processed, |x, y, luma| picker.pick(x, y, luma))?;
+        self.fill_transparent(&mut image);
+        let correction = self.color_correction(processed);
+        self.adjust_colors(&mut image, &correction);
+
+        Ok(AsciiArt {
+            image,
+            render_options: self.output_options(),
+        })
+    }
+
+    /// Run the pipeline and measure how closely the art reproduces the image
+    pub fn score(&self, image: &DynamicImage) -> Result<Score, AsciiArtError> {
+        self.score_art(&self.generate(image)?, image)
+    }
+
+    /// Like [`score`](Self::score) for the cache's image, converting with
+    /// [`generate_cached`](Self::generate_cached)
+    pub fn score_cached(&self, cache: &mut StageCache) -> Result<Score, AsciiArtError> {
+        let art = self.generate_cached(cache)?;
+        self.score_art(&art, cache.image)
+    }
+
+    /// How closely `art`, converted from `image`, reproduces it
+    fn score_art(&self, art: &AsciiArt, image: &DynamicImage) -> Result<Score, AsciiArtError> {
+        let art = art.image();
+        let colored = self.output_options().color_depth != ColorDepth::Monochrome;
+        let reconstructed = score::reconstruct(art, colored, self.background);
+        let (width, height) = (reconstructed.width(), reconstructed.height());
+        let reference = score::reference(image, width, height, self.background);
+        Ok(Score::compare(&reference, &reconstructed))
+    }
+
+    /// Run the pipeline and write the colored output strip by strip
+    ///
+    /// Only `strip_rows` rows of cells and their rendered text are held at once,
+    /// so very tall outputs never materialize as a single string. The bytes
+    /// written are identical to [`AsciiArt::write_to`].
+    ///
+    /// Returns the time spent in each stage; `load` is left at zero since the
+    /// image was decoded by the caller.
+    pub fn write_streaming<W: Write>(
+        &self,
+        image: &DynamicImage,
+        writer: &mut W,
+        strip_rows: u32,
+    ) -> Result<Timings, AsciiArtError> {
+        self.write_streaming_with_progress(image, writer, strip_rows, |_, _| {})
+    }
+
+    /// Like [`write_streaming`](Self::write_streaming), calling `on_strip`
+    /// with the rows written so far and the total after each strip
+    pub fn write_streaming_with_progress<W, F>(
+        &self,
+        image: &DynamicImage,
+        writer: &mut W,
+        strip_rows: u32,
+        on_strip: F,
+    ) -> Result<Timings, AsciiArtError>
+    where
+        W: Write,
+        F: FnMut(u32, u32),
+    {
+        self.write_streaming_with_stats(image, writer, strip_rows, None, on_strip)
+    }
+
+    /// Like [`write_streaming_with_progress`](Self::write_streaming_with_progress),
+    /// also counting the cells and bytes written into `stats`
+    ///
+    /// Cell colors are only counted when the output is colored.
+    pub fn write_streaming_with_stats<W, F>(
+        &self,
+        image: &DynamicImage,
+        writer: &mut W,
+        strip_rows: u32,
+        mut stats: Option<&mut Stats>,
+        mut on_strip: F,
+    ) -> Result<Timings, AsciiArtError>
+    where
+        W: Write,
+        F: FnMut(u32, u32),
+    {
+        let mut timings = Timings::default();
+        let (processed, picker) = self.prepare(image, &mut timings)?;
+        let height = processed.gray.height();
+        let strip_rows = strip_rows.max(1);
+        let correction = time(&mut timings.conversion, || {
+            self.color_correction(&processed)
+        });
+        let render_options = self.output_options();
+
+        let mut start = 0;
+        while start < height {
+            let end = start.saturating_add(strip_rows).min(height);
+            let strip = time(&mut timings.conversion, || {
+                let mut strip =
+                    map_cell_rows(&processed, start..end, |x, y, luma| picker.pick(x, y, luma))?;
+                self.fill_transparent(&mut strip);
+                self.adjust_colors(&mut strip, &correction);
+                Ok::<_, AsciiArtError>(strip)
+            })?;
+            let text = time(&mut timings.render, || {
+                let text = renderer::render_rows(&strip, &render_options);
+                writer.write_all(text.as_bytes()).map(|()| text.len())
+            })?;
+            if let Some(stats) = stats.as_deref_mut() {
+                let colored = render_options.color_depth != ColorDepth::Monochrome;
+                stats.record(&strip, colored);
+                stats.record_bytes(text);
+            }
+            start = end;
+            on_strip(end, height);
+        }
+
+        let reset = renderer::final_reset(&render_options);
+        time(&mut timings.render, || {
+            writer.write_all(reset.as_bytes())?;
+            writer.flush()
+        })?;
+        if let Some(stats) = stats {
+            stats.record_bytes(reset.len());
+        }
+        Ok(timings)
+    }
+
+    /// Resize and filter the image, and prepare the per-pixel character choice
+    fn prepare(
+        &self,
+        image: &DynamicImage,
+        timings: &mut Timings,
+    ) -> Result<(ProcessedImage, CharPicker<'_>), AsciiArtError> {
+        let resized = self.resize(image, timings)?;
+        let filtered = time(&mut timings.resize, || self.apply_filters(resized));
+        let processed = time(&mut timings.grayscale, || self.process(filtered));
+        let picker = time(&mut timings.conversion, || self.char_picker(&processed))?;
+
+        Ok((processed, picker))
+    }
+
+    /// The settings [`resize`](Self::resize) depends on
+    fn resize_stage(&self) -> ResizeStage {
+        // Wide glyphs cover two columns, so sample half as many cells, each
+        // twice as wide
+        let cell_width = self.cell_width();
+        ResizeStage {
+            columns: (self.width / cell_width).max(1),
+            rows: self.height,
+            char_aspect: self.char_aspect / cell_width as f32,
+            resize_filter: self.resize_filter,
+            pixel_art: self.pixel_art,
+            denoise: self.denoise,
+            chroma_key: self.chroma_key,
+            background_color: self.background_color,
+        }
+    }
+
+    /// Denoise, key, and fill the image, then shrink it to the character grid
+    fn resize(
+        &self,
+        image: &DynamicImage,
+        timings: &mut Timings,
+    ) -> Result<DynamicImage, AsciiArtError> {
+        let stage = self.resize_stage();
+        let denoised = time(&mut timings.denoise, || {
+            stage
+                .denoise
+                .map(|denoise| denoise.apply(image))
+                .transpose()
+        })?;
+        let image = denoised.as_ref().unwrap_or(image);
+        let resized = time(&mut timings.resize, || {
+            let keyed = stage.chroma_key.map(|key| key.mask(image));
+            let image = keyed.as_ref().unwrap_or(image);
+            let filled = stage
+                .background_color
+                .filter(|_| image.color().has_alpha())
+                .map(|color| composite_over(image, color));
+            let image = filled.as_ref().unwrap_or(image);
+            let (width, filter) = if stage.pixel_art {
+                (
+                    pixel_art_width(image.width(), stage.columns),
+                    ResizeFilter::Nearest,
+                )
+            } else {
+                (stage.columns, stage.resize_filter)
+            };
+            resize_to_grid_with_filter(image, width, stage.rows, stage.char_aspect, filter)
+        })?;
+        debug!(
+            columns = resized.width(),
+            rows = resized.height(),
+            "Resized to the character grid"
+        );
+        Ok(resized)
+    }
+
+    /// The settings [`apply_filters`](Self::apply_filters) and
+    /// [`process`](Self::process) depend on
+    fn process_stage(&self) -> ProcessStage {
+        ProcessStage {
+            filters: self.filters.clone(),
+            hsl: Some(self.hsl).filter(|hsl| self.hsl_brightness && !hsl.is_identity()),
+            alpha_threshold: self.alpha_threshold,
+            clahe: self.clahe,
+            sharpen: self.sharpen,
+            sharpen_color: self.sharpen_color,
+        }
+    }
+
+    /// Apply the filters to the resized image
+    fn apply_filters(&self, resized: DynamicImage) -> DynamicImage {
+        let filtered = apply_filters(resized, &self.filters);
+        if self.hsl_brightness && !self.hsl.is_identity() {
+            adjust_pixels(filtered, self.hsl)
+        } else {
+            filtered
+        }
+    }
+
+    /// Split the filtered image into the brightness and colors cells are
+    /// picked from
+    fn process(&self, filtered: DynamicImage) -> ProcessedImage {
+        let mut processed = ProcessedImage {
+            alpha_threshold: self.alpha_threshold,
+            ..ProcessedImage::new(filtered)
+        };
+        if let Some(clahe) = &self.clahe {
+            processed.gray = clahe.apply(&processed.gray);
+        }
+        if self.sharpen > 0.0 {
+            processed.gray = unsharp_mask(&processed.gray, self.sharpen);
+            if self.sharpen_color {
+                processed.rgb = unsharp_mask(&processed.rgb, self.sharpen);
+            }
+        }
+        processed
+    }
+
+    /// Terminal columns each cell takes: 2 for a ramp of double-width glyphs
+    fn cell_width(&self) -> u32 {
+        match self.mode {
+            Mode::Standard => self.charset.cell_width(),
+            Mode::Edge => 1,
+        }
+    }
+
+    /// Keep transparent cells as wide as the glyphs around them
+    fn fill_transparent(&self, image: &mut AsciiImage) {
+        if self.cell_width() > 1 {
+            image.fill_transparent(self.charset.blank());
+        }
+    }
+
+    /// Corrections that depend on the colors of the whole image, worked out
+    /// once so streamed strips share them
+    fn color_correction(&self, processed: &ProcessedImage) -> ColorCorrection {
+        if !self.auto_white_balance && self.adaptive_palette.is_none() {
+            return ColorCorrection::default();
+        }
+        let colors = opaque_colors(processed);
+        let mut correction = ColorCorrection {
+            white_balance: self
+                .auto_white_balance
+                .then(|| WhiteBalance::gray_world(colors.iter().copied())),
+            palette: None,
+        };
+        correction.palette = self.extract_palette(colors, &correction);
+        correction
+    }
+
+    /// The adaptive palette for an image, from the colors its cells end up with
+    fn extract_palette(
+        &self,
+        colors: Vec<[u8; 3]>,
+        correction: &ColorCorrection,
+    ) -> Option<Vec<[u8; 3]>> {
+        let adaptive = self.adaptive_palette?;
+        let cells: Vec<Cell> = colors
+            .into_iter()
+            .map(|rgb| Cell {
+                ch: '#',
+                rgb,
+                luma: 0,
+                transparent: false,
+            })
+            .collect();
+        let mut samples = AsciiImage::new(cells.len(), 1, cells).ok()?;
+        self.adjust_colors(&mut samples, correction);
+        let colors: Vec<[u8; 3]> = samples.cells().iter().map(|cell| cell.rgb).collect();
+        Some(adaptive.extract(&colors))
+    }
+
+    /// Render options for the final output; adaptive palettes need truecolor,
+    /// and pixel art is never dithered
+    fn output_options(&self) -> RenderOptions {
+        let mut options = self.render_options.clone();
+        options.color_dither &= !self.pixel_art;
+        if self.adaptive_palette.is_some() && options.color_depth != ColorDepth::Monochrome {
+            options.color_depth = ColorDepth::TrueColor;
+        }
+        options
+    }
+
+    /// Apply the color adjustments, then snap to the palette if one was extracted
+    fn adjust_colors(&self, image: &mut AsciiImage, correction: &ColorCorrection) {
+        if let Some(balance) = correction.white_balance {
+            image.map_colors(|rgb| balance.apply(rgb));
+        }
+        if !self.hsl_brightness && !self.hsl.is_identity() {
+            image.map_colors(|rgb| self.hsl.apply(rgb));
+        }
+        if !self.color_filters.is_empty() {
+            image.map_colors(|rgb| apply_color_filters(rgb, &self.color_filters));
+        }
+        if self.background == Background::Light {
+            image.map_colors(darken_for_light_background);
+        }
+        if let Some(theme) = self.theme {
+            theme.quantize(image, self.render_options.color_metric);
+        }
+        if let Some(colors) = correction.palette.as_deref() {
+            let metric = self.render_options.color_metric;
+            let lab: Vec<[f32; 3]> = colors.iter().copied().map(srgb_to_oklab).collect();
+            image.map_colors(|rgb| colors[nearest_color(rgb, colors, &lab, metric)]);
+        }
+    }
+
+    fn char_picker(&self, processed: &ProcessedImage) -> Result<CharPicker<'_>, AsciiArtError> {
+        let invert = self.background == Background::Light;
+        Ok(match self.mode {
+            Mode::Edge => CharPicker::Edges(edge_map(processed)?),
+            Mode::Standard if self.dithering && !self.pixel_art => CharPicker::Dithered {
+                charset: &self.charset,
+                dithered: dither_gray(&processed.gray, self.charset.levels()),
+                invert,
+            },
+            Mode::Standard => CharPicker::Ramp {
+                charset: &self.charset,
+                invert,
+            },
+        })
+    }
+}
+
+/// An image and the intermediate results of converting it, kept by
+/// [`AsciiArtBuilder::generate_cached`] for the next conversion
+///
+/// Each stage is stored with the settings that produced it and redone only
+/// when they change: the resized image, then the filtered brightness and
+/// colors, then the edge map.
+#[derive(Debug)]
+pub struct StageCache<'a> {
+    image: &'a DynamicImage,
+    resized: Option<(ResizeStage, DynamicImage)>,
+    processed: Option<(ProcessStage, ProcessedImage)>,
+    /// Edge map of the processed brightness, for edge mode
+    edges: Option<GrayImage>,
+}
+
+impl<'a> StageCache<'a> {
+    /// An empty cache for converting `image`
+    pub fn new(image: &'a DynamicImage) -> Self {
+        Self {
+            image,
+            resized: None,
+            processed: None,
+            edges: None,
+        }
+    }
+}
+
+/// Settings the resized image depends on
+#[derive(Debug, Clone, PartialEq)]
+struct ResizeStage {
+    columns: u32,
+    rows: Option<u32>,
+    char_aspect: f32,
+    resize_filter: ResizeFilter,
+    pixel_art: bool,
+    denoise: Option<Denoise>,
+    chroma_key: Option<ChromaKey>,
+    background_color: Option<[u8; 3]>,
+}
+
+/// Settings the processed image depends on, besides the resized image
+#[derive(Debug, Clone, PartialEq)]
+struct ProcessStage {
+    filters: Vec<Filter>,
+    /// Changes applied to the pixels before characters are picked
+    hsl: Option<HslAdjustment>,
+    alpha_threshold: u8,
+    clahe: Option<Clahe>,
+    sharpen: f32,
+    sharpen_color: bool,
+}
+
+/// Image-wide color corrections applied to every cell
+#[derive(Debug, Default)]
+struct ColorCorrection {
+    white_balance: Option<WhiteBalance>,
+    /// Adaptive palette extracted from the corrected colors
+    palette: Option<Vec<[u8; 3]>>,
+}
+
+/// Canny edges of the processed image for edge mode
+///
+/// A missing `edge` feature is reported as is; any other failure is marked as
+/// edge detection's.
+fn edge_map(processed: &ProcessedImage) -> Result<GrayImage, AsciiArtError> {
+    edge_detector::edge_map(&processed.gray).map_err(|err| match err {
+        AsciiArtError::FeatureDisabled { .. } => err,
+        err => AsciiArtError::EdgeDetection(Box::new(err)),
+    })
+}
+
+/// Colors of the resized pixels that become visible cells
+fn opaque_colors(processed: &ProcessedImage) -> Vec<[u8; 3]> {
+    let opaque = |x: u32, y: u32| {
+        processed
+            .alpha
+            .as_ref()
+            .is_none_or(|alpha| alpha.get_pixel(x, y)[0] >= processed.alpha_threshold)
+    };
+    processed
+        .rgb
+        .enumerate_pixels()
+        .filter(|&(x, y, _)| opaque(x, y))
+        .map(|(_, _, pixel)| pixel.0)
+        .collect()
+}
+
+/// Apply `hsl` to every pixel of the resized image, keeping alpha
+fn adjust_pixels(image: DynamicImage, hsl: HslAdjustment) -> DynamicImage {
+    let mut rgba = image.to_rgba8();
+    for pixel in rgba.pixels_mut() {
+        let [r, g, b] = hsl.apply([pixel[0], pixel[1], pixel[2]]);
+        pixel.0 = [r, g, b, pixel[3]];
+    }
+    DynamicImage::ImageRgba8(rgba)
+}
+
+/// Per-pixel character selection, computed once for the whole image
+///
+/// Edge maps and dithering need the full image, so they are prepared up front;
+/// the actual cells can then be produced for any range of rows.
+///
+/// With `invert`, the ramp is read backwards so dense characters mark dark
+/// pixels, as needed on light backgrounds.
+enum CharPicker<'a> {
+    Ramp {
+        charset: &'a Charset,
+        invert: bool,
+    },
+    Dithered {
+        charset: &'a Charset,
+        dithered: GrayImage,
+        invert: bool,
+    },
+    Edges(GrayImage),
+}
+
+impl CharPicker<'_> {
+    fn pick(&self, x: u32, y: u32, luma: u8) -> char {
+        let level = |luma: u8, invert: bool| if invert { 255 - luma } else { luma };
+        match self {
+            CharPicker::Ramp { charset, invert } => charset.char_for(level(luma, *invert)),
+            CharPicker::Dithered {
+                charset,
+                dithered,
+                invert,
+            } => charset.char_for(level(dithered.get_pixel(x, y)[0], *invert)),
+            CharPicker::Edges(edges) => edge_detector::edge_to_char(edges.get_pixel(x, y)[0]),
+        }
+    }
+}
+
+/// A converted image that can be rendered or exported any number of times
+#[derive(Debug, Clone)]
+pub struct AsciiArt {
+    image: AsciiImage,
+    render_options: RenderOptions,
+}
+
+impl AsciiArt {
+    /// The converted cells with their characters, colors, and brightness
+    pub fn image(&self) -> &AsciiImage {
+        &self.image
+    }
+
+    pub fn width(&self) -> usize {
+        self.image.width()
+    }
+
+    pub fn height(&self) -> usize {
+        self.image.height()
+    }
+
+    /// Print the art to the terminal with ANSI colors
+    pub fn render(&self) -> Result<(), AsciiArtError> {
+        renderer::render_colored_with_options(&self.image, &self.render_options)
+    }
+
+    /// Export the art as a string of ANSI-colored text
+    pub fn to_ansi_string(&self) -> String {
+        renderer::render_to_string(&self.image, &self.render_options)
+    }
+
+    /// Write the ANSI-colored art to any writer
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<(), AsciiArtError> {
+        renderer::render_to_writer(&self.image, &self.render_options, writer)
+    }
+
+    /// Export the art as plain text without escape sequences
+    pub fn to_text(&self) -> String {
+        self.image.to_text()
+    }
+
+    /// Export the art as an HTML `<pre>` block with inline colors
+    pub fn to_html(&self) -> String {
+        renderer::render_html(&self.image, &self.render_options)
+    }
+
+    /// Draw the art as a picture with the built-in 8x8 font
+    pub fn to_image(&self) -> RgbImage {
+        renderer::render_image(&self.image, &self.render_options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageBuffer, Rgb, Rgba};
+
+    fn sample_image() -> DynamicImage {
+        DynamicImage::ImageRgb8(ImageBuffer::from_fn(16, 16, |x, y| {
+            Rgb([(x * 16) as u8, (y * 16) as u8, 128])
+        }))
+    }
+
+    #[test]
+    fn mode_parses_known_values() {
+        assert_eq!("edge".parse::<Mode>().ok(), Some(Mode::Edge));
+        let err = "bogus".parse::<Mode>().unwrap_err();
+        assert!(matches!(err, AsciiArtError::UnknownMode(ref mode) if mode == "bogus"));
+        assert_eq!(
+            err.to_string(),
+            "Unknown mode 'bogus'. Use 'standard' or 'edge'."
+        );
+    }
+
+    #[test]
+    fn generate_uses_configured_dimensions() {
+        let art = AsciiArtBuilder::new()
+            .width(12)
+            .height(Some(5))
+            .generate(&sample_image())
+            .expect("generation succeeds");
+
+        assert_eq!((art.width(), art.height()), (12, 5));
+        assert_eq!(art.to_text().lines().count(), 5);
+    }
+
+    #[test]
+    fn generate_uses_custom_charset() {
+        let charset = Charset::new("ab").expect("valid charset");
+        let art = AsciiArtBuilder::new()
+            .width(8)
+            .charset(charset)
+            .dithering(true)
+            .generate(&sample_image())
+            .expect("generation succeeds");
+
+        assert!(art.to_text().chars().all(|c| matches!(c, 'a' | 'b' | '\n')));
+    }
+
+    #[test]
+    fn pixel_art_snaps_width_and_skips_dithering() {
+        let sprite = DynamicImage::ImageRgb8(ImageBuffer::from_fn(8, 8, |x, _| {
+            Rgb(if x % 2 == 0 {
+                [0, 0, 0]
+            } else {
+                [255, 255, 255]
+            })
+        }));
+        let art = AsciiArtBuilder::new()
+            .width(20)
+            .pixel_art(true)
+            .dithering(true)
+            .color_dithering(true)
+            .generate(&sprite)
+            .expect("generation succeeds");
+
+        assert_eq!(art.width(), 16);
+        assert_eq!(art.to_text().lines().next(), Some("  @@  @@  @@  @@"));
+    }
+
+    #[test]
+    fn wide_charsets_halve_the_columns() {
+        let image = DynamicImage::ImageRgba8(ImageBuffer::from_fn(40, 20, |x, _| {
+            Rgba([255, 255, 255, if x < 20 { 0 } else { 255 }])
+        }));
+        let art = AsciiArtBuilder::new()
+            .width(20)
+            .charset(Charset::new("\u{3000}口").expect("wide charset"))
+            .generate(&image)
+            .expect("generation succeeds");
+
+        // Ten double-width cells fill twenty columns, with square cells
+        assert_eq!((art.width(), art.height()), (10, 5));
+        assert_eq!(
+            art.to_text().lines().next(),
+            Some("\u{3000}\u{3000}\u{3000}\u{3000}\u{3000}口口口口口")
+        );
+    }
+
+    #[test]
+    fn light_background_inverts_ramp_and_darkens_colors() {
+        let white = DynamicImage::ImageRgb8(ImageBuffer::from_pixel(4, 4, Rgb([255, 255, 255])));
+        let dark = AsciiArtBuilder::new()
+            .width(4)
+            .generate(&white)
+            .expect("dark");
+        let light = AsciiArtBuilder::new()
+            .width(4)
+            .background(Background::Light)
+            .generate(&white)
+            .expect("light");
+
+        assert!(dark.to_text().chars().all(|c| c == '@' || c == '\n'));
+        assert!(light.to_text().chars().all(|c| c == ' ' || c == '\n'));
+        assert!(light
+            .image()
+            .cells()
+            .iter()
+            .all(|cell| cell.rgb == [160, 160, 160]));
+    }
+
+    #[test]
+    fn transparent_regions_render_as_untouched_background() {
+        let logo = DynamicImage::ImageRgba8(ImageBuffer::from_fn(4, 2, |x, _| {
+            Rgba([255, 0, 0, if x < 2 { 0 } else { 255 }])
+        }));
+        let builder = AsciiArtBuilder::new()
+            .width(4)
+            .height(Some(2))
+            .color_depth(ColorDepth::TrueColor);
+
+        let art = builder.generate(&logo).expect("generation succeeds");
+        let text = art.to_text();
+        let rows: Vec<&str> = text.lines().map(|line| &line[..2]).collect();
+        assert_eq!(rows, ["  ", "  "]);
+        assert!(art.to_ansi_string().starts_with("  \x1b[38;2;"));
+
+        let opaque = builder
+            .clone()
+            .alpha_threshold(0)
+            .generate(&logo)
+            .expect("opaque");
+        assert!(opaque.image().cells().iter().all(|cell| !cell.transparent));
+
+        let filled = builder
+            .background_color(Some([255, 255, 255]))
+            .generate(&logo)
+            .expect("filled");
+        let cell = filled.image().get(0, 0).expect("cell");
+        assert_eq!(
+            (cell.ch, cell.rgb, cell.transparent),
+            ('@', [255, 255, 255], false)
+        );
+    }
+
+    #[test]
+    fn chroma_key_blanks_the_keyed_background() {
+        let green_screen = DynamicImage::ImageRgb8(ImageBuffer::from_fn(4, 2, |x, _| {
+            if x < 2 {
+                Rgb([10, 250, 5])
+            } else {
+                Rgb([255, 255, 255])
+            }
+        }));
+        let builder = AsciiArtBuilder::new()
+            .width(4)
+            .height(Some(2))
+            .chroma_key(Some(ChromaKey::new([0, 255, 0])));
+
+        let art = builder
+            .generate(&green_screen)
+            .expect("generation succeeds");
+        assert!(art.to_text().lines().all(|line| line.starts_with("  ")));
+        assert!(art.image().get(0, 0).expect("cell").transparent);
+        assert_eq!(art.image().get(3, 0).expect("cell").ch, '@');
+    }
+
+    #[test]
+    fn theme_limits_cell_colors() {
+        let art = AsciiArtBuilder::new()
+            .width(8)
+            .theme(Some(Theme::Nord))
+            .generate(&sample_image())
+            .expect("generation succeeds");
+
+        let colors = Theme::Nord.colors();
+        assert!(art
+            .image()
+            .cells()
+            .iter()
+            .all(|cell| colors.contains(&cell.rgb)));
+    }
+
+    #[test]
+    fn write_streaming_matches_buffered_output() {
+        for mode in [Mode::Standard, Mode::Edge] {
+            let builder = AsciiArtBuilder::new()
+                .width(10)
+                .height(Some(7))
+                .mode(mode)
+                .theme(Some(Theme::Dracula));
+            let expected = builder
+                .generate(&sample_image())
+                .expect("generation succeeds")
+                .to_ansi_string();
+
+            let mut streamed = Vec::new();
+            builder
+                .write_streaming(&sample_image(), &mut streamed, 3)
+                .expect("streaming succeeds");
+            assert_eq!(String::from_utf8(streamed).expect("utf8"), expected);
+        }
+    }
+
+    #[test]
+    fn cached_stages_are_reused_until_their_settings_change() {
+        let image = sample_image();
+        let mut cache = StageCache::new(&image);
+        let gray =
+            |cache: &StageCache| cache.processed.as_ref().expect("processed").1.gray.as_ptr();
+        let builders = [
+            AsciiArtBuilder::new().width(8).mode(Mode::Edge),
+            AsciiArtBuilder::new().width(8),
+            AsciiArtBuilder::new()
+                .width(8)
+                .charset(Charset::builtin("blocks").expect("blocks"))
+                .dithering(true),
+            AsciiArtBuilder::new()
+                .width(8)
+                .filter(Filter::Contrast(40.0)),
+            AsciiArtBuilder::new().width(6),
+        ];
+
+        let mut kept = Vec::new();
+        let mut last = None;
+        for builder in &builders {
+            let cached = builder.generate_cached(&mut cache).expect("cached");
+            let expected = builder.generate(&image).expect("generated");
+            assert_eq!(cached.to_ansi_string(), expected.to_ansi_string());
+            kept.push(last == Some(gray(&cache)));
+            last = Some(gray(&cache));
+        }
+        // Only the charset, mode, and dithering changed for the second and third
+        assert_eq!(kept, [false, true, true, false, false]);
+        assert!(cache.edges.is_none());
+    }
+}
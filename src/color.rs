@@ -0,0 +1,357 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::AsciiArtError;
+use crate::palette::parse_hex_color;
+
+/// Distance function used to find the nearest palette color
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum ColorMetric {
+    /// Euclidean distance between raw sRGB components
+    Rgb,
+    /// Euclidean distance in the perceptually uniform OKLab space
+    #[default]
+    Oklab,
+}
+
+impl FromStr for ColorMetric {
+    type Err = AsciiArtError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "rgb" => Ok(ColorMetric::Rgb),
+            "oklab" => Ok(ColorMetric::Oklab),
+            unknown => Err(AsciiArtError::InvalidArgument(format!(
+                "Unknown color metric '{}'. Use 'rgb' or 'oklab'.",
+                unknown
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for ColorMetric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ColorMetric::Rgb => write!(f, "rgb"),
+            ColorMetric::Oklab => write!(f, "oklab"),
+        }
+    }
+}
+
+impl ColorMetric {
+    /// Squared distance between two sRGB colors under this metric
+    pub fn distance(&self, a: [u8; 3], b: [u8; 3]) -> f32 {
+        match self {
+            ColorMetric::Rgb => a
+                .iter()
+                .zip(b.iter())
+                .map(|(&x, &y)| (x as f32 - y as f32).powi(2))
+                .sum(),
+            ColorMetric::Oklab => {
+                let (la, lb) = (srgb_to_oklab(a), srgb_to_oklab(b));
+                oklab_distance(la, lb)
+            }
+        }
+    }
+}
+
+/// Brightest luma a cell color may keep on a light background
+const LIGHT_BACKGROUND_MAX_LUMA: f32 = 160.0;
+
+/// Color names accepted wherever a single color is given on the command line
+const NAMED_COLORS: [(&str, [u8; 3]); 10] = [
+    ("black", [0, 0, 0]),
+    ("white", [255, 255, 255]),
+    ("gray", [128, 128, 128]),
+    ("grey", [128, 128, 128]),
+    ("red", [255, 0, 0]),
+    ("green", [0, 255, 0]),
+    ("blue", [0, 0, 255]),
+    ("yellow", [255, 255, 0]),
+    ("cyan", [0, 255, 255]),
+    ("magenta", [255, 0, 255]),
+];
+
+/// Parse a color given as `#rrggbb` or as a basic name such as `white`
+pub fn parse_color(value: &str) -> Result<[u8; 3], AsciiArtError> {
+    let name = value.to_ascii_lowercase();
+    match NAMED_COLORS.iter().find(|(known, _)| *known == name) {
+        Some(&(_, rgb)) => Ok(rgb),
+        None => parse_hex_color(value).map_err(|_| {
+            AsciiArtError::InvalidArgument(format!(
+                "Invalid color '{value}'. Use '#rrggbb' or a name such as 'white'."
+            ))
+        }),
+    }
+}
+
+/// Perceived brightness (Rec. 601 luma) of an sRGB color, 0-255
+pub fn luma(rgb: [u8; 3]) -> f32 {
+    0.299 * rgb[0] as f32 + 0.587 * rgb[1] as f32 + 0.114 * rgb[2] as f32
+}
+
+/// Darken pale colors so they stay visible on a light terminal background
+///
+/// Colors at or below the luma limit are unchanged; brighter ones are scaled
+/// down uniformly, which keeps their hue.
+pub fn darken_for_light_background(rgb: [u8; 3]) -> [u8; 3] {
+    let brightness = luma(rgb);
+    if brightness <= LIGHT_BACKGROUND_MAX_LUMA {
+        return rgb;
+    }
+    let scale = LIGHT_BACKGROUND_MAX_LUMA / brightness;
+    rgb.map(|channel| (channel as f32 * scale).round() as u8)
+}
+
+/// Convert an 8-bit sRGB channel to linear light
+pub fn srgb_to_linear(value: u8) -> f32 {
+    let c = value as f32 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Convert an sRGB color to OKLab `[L, a, b]`
+pub fn srgb_to_oklab(rgb: [u8; 3]) -> [f32; 3] {
+    let [r, g, b] = rgb.map(srgb_to_linear);
+
+    let l = 0.412_221_46 * r + 0.536_332_55 * g + 0.051_445_995 * b;
+    let m = 0.211_903_5 * r + 0.680_699_5 * g + 0.107_396_96 * b;
+    let s = 0.088_302_46 * r + 0.281_718_85 * g + 0.629_978_7 * b;
+
+    let (l, m, s) = (l.cbrt(), m.cbrt(), s.cbrt());
+
+    [
+        0.210_454_26 * l + 0.793_617_8 * m - 0.004_072_047 * s,
+        1.977_998_5 * l - 2.428_592_2 * m + 0.450_593_7 * s,
+        0.025_904_037 * l + 0.782_771_77 * m - 0.808_675_77 * s,
+    ]
+}
+
+/// Squared Euclidean distance between two OKLab colors
+pub fn oklab_distance(a: [f32; 3], b: [f32; 3]) -> f32 {
+    (a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)
+}
+
+/// Convert an sRGB color to HSL: hue in degrees (0-360), saturation and
+/// lightness in 0-1
+pub fn rgb_to_hsl(rgb: [u8; 3]) -> [f32; 3] {
+    let [r, g, b] = rgb.map(|channel| channel as f32 / 255.0);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let lightness = (max + min) / 2.0;
+    let delta = max - min;
+    if delta == 0.0 {
+        return [0.0, 0.0, lightness];
+    }
+
+    let saturation = delta / (1.0 - (2.0 * lightness - 1.0).abs());
+    let hue = if max == r {
+        60.0 * ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+    [hue, saturation, lightness]
+}
+
+/// Convert an HSL color (see [`rgb_to_hsl`]) back to sRGB
+pub fn hsl_to_rgb(hsl: [f32; 3]) -> [u8; 3] {
+    let [hue, saturation, lightness] = hsl;
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let sector = hue.rem_euclid(360.0) / 60.0;
+    let x = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+    let [r, g, b] = match sector as u32 {
+        0 => [chroma, x, 0.0],
+        1 => [x, chroma, 0.0],
+        2 => [0.0, chroma, x],
+        3 => [0.0, x, chroma],
+        4 => [x, 0.0, chroma],
+        _ => [chroma, 0.0, x],
+    };
+    let offset = lightness - chroma / 2.0;
+    [r, g, b].map(|channel| ((channel + offset) * 255.0).round().clamp(0.0, 255.0) as u8)
+}
+
+/// Hue, saturation, and lightness changes, as given with `--hue-shift`,
+/// `--saturation`, and `--lightness`
+///
+/// Saturation scales by a percentage (`50` is 1.5 times as saturated, `-100`
+/// is gray); lightness moves by a percentage of the full range (`-100` is
+/// black, `100` white).
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct HslAdjustment {
+    /// Degrees to rotate the hue by
+    pub hue_shift: f32,
+    /// Percent change in saturation
+    pub saturation: f32,
+    /// Percent of the full range to add to lightness
+    pub lightness: f32,
+}
+
+impl HslAdjustment {
+    /// Whether this adjustment leaves every color unchanged
+    pub fn is_identity(&self) -> bool {
+        self.hue_shift % 360.0 == 0.0 && self.saturation == 0.0 && self.lightness == 0.0
+    }
+
+    /// Adjust one color
+    pub fn apply(&self, rgb: [u8; 3]) -> [u8; 3] {
+        let [hue, saturation, lightness] = rgb_to_hsl(rgb);
+        hsl_to_rgb([
+            hue + self.hue_shift,
+            (saturation * (1.0 + self.saturation / 100.0)).clamp(0.0, 1.0),
+            (lightness + self.lightness / 100.0).clamp(0.0, 1.0),
+        ])
+    }
+}
+
+/// Largest factor white balance may scale a channel by, up or down, so images
+/// that really are mostly one color are not pushed to gray
+const MAX_WHITE_BALANCE_GAIN: f32 = 2.0;
+
+/// Per-channel gains that remove a color cast, as applied by `--auto-wb`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WhiteBalance {
+    /// Factors for the red, green, and blue channels
+    pub gains: [f32; 3],
+}
+
+impl WhiteBalance {
+    /// Gray-world correction: scale each channel so the average color is gray
+    ///
+    /// Without any colors, or with a black average, the gains are all 1.
+    pub fn gray_world(colors: impl IntoIterator<Item = [u8; 3]>) -> Self {
+        let mut sums = [0u64; 3];
+        for rgb in colors {
+            for (sum, value) in sums.iter_mut().zip(rgb) {
+                *sum += u64::from(value);
+            }
+        }
+        let gray = sums.iter().sum::<u64>() as f32 / 3.0;
+        let gains = sums.map(|sum| match sum {
+            0 => 1.0,
+            sum => (gray / sum as f32).clamp(1.0 / MAX_WHITE_BALANCE_GAIN, MAX_WHITE_BALANCE_GAIN),
+        });
+        Self { gains }
+    }
+
+    /// Correct one color
+    pub fn apply(&self, rgb: [u8; 3]) -> [u8; 3] {
+        [0, 1, 2].map(|c| {
+            (f32::from(rgb[c]) * self.gains[c])
+                .round()
+                .clamp(0.0, 255.0) as u8
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn oklab_of_white_and_black() {
+        let white = srgb_to_oklab([255, 255, 255]);
+        assert!((white[0] - 1.0).abs() < 1e-3);
+        assert!(white[1].abs() < 1e-3 && white[2].abs() < 1e-3);
+
+        let black = srgb_to_oklab([0, 0, 0]);
+        assert!(black[0].abs() < 1e-6);
+    }
+
+    #[test]
+    fn gray_world_removes_a_color_cast() {
+        let warm = [[200, 150, 100], [100, 75, 50]];
+        let balance = WhiteBalance::gray_world(warm);
+        assert_eq!(balance.apply([200, 150, 100]), [150, 150, 150]);
+        assert_eq!(balance.apply([100, 75, 50]), [75, 75, 75]);
+
+        let pure_red = WhiteBalance::gray_world([[200, 0, 0]]);
+        assert_eq!(pure_red.gains, [0.5, 1.0, 1.0]);
+        assert_eq!(WhiteBalance::gray_world([]).gains, [1.0; 3]);
+    }
+
+    #[test]
+    fn color_metric_parses_names() {
+        assert_eq!("rgb".parse::<ColorMetric>().ok(), Some(ColorMetric::Rgb));
+        let err = "lab".parse::<ColorMetric>().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unknown color metric 'lab'. Use 'rgb' or 'oklab'."
+        );
+    }
+
+    #[test]
+    fn darkening_only_affects_pale_colors() {
+        assert_eq!(darken_for_light_background([20, 40, 60]), [20, 40, 60]);
+        let darkened = darken_for_light_background([255, 255, 255]);
+        assert_eq!(darkened, [160, 160, 160]);
+        assert!(luma(darken_for_light_background([255, 240, 120])) <= 160.5);
+    }
+
+    #[test]
+    fn colors_parse_from_hex_or_name() {
+        assert_eq!(parse_color("White").ok(), Some([255, 255, 255]));
+        assert_eq!(parse_color("#1e90ff").ok(), Some([0x1e, 0x90, 0xff]));
+        let err = parse_color("chartreuse").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid color 'chartreuse'. Use '#rrggbb' or a name such as 'white'."
+        );
+    }
+
+    #[test]
+    fn hsl_round_trips_and_adjusts() {
+        for rgb in [
+            [0, 0, 0],
+            [255, 255, 255],
+            [255, 0, 0],
+            [12, 200, 99],
+            [90, 30, 240],
+        ] {
+            assert_eq!(hsl_to_rgb(rgb_to_hsl(rgb)), rgb);
+        }
+        assert_eq!(rgb_to_hsl([0, 0, 255]), [240.0, 1.0, 0.5]);
+
+        let identity = HslAdjustment::default();
+        assert!(identity.is_identity());
+        assert_eq!(identity.apply([12, 200, 99]), [12, 200, 99]);
+
+        let shift = |hue_shift| HslAdjustment {
+            hue_shift,
+            ..HslAdjustment::default()
+        };
+        assert_eq!(shift(120.0).apply([255, 0, 0]), [0, 255, 0]);
+        assert_eq!(shift(-120.0).apply([255, 0, 0]), [0, 0, 255]);
+
+        let desaturate = HslAdjustment {
+            saturation: -100.0,
+            ..HslAdjustment::default()
+        };
+        assert_eq!(desaturate.apply([255, 0, 0]), [128, 128, 128]);
+        let darken = HslAdjustment {
+            lightness: -100.0,
+            ..HslAdjustment::default()
+        };
+        assert_eq!(darken.apply([12, 200, 99]), [0, 0, 0]);
+
+        let boost = HslAdjustment {
+            saturation: 100.0,
+            ..HslAdjustment::default()
+        };
+        let [_, boosted, _] = rgb_to_hsl(boost.apply([150, 120, 110]));
+        assert!(boosted > rgb_to_hsl([150, 120, 110])[1]);
+    }
+
+    #[test]
+    fn distance_is_zero_for_identical_colors() {
+        for metric in [ColorMetric::Rgb, ColorMetric::Oklab] {
+            assert_eq!(metric.distance([12, 34, 56], [12, 34, 56]), 0.0);
+        }
+    }
+}
@@ -0,0 +1,623 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::ascii_converter::{builtin_ramp, Charset};
+use crate::builder::AsciiArtBuilder;
+use crate::color::{parse_color, HslAdjustment};
+use crate::error::AsciiArtError;
+use crate::filters::{Clahe, Filter, DEFAULT_CLAHE_CLIP_LIMIT, DEFAULT_CLAHE_TILES};
+use crate::image_loader::{ChromaKey, DEFAULT_CHROMA_TOLERANCE};
+use crate::layout::Extent;
+use crate::palette::Palette;
+use crate::quantize::AdaptivePalette;
+
+/// Names of the presets that ship with the program
+pub const BUILTIN_PRESETS: [&str; 4] = ["photo", "logo", "document", "pixel-art"];
+
+/// Option values named after the matching command-line flags
+///
+/// Every value is optional; unset values fall through to the next source.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Options {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<Extent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<Extent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub render: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layout: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub charset: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub charset_file: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub char_aspect: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dither: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color_dither: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shade: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub levels: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub contrast: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clahe: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clahe_tiles: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clahe_clip: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sharpen: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub denoise: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sharpen_color: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub negate: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alpha_threshold: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resize_filter: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pixel_art: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color_metric: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color_depth: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub palette: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub palette_seed: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub theme: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hue_shift: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub saturation: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lightness: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hsl_brightness: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auto_wb: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub background: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub background_color: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chroma_key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chroma_tolerance: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub threads: Option<usize>,
+}
+
+impl Options {
+    /// The options of a built-in preset, if `name` is one
+    pub fn builtin_preset(name: &str) -> Option<Self> {
+        let (mode, charset, dither, contrast) = match name {
+            "photo" => ("standard", builtin_ramp("detailed")?, true, 10.0),
+            "logo" => ("standard", builtin_ramp("standard")?, false, 40.0),
+            "document" => ("standard", builtin_ramp("simple")?, false, 60.0),
+            "pixel-art" => ("standard", builtin_ramp("blocks")?, false, 0.0),
+            _ => return None,
+        };
+        Some(Self {
+            mode: Some(mode.to_string()),
+            charset: Some(charset.to_string()),
+            dither: Some(dither),
+            contrast: Some(contrast),
+            pixel_art: (name == "pixel-art").then(|| "always".to_string()),
+            ..Self::default()
+        })
+    }
+
+    /// Fill every value unset here from `fallback`
+    pub fn or(self, fallback: Self) -> Self {
+        // A ramp and a ramp file replace each other, so take both from one side
+        let (charset, charset_file) = if self.charset.is_some() || self.charset_file.is_some() {
+            (self.charset, self.charset_file)
+        } else {
+            (fallback.charset, fallback.charset_file)
+        };
+        Self {
+            width: self.width.or(fallback.width),
+            height: self.height.or(fallback.height),
+            render: self.render.or(fallback.render),
+            layout: self.layout.or(fallback.layout),
+            mode: self.mode.or(fallback.mode),
+            charset,
+            charset_file,
+            char_aspect: self.char_aspect.or(fallback.char_aspect),
+            dither: self.dither.or(fallback.dither),
+            color_dither: self.color_dither.or(fallback.color_dither),
+            shade: self.shade.or(fallback.shade),
+            levels: self.levels.or(fallback.levels),
+            contrast: self.contrast.or(fallback.contrast),
+            clahe: self.clahe.or(fallback.clahe),
+            clahe_tiles: self.clahe_tiles.or(fallback.clahe_tiles),
+            clahe_clip: self.clahe_clip.or(fallback.clahe_clip),
+            sharpen: self.sharpen.or(fallback.sharpen),
+            denoise: self.denoise.or(fallback.denoise),
+            sharpen_color: self.sharpen_color.or(fallback.sharpen_color),
+            negate: self.negate.or(fallback.negate),
+            alpha_threshold: self.alpha_threshold.or(fallback.alpha_threshold),
+            resize_filter: self.resize_filter.or(fallback.resize_filter),
+            pixel_art: self.pixel_art.or(fallback.pixel_art),
+            color_metric: self.color_metric.or(fallback.color_metric),
+            color: self.color.or(fallback.color),
+            color_depth: self.color_depth.or(fallback.color_depth),
+            palette: self.palette.or(fallback.palette),
+            palette_seed: self.palette_seed.or(fallback.palette_seed),
+            theme: self.theme.or(fallback.theme),
+            filter: self.filter.or(fallback.filter),
+            hue_shift: self.hue_shift.or(fallback.hue_shift),
+            saturation: self.saturation.or(fallback.saturation),
+            lightness: self.lightness.or(fallback.lightness),
+            hsl_brightness: self.hsl_brightness.or(fallback.hsl_brightness),
+            auto_wb: self.auto_wb.or(fallback.auto_wb),
+            background: self.background.or(fallback.background),
+            background_color: self.background_color.or(fallback.background_color),
+            chroma_key: self.chroma_key.or(fallback.chroma_key),
+            chroma_tolerance: self.chroma_tolerance.or(fallback.chroma_tolerance),
+            threads: self.threads.or(fallback.threads),
+        }
+    }
+
+    /// Reject values no conversion can use, such as a `contrast` of NaN
+    pub fn validate(&self) -> Result<(), AsciiArtError> {
+        finite("contrast", self.contrast)?;
+        finite("hue-shift", self.hue_shift)?;
+        finite("saturation", self.saturation)?;
+        finite("lightness", self.lightness)?;
+        positive("clahe-clip", self.clahe_clip)?;
+        Ok(())
+    }
+
+    /// A builder configured with the conversion options set here
+    ///
+    /// Options that depend on a terminal (`render`, `layout`, and `color` other
+    /// than `"gray"`) and `threads` are ignored; values the library cannot resolve on its own,
+    /// such as `color-depth = "auto"` or `width = "75%"`, are rejected.
+    pub fn to_builder(&self) -> Result<AsciiArtBuilder, AsciiArtError> {
+        self.validate()?;
+        let cells = |name: &str, extent: Extent| match extent {
+            Extent::Cells(cells) => Ok(cells),
+            Extent::Percent(_) => Err(AsciiArtError::InvalidArgument(format!(
+                "{name} = \"{extent}\" is a share of a terminal; give a number of characters."
+            ))),
+        };
+        let mut builder = AsciiArtBuilder::new();
+        if let Some(width) = self.width {
+            builder = builder.width(cells("width", width)?);
+        }
+        if let Some(height) = self.height {
+            builder = builder.height(Some(cells("height", height)?));
+        }
+        if let Some(mode) = &self.mode {
+            builder = builder.mode(mode.parse()?);
+        }
+        if let Some(charset) = &self.charset {
+            builder = builder.charset(Charset::named(charset)?);
+        } else if let Some(path) = &self.charset_file {
+            builder = builder.charset(Charset::load(path)?);
+        }
+        if let Some(char_aspect) = self.char_aspect {
+            builder = builder.char_aspect(char_aspect);
+        }
+        if let Some(dither) = self.dither {
+            builder = builder.dithering(dither);
+        }
+        if let Some(color_dither) = self.color_dither {
+            builder = builder.color_dithering(color_dither);
+        }
+        if let Some(shade) = self.shade {
+            builder = builder.shading(shade);
+        }
+        if let Some(levels) = &self.levels {
+            builder = builder.filter(Filter::Levels(levels.parse()?));
+        }
+        if let Some(contrast) = self.contrast.filter(|&contrast| contrast != 0.0) {
+            builder = builder.filter(Filter::Contrast(contrast));
+        }
+        if let Some(denoise) = &self.denoise {
+            builder = builder.denoise(Some(denoise.parse()?));
+        }
+        if self.clahe == Some(true) {
+            builder = builder.clahe(Some(Clahe {
+                tiles: self.clahe_tiles.unwrap_or(DEFAULT_CLAHE_TILES).max(1),
+                clip_limit: self.clahe_clip.unwrap_or(DEFAULT_CLAHE_CLIP_LIMIT),
+            }));
+        }
+        if let Some(sharpen) = self.sharpen {
+            builder = builder.sharpen(sharpen);
+        }
+        if let Some(sharpen_color) = self.sharpen_color {
+            builder = builder.sharpen_color(sharpen_color);
+        }
+        if self.negate == Some(true) {
+            builder = builder.filter(Filter::Negate);
+        }
+        if let Some(alpha_threshold) = self.alpha_threshold {
+            builder = builder.alpha_threshold(alpha_threshold);
+        }
+        if let Some(resize_filter) = &self.resize_filter {
+            builder = builder.resize_filter(resize_filter.parse()?);
+        }
+        match self.pixel_art.as_deref() {
+            None => {}
+            Some("always") => builder = builder.pixel_art(true),
+            Some(other) => {
+                return Err(AsciiArtError::InvalidArgument(format!(
+                    "Pixel art '{}' cannot be resolved without the image; use 'always'.",
+                    other
+                )))
+            }
+        }
+        if let Some(color_metric) = &self.color_metric {
+            builder = builder.color_metric(color_metric.parse()?);
+        }
+        if let Some(color_depth) = &self.color_depth {
+            builder = builder.color_depth(color_depth.parse()?);
+        }
+        if self.color.as_deref() == Some("gray") {
+            builder = builder.grayscale(true);
+        }
+        if let Some(palette) = &self.palette {
+            let seed = self.palette_seed.unwrap_or(0);
+            builder = match AdaptivePalette::parse(palette, seed)? {
+                Some(adaptive) => builder.adaptive_palette(Some(adaptive)),
+                None => builder.palette(Palette::load(palette)?),
+            };
+        }
+        if let Some(theme) = &self.theme {
+            builder = builder.theme(Some(theme.parse()?));
+        }
+        for filter in self.filter.iter().flatten() {
+            builder = builder.color_filter(filter.parse()?);
+        }
+        builder = builder.hsl(HslAdjustment {
+            hue_shift: self.hue_shift.unwrap_or(0.0),
+            saturation: self.saturation.unwrap_or(0.0),
+            lightness: self.lightness.unwrap_or(0.0),
+        });
+        if let Some(hsl_brightness) = self.hsl_brightness {
+            builder = builder.hsl_brightness(hsl_brightness);
+        }
+        if let Some(auto_wb) = self.auto_wb {
+            builder = builder.auto_white_balance(auto_wb);
+        }
+        if let Some(background) = &self.background {
+            builder = builder.background(background.parse()?);
+        }
+        if let Some(color) = &self.background_color {
+            builder = builder.background_color(Some(parse_color(color)?));
+        }
+        if let Some(color) = &self.chroma_key {
+            let key = ChromaKey {
+                color: parse_color(color)?,
+                tolerance: self.chroma_tolerance.unwrap_or(DEFAULT_CHROMA_TOLERANCE),
+            };
+            builder = builder.chroma_key(Some(key));
+        }
+        Ok(builder)
+    }
+
+    fn resolve_paths(&mut self, dir: &Path) {
+        if let Some(palette) = self
+            .palette
+            .as_ref()
+            .filter(|p| !AdaptivePalette::is_adaptive(p))
+        {
+            self.palette = Some(dir.join(palette));
+        }
+        if let Some(charset_file) = &self.charset_file {
+            self.charset_file = Some(dir.join(charset_file));
+        }
+    }
+}
+
+/// Defaults and saved presets loaded from a TOML configuration file
+///
+/// Top-level keys are [`Options`] applied to every run; each
+/// `[presets.<name>]` table holds options selected with `--preset <name>`.
+/// Values given on the command line take precedence over both.
+///
+/// ```toml
+/// mode = "edge"
+/// color-depth = "256"
+///
+/// [presets.poster]
+/// charset = " .oO@"
+/// contrast = 30.0
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Config {
+    pub defaults: Options,
+    pub presets: BTreeMap<String, Options>,
+}
+
+impl Config {
+    /// Where the configuration is looked up when `--config` is not given
+    ///
+    /// `$XDG_CONFIG_HOME/ascii-art/config.toml`, falling back to
+    /// `~/.config/ascii-art/config.toml`.
+    pub fn default_path() -> Option<PathBuf> {
+        let base = match env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+            Some(dir) => PathBuf::from(dir),
+            None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+        };
+        Some(base.join("ascii-art").join("config.toml"))
+    }
+
+    /// Read a configuration file
+    ///
+    /// Relative `palette` and `charset-file` paths are resolved against the
+    /// file's directory.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, AsciiArtError> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path).map_err(|err| invalid_file(path, err))?;
+        let mut config = Self::from_toml(&source).map_err(|err| invalid_file(path, err))?;
+        if let Some(dir) = path.parent() {
+            config.defaults.resolve_paths(dir);
+            for preset in config.presets.values_mut() {
+                preset.resolve_paths(dir);
+            }
+        }
+        Ok(config)
+    }
+
+    /// Load the default configuration file, if there is one
+    pub fn load_default() -> Result<Self, AsciiArtError> {
+        match Self::default_path() {
+            Some(path) if path.is_file() => Self::load(path),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    /// Parse a configuration from a TOML document
+    pub fn from_toml(source: &str) -> Result<Self, AsciiArtError> {
+        let mut table: toml::Table = toml::from_str(source).map_err(toml_error)?;
+        let presets = match table.remove("presets") {
+            Some(presets) => presets.try_into().map_err(toml_error)?,
+            None => BTreeMap::new(),
+        };
+        let defaults = toml::Value::Table(table).try_into().map_err(toml_error)?;
+        Ok(Self { defaults, presets })
+    }
+
+    /// Look up a preset saved in this file, then the built-in ones
+    pub fn preset(&self, name: &str) -> Result<Options, AsciiArtError> {
+        self.presets
+            .get(name)
+            .cloned()
+            .or_else(|| Options::builtin_preset(name))
+            .ok_or_else(|| {
+                AsciiArtError::InvalidArgument(format!(
+                    "Unknown preset '{name}'. Use {}, or a preset saved with --save-preset.",
+                    BUILTIN_PRESETS
+                        .map(|preset| format!("'{preset}'"))
+                        .join(", ")
+                ))
+            })
+    }
+}
+
+/// Store `options` as `[presets.<name>]` in the configuration file at `path`
+///
+/// The file and its directory are created when missing. Other settings are
+/// kept, but comments in the file are not preserved.
+pub fn save_preset(path: &Path, name: &str, options: &Options) -> Result<(), AsciiArtError> {
+    let mut table: toml::Table = match fs::read_to_string(path) {
+        Ok(source) => toml::from_str(&source).map_err(|err| invalid_file(path, toml_error(err)))?,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => toml::Table::new(),
+        Err(err) => return Err(invalid_file(path, err)),
+    };
+    let preset = toml::Value::try_from(options).map_err(|err| invalid_file(path, err))?;
+    let presets = table
+        .entry("presets")
+        .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+    let Some(presets) = presets.as_table_mut() else {
+        return Err(invalid_file(path, "'presets' must be a table"));
+    };
+    presets.insert(name.to_string(), preset);
+
+    let source = toml::to_string(&table).map_err(|err| invalid_file(path, err))?;
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).map_err(|err| invalid_file(path, err))?;
+    }
+    fs::write(path, source).map_err(|err| invalid_file(path, err))?;
+    Ok(())
+}
+
+fn invalid_file(path: &Path, reason: impl std::fmt::Display) -> AsciiArtError {
+    AsciiArtError::InvalidArgument(format!(
+        "Invalid config file \"{}\": {reason}",
+        path.display()
+    ))
+}
+
+/// Reject a value of the option `name` that is not a finite number
+fn finite(name: &str, value: Option<f32>) -> Result<(), AsciiArtError> {
+    match value {
+        Some(value) if !value.is_finite() => Err(AsciiArtError::InvalidArgument(format!(
+            "{name} = {value} is not a finite number."
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Reject a value of the option `name` that is not a finite number above zero
+fn positive(name: &str, value: Option<f32>) -> Result<(), AsciiArtError> {
+    match value {
+        Some(value) if !(value.is_finite() && value > 0.0) => Err(AsciiArtError::InvalidArgument(
+            format!("{name} = {value} must be greater than zero."),
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn toml_error(err: toml::de::Error) -> AsciiArtError {
+    AsciiArtError::InvalidArgument(err.message().trim_end().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_flag_named_keys() {
+        let config = Config::from_toml(
+            "mode = \"edge\"\ncolor-depth = \"256\"\nchar-aspect = 2.5\nwidth = 60\n\
+             height = \"50%\"\n",
+        )
+        .expect("valid config");
+        assert_eq!(config.defaults.mode.as_deref(), Some("edge"));
+        assert_eq!(config.defaults.color_depth.as_deref(), Some("256"));
+        assert_eq!(config.defaults.char_aspect, Some(2.5));
+        assert_eq!(config.defaults.width, Some(Extent::Cells(60)));
+        assert_eq!(config.defaults.height, Some(Extent::Percent(50.0)));
+        assert!(config.defaults.to_builder().is_err());
+        assert_eq!(config.defaults.theme, None);
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        let err = Config::from_toml("colour = \"always\"").unwrap_err();
+        assert!(err.to_string().contains("colour"), "{err}");
+        let err = Config::from_toml("[presets.mine]\ncolour = \"always\"").unwrap_err();
+        assert!(err.to_string().contains("colour"), "{err}");
+    }
+
+    #[test]
+    fn palette_is_relative_to_the_file() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("config.toml");
+        fs::write(&path, "palette = \"c64.toml\"").expect("write config");
+
+        let config = Config::load(&path).expect("valid config");
+        assert_eq!(config.defaults.palette, Some(dir.path().join("c64.toml")));
+
+        fs::write(&path, "palette = \"adaptive:8\"").expect("write config");
+        let config = Config::load(&path).expect("valid config");
+        assert_eq!(config.defaults.palette, Some(PathBuf::from("adaptive:8")));
+        assert!(config.defaults.to_builder().is_ok());
+
+        fs::write(&path, "charset-file = \"ramps/dense.txt\"").expect("write config");
+        let config = Config::load(&path).expect("valid config");
+        assert_eq!(
+            config.defaults.charset_file,
+            Some(dir.path().join("ramps/dense.txt"))
+        );
+    }
+
+    #[test]
+    fn saved_presets_shadow_builtins_and_keep_other_settings() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("ascii-art").join("config.toml");
+        let options = Options {
+            contrast: Some(25.0),
+            ..Options::default()
+        };
+        save_preset(&path, "photo", &options).expect("created");
+        let mut config = Config::load(&path).expect("valid config");
+        assert_eq!(config.preset("photo").expect("saved"), options);
+
+        fs::write(&path, "mode = \"edge\"\n").expect("write config");
+        save_preset(&path, "mine", &options).expect("updated");
+        config = Config::load(&path).expect("valid config");
+        assert_eq!(config.defaults.mode.as_deref(), Some("edge"));
+        assert_eq!(config.preset("mine").expect("saved"), options);
+        assert_eq!(config.preset("logo").ok(), Options::builtin_preset("logo"));
+        assert!(config.preset("poster").is_err());
+    }
+
+    #[test]
+    fn options_configure_a_builder() {
+        let options = Options {
+            width: Some(Extent::Cells(8)),
+            charset: Some(" #".to_string()),
+            color_depth: Some("mono".to_string()),
+            ..Options::default()
+        };
+        let image = image::DynamicImage::new_rgb8(16, 16);
+        let art = options
+            .to_builder()
+            .expect("valid options")
+            .generate(&image);
+        assert_eq!(art.expect("converted").to_text(), "        \n".repeat(4));
+
+        let red = image::DynamicImage::ImageRgb8(image::RgbImage::from_pixel(
+            16,
+            16,
+            image::Rgb([255, 0, 0]),
+        ));
+        let gray = Options {
+            charset: Some("#".to_string()),
+            color: Some("gray".to_string()),
+            color_depth: Some("truecolor".to_string()),
+            ..Options::default()
+        };
+        let art = gray.to_builder().expect("valid options").generate(&red);
+        let ansi = art.expect("converted").to_ansi_string();
+        assert!(ansi.contains("\x1b[38;2;"), "{ansi:?}");
+        assert!(!ansi.contains("38;2;255;0;0"), "{ansi:?}");
+
+        let auto = Options {
+            color_depth: Some("auto".to_string()),
+            ..Options::default()
+        };
+        assert!(auto.to_builder().is_err());
+        let auto_mode = Options {
+            mode: Some("auto".to_string()),
+            ..Options::default()
+        };
+        assert!(auto_mode.to_builder().is_err());
+        let pixel_art = |value: &str| Options {
+            pixel_art: Some(value.to_string()),
+            ..Options::default()
+        };
+        assert!(pixel_art("always").to_builder().is_ok());
+        assert!(pixel_art("auto").to_builder().is_err());
+
+        let filters = Config::from_toml("filter = [\"sepia\", \"noir\"]").expect("valid config");
+        assert!(filters.defaults.to_builder().is_ok());
+        let hsl =
+            Config::from_toml("saturation = -100.0\nhsl-brightness = true").expect("valid config");
+        assert_eq!(hsl.defaults.saturation, Some(-100.0));
+        assert_eq!(hsl.defaults.hsl_brightness, Some(true));
+        let unknown = Config::from_toml("filter = [\"vintage\"]").expect("valid config");
+        assert!(unknown.defaults.to_builder().is_err());
+        let nan = Config::from_toml("contrast = nan").expect("valid config");
+        assert!(nan.defaults.to_builder().is_err());
+        let inf = Config::from_toml("saturation = inf").expect("valid config");
+        assert!(inf.defaults.to_builder().is_err());
+        let clip = Config::from_toml("clahe = true\nclahe-clip = 0.0").expect("valid config");
+        assert!(clip.defaults.to_builder().is_err());
+    }
+
+    #[test]
+    fn earlier_options_take_precedence() {
+        let cli = Options {
+            mode: Some("edge".to_string()),
+            ..Options::default()
+        };
+        let merged = cli.or(Options::builtin_preset("photo").expect("builtin"));
+        assert_eq!(merged.mode.as_deref(), Some("edge"));
+        assert_eq!(merged.dither, Some(true));
+    }
+}
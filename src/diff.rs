@@ -0,0 +1,188 @@
+//! Cell-by-cell comparison of two converted grids, as printed by the `diff`
+//! subcommand
+
+use std::fmt;
+
+use crate::ascii_converter::{AsciiImage, Cell};
+use crate::renderer::RESET;
+
+/// Largest difference in any color channel still counted as the same color
+pub const COLOR_TOLERANCE: u8 = 16;
+
+/// How a cell of the second grid differs from the first
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Same,
+    /// Same character in a different color
+    Color,
+    /// A different character, a transparent cell on one side only, or a cell
+    /// outside one of the grids
+    Character,
+}
+
+/// The two grids aligned at their top-left corner, over the area covered by
+/// either of them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diff {
+    width: usize,
+    height: usize,
+    /// The second grid's cell, if it covers the position, and its change
+    cells: Vec<(Option<Cell>, Change)>,
+}
+
+impl Diff {
+    /// Compare `after` against `before`
+    pub fn new(before: &AsciiImage, after: &AsciiImage) -> Self {
+        let width = before.width().max(after.width());
+        let height = before.height().max(after.height());
+        let mut cells = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let (old, new) = (before.get(x, y), after.get(x, y));
+                let change = match (old, new) {
+                    (Some(old), Some(new)) => compare(old, new),
+                    _ => Change::Character,
+                };
+                cells.push((new.copied(), change));
+            }
+        }
+        Self {
+            width,
+            height,
+            cells,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Cells with the given kind of change
+    pub fn count(&self, change: Change) -> usize {
+        self.cells.iter().filter(|(_, c)| *c == change).count()
+    }
+
+    /// Cells that changed in any way
+    pub fn changed(&self) -> usize {
+        self.cells.len() - self.count(Change::Same)
+    }
+
+    /// Share of changed cells, 0-100
+    pub fn percentage(&self) -> f64 {
+        match self.cells.len() {
+            0 => 0.0,
+            total => self.changed() as f64 * 100.0 / total as f64,
+        }
+    }
+
+    /// The second grid with its changes marked
+    ///
+    /// With `color`, its characters are shown dimmed where unchanged, on red
+    /// where the character changed, and on yellow where only the color did.
+    /// Without, changed characters are marked `#`, changed colors `~`, and
+    /// unchanged cells `.`.
+    pub fn render(&self, color: bool) -> String {
+        let mut out = String::new();
+        for row in self.cells.chunks(self.width.max(1)) {
+            for &(cell, change) in row {
+                let ch = cell.map_or(' ', |cell| cell.ch);
+                match (color, change) {
+                    (false, Change::Same) => out.push('.'),
+                    (false, Change::Color) => out.push('~'),
+                    (false, Change::Character) => out.push('#'),
+                    (true, Change::Same) => out.push_str(&format!("\x1b[2m{ch}{RESET}")),
+                    (true, Change::Color) => out.push_str(&format!("\x1b[30;43m{ch}{RESET}")),
+                    (true, Change::Character) => out.push_str(&format!("\x1b[97;41m{ch}{RESET}")),
+                }
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+impl fmt::Display for Diff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:.2}% of cells changed ({} of {}: {} characters, {} colors only)",
+            self.percentage(),
+            self.changed(),
+            self.cells.len(),
+            self.count(Change::Character),
+            self.count(Change::Color)
+        )
+    }
+}
+
+fn compare(old: &Cell, new: &Cell) -> Change {
+    if old.transparent || new.transparent {
+        return if old.transparent == new.transparent {
+            Change::Same
+        } else {
+            Change::Character
+        };
+    }
+    if old.ch != new.ch {
+        return Change::Character;
+    }
+    let close = old
+        .rgb
+        .iter()
+        .zip(new.rgb)
+        .all(|(&a, b)| a.abs_diff(b) <= COLOR_TOLERANCE);
+    if close {
+        Change::Same
+    } else {
+        Change::Color
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid(width: usize, chars: &str, rgb: [u8; 3]) -> AsciiImage {
+        let cells: Vec<Cell> = chars.chars().map(|ch| Cell::opaque(ch, rgb, 0)).collect();
+        AsciiImage::new(width, cells.len() / width, cells).expect("valid grid")
+    }
+
+    #[test]
+    fn counts_character_and_color_changes() {
+        let gray = [100, 100, 100];
+        let before = grid(2, "abcd", gray);
+        let cells = vec![
+            Cell::opaque('a', gray, 0),
+            Cell::opaque('b', [200, 100, 100], 0),
+            Cell::opaque('x', gray, 0),
+            // Within the tolerance
+            Cell::opaque('d', [110, 100, 90], 0),
+        ];
+        let after = AsciiImage::new(2, 2, cells).expect("valid grid");
+
+        let diff = Diff::new(&before, &after);
+        assert_eq!(diff.count(Change::Character), 1);
+        assert_eq!(diff.count(Change::Color), 1);
+        assert_eq!(diff.percentage(), 50.0);
+        assert_eq!(diff.render(false), ".~\n#.\n");
+        assert!(diff.render(true).contains("\x1b[97;41mx"));
+        assert_eq!(
+            diff.to_string(),
+            "50.00% of cells changed (2 of 4: 1 characters, 1 colors only)"
+        );
+    }
+
+    #[test]
+    fn cells_outside_either_grid_count_as_changed() {
+        let before = grid(2, "abcd", [0, 0, 0]);
+        let after = grid(3, "abz", [0, 0, 0]);
+        let diff = Diff::new(&before, &after);
+        assert_eq!((diff.width(), diff.height()), (3, 2));
+        assert_eq!(diff.render(false), "..#\n###\n");
+        assert_eq!(Diff::new(&before, &before).changed(), 0);
+    }
+}
@@ -1,12 +1,19 @@
 use image::GrayImage;
+#[cfg(feature = "edge")]
 use imageproc::edges::canny;
+#[cfg(feature = "edge")]
+use tracing::debug;
 
-use crate::ascii_converter::AsciiGrid;
+use crate::ascii_converter::{map_cells, AsciiImage};
+use crate::error::AsciiArtError;
+use crate::image_loader::ProcessedImage;
 
 /// Low threshold for Canny edge detection (hardcoded for MVP)
+#[cfg(feature = "edge")]
 const LOW_THRESHOLD: f32 = 50.0;
 
 /// High threshold for Canny edge detection (hardcoded for MVP)
+#[cfg(feature = "edge")]
 const HIGH_THRESHOLD: f32 = 100.0;
 
 /// Maps an edge pixel value to an ASCII character
@@ -29,7 +36,30 @@ pub fn edge_to_char(edge_value: u8) -> char {
     }
 }
 
-/// Applies Canny edge detection and converts the result to an ASCII grid
+/// Computes the binary Canny edge map (255=edge, 0=non-edge) of a grayscale image
+#[cfg(feature = "edge")]
+pub fn edge_map(gray: &GrayImage) -> Result<GrayImage, AsciiArtError> {
+    debug!(
+        low = LOW_THRESHOLD,
+        high = HIGH_THRESHOLD,
+        "Canny thresholds"
+    );
+    Ok(canny(gray, LOW_THRESHOLD, HIGH_THRESHOLD))
+}
+
+/// Computes the binary Canny edge map (255=edge, 0=non-edge) of a grayscale image
+///
+/// Fails with [`AsciiArtError::FeatureDisabled`] when built without the
+/// `edge` feature.
+#[cfg(not(feature = "edge"))]
+pub fn edge_map(_gray: &GrayImage) -> Result<GrayImage, AsciiArtError> {
+    Err(AsciiArtError::FeatureDisabled {
+        what: "Edge detection",
+        feature: "edge",
+    })
+}
+
+/// Applies Canny edge detection and converts the result to an ASCII image
 ///
 /// This function performs the following steps:
 /// 1. Applies Canny edge detection using hardcoded thresholds
@@ -37,41 +67,35 @@ pub fn edge_to_char(edge_value: u8) -> char {
 /// 3. Returns a grid with dimensions matching the input image
 ///
 /// # Arguments
-/// * `gray` - The grayscale image to process
+/// * `image` - The resized color and grayscale image to process
 ///
 /// # Returns
-/// * `Ok(AsciiGrid)` - A 2D vector of characters ('#' for edges, ' ' for non-edges)
-/// * `Err(String)` - Error message if conversion fails
-pub fn detect_and_convert(gray: &GrayImage) -> Result<AsciiGrid, String> {
+/// * `Ok(AsciiImage)` - Cells with '#' for edges and ' ' for non-edges
+/// * `Err(AsciiArtError::EmptyImage)` - If the image has no pixels
+/// * `Err(AsciiArtError::FeatureDisabled)` - If built without the `edge` feature
+pub fn detect_and_convert(image: &ProcessedImage) -> Result<AsciiImage, AsciiArtError> {
+    let gray: &GrayImage = &image.gray;
     let (width, height) = gray.dimensions();
-    
+
     if width == 0 || height == 0 {
-        return Err("Image dimensions must be greater than zero.".to_string());
+        return Err(AsciiArtError::EmptyImage);
     }
 
     // Apply Canny edge detection
-    let edge_map = canny(gray, LOW_THRESHOLD, HIGH_THRESHOLD);
-    
-    // Convert edge map to ASCII grid
-    let mut grid = Vec::with_capacity(height as usize);
-    
-    for y in 0..height {
-        let mut row = Vec::with_capacity(width as usize);
-        for x in 0..width {
-            let pixel = edge_map.get_pixel(x, y);
-            let edge_value = pixel[0];
-            row.push(edge_to_char(edge_value));
-        }
-        grid.push(row);
-    }
-    
-    Ok(grid)
+    let edge_map = edge_map(gray)?;
+
+    // Convert edge map to ASCII cells
+    map_cells(image, |x, y, _| edge_to_char(edge_map.get_pixel(x, y)[0]))
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "edge"))]
 mod tests {
     use super::*;
-    use image::ImageBuffer;
+    use image::{DynamicImage, ImageBuffer};
+
+    fn processed(gray: GrayImage) -> ProcessedImage {
+        ProcessedImage::new(DynamicImage::ImageLuma8(gray))
+    }
 
     #[test]
     fn test_edge_to_char_boundary_values() {
@@ -84,10 +108,14 @@ mod tests {
         // Create a simple test grayscale image
         let gray = GrayImage::from_pixel(10, 5, image::Luma([128]));
         
-        let grid = detect_and_convert(&gray).expect("conversion succeeds");
-        
-        assert_eq!(grid.len(), 5, "Grid should have 5 rows (height)");
-        assert_eq!(grid[0].len(), 10, "Each row should have 10 characters (width)");
+        let ascii = detect_and_convert(&processed(gray)).expect("conversion succeeds");
+
+        assert_eq!(ascii.height(), 5, "Grid should have 5 rows (height)");
+        assert_eq!(
+            ascii.width(),
+            10,
+            "Each row should have 10 characters (width)"
+        );
     }
 
     #[test]
@@ -95,11 +123,11 @@ mod tests {
         // Create image with all 255 values (should be detected as edges)
         let gray = GrayImage::from_pixel(4, 3, image::Luma([255]));
         
-        let grid = detect_and_convert(&gray).expect("conversion succeeds");
-        
+        let ascii = detect_and_convert(&processed(gray)).expect("conversion succeeds");
+
         // Note: Canny may not detect uniform images as edges, but this tests the mapping logic
-        assert_eq!(grid.len(), 3);
-        assert_eq!(grid[0].len(), 4);
+        assert_eq!(ascii.height(), 3);
+        assert_eq!(ascii.width(), 4);
     }
 
     #[test]
@@ -107,20 +135,22 @@ mod tests {
         // Create image with all 0 values (black, no edges)
         let gray = GrayImage::from_pixel(4, 3, image::Luma([0]));
         
-        let grid = detect_and_convert(&gray).expect("conversion succeeds");
-        
+        let ascii = detect_and_convert(&processed(gray)).expect("conversion succeeds");
+
         // Fully black image should have no edges, all spaces
-        for row in &grid {
-            for &ch in row {
-                assert_eq!(ch, ' ', "Fully black image should produce all spaces");
-            }
+        for cell in ascii.cells() {
+            assert_eq!(cell.ch, ' ', "Fully black image should produce all spaces");
         }
     }
 
     #[test]
     fn test_detect_and_convert_rejects_zero_dimensions() {
         let gray = ImageBuffer::new(0, 0);
-        let err = detect_and_convert(&gray).unwrap_err();
-        assert_eq!(err, "Image dimensions must be greater than zero.");
+        let err = detect_and_convert(&processed(gray)).unwrap_err();
+        assert!(matches!(err, AsciiArtError::EmptyImage));
+        assert_eq!(
+            err.to_string(),
+            "Image dimensions must be greater than zero."
+        );
     }
 }
@@ -0,0 +1,117 @@
+use std::io;
+
+use thiserror::Error;
+
+use crate::image_loader::ImageLoaderError;
+
+/// Crate-wide error type covering every stage of the pipeline
+#[derive(Debug, Error)]
+pub enum AsciiArtError {
+    /// The input image could not be loaded or resized
+    #[error(transparent)]
+    Image(#[from] ImageLoaderError),
+
+    /// A conversion stage received an image without pixels
+    #[error("Image dimensions must be greater than zero.")]
+    EmptyImage,
+
+    /// Two images that must be the same size were not
+    #[error("Image size mismatch: expected {expected:?}, found {actual:?}.")]
+    DimensionMismatch {
+        expected: (u32, u32),
+        actual: (u32, u32),
+    },
+
+    /// A custom charset did not contain any characters
+    #[error("Charset must contain at least one character.")]
+    EmptyCharset,
+
+    /// The requested rendering mode does not exist
+    #[error("Unknown mode '{0}'. Use 'standard' or 'edge'.")]
+    UnknownMode(String),
+
+    /// A command-line argument was rejected during validation
+    #[error("{0}")]
+    InvalidArgument(String),
+
+    /// Edge mode failed to trace the image
+    #[error("Edge detection failed: {0}")]
+    EdgeDetection(#[source] Box<AsciiArtError>),
+
+    /// The requested functionality was left out of this build
+    #[error("{what} is not available in this build; rebuild with the '{feature}' feature.")]
+    FeatureDisabled {
+        what: &'static str,
+        feature: &'static str,
+    },
+
+    /// Writing the rendered output failed
+    #[error("Rendering error: {0}")]
+    Render(#[from] io::Error),
+}
+
+impl AsciiArtError {
+    /// Name of the failure, for machine-readable error reports
+    ///
+    /// Image errors report the loader's own kind, such as `FileNotFound`, and
+    /// edge detection errors the kind of the failure behind them.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AsciiArtError::Image(err) => err.kind(),
+            AsciiArtError::EmptyImage => "EmptyImage",
+            AsciiArtError::DimensionMismatch { .. } => "DimensionMismatch",
+            AsciiArtError::EmptyCharset => "EmptyCharset",
+            AsciiArtError::UnknownMode(_) => "UnknownMode",
+            AsciiArtError::InvalidArgument(_) => "InvalidArgument",
+            AsciiArtError::EdgeDetection(err) => err.kind(),
+            AsciiArtError::FeatureDisabled { .. } => "FeatureDisabled",
+            AsciiArtError::Render(_) => "Render",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kinds_name_the_underlying_failure() {
+        let err = AsciiArtError::from(ImageLoaderError::FileNotFound("a.png".to_string()));
+        assert_eq!(err.kind(), "FileNotFound");
+        assert_eq!(AsciiArtError::EmptyCharset.kind(), "EmptyCharset");
+    }
+
+    #[test]
+    fn image_errors_keep_their_message() {
+        let err = AsciiArtError::from(ImageLoaderError::FileNotFound("a.png".to_string()));
+        assert_eq!(err.to_string(), "Could not find image file \"a.png\".");
+    }
+
+    #[test]
+    fn disabled_features_name_the_cargo_feature() {
+        let err = AsciiArtError::FeatureDisabled {
+            what: "Edge detection",
+            feature: "edge",
+        };
+        assert_eq!(
+            err.to_string(),
+            "Edge detection is not available in this build; rebuild with the 'edge' feature."
+        );
+    }
+
+    #[test]
+    fn edge_detection_errors_are_prefixed() {
+        let err = AsciiArtError::EdgeDetection(Box::new(AsciiArtError::EmptyImage));
+        assert_eq!(
+            err.to_string(),
+            "Edge detection failed: Image dimensions must be greater than zero."
+        );
+        assert_eq!(err.kind(), "EmptyImage");
+    }
+
+    #[test]
+    fn render_errors_are_prefixed() {
+        let err = AsciiArtError::from(io::Error::new(io::ErrorKind::BrokenPipe, "pipe closed"));
+        assert_eq!(err.to_string(), "Rendering error: pipe closed");
+    }
+}
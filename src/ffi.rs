@@ -0,0 +1,248 @@
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+
+use image::{DynamicImage, RgbImage};
+
+use crate::ascii_converter::Charset;
+use crate::builder::{AsciiArtBuilder, Mode};
+use crate::error::AsciiArtError;
+use crate::renderer::ColorDepth;
+
+/// Brightness-to-character conversion
+pub const ASCII_ART_MODE_STANDARD: u32 = 0;
+/// Canny edge detection
+pub const ASCII_ART_MODE_EDGE: u32 = 1;
+
+/// The 16 basic ANSI colors
+pub const ASCII_ART_COLOR_16: u32 = 0;
+/// The xterm 256-color palette
+pub const ASCII_ART_COLOR_256: u32 = 1;
+/// 24-bit RGB escapes
+pub const ASCII_ART_COLOR_TRUECOLOR: u32 = 2;
+/// Plain text without escapes
+pub const ASCII_ART_COLOR_MONO: u32 = 3;
+/// The 8 standard ANSI colors, for terminals that lack the bright set
+pub const ASCII_ART_COLOR_8: u32 = 4;
+
+/// Conversion options passed from C; zeroed fields select the defaults
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct AsciiArtOptions {
+    /// Output width in characters, or 0 for 80
+    pub width: u32,
+    /// One of the `ASCII_ART_MODE_*` constants
+    pub mode: u32,
+    /// One of the `ASCII_ART_COLOR_*` constants
+    pub color_depth: u32,
+    /// Height-to-width ratio of a character cell, or 0 for 2.0
+    pub char_aspect: f32,
+    /// Nonzero to dither the character ramp
+    pub dither: u8,
+    /// NUL-terminated UTF-8 ramp from dark to light, or NULL for the default
+    pub charset: *const c_char,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Convert a packed 8-bit RGB buffer to ASCII art
+///
+/// Returns NUL-terminated UTF-8 text to release with [`ascii_art_free`], or
+/// NULL on failure; [`ascii_art_last_error`] then describes the problem.
+///
+/// # Safety
+///
+/// `pixels` must point to `width * height * 3` readable bytes, rows top to
+/// bottom with no padding. `options` must be NULL or point to a valid
+/// [`AsciiArtOptions`] whose `charset` is NULL or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn ascii_art_convert(
+    pixels: *const u8,
+    width: u32,
+    height: u32,
+    options: *const AsciiArtOptions,
+) -> *mut c_char {
+    // A panic must not unwind into the C caller
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        convert_raw(pixels, width, height, options)
+    }));
+    match result {
+        Ok(Ok(text)) => text.into_raw(),
+        Ok(Err(err)) => {
+            set_last_error(&err.to_string());
+            ptr::null_mut()
+        }
+        Err(payload) => {
+            let reason = payload
+                .downcast_ref::<&str>()
+                .copied()
+                .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                .unwrap_or("unknown cause");
+            set_last_error(&format!("Conversion panicked: {reason}"));
+            ptr::null_mut()
+        }
+    }
+}
+
+unsafe fn convert_raw(
+    pixels: *const u8,
+    width: u32,
+    height: u32,
+    options: *const AsciiArtOptions,
+) -> Result<CString, AsciiArtError> {
+    let len = (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|len| len.checked_mul(3));
+    match len {
+        _ if pixels.is_null() => Err(AsciiArtError::InvalidArgument(
+            "Pixel buffer must not be NULL.".to_string(),
+        )),
+        None => Err(AsciiArtError::InvalidArgument(format!(
+            "Image of {width}x{height} pixels is too large."
+        ))),
+        Some(len) => {
+            let pixels = slice::from_raw_parts(pixels, len);
+            let options = options.as_ref().copied();
+            options
+                .map_or_else(|| Ok(AsciiArtBuilder::new()), |options| builder(&options))
+                .and_then(|builder| convert(pixels, width, height, &builder))
+        }
+    }
+}
+
+/// Release text returned by [`ascii_art_convert`]; NULL is ignored
+///
+/// # Safety
+///
+/// `text` must be NULL or a pointer returned by [`ascii_art_convert`] that
+/// has not been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn ascii_art_free(text: *mut c_char) {
+    if !text.is_null() {
+        drop(CString::from_raw(text));
+    }
+}
+
+/// Why the last [`ascii_art_convert`] on this thread failed, or NULL
+///
+/// The message stays valid until the next failed conversion on the same
+/// thread and must not be freed.
+#[no_mangle]
+pub extern "C" fn ascii_art_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |err| err.as_ptr())
+    })
+}
+
+unsafe fn builder(options: &AsciiArtOptions) -> Result<AsciiArtBuilder, AsciiArtError> {
+    let mode = match options.mode {
+        ASCII_ART_MODE_STANDARD => Mode::Standard,
+        ASCII_ART_MODE_EDGE => Mode::Edge,
+        unknown => return Err(AsciiArtError::UnknownMode(unknown.to_string())),
+    };
+    let color_depth = match options.color_depth {
+        ASCII_ART_COLOR_16 => ColorDepth::Ansi16,
+        ASCII_ART_COLOR_256 => ColorDepth::Ansi256,
+        ASCII_ART_COLOR_TRUECOLOR => ColorDepth::TrueColor,
+        ASCII_ART_COLOR_MONO => ColorDepth::Monochrome,
+        ASCII_ART_COLOR_8 => ColorDepth::Ansi8,
+        unknown => {
+            return Err(AsciiArtError::InvalidArgument(format!(
+                "Unknown color depth {unknown}."
+            )))
+        }
+    };
+
+    let mut builder = AsciiArtBuilder::new()
+        .mode(mode)
+        .color_depth(color_depth)
+        .dithering(options.dither != 0);
+    if options.width != 0 {
+        builder = builder.width(options.width);
+    }
+    if options.char_aspect != 0.0 {
+        builder = builder.char_aspect(options.char_aspect);
+    }
+    if !options.charset.is_null() {
+        let ramp = CStr::from_ptr(options.charset).to_str().map_err(|_| {
+            AsciiArtError::InvalidArgument("Charset must be valid UTF-8.".to_string())
+        })?;
+        builder = builder.charset(Charset::new(ramp)?);
+    }
+    Ok(builder)
+}
+
+fn convert(
+    pixels: &[u8],
+    width: u32,
+    height: u32,
+    builder: &AsciiArtBuilder,
+) -> Result<CString, AsciiArtError> {
+    let image = RgbImage::from_raw(width, height, pixels.to_vec())
+        .map(DynamicImage::ImageRgb8)
+        .ok_or(AsciiArtError::EmptyImage)?;
+    let text = builder.generate(&image)?.to_ansi_string();
+    CString::new(text).map_err(|_| {
+        AsciiArtError::InvalidArgument("Charset must not contain NUL characters.".to_string())
+    })
+}
+
+fn set_last_error(message: &str) {
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options() -> AsciiArtOptions {
+        AsciiArtOptions {
+            width: 4,
+            mode: ASCII_ART_MODE_STANDARD,
+            color_depth: ASCII_ART_COLOR_MONO,
+            char_aspect: 1.0,
+            dither: 0,
+            charset: c" #".as_ptr(),
+        }
+    }
+
+    #[test]
+    fn converts_rgb_buffers_to_text() {
+        let pixels = [255u8; 4 * 4 * 3];
+        unsafe {
+            let text = ascii_art_convert(pixels.as_ptr(), 4, 4, &options());
+            assert!(!text.is_null());
+            assert_eq!(
+                CStr::from_ptr(text).to_str(),
+                Ok("####\n".repeat(4).as_str())
+            );
+            ascii_art_free(text);
+        }
+    }
+
+    #[test]
+    fn failures_set_the_last_error() {
+        let pixels = [0u8; 3];
+        let bad = AsciiArtOptions {
+            mode: 7,
+            ..options()
+        };
+        unsafe {
+            assert!(ascii_art_convert(pixels.as_ptr(), 1, 1, &bad).is_null());
+            let message = CStr::from_ptr(ascii_art_last_error());
+            assert_eq!(
+                message.to_str(),
+                Ok("Unknown mode '7'. Use 'standard' or 'edge'.")
+            );
+            assert!(ascii_art_convert(ptr::null(), 1, 1, ptr::null()).is_null());
+            ascii_art_free(ptr::null_mut());
+        }
+    }
+}
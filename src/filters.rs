@@ -0,0 +1,740 @@
+use std::fmt;
+use std::str::FromStr;
+
+use image::{imageops, DynamicImage, GrayImage, ImageBuffer, Luma, Pixel, RgbaImage};
+
+use crate::color::luma;
+use crate::error::AsciiArtError;
+use crate::image_loader::is_high_precision;
+use crate::parallel::map_rows;
+
+/// Image adjustment applied to the resized image before conversion
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Filter {
+    /// Add a constant to every channel, in 8-bit units (negative values darken)
+    Brightness(i32),
+    /// Scale contrast around mid-gray (positive values increase contrast)
+    Contrast(f32),
+    /// Invert every color channel, like a photographic negative
+    Negate,
+    /// Remap the input range like an image editor's Levels dialog
+    Levels(Levels),
+}
+
+/// Black point, white point, and midtone gamma of a levels adjustment
+///
+/// Channel values at or below `black` become black, those at or above `white`
+/// become white, and the range between is stretched, with `gamma` above 1
+/// brightening the midtones and below 1 darkening them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Levels {
+    pub black: u8,
+    pub white: u8,
+    pub gamma: f32,
+}
+
+impl FromStr for Levels {
+    type Err = AsciiArtError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            AsciiArtError::InvalidArgument(format!(
+                "Invalid levels '{}'. Use BLACK,WHITE[,GAMMA] with 0 <= BLACK < WHITE <= 255 \
+                 and a positive GAMMA.",
+                value
+            ))
+        };
+        let parts: Vec<&str> = value.split(',').map(str::trim).collect();
+        let (black, white, gamma) = match parts[..] {
+            [black, white] => (black, white, "1"),
+            [black, white, gamma] => (black, white, gamma),
+            _ => return Err(invalid()),
+        };
+        match (
+            black.parse::<u8>(),
+            white.parse::<u8>(),
+            gamma.parse::<f32>(),
+        ) {
+            (Ok(black), Ok(white), Ok(gamma))
+                if black < white && gamma > 0.0 && gamma.is_finite() =>
+            {
+                Ok(Levels {
+                    black,
+                    white,
+                    gamma,
+                })
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for Levels {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{},{}", self.black, self.white, self.gamma)
+    }
+}
+
+impl Levels {
+    /// Remap one channel value, given as a fraction of full scale (0-1)
+    pub fn map(&self, value: f32) -> f32 {
+        let black = f32::from(self.black) / 255.0;
+        let white = f32::from(self.white) / 255.0;
+        let stretched = ((value - black) / (white - black)).clamp(0.0, 1.0);
+        stretched.powf(1.0 / self.gamma)
+    }
+
+    /// Apply the adjustment to every color channel, leaving alpha alone
+    ///
+    /// 16-bit images stay 16-bit; everything else comes back as 8-bit RGBA.
+    fn apply(&self, image: &DynamicImage) -> DynamicImage {
+        if is_high_precision(image) {
+            let mut rgba = image.to_rgba16();
+            for pixel in rgba.pixels_mut() {
+                for channel in &mut pixel.0[..3] {
+                    let value = self.map(f32::from(*channel) / 65535.0);
+                    *channel = (value * 65535.0).round() as u16;
+                }
+            }
+            DynamicImage::ImageRgba16(rgba)
+        } else {
+            let table: Vec<u8> = (0..=255u8)
+                .map(|value| (self.map(f32::from(value) / 255.0) * 255.0).round() as u8)
+                .collect();
+            let mut rgba = image.to_rgba8();
+            for pixel in rgba.pixels_mut() {
+                for channel in &mut pixel.0[..3] {
+                    *channel = table[*channel as usize];
+                }
+            }
+            DynamicImage::ImageRgba8(rgba)
+        }
+    }
+}
+
+impl Filter {
+    /// Apply this filter to an image, returning the adjusted copy
+    pub fn apply(&self, image: &DynamicImage) -> DynamicImage {
+        match *self {
+            Filter::Brightness(amount) if is_high_precision(image) => {
+                image.brighten(amount.saturating_mul(257))
+            }
+            Filter::Brightness(amount) => image.brighten(amount),
+            Filter::Contrast(amount) => image.adjust_contrast(amount),
+            Filter::Negate => {
+                let mut negated = image.clone();
+                negated.invert();
+                negated
+            }
+            Filter::Levels(levels) => levels.apply(image),
+        }
+    }
+}
+
+/// Apply a chain of filters in order
+pub fn apply_filters(image: DynamicImage, filters: &[Filter]) -> DynamicImage {
+    filters
+        .iter()
+        .fold(image, |current, filter| filter.apply(&current))
+}
+
+/// Noise reduction applied to the full-resolution image before resizing
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Denoise {
+    /// Gaussian blur with this standard deviation, in source pixels
+    Gaussian(f32),
+    /// Median of the square window of this radius around each pixel, which
+    /// removes speckles while keeping edges sharp
+    Median(u32),
+    /// Edge-preserving smoothing: neighbors are averaged with Gaussian weights
+    /// over distance (`spatial`, in source pixels) and over color difference
+    /// (`range`, in 8-bit levels), so texture flattens but edges stay sharp
+    Bilateral { spatial: f32, range: f32 },
+}
+
+/// Default spatial and range sigmas of the bilateral filter
+const BILATERAL_SPATIAL: f32 = 3.0;
+const BILATERAL_RANGE: f32 = 30.0;
+
+/// Largest blur sigma accepted, in source pixels; the bilateral window
+/// grows with it, so far larger values would take practically forever
+const MAX_DENOISE_SIGMA: f32 = 100.0;
+/// Largest bilateral range sigma, beyond which every color difference
+/// weighs the same anyway
+const MAX_BILATERAL_RANGE: f32 = 1000.0;
+/// Largest median radius, matching the largest blur sigma
+const MAX_MEDIAN_RADIUS: u32 = 100;
+
+impl FromStr for Denoise {
+    type Err = AsciiArtError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            AsciiArtError::InvalidArgument(format!(
+                "Unknown denoise filter '{}'. Use 'gaussian[:SIGMA]', 'median[:RADIUS]', or \
+                 'bilateral[:SPATIAL[:RANGE]]', with SIGMA and SPATIAL up to {}, RADIUS \
+                 up to {}, and RANGE up to {}.",
+                value, MAX_DENOISE_SIGMA, MAX_MEDIAN_RADIUS, MAX_BILATERAL_RANGE
+            ))
+        };
+        let (name, amount) = match value.split_once(':') {
+            Some((name, amount)) => (name, Some(amount)),
+            None => (value, None),
+        };
+        let bounded =
+            |value: &str, max: f32| value.parse::<f32>().ok().filter(|s| *s > 0.0 && *s <= max);
+        let sigma = |value: &str| bounded(value, MAX_DENOISE_SIGMA);
+        match name {
+            "gaussian" => match amount.map(sigma) {
+                None => Ok(Denoise::Gaussian(1.0)),
+                Some(Some(sigma)) => Ok(Denoise::Gaussian(sigma)),
+                Some(None) => Err(invalid()),
+            },
+            "median" => match amount.map(str::parse::<u32>) {
+                None => Ok(Denoise::Median(1)),
+                Some(Ok(radius)) if (1..=MAX_MEDIAN_RADIUS).contains(&radius) => {
+                    Ok(Denoise::Median(radius))
+                }
+                Some(_) => Err(invalid()),
+            },
+            "bilateral" => {
+                let (spatial, range) = match amount.map(|amount| amount.split_once(':')) {
+                    None => (Some(BILATERAL_SPATIAL), Some(BILATERAL_RANGE)),
+                    Some(None) => (amount.and_then(sigma), Some(BILATERAL_RANGE)),
+                    Some(Some((spatial, range))) => {
+                        (sigma(spatial), bounded(range, MAX_BILATERAL_RANGE))
+                    }
+                };
+                match (spatial, range) {
+                    (Some(spatial), Some(range)) => Ok(Denoise::Bilateral { spatial, range }),
+                    _ => Err(invalid()),
+                }
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for Denoise {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Denoise::Gaussian(sigma) => write!(f, "gaussian:{sigma}"),
+            Denoise::Median(radius) => write!(f, "median:{radius}"),
+            Denoise::Bilateral { spatial, range } => write!(f, "bilateral:{spatial}:{range}"),
+        }
+    }
+}
+
+impl Denoise {
+    /// Smooth an image, returning the filtered copy
+    ///
+    /// The median and bilateral filters work on 8-bit channels, so they reduce
+    /// 16-bit images to 8 bits; the median filter needs the `edge` feature,
+    /// which provides imageproc.
+    pub fn apply(&self, image: &DynamicImage) -> Result<DynamicImage, AsciiArtError> {
+        match *self {
+            Denoise::Gaussian(sigma) => Ok(image.blur(sigma)),
+            Denoise::Median(radius) => median(image, radius),
+            Denoise::Bilateral { spatial, range } => Ok(DynamicImage::ImageRgba8(bilateral(
+                &image.to_rgba8(),
+                spatial,
+                range,
+            ))),
+        }
+    }
+}
+
+#[cfg(feature = "edge")]
+fn median(image: &DynamicImage, radius: u32) -> Result<DynamicImage, AsciiArtError> {
+    use imageproc::filter::median_filter;
+
+    Ok(match image {
+        DynamicImage::ImageLuma8(gray) => {
+            DynamicImage::ImageLuma8(median_filter(gray, radius, radius))
+        }
+        DynamicImage::ImageRgb8(rgb) => DynamicImage::ImageRgb8(median_filter(rgb, radius, radius)),
+        other => DynamicImage::ImageRgba8(median_filter(&other.to_rgba8(), radius, radius)),
+    })
+}
+
+#[cfg(not(feature = "edge"))]
+fn median(_image: &DynamicImage, _radius: u32) -> Result<DynamicImage, AsciiArtError> {
+    Err(AsciiArtError::FeatureDisabled {
+        what: "Median denoising",
+        feature: "edge",
+    })
+}
+
+/// Fast approximate bilateral filter: a horizontal and then a vertical
+/// one-dimensional pass
+///
+/// The exact filter weighs a whole square window per pixel; two passes cost
+/// a few dozen neighbors instead and look nearly the same, since the range
+/// weight still stops smoothing at edges in either direction. Colors are
+/// compared by their mean absolute channel difference.
+fn bilateral(image: &RgbaImage, spatial: f32, range: f32) -> RgbaImage {
+    let radius = (spatial * 2.0).ceil() as i64;
+    let spatial_weights: Vec<f32> = (-radius..=radius)
+        .map(|d| (-((d * d) as f32) / (2.0 * spatial * spatial)).exp())
+        .collect();
+    let range_weights: Vec<f32> = (0..=255)
+        .map(|d: i32| (-((d * d) as f32) / (2.0 * range * range)).exp())
+        .collect();
+
+    let pass = |source: &RgbaImage, horizontal: bool| {
+        let (width, height) = source.dimensions();
+        let rows = map_rows(height as usize, |y| {
+            let y = y as i64;
+            let mut row = Vec::with_capacity(width as usize * 4);
+            for x in 0..width as i64 {
+                let center = source.get_pixel(x as u32, y as u32).0;
+                let mut sum = [0f32; 4];
+                let mut total = 0f32;
+                for d in -radius..=radius {
+                    let (nx, ny) = if horizontal { (x + d, y) } else { (x, y + d) };
+                    if nx < 0 || ny < 0 || nx >= width as i64 || ny >= height as i64 {
+                        continue;
+                    }
+                    let neighbor = source.get_pixel(nx as u32, ny as u32).0;
+                    let difference: i32 = (0..3)
+                        .map(|c| (center[c] as i32 - neighbor[c] as i32).abs())
+                        .sum();
+                    let weight = spatial_weights[(d + radius) as usize]
+                        * range_weights[(difference / 3) as usize];
+                    for c in 0..4 {
+                        sum[c] += f32::from(neighbor[c]) * weight;
+                    }
+                    total += weight;
+                }
+                row.extend(sum.map(|value| (value / total).round().clamp(0.0, 255.0) as u8));
+            }
+            row
+        });
+        RgbaImage::from_raw(width, height, rows.concat()).expect("rows fill the image")
+    };
+    pass(&pass(image, true), false)
+}
+
+/// Contrast-limited adaptive histogram equalization (CLAHE)
+///
+/// The image is split into a grid of tiles, each equalized on its own so
+/// bright skies and dark shadows both get the full range. Clipping the tile
+/// histograms limits how much flat areas (and their noise) are stretched.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Clahe {
+    /// Tiles along each side of the image
+    pub tiles: u32,
+    /// Histogram bins are clipped at this multiple of the average bin count;
+    /// 1.0 leaves the image nearly unchanged, larger values equalize harder
+    pub clip_limit: f32,
+}
+
+pub const DEFAULT_CLAHE_TILES: u32 = 8;
+pub const DEFAULT_CLAHE_CLIP_LIMIT: f32 = 2.0;
+
+impl Default for Clahe {
+    fn default() -> Self {
+        Self {
+            tiles: DEFAULT_CLAHE_TILES,
+            clip_limit: DEFAULT_CLAHE_CLIP_LIMIT,
+        }
+    }
+}
+
+impl Clahe {
+    /// Equalize a grayscale image, blending the mappings of the four nearest
+    /// tiles at every pixel so tile borders do not show
+    pub fn apply(&self, image: &GrayImage) -> GrayImage {
+        let (width, height) = image.dimensions();
+        if width == 0 || height == 0 {
+            return image.clone();
+        }
+        let tiles_x = self.tiles.clamp(1, width);
+        let tiles_y = self.tiles.clamp(1, height);
+
+        let luts: Vec<[u8; 256]> = (0..tiles_y)
+            .flat_map(|ty| (0..tiles_x).map(move |tx| (tx, ty)))
+            .map(|(tx, ty)| {
+                let x0 = tx * width / tiles_x;
+                let y0 = ty * height / tiles_y;
+                let x1 = (tx + 1) * width / tiles_x;
+                let y1 = (ty + 1) * height / tiles_y;
+                let mut histogram = [0u32; 256];
+                for y in y0..y1 {
+                    for x in x0..x1 {
+                        histogram[image.get_pixel(x, y)[0] as usize] += 1;
+                    }
+                }
+                self.tile_lut(histogram, (x1 - x0) * (y1 - y0))
+            })
+            .collect();
+
+        // Position of a pixel between tile centers, as (lower tile, weight of the upper one)
+        let locate = |position: u32, size: u32, count: u32| {
+            let center = (position as f32 + 0.5) * count as f32 / size as f32 - 0.5;
+            let lower = center.floor().clamp(0.0, (count - 1) as f32);
+            let weight = (center - lower).clamp(0.0, 1.0);
+            (lower as u32, weight)
+        };
+        GrayImage::from_fn(width, height, |x, y| {
+            let (tx, wx) = locate(x, width, tiles_x);
+            let (ty, wy) = locate(y, height, tiles_y);
+            let tx1 = (tx + 1).min(tiles_x - 1);
+            let ty1 = (ty + 1).min(tiles_y - 1);
+            let value = image.get_pixel(x, y)[0] as usize;
+            let at = |tx: u32, ty: u32| f32::from(luts[(ty * tiles_x + tx) as usize][value]);
+            let top = at(tx, ty) * (1.0 - wx) + at(tx1, ty) * wx;
+            let bottom = at(tx, ty1) * (1.0 - wx) + at(tx1, ty1) * wx;
+            Luma([(top * (1.0 - wy) + bottom * wy).round() as u8])
+        })
+    }
+
+    /// The equalizing map of one tile, after clipping its histogram and
+    /// spreading the clipped counts evenly over all levels
+    fn tile_lut(&self, mut histogram: [u32; 256], pixels: u32) -> [u8; 256] {
+        let limit = ((self.clip_limit * pixels as f32 / 256.0).ceil() as u32).max(1);
+        let mut excess = 0;
+        for count in &mut histogram {
+            excess += count.saturating_sub(limit);
+            *count = (*count).min(limit);
+        }
+        let (share, remainder) = (excess / 256, excess % 256);
+        for (level, count) in histogram.iter_mut().enumerate() {
+            *count += share + u32::from((level as u32) < remainder);
+        }
+
+        let mut lut = [0u8; 256];
+        let mut cumulative = 0u32;
+        for (level, count) in histogram.iter().enumerate() {
+            cumulative += count;
+            lut[level] = (cumulative as f32 * 255.0 / pixels as f32)
+                .round()
+                .min(255.0) as u8;
+        }
+        lut
+    }
+}
+
+/// Blur radius (Gaussian sigma, in cells) of the unsharp mask
+const UNSHARP_SIGMA: f32 = 1.0;
+
+/// Sharpen an image with an unsharp mask
+///
+/// Each channel moves away from a blurred copy of the image by `amount` times
+/// the difference, so 1.0 doubles the local contrast of fine detail.
+pub fn unsharp_mask<P>(image: &ImageBuffer<P, Vec<u8>>, amount: f32) -> ImageBuffer<P, Vec<u8>>
+where
+    P: Pixel<Subpixel = u8> + 'static,
+{
+    let blurred = imageops::blur(image, UNSHARP_SIGMA);
+    let mut sharpened = image.clone();
+    for (value, &soft) in sharpened.iter_mut().zip(blurred.iter()) {
+        let detail = f32::from(*value) - f32::from(soft);
+        *value = (f32::from(*value) + amount * detail)
+            .round()
+            .clamp(0.0, 255.0) as u8;
+    }
+    sharpened
+}
+
+/// Stylized color transform applied to each cell's sampled color
+///
+/// These run on the cell colors before quantization, theming, and rendering,
+/// so every output format sees the same colors. Several can be chained.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum ColorFilter {
+    /// Brownish tones of an old photograph
+    Sepia,
+    /// Shift colors toward blue
+    Cool,
+    /// Shift colors toward orange
+    Warm,
+    /// High-contrast black and white
+    Noir,
+}
+
+impl FromStr for ColorFilter {
+    type Err = AsciiArtError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "sepia" => Ok(ColorFilter::Sepia),
+            "cool" => Ok(ColorFilter::Cool),
+            "warm" => Ok(ColorFilter::Warm),
+            "noir" => Ok(ColorFilter::Noir),
+            unknown => Err(AsciiArtError::InvalidArgument(format!(
+                "Unknown filter '{}'. Use 'sepia', 'cool', 'warm', or 'noir'.",
+                unknown
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for ColorFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ColorFilter::Sepia => write!(f, "sepia"),
+            ColorFilter::Cool => write!(f, "cool"),
+            ColorFilter::Warm => write!(f, "warm"),
+            ColorFilter::Noir => write!(f, "noir"),
+        }
+    }
+}
+
+impl ColorFilter {
+    /// Transform one color
+    pub fn apply(self, rgb: [u8; 3]) -> [u8; 3] {
+        let [r, g, b] = rgb.map(f32::from);
+        let mixed = match self {
+            // The classic sepia matrix
+            ColorFilter::Sepia => [
+                0.393 * r + 0.769 * g + 0.189 * b,
+                0.349 * r + 0.686 * g + 0.168 * b,
+                0.272 * r + 0.534 * g + 0.131 * b,
+            ],
+            ColorFilter::Cool => [0.85 * r, 0.97 * g, 1.15 * b + 8.0],
+            ColorFilter::Warm => [1.12 * r + 8.0, 1.02 * g, 0.85 * b],
+            ColorFilter::Noir => {
+                let level = (luma(rgb) - 128.0) * 1.5 + 128.0;
+                [level; 3]
+            }
+        };
+        mixed.map(|value| value.round().clamp(0.0, 255.0) as u8)
+    }
+}
+
+/// Run a color through a chain of color filters in order
+pub fn apply_color_filters(rgb: [u8; 3], filters: &[ColorFilter]) -> [u8; 3] {
+    filters
+        .iter()
+        .fold(rgb, |current, filter| filter.apply(current))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GenericImageView, Rgb, Rgba};
+
+    #[test]
+    fn brightness_filter_shifts_channels() {
+        let image = DynamicImage::ImageRgb8(ImageBuffer::from_pixel(2, 2, Rgb([100, 100, 100])));
+        let adjusted = apply_filters(image, &[Filter::Brightness(50)]);
+        assert_eq!(adjusted.get_pixel(0, 0)[0], 150);
+    }
+
+    #[test]
+    fn brightness_filter_scales_for_16_bit_images() {
+        let image = DynamicImage::ImageRgb16(ImageBuffer::from_pixel(2, 2, Rgb([100 * 257; 3])));
+        let adjusted = apply_filters(image, &[Filter::Brightness(50)]);
+        assert_eq!(adjusted.color(), image::ColorType::Rgb16);
+        assert_eq!(adjusted.to_rgb8().get_pixel(0, 0)[0], 150);
+    }
+
+    #[test]
+    fn negate_filter_inverts_colors_but_not_alpha() {
+        let image =
+            DynamicImage::ImageRgba8(ImageBuffer::from_pixel(2, 2, Rgba([10, 200, 30, 77])));
+        let adjusted = apply_filters(image, &[Filter::Negate]);
+        assert_eq!(adjusted.get_pixel(1, 1), Rgba([245, 55, 225, 77]));
+    }
+
+    #[test]
+    fn unsharp_mask_boosts_edges_and_keeps_flat_areas() {
+        let image = ImageBuffer::from_fn(8, 1, |x, _| Luma([if x < 4 { 100u8 } else { 150 }]));
+        let sharpened = unsharp_mask(&image, 1.0);
+        assert!(sharpened.get_pixel(3, 0)[0] < 100);
+        assert!(sharpened.get_pixel(4, 0)[0] > 150);
+        assert_eq!(unsharp_mask(&image, 0.0), image);
+
+        let flat = ImageBuffer::from_pixel(8, 8, Rgb([30u8, 60, 90]));
+        assert_eq!(unsharp_mask(&flat, 2.0), flat);
+    }
+
+    #[test]
+    fn color_filters_transform_and_chain() {
+        assert_eq!(ColorFilter::Sepia.apply([100, 100, 100]), [135, 120, 94]);
+        assert_eq!(ColorFilter::Noir.apply([200, 200, 200]), [236, 236, 236]);
+        assert_eq!(ColorFilter::Noir.apply([20, 20, 20]), [0, 0, 0]);
+
+        let [r, _, b] = ColorFilter::Warm.apply([100, 100, 100]);
+        assert!(r > b);
+        let [r, _, b] = ColorFilter::Cool.apply([100, 100, 100]);
+        assert!(b > r);
+
+        let chained = apply_color_filters([0, 0, 255], &[ColorFilter::Noir, ColorFilter::Sepia]);
+        assert_eq!(
+            chained,
+            ColorFilter::Sepia.apply(ColorFilter::Noir.apply([0, 0, 255]))
+        );
+        assert_eq!(apply_color_filters([1, 2, 3], &[]), [1, 2, 3]);
+    }
+
+    #[cfg(feature = "cli")]
+    #[test]
+    fn color_filter_round_trips_through_strings() {
+        use clap::ValueEnum;
+
+        for filter in ColorFilter::value_variants() {
+            assert_eq!(filter.to_string().parse::<ColorFilter>().unwrap(), *filter);
+        }
+        assert!("vintage".parse::<ColorFilter>().is_err());
+    }
+
+    #[test]
+    fn denoise_parses_and_round_trips() {
+        assert_eq!(
+            "gaussian".parse::<Denoise>().unwrap(),
+            Denoise::Gaussian(1.0)
+        );
+        assert_eq!(
+            "gaussian:2.5".parse::<Denoise>().unwrap(),
+            Denoise::Gaussian(2.5)
+        );
+        assert_eq!("median".parse::<Denoise>().unwrap(), Denoise::Median(1));
+        assert_eq!("median:3".parse::<Denoise>().unwrap(), Denoise::Median(3));
+        assert_eq!(
+            "bilateral".parse::<Denoise>().unwrap(),
+            Denoise::Bilateral {
+                spatial: 3.0,
+                range: 30.0
+            }
+        );
+        assert_eq!(
+            "bilateral:2:15".parse::<Denoise>().unwrap(),
+            Denoise::Bilateral {
+                spatial: 2.0,
+                range: 15.0
+            }
+        );
+        for invalid in [
+            "median:0",
+            "gaussian:-1",
+            "median:x",
+            "median:100000",
+            "bilateral:0",
+            "bilateral:1e9",
+            "bilateral:2:1e9",
+            "gaussian:inf",
+            "sharpen",
+        ] {
+            assert!(invalid.parse::<Denoise>().is_err(), "{invalid}");
+        }
+        for denoise in [
+            Denoise::Gaussian(0.5),
+            Denoise::Median(2),
+            Denoise::Bilateral {
+                spatial: 1.5,
+                range: 20.0,
+            },
+        ] {
+            assert_eq!(denoise.to_string().parse::<Denoise>().unwrap(), denoise);
+        }
+    }
+
+    #[cfg(feature = "edge")]
+    #[test]
+    fn median_denoise_removes_speckles() {
+        let mut image = ImageBuffer::from_pixel(5, 5, Rgb([40u8, 40, 40]));
+        image.put_pixel(2, 2, Rgb([255, 255, 255]));
+        let denoised = Denoise::Median(1)
+            .apply(&DynamicImage::ImageRgb8(image))
+            .expect("median filter");
+        assert_eq!(denoised.get_pixel(2, 2), Rgba([40, 40, 40, 255]));
+    }
+
+    #[test]
+    fn bilateral_denoise_flattens_texture_but_keeps_edges() {
+        // Low-contrast texture on either side of a hard edge
+        let image = ImageBuffer::from_fn(16, 16, |x, y| {
+            let base = if x < 8 { 40 } else { 200 };
+            let texture = if (x + y) % 2 == 0 { 6 } else { 0 };
+            Rgba([base + texture, base + texture, base + texture, 255])
+        });
+        let smoothed = bilateral(&image, 2.0, 20.0);
+
+        let spread = |x0: u32, x1: u32| {
+            let values: Vec<u8> = (x0..x1)
+                .flat_map(|x| (0..16).map(move |y| (x, y)))
+                .map(|(x, y)| smoothed.get_pixel(x, y)[0])
+                .collect();
+            values.iter().max().unwrap() - values.iter().min().unwrap()
+        };
+        assert!(spread(0, 8) < 6, "texture left on the dark side");
+        assert!(spread(8, 16) < 6, "texture left on the bright side");
+        assert!(smoothed.get_pixel(7, 8)[0] < 60);
+        assert!(smoothed.get_pixel(8, 8)[0] > 180);
+    }
+
+    #[test]
+    fn clahe_stretches_each_region_on_its_own() {
+        // A dark half and a bright half, each with faint detail
+        let image = GrayImage::from_fn(64, 64, |x, y| {
+            let base = if x < 32 { 20 } else { 220 };
+            Luma([base + ((x + y) % 4) as u8 * 3])
+        });
+        let equalized = Clahe {
+            tiles: 4,
+            clip_limit: 40.0,
+        }
+        .apply(&image);
+
+        let range = |x0: u32, x1: u32, image: &GrayImage| {
+            let values: Vec<u8> = (x0..x1)
+                .flat_map(|x| (0..64).map(move |y| (x, y)))
+                .map(|(x, y)| image.get_pixel(x, y)[0])
+                .collect();
+            values.iter().max().unwrap() - values.iter().min().unwrap()
+        };
+        assert!(range(0, 16, &equalized) > 3 * range(0, 16, &image));
+        assert!(range(48, 64, &equalized) > 3 * range(48, 64, &image));
+
+        let flat = GrayImage::from_pixel(8, 8, Luma([90]));
+        let unchanged = Clahe::default().apply(&flat);
+        assert!(unchanged
+            .pixels()
+            .all(|pixel| pixel == unchanged.get_pixel(0, 0)));
+    }
+
+    #[test]
+    fn clahe_handles_sizes_that_do_not_divide_into_tiles() {
+        let image = GrayImage::from_fn(10, 20, |x, y| Luma([(x * 20 + y) as u8]));
+        let equalized = Clahe::default().apply(&image);
+        assert_eq!(equalized.dimensions(), (10, 20));
+        assert!(equalized.get_pixel(0, 0)[0] < equalized.get_pixel(9, 19)[0]);
+    }
+
+    #[test]
+    fn levels_remap_the_input_range() {
+        let levels: Levels = "50,200".parse().unwrap();
+        assert_eq!(
+            levels,
+            Levels {
+                black: 50,
+                white: 200,
+                gamma: 1.0
+            }
+        );
+        let image = DynamicImage::ImageRgb8(ImageBuffer::from_fn(3, 1, |x, _| {
+            Rgb([[40, 125, 210][x as usize]; 3])
+        }));
+        let adjusted = apply_filters(image, &[Filter::Levels(levels)]);
+        assert_eq!(adjusted.get_pixel(0, 0)[0], 0);
+        assert!(adjusted.get_pixel(1, 0)[0].abs_diff(128) <= 1);
+        assert_eq!(adjusted.get_pixel(2, 0)[0], 255);
+
+        let brighter: Levels = "0,255,2.0".parse().unwrap();
+        assert!(brighter.map(0.25) > 0.25);
+        assert_eq!(brighter.to_string().parse::<Levels>().unwrap(), brighter);
+        for invalid in ["200,50", "0", "0,255,0", "0,256", "a,b"] {
+            assert!(invalid.parse::<Levels>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn empty_filter_chain_is_identity() {
+        let image = DynamicImage::ImageRgb8(ImageBuffer::from_pixel(2, 2, Rgb([10, 20, 30])));
+        let adjusted = apply_filters(image.clone(), &[]);
+        assert_eq!(adjusted, image);
+    }
+}
@@ -0,0 +1,48 @@
+//! Built-in 8x8 bitmap font, used to measure how much ink each character
+//! puts on screen
+
+use font8x8::{UnicodeFonts, BASIC_FONTS, BLOCK_FONTS, BOX_FONTS, GREEK_FONTS, LATIN_FONTS};
+
+/// Width and height of a glyph in pixels
+pub const GLYPH_SIZE: u32 = 8;
+
+/// The bitmap of `ch`, one byte per row from the top with the leftmost pixel
+/// in the lowest bit, or `None` if the font has no glyph for it
+///
+/// Covers ASCII, Latin-1, Greek, box drawing, and block elements.
+pub fn glyph(ch: char) -> Option<[u8; 8]> {
+    BASIC_FONTS
+        .get(ch)
+        .or_else(|| LATIN_FONTS.get(ch))
+        .or_else(|| BLOCK_FONTS.get(ch))
+        .or_else(|| BOX_FONTS.get(ch))
+        .or_else(|| GREEK_FONTS.get(ch))
+}
+
+/// Fraction (0-1) of the glyph's pixels that `ch` inks
+pub fn ink_coverage(ch: char) -> Option<f32> {
+    let rows = glyph(ch)?;
+    let inked: u32 = rows.iter().map(|row| row.count_ones()).sum();
+    Some(inked as f32 / (GLYPH_SIZE * GLYPH_SIZE) as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coverage_spans_blank_to_solid() {
+        assert_eq!(ink_coverage(' '), Some(0.0));
+        assert_eq!(ink_coverage('█'), Some(1.0));
+        let (dot, at) = (ink_coverage('.').unwrap(), ink_coverage('@').unwrap());
+        assert!(0.0 < dot && dot < at, "{dot} {at}");
+        assert!(ink_coverage('░').unwrap() < ink_coverage('▓').unwrap());
+        assert_eq!(ink_coverage('😀'), None);
+    }
+
+    #[test]
+    fn letters_outside_the_font_have_no_coverage() {
+        assert_eq!(ink_coverage('Ж'), None);
+        assert!(glyph('Ж').is_none());
+    }
+}
@@ -0,0 +1,304 @@
+use std::fmt::{self, Write as _};
+use std::io::{self, Cursor};
+use std::str::FromStr;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine as _;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageOutputFormat, RgbImage};
+
+use crate::error::AsciiArtError;
+use crate::image_loader::DEFAULT_MAX_DIMENSION;
+use crate::renderer::{ansi256_rgb, ColorDepth, ColorLut, RenderOptions};
+
+/// How the image is drawn in the terminal
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum RenderMode {
+    /// Colored character art
+    #[default]
+    Ascii,
+    /// Sixel bitmap graphics (xterm, foot, mlterm, WezTerm)
+    Sixel,
+    /// Inline image through the iTerm2 or kitty protocol, in full color
+    Image,
+}
+
+impl FromStr for RenderMode {
+    type Err = AsciiArtError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "ascii" => Ok(RenderMode::Ascii),
+            "sixel" => Ok(RenderMode::Sixel),
+            "image" => Ok(RenderMode::Image),
+            unknown => Err(AsciiArtError::InvalidArgument(format!(
+                "Unknown render mode '{}'. Use 'ascii', 'sixel', or 'image'.",
+                unknown
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for RenderMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenderMode::Ascii => write!(f, "ascii"),
+            RenderMode::Sixel => write!(f, "sixel"),
+            RenderMode::Image => write!(f, "image"),
+        }
+    }
+}
+
+/// Terminal graphics protocol used to draw a bitmap
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    /// DEC sixel bands, quantized to 256 colors
+    Sixel,
+    /// The kitty graphics protocol, carrying a PNG
+    Kitty,
+    /// iTerm2 inline images (OSC 1337), carrying a PNG
+    Iterm2,
+}
+
+impl Protocol {
+    /// Escape sequence drawing `image` across `columns` terminal cells
+    pub fn encode(self, image: &RgbImage, columns: u32) -> Result<String, AsciiArtError> {
+        match self {
+            Protocol::Sixel => Ok(encode_sixel(image)),
+            Protocol::Kitty => encode_kitty(image, columns),
+            Protocol::Iterm2 => encode_iterm2(image, columns),
+        }
+    }
+}
+
+/// Largest base64 payload kitty accepts in a single escape sequence
+const KITTY_CHUNK: usize = 4096;
+
+/// Encode an image as kitty graphics escapes, scaled to span `columns` cells
+///
+/// The image is sent as PNG and split into chunks; every chunk but the last
+/// carries `m=1` to tell the terminal more data follows.
+pub fn encode_kitty(image: &RgbImage, columns: u32) -> Result<String, AsciiArtError> {
+    let payload = BASE64.encode(encode_png(image)?);
+    let chunks: Vec<&[u8]> = payload.as_bytes().chunks(KITTY_CHUNK).collect();
+
+    let mut out = String::with_capacity(payload.len() + chunks.len() * 16);
+    for (index, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(index + 1 < chunks.len());
+        if index == 0 {
+            let _ = write!(out, "\x1b_Ga=T,f=100,c={columns},m={more};");
+        } else {
+            let _ = write!(out, "\x1b_Gm={more};");
+        }
+        // Base64 output is always ASCII
+        out.push_str(std::str::from_utf8(chunk).unwrap_or_default());
+        out.push_str("\x1b\\");
+    }
+    Ok(out)
+}
+
+/// Encode an image as an iTerm2 inline image, scaled to span `columns` cells
+///
+/// Unlike kitty, the whole PNG travels in one OSC 1337 sequence.
+pub fn encode_iterm2(image: &RgbImage, columns: u32) -> Result<String, AsciiArtError> {
+    let png = encode_png(image)?;
+    Ok(format!(
+        "\x1b]1337;File=inline=1;size={};width={columns};preserveAspectRatio=1:{}\x07",
+        png.len(),
+        BASE64.encode(&png)
+    ))
+}
+
+fn encode_png(image: &RgbImage) -> Result<Vec<u8>, AsciiArtError> {
+    let mut png = Vec::new();
+    DynamicImage::ImageRgb8(image.clone())
+        .write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)
+        .map_err(|err| {
+            AsciiArtError::Render(io::Error::other(format!("Could not encode PNG: {err}")))
+        })?;
+    Ok(png)
+}
+
+/// Resize an image to span `columns` terminal cells of `cell_size` pixels
+///
+/// Unlike the character grid, pixels are square, so the aspect ratio is kept
+/// as-is instead of halving the height.
+pub fn resize_for_cells(image: &DynamicImage, columns: u32, cell_size: (u32, u32)) -> RgbImage {
+    let width = columns.max(1) * cell_size.0.max(1);
+    image
+        .resize_exact(width, scaled_height(image, width), FilterType::Triangle)
+        .to_rgb8()
+}
+
+/// Height in pixels of `image` scaled to `width` pixels, capped at
+/// [`DEFAULT_MAX_DIMENSION`] so a sliver of an image cannot grow without bound
+pub fn scaled_height(image: &DynamicImage, width: u32) -> u32 {
+    let height = (image.height() as u64 * width as u64) / image.width().max(1) as u64;
+    height.clamp(1, DEFAULT_MAX_DIMENSION as u64) as u32
+}
+
+/// Encode an image as a sixel sequence using the xterm 256-color palette
+///
+/// Colors are posterized to the 256-color cube and grayscale ramp, and runs of
+/// identical columns are run-length encoded.
+pub fn encode_sixel(image: &RgbImage) -> String {
+    let (width, height) = image.dimensions();
+    let options = RenderOptions {
+        color_depth: ColorDepth::Ansi256,
+        ..RenderOptions::default()
+    };
+    let lut = ColorLut::shared(&options);
+    let indices: Vec<u8> = image
+        .pixels()
+        .map(|pixel| {
+            let [r, g, b] = pixel.0;
+            lut.index(r, g, b).unwrap_or(16)
+        })
+        .collect();
+
+    let mut used = [false; 256];
+    for &index in &indices {
+        used[index as usize] = true;
+    }
+
+    // Writing into a String cannot fail
+    let mut out = String::new();
+    let _ = write!(out, "\x1bPq\"1;1;{width};{height}");
+    for index in (0..=255u8).filter(|&index| used[index as usize]) {
+        let [r, g, b] = ansi256_rgb(index).map(|c| c as u32 * 100 / 255);
+        let _ = write!(out, "#{index};2;{r};{g};{b}");
+    }
+
+    let width = width as usize;
+    for band_top in (0..height as usize).step_by(6) {
+        let band_rows = (height as usize - band_top).min(6);
+        let band = &indices[band_top * width..(band_top + band_rows) * width];
+
+        let mut in_band = [false; 256];
+        for &index in band {
+            in_band[index as usize] = true;
+        }
+
+        let mut first = true;
+        for color in (0..=255u8).filter(|&color| in_band[color as usize]) {
+            if !first {
+                // Return to the start of the band for the next color
+                out.push('$');
+            }
+            first = false;
+            let _ = write!(out, "#{color}");
+
+            let mut run: Option<(char, usize)> = None;
+            for x in 0..width {
+                let bits = (0..band_rows)
+                    .filter(|&dy| band[dy * width + x] == color)
+                    .fold(0u8, |bits, dy| bits | (1 << dy));
+                let ch = (63 + bits) as char;
+                run = match run {
+                    Some((current, count)) if current == ch => Some((ch, count + 1)),
+                    Some((current, count)) => {
+                        push_run(&mut out, current, count);
+                        Some((ch, 1))
+                    }
+                    None => Some((ch, 1)),
+                };
+            }
+            if let Some((ch, count)) = run {
+                push_run(&mut out, ch, count);
+            }
+        }
+        out.push('-');
+    }
+
+    out.push_str("\x1b\\");
+    out
+}
+
+/// Append `count` copies of a sixel character, using `!` repeats for long runs
+fn push_run(out: &mut String, ch: char, count: usize) {
+    if count >= 4 {
+        let _ = write!(out, "!{count}{ch}");
+    } else {
+        for _ in 0..count {
+            out.push(ch);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+
+    #[test]
+    fn encodes_single_pixel() {
+        let image = RgbImage::from_pixel(1, 1, Rgb([255, 0, 0]));
+        assert_eq!(
+            encode_sixel(&image),
+            "\x1bPq\"1;1;1;1#196;2;100;0;0#196@-\x1b\\"
+        );
+    }
+
+    #[test]
+    fn run_length_encodes_bands() {
+        // 8 columns, 7 rows: one full band plus a single-row band
+        let image = RgbImage::from_pixel(8, 7, Rgb([0, 0, 0]));
+        let sixel = encode_sixel(&image);
+        assert!(sixel.contains("#16!8~-#16!8@-"), "{sixel}");
+    }
+
+    #[test]
+    fn kitty_output_is_chunked_png() {
+        let image = RgbImage::from_fn(64, 64, |x, y| Rgb([(x * 4) as u8, (y * 4) as u8, 7]));
+        let kitty = encode_kitty(&image, 12).expect("encodes");
+        assert!(kitty.starts_with("\x1b_Ga=T,f=100,c=12,m="));
+        assert!(kitty.ends_with("\x1b\\"));
+
+        let payload: String = kitty
+            .split("\x1b_G")
+            .skip(1)
+            .map(|chunk| chunk.split_once(';').expect("has payload").1)
+            .map(|data| data.trim_end_matches("\x1b\\"))
+            .collect();
+        let png = BASE64.decode(payload).expect("valid base64");
+        let decoded = image::load_from_memory(&png).expect("valid png");
+        assert_eq!((decoded.width(), decoded.height()), (64, 64));
+    }
+
+    #[test]
+    fn iterm2_output_declares_size_and_width() {
+        let image = RgbImage::from_pixel(3, 2, Rgb([10, 20, 30]));
+        let inline = encode_iterm2(&image, 5).expect("encodes");
+        let (header, payload) = inline
+            .strip_prefix("\x1b]1337;File=")
+            .and_then(|rest| rest.strip_suffix('\x07'))
+            .and_then(|rest| rest.split_once(':'))
+            .expect("OSC 1337 envelope");
+
+        let png = BASE64.decode(payload).expect("valid base64");
+        assert_eq!(
+            header,
+            format!("inline=1;size={};width=5;preserveAspectRatio=1", png.len())
+        );
+        let decoded = image::load_from_memory(&png).expect("valid png");
+        assert_eq!((decoded.width(), decoded.height()), (3, 2));
+    }
+
+    #[test]
+    fn resize_keeps_square_pixels() {
+        let image = DynamicImage::new_rgb8(200, 100);
+        let resized = resize_for_cells(&image, 10, (8, 16));
+        assert_eq!(resized.dimensions(), (80, 40));
+
+        let sliver = DynamicImage::new_rgb8(1, 1000);
+        assert_eq!(scaled_height(&sliver, 640), DEFAULT_MAX_DIMENSION);
+    }
+
+    #[test]
+    fn render_mode_parses_names() {
+        assert_eq!("sixel".parse::<RenderMode>().ok(), Some(RenderMode::Sixel));
+        assert_eq!("image".parse::<RenderMode>().ok(), Some(RenderMode::Image));
+        assert!("png".parse::<RenderMode>().is_err());
+    }
+}
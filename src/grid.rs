@@ -0,0 +1,243 @@
+//! The `.aag` (ASCII-art grid) document: converted cells saved with a few
+//! notes on how they were made, so art can be shown again, in any color
+//! depth, without converting the image again
+//!
+//! A document starts with a text header: an `AAG 1` line, then `key value`
+//! lines, then an empty line. `width` and `height` give the size of the grid;
+//! any other keys, such as `source` and `mode`, are kept as metadata. The
+//! cells follow row by row, each as its character in UTF-8 and then five
+//! bytes: red, green, blue, brightness, and flags (bit 0 marks a transparent
+//! cell).
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+
+use unicode_width::UnicodeWidthChar;
+
+use crate::ascii_converter::{AsciiImage, Cell};
+use crate::error::AsciiArtError;
+
+/// First line of every grid document, naming the format and its version
+pub const MAGIC: &[u8] = b"AAG 1\n";
+
+/// File extension of grid documents
+pub const EXTENSION: &str = "aag";
+
+/// Flag bit of a transparent cell
+const TRANSPARENT: u8 = 1;
+
+/// Bytes stored after each cell's character
+const CELL_BYTES: usize = 5;
+
+/// A converted grid and its metadata, as stored in a `.aag` file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GridDocument {
+    pub image: AsciiImage,
+    /// Notes kept beside the cells, such as the `source` file and `mode`
+    pub metadata: BTreeMap<String, String>,
+}
+
+impl GridDocument {
+    pub fn new(image: AsciiImage) -> Self {
+        Self {
+            image,
+            metadata: BTreeMap::new(),
+        }
+    }
+
+    /// Add a note; line breaks in `value` become spaces, since each note
+    /// takes one header line
+    pub fn note(mut self, key: &str, value: impl Into<String>) -> Self {
+        let value = value.into().replace(['\r', '\n'], " ");
+        self.metadata.insert(key.to_string(), value);
+        self
+    }
+
+    /// Write the document in the `.aag` format
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writeln!(writer, "width {}", self.image.width())?;
+        writeln!(writer, "height {}", self.image.height())?;
+        for (key, value) in &self.metadata {
+            // The size is written from the grid itself
+            if !matches!(key.as_str(), "width" | "height") {
+                writeln!(writer, "{key} {value}")?;
+            }
+        }
+        writer.write_all(b"\n")?;
+        let mut utf8 = [0; 4];
+        for cell in self.image.cells() {
+            writer.write_all(cell.ch.encode_utf8(&mut utf8).as_bytes())?;
+            let flags = if cell.transparent { TRANSPARENT } else { 0 };
+            let [r, g, b] = cell.rgb;
+            writer.write_all(&[r, g, b, cell.luma, flags])?;
+        }
+        writer.flush()
+    }
+
+    /// Write the document to the file at `path`
+    pub fn save(&self, path: &Path) -> Result<(), AsciiArtError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        Ok(self.write_to(&mut writer)?)
+    }
+
+    /// Read a document from the bytes of a `.aag` file
+    pub fn parse(bytes: &[u8]) -> Result<Self, AsciiArtError> {
+        let invalid = |reason: &str| {
+            AsciiArtError::InvalidArgument(format!("Not a valid grid document: {reason}."))
+        };
+        let mut rest = bytes
+            .strip_prefix(MAGIC)
+            .ok_or_else(|| invalid("it does not start with `AAG 1`"))?;
+
+        let mut metadata = BTreeMap::new();
+        loop {
+            let end = rest
+                .iter()
+                .position(|&byte| byte == b'\n')
+                .ok_or_else(|| invalid("the header does not end"))?;
+            let line = std::str::from_utf8(&rest[..end])
+                .map_err(|_| invalid("the header is not UTF-8"))?;
+            rest = &rest[end + 1..];
+            if line.is_empty() {
+                break;
+            }
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            metadata.insert(key.to_string(), value.to_string());
+        }
+        let mut size = |key: &str| {
+            metadata
+                .remove(key)
+                .and_then(|value| value.parse::<usize>().ok())
+                .ok_or_else(|| invalid(&format!("the header has no {key}")))
+        };
+        let (width, height) = (size("width")?, size("height")?);
+
+        let count = width
+            .checked_mul(height)
+            .ok_or_else(|| invalid("the grid is too large"))?;
+        // Every cell takes at least one byte for its character
+        if count > rest.len() / (CELL_BYTES + 1) {
+            return Err(invalid("the cells are cut short"));
+        }
+        let mut cells = Vec::with_capacity(count);
+        for _ in 0..count {
+            let len = rest
+                .first()
+                .map(|&byte| utf8_len(byte))
+                .ok_or_else(|| invalid("the cells are cut short"))?;
+            if rest.len() < len + CELL_BYTES {
+                return Err(invalid("the cells are cut short"));
+            }
+            let ch = std::str::from_utf8(&rest[..len])
+                .ok()
+                .and_then(|ch| ch.chars().next())
+                .ok_or_else(|| invalid("a cell's character is not UTF-8"))?;
+            // Control characters would reach the terminal as escape sequences
+            if ch.width().unwrap_or(0) == 0 {
+                return Err(invalid("a cell's character cannot be shown"));
+            }
+            let [r, g, b, luma, flags] = rest[len..len + CELL_BYTES]
+                .try_into()
+                .expect("length checked above");
+            cells.push(Cell {
+                ch,
+                rgb: [r, g, b],
+                luma,
+                transparent: flags & TRANSPARENT != 0,
+            });
+            rest = &rest[len + CELL_BYTES..];
+        }
+        if !rest.is_empty() {
+            return Err(invalid("there are bytes after the last cell"));
+        }
+        Ok(Self {
+            image: AsciiImage::new(width, height, cells)?,
+            metadata,
+        })
+    }
+
+    /// Read the document in the file at `path`
+    pub fn load(path: &Path) -> Result<Self, AsciiArtError> {
+        let bytes = std::fs::read(path).map_err(|err| {
+            AsciiArtError::InvalidArgument(format!("Cannot read {}: {err}", path.display()))
+        })?;
+        Self::parse(&bytes)
+            .map_err(|err| AsciiArtError::InvalidArgument(format!("{}: {err}", path.display())))
+    }
+}
+
+/// Whether the file at `path` starts like a grid document
+///
+/// Documents are recognized by their contents, like images, whatever their
+/// extension.
+pub fn is_grid_document(path: &Path) -> bool {
+    let mut magic = Vec::with_capacity(MAGIC.len());
+    File::open(path)
+        .and_then(|file| file.take(MAGIC.len() as u64).read_to_end(&mut magic))
+        .is_ok_and(|_| magic == MAGIC)
+}
+
+/// Length of the UTF-8 sequence that starts with `byte`
+fn utf8_len(byte: u8) -> usize {
+    match byte {
+        0xf0.. => 4,
+        0xe0.. => 3,
+        0xc0.. => 2,
+        _ => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document() -> GridDocument {
+        let mut blank = Cell::opaque(' ', [0, 0, 0], 0);
+        blank.transparent = true;
+        let cells = vec![
+            Cell::opaque('@', [200, 10, 20], 200),
+            Cell::opaque('▓', [90, 90, 90], 90),
+            blank,
+            Cell::opaque('口', [30, 40, 50], 30),
+        ];
+        let image = AsciiImage::new(2, 2, cells).expect("grid");
+        GridDocument::new(image)
+            .note("source", "cat\nphoto.png")
+            .note("mode", "standard")
+    }
+
+    #[test]
+    fn documents_round_trip() {
+        let document = document();
+        let mut bytes = Vec::new();
+        document.write_to(&mut bytes).expect("written");
+
+        assert!(bytes.starts_with(b"AAG 1\nwidth 2\nheight 2\nmode standard\n"));
+        let parsed = GridDocument::parse(&bytes).expect("parsed");
+        assert_eq!(parsed, document);
+        assert_eq!(parsed.metadata["source"], "cat photo.png");
+    }
+
+    #[test]
+    fn damaged_documents_are_rejected() {
+        let mut bytes = Vec::new();
+        document().write_to(&mut bytes).expect("written");
+
+        for damaged in [
+            &bytes[1..],
+            &bytes[..bytes.len() - 1],
+            &[&bytes[..], b"x"].concat(),
+            b"AAG 1\nwidth 2\n\n",
+            b"AAG 1\nwidth 1\nheight 1\n\n\x1b\0\0\0\0\0",
+        ] {
+            let err = GridDocument::parse(damaged).unwrap_err();
+            assert!(
+                err.to_string().starts_with("Not a valid grid document"),
+                "{err}"
+            );
+        }
+    }
+}
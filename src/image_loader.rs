@@ -1,13 +1,212 @@
+use std::collections::HashSet;
 use std::fmt;
-use std::io;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Cursor, Read};
+use std::str::FromStr;
 
+use image::codecs::jpeg::JpegDecoder;
+use image::error::{ImageFormatHint, UnsupportedError, UnsupportedErrorKind};
 use image::imageops::FilterType;
-use image::{DynamicImage, GenericImageView, GrayImage, ImageError};
+use image::io::{Limits, Reader};
+use image::{
+    DynamicImage, ExtendedColorType, GenericImageView, GrayImage, ImageBuffer, ImageDecoder as _,
+    ImageError, ImageFormat, Luma, Pixel, Rgb, RgbImage,
+};
+use tracing::debug;
+
+use crate::color;
+use crate::error::AsciiArtError;
+use crate::parallel::map_rows;
+
+/// Default cap on the total number of pixels a decoded image may have (100 megapixels)
+pub const DEFAULT_MAX_PIXELS: u64 = 100_000_000;
+
+/// Height-to-width ratio of a terminal character cell
+pub const DEFAULT_CHAR_ASPECT: f32 = 2.0;
+
+/// Default cap on either side of a decoded image
+pub const DEFAULT_MAX_DIMENSION: u32 = 32_768;
+
+/// How many source pixels per output character a reduced JPEG decode keeps,
+/// so the final resize still has detail to average over
+const DCT_OVERSAMPLE: u32 = 2;
+
+/// Alpha below which a pixel becomes a transparent (blank, uncolored) cell
+pub const DEFAULT_ALPHA_THRESHOLD: u8 = 128;
+
+/// Largest side an image may have to be detected as pixel art
+pub const PIXEL_ART_MAX_SIDE: u32 = 256;
+
+/// Most distinct colors an image may have to be detected as pixel art
+pub const PIXEL_ART_MAX_COLORS: usize = 64;
+
+/// Largest side an image is shrunk to before measuring whether it is line art
+const LINE_ART_SAMPLE_SIDE: u32 = 256;
+
+/// Smallest share of pixels near pure black or white for line art
+pub const LINE_ART_MIN_EXTREMES: f32 = 0.85;
+
+/// Largest mean chroma (spread between the strongest and weakest channel) for
+/// line art
+pub const LINE_ART_MAX_CHROMA: f32 = 24.0;
+
+/// Largest share of pixels on an edge for line art; denser detail is texture
+pub const LINE_ART_MAX_EDGES: f32 = 0.25;
+
+/// Sampling filter used to shrink an image to the character grid
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum ResizeFilter {
+    /// Nearest neighbor: by far the fastest, and keeps pixel art crisp
+    Nearest,
+    /// Bilinear interpolation
+    Triangle,
+    /// Bicubic Catmull-Rom spline
+    #[cfg_attr(feature = "cli", value(name = "catmullrom"))]
+    CatmullRom,
+    /// Windowed sinc over three lobes: the sharpest, and the slowest
+    #[default]
+    Lanczos3,
+    /// Average of the pixels each cell covers: nearly as fast as nearest, and
+    /// smooth when shrinking a lot, as video frames are
+    Box,
+}
+
+impl FromStr for ResizeFilter {
+    type Err = AsciiArtError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "nearest" => Ok(ResizeFilter::Nearest),
+            "triangle" => Ok(ResizeFilter::Triangle),
+            "catmullrom" => Ok(ResizeFilter::CatmullRom),
+            "lanczos3" => Ok(ResizeFilter::Lanczos3),
+            "box" => Ok(ResizeFilter::Box),
+            unknown => Err(AsciiArtError::InvalidArgument(format!(
+                "Unknown resize filter '{}'. Use 'nearest', 'triangle', 'catmullrom', \
+                 'lanczos3' or 'box'.",
+                unknown
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for ResizeFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResizeFilter::Nearest => write!(f, "nearest"),
+            ResizeFilter::Triangle => write!(f, "triangle"),
+            ResizeFilter::CatmullRom => write!(f, "catmullrom"),
+            ResizeFilter::Lanczos3 => write!(f, "lanczos3"),
+            ResizeFilter::Box => write!(f, "box"),
+        }
+    }
+}
+
+impl ResizeFilter {
+    /// Resize `img` to exactly `width` x `height` pixels with this filter
+    pub fn resize(self, img: &DynamicImage, width: u32, height: u32) -> DynamicImage {
+        let filter = match self {
+            ResizeFilter::Nearest => FilterType::Nearest,
+            ResizeFilter::Triangle => FilterType::Triangle,
+            ResizeFilter::CatmullRom => FilterType::CatmullRom,
+            ResizeFilter::Lanczos3 => FilterType::Lanczos3,
+            ResizeFilter::Box => return box_resize(img, width, height),
+        };
+        img.resize_exact(width, height, filter)
+    }
+}
+
+/// Resize `img` by averaging, for each output pixel, the source pixels it
+/// covers
+///
+/// Footprints are whole source pixels, so every source pixel counts towards
+/// exactly one output pixel when shrinking, and the sums run over contiguous
+/// bytes the compiler can vectorize. Images with more than 8 bits per channel
+/// come out with 8.
+fn box_resize(img: &DynamicImage, width: u32, height: u32) -> DynamicImage {
+    match img {
+        DynamicImage::ImageLuma8(image) => {
+            DynamicImage::ImageLuma8(box_resize_buffer(image, width, height))
+        }
+        DynamicImage::ImageLumaA8(image) => {
+            DynamicImage::ImageLumaA8(box_resize_buffer(image, width, height))
+        }
+        DynamicImage::ImageRgb8(image) => {
+            DynamicImage::ImageRgb8(box_resize_buffer(image, width, height))
+        }
+        DynamicImage::ImageRgba8(image) => {
+            DynamicImage::ImageRgba8(box_resize_buffer(image, width, height))
+        }
+        image if image.color().has_alpha() => {
+            DynamicImage::ImageRgba8(box_resize_buffer(&image.to_rgba8(), width, height))
+        }
+        image => DynamicImage::ImageRgb8(box_resize_buffer(&image.to_rgb8(), width, height)),
+    }
+}
+
+/// The source range each of `to` output pixels averages over `from` source
+/// pixels, at least one pixel wide
+fn box_spans(from: u32, to: u32) -> Vec<(usize, usize)> {
+    let (from, to) = (u64::from(from), u64::from(to));
+    (0..to)
+        .map(|index| {
+            let start = (index * from / to) as usize;
+            let end = ((index + 1) * from / to) as usize;
+            (start, end.max(start + 1))
+        })
+        .collect()
+}
+
+fn box_resize_buffer<P>(
+    src: &ImageBuffer<P, Vec<u8>>,
+    width: u32,
+    height: u32,
+) -> ImageBuffer<P, Vec<u8>>
+where
+    P: Pixel<Subpixel = u8> + Send + Sync,
+{
+    let channels = P::CHANNEL_COUNT as usize;
+    let stride = src.width() as usize * channels;
+    let columns = box_spans(src.width(), width);
+    let rows = box_spans(src.height(), height);
+    let samples = src.as_raw();
+
+    let out_rows = map_rows(rows.len(), |y| {
+        let (top, bottom) = rows[y];
+        // A cell of a huge image shrunk to a few columns can cover more
+        // pixels than a u32 sum of 8-bit samples holds
+        let mut sums = vec![0u64; columns.len() * channels];
+        for row in samples[top * stride..bottom * stride].chunks_exact(stride) {
+            for (sum, &(left, right)) in sums.chunks_exact_mut(channels).zip(&columns) {
+                for pixel in row[left * channels..right * channels].chunks_exact(channels) {
+                    for (total, &value) in sum.iter_mut().zip(pixel) {
+                        *total += u64::from(value);
+                    }
+                }
+            }
+        }
+        let mut out = Vec::with_capacity(sums.len());
+        for (sum, &(left, right)) in sums.chunks_exact(channels).zip(&columns) {
+            let count = ((right - left) * (bottom - top)) as u64;
+            out.extend(sum.iter().map(|&total| ((total + count / 2) / count) as u8));
+        }
+        out
+    });
+    ImageBuffer::from_raw(width, height, out_rows.concat())
+        .expect("one row of samples per output row")
+}
 
 /// Bundle of image data prepared for downstream conversion/rendering stages.
 #[derive(Debug)]
 pub struct ProcessedImage {
     pub gray: GrayImage,
+    /// Concrete 8-bit RGB copy of `original`, used for fast color sampling
+    pub rgb: RgbImage,
+    /// Alpha channel of `original`, or `None` if every pixel is opaque
+    pub alpha: Option<GrayImage>,
+    /// Pixels with less alpha than this are converted to transparent cells
+    pub alpha_threshold: u8,
     pub original: DynamicImage,
 }
 
@@ -15,11 +214,65 @@ pub struct ProcessedImage {
 pub enum ImageLoaderError {
     FileNotFound(String),
     UnsupportedFormat(String),
+    /// The format is known, but this file uses a variant of it (such as a
+    /// 16-bit CMYK TIFF) that the decoder cannot handle
+    UnsupportedFeature(String),
     InvalidDimensions(String),
     DecodeFailed(String),
+    LimitsExceeded(String),
     IoError(String),
 }
 
+/// Size limits checked against the image header before a full decode
+///
+/// These keep a huge (or malicious) file from exhausting memory: the header is
+/// read first, and the decoder itself is also capped on allocation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeLimits {
+    pub max_pixels: u64,
+    pub max_dimension: u32,
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        Self {
+            max_pixels: DEFAULT_MAX_PIXELS,
+            max_dimension: DEFAULT_MAX_DIMENSION,
+        }
+    }
+}
+
+impl DecodeLimits {
+    /// Reject dimensions that exceed these limits
+    pub fn check(&self, path: &str, width: u32, height: u32) -> Result<(), ImageLoaderError> {
+        if width > self.max_dimension || height > self.max_dimension {
+            return Err(ImageLoaderError::LimitsExceeded(format!(
+                "Image \"{path}\" is {width}x{height} pixels, exceeding the maximum dimension of {}.",
+                self.max_dimension
+            )));
+        }
+
+        let pixels = u64::from(width) * u64::from(height);
+        if pixels > self.max_pixels {
+            return Err(ImageLoaderError::LimitsExceeded(format!(
+                "Image \"{path}\" has {pixels} pixels, exceeding the limit of {}.",
+                self.max_pixels
+            )));
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn to_decoder_limits(self) -> Limits {
+        let mut limits = Limits::default();
+        limits.max_image_width = Some(self.max_dimension);
+        limits.max_image_height = Some(self.max_dimension);
+        // Allow up to 16-bit RGBA (8 bytes per pixel) for the permitted pixel count
+        limits.max_alloc = Some(self.max_pixels.saturating_mul(8));
+        limits
+    }
+}
+
 impl fmt::Display for ImageLoaderError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -29,8 +282,10 @@ impl fmt::Display for ImageLoaderError {
             ImageLoaderError::UnsupportedFormat(path) => {
                 write!(f, "Unsupported image format for file \"{}\".", path)
             }
+            ImageLoaderError::UnsupportedFeature(message) => write!(f, "{message}"),
             ImageLoaderError::InvalidDimensions(message) => write!(f, "{message}"),
             ImageLoaderError::DecodeFailed(message) => write!(f, "{message}"),
+            ImageLoaderError::LimitsExceeded(message) => write!(f, "{message}"),
             ImageLoaderError::IoError(message) => write!(f, "{message}"),
         }
     }
@@ -38,20 +293,960 @@ impl fmt::Display for ImageLoaderError {
 
 impl std::error::Error for ImageLoaderError {}
 
+impl ImageLoaderError {
+    /// Name of the variant, for machine-readable error reports
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ImageLoaderError::FileNotFound(_) => "FileNotFound",
+            ImageLoaderError::UnsupportedFormat(_) => "UnsupportedFormat",
+            ImageLoaderError::UnsupportedFeature(_) => "UnsupportedFeature",
+            ImageLoaderError::InvalidDimensions(_) => "InvalidDimensions",
+            ImageLoaderError::DecodeFailed(_) => "DecodeFailed",
+            ImageLoaderError::LimitsExceeded(_) => "LimitsExceeded",
+            ImageLoaderError::IoError(_) => "IoError",
+        }
+    }
+
+    /// The offending file, for the variants that carry only a path
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            ImageLoaderError::FileNotFound(path) | ImageLoaderError::UnsupportedFormat(path) => {
+                Some(path)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl ProcessedImage {
+    /// Wrap an already-resized image, deriving its grayscale and RGB versions
+    pub fn new(resized: DynamicImage) -> Self {
+        // Luma is computed at the image's own precision before rounding to 8 bits
+        let gray = resized.to_luma8();
+        let rgb = resized.to_rgb8();
+        let alpha = if resized.color().has_alpha() {
+            let rgba = resized.to_rgba8();
+            Some(GrayImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+                Luma([rgba.get_pixel(x, y)[3]])
+            }))
+            .filter(|alpha| alpha.pixels().any(|pixel| pixel[0] < u8::MAX))
+        } else {
+            None
+        };
+        ProcessedImage {
+            original: resized,
+            rgb,
+            alpha,
+            alpha_threshold: DEFAULT_ALPHA_THRESHOLD,
+            gray,
+        }
+    }
+}
+
 pub fn load_image(path: &str) -> Result<DynamicImage, ImageLoaderError> {
-    image::open(path).map_err(|err| map_image_error(err, path))
+    load_image_with_limits(path, &DecodeLimits::default())
+}
+
+/// Load an image, refusing to decode it if it exceeds `limits`
+pub fn load_image_with_limits(
+    path: &str,
+    limits: &DecodeLimits,
+) -> Result<DynamicImage, ImageLoaderError> {
+    decoder().decode_file(path, limits)
+}
+
+/// Decode an image held in memory, guessing its format from the content
+///
+/// Used where there is no file system, such as the WebAssembly build; errors
+/// name the input `<memory>`.
+pub fn load_image_from_bytes(
+    bytes: &[u8],
+    limits: &DecodeLimits,
+) -> Result<DynamicImage, ImageLoaderError> {
+    decoder().decode_bytes(bytes, "<memory>", limits)
+}
+
+/// Open `path` for decoding, trusting the file's content over its extension
+///
+/// A PNG saved as `photo.jpg` still decodes as PNG; the extension is only
+/// used for formats without a recognizable signature, such as TGA.
+pub(crate) fn open_reader(path: &str) -> Result<Reader<BufReader<File>>, ImageLoaderError> {
+    Reader::open(path)
+        .and_then(Reader::with_guessed_format)
+        .map_err(|err| map_io_error(err, path))
+}
+
+/// Prepare a freshly decoded image for the rest of the pipeline
+///
+/// Floating-point images become 16-bit, so resizing and filters keep their
+/// precision without every stage handling floats; 8-bit conversion happens
+/// only after resizing. With the `icc` feature, an embedded color profile
+/// (read on demand through `icc_profile`) is converted to sRGB.
+fn finish_decode(
+    image: DynamicImage,
+    icc_profile: impl FnOnce() -> Option<Vec<u8>>,
+) -> DynamicImage {
+    let image = match image {
+        DynamicImage::ImageRgb32F(_) => DynamicImage::ImageRgb16(image.to_rgb16()),
+        DynamicImage::ImageRgba32F(_) => DynamicImage::ImageRgba16(image.to_rgba16()),
+        image => image,
+    };
+    if cfg!(feature = "icc") {
+        if let Some(profile) = icc_profile() {
+            return to_srgb(image, &profile);
+        }
+    }
+    image
+}
+
+/// The ICC profile embedded in a PNG or JPEG, read from its header
+fn icc_profile<R: std::io::BufRead + std::io::Seek>(
+    reader: R,
+    format: Option<ImageFormat>,
+) -> Option<Vec<u8>> {
+    match format? {
+        ImageFormat::Png => image::codecs::png::PngDecoder::new(reader)
+            .ok()?
+            .icc_profile(),
+        ImageFormat::Jpeg => JpegDecoder::new(reader).ok()?.icc_profile(),
+        _ => None,
+    }
+}
+
+/// Convert an image from the color space described by an ICC profile to sRGB
+///
+/// Only RGB profiles are applied; a profile that cannot be parsed, or one for
+/// gray or CMYK data, leaves the image as decoded. 16-bit images are
+/// converted at full precision.
+#[cfg(feature = "icc")]
+pub fn to_srgb(image: DynamicImage, profile: &[u8]) -> DynamicImage {
+    icc::to_srgb(image, profile)
+}
+
+/// Without the `icc` feature, images are assumed to be sRGB already
+#[cfg(not(feature = "icc"))]
+pub fn to_srgb(image: DynamicImage, _profile: &[u8]) -> DynamicImage {
+    image
+}
+
+#[cfg(feature = "icc")]
+mod icc {
+    use image::DynamicImage;
+    use lcms2::{ColorSpaceSignature, Flags, Intent, PixelFormat, Profile, Transform};
+
+    pub(super) fn to_srgb(image: DynamicImage, profile: &[u8]) -> DynamicImage {
+        let Ok(profile) = Profile::new_icc(profile) else {
+            return image;
+        };
+        if profile.color_space() != ColorSpaceSignature::RgbData {
+            return image;
+        }
+        let srgb = Profile::new_srgb();
+        // Alpha is carried through untouched
+        let transform = |format: PixelFormat| {
+            Transform::<u8, u8>::new_flags(
+                &profile,
+                format,
+                &srgb,
+                format,
+                Intent::Perceptual,
+                Flags::COPY_ALPHA,
+            )
+            .ok()
+        };
+
+        match image {
+            DynamicImage::ImageRgb8(mut buffer) => {
+                if let Some(transform) = transform(PixelFormat::RGB_8) {
+                    transform.transform_in_place(&mut buffer);
+                }
+                DynamicImage::ImageRgb8(buffer)
+            }
+            DynamicImage::ImageRgba8(mut buffer) => {
+                if let Some(transform) = transform(PixelFormat::RGBA_8) {
+                    transform.transform_in_place(&mut buffer);
+                }
+                DynamicImage::ImageRgba8(buffer)
+            }
+            DynamicImage::ImageRgb16(mut buffer) => {
+                if let Some(transform) = transform(PixelFormat::RGB_16) {
+                    transform.transform_in_place(bytemuck::cast_slice_mut(&mut buffer));
+                }
+                DynamicImage::ImageRgb16(buffer)
+            }
+            DynamicImage::ImageRgba16(mut buffer) => {
+                if let Some(transform) = transform(PixelFormat::RGBA_16) {
+                    transform.transform_in_place(bytemuck::cast_slice_mut(&mut buffer));
+                }
+                DynamicImage::ImageRgba16(buffer)
+            }
+            other => other,
+        }
+    }
+}
+
+/// A backend that turns encoded images into pixels
+///
+/// Every loader in this module goes through [`decoder`], which returns the
+/// backend picked at compile time.
+pub trait ImageDecoder: Send + Sync {
+    /// Short name of the backend, for logs
+    fn name(&self) -> &'static str;
+
+    /// Decode the image at `path`, refusing it if it exceeds `limits`
+    fn decode_file(
+        &self,
+        path: &str,
+        limits: &DecodeLimits,
+    ) -> Result<DynamicImage, ImageLoaderError> {
+        let bytes = fs::read(path).map_err(|err| map_io_error(err, path))?;
+        self.decode_bytes(&bytes, path, limits)
+    }
+
+    /// Decode an image held in memory; `name` stands in for the path in errors
+    fn decode_bytes(
+        &self,
+        bytes: &[u8],
+        name: &str,
+        limits: &DecodeLimits,
+    ) -> Result<DynamicImage, ImageLoaderError>;
+}
+
+/// The backend used by this build: [`ZuneDecoder`] with the `zune` feature,
+/// otherwise [`ImageCrateDecoder`]
+pub fn decoder() -> &'static dyn ImageDecoder {
+    #[cfg(feature = "zune")]
+    {
+        &ZuneDecoder
+    }
+    #[cfg(not(feature = "zune"))]
+    {
+        &ImageCrateDecoder
+    }
+}
+
+/// Decodes every format through the `image` crate
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ImageCrateDecoder;
+
+impl ImageDecoder for ImageCrateDecoder {
+    fn name(&self) -> &'static str {
+        "image"
+    }
+
+    fn decode_file(
+        &self,
+        path: &str,
+        limits: &DecodeLimits,
+    ) -> Result<DynamicImage, ImageLoaderError> {
+        if is_jpeg_xl(&read_magic(path)?) {
+            let bytes = fs::read(path).map_err(|err| map_io_error(err, path))?;
+            return decode_jpeg_xl(&bytes, path, limits);
+        }
+        if is_camera_raw(path) {
+            return decode_camera_raw(path, limits);
+        }
+        let open = || open_reader(path);
+
+        let (width, height) = open()?
+            .into_dimensions()
+            .map_err(|err| map_image_error(err, path))?;
+        limits.check(path, width, height)?;
+
+        let mut reader = open()?;
+        let format = reader.format();
+        reader.limits(limits.to_decoder_limits());
+        debug!(decoder = "image", ?format, "Decoding {path}");
+        let image = reader.decode().map_err(|err| map_image_error(err, path))?;
+        Ok(finish_decode(image, || {
+            icc_profile(BufReader::new(File::open(path).ok()?), format)
+        }))
+    }
+
+    fn decode_bytes(
+        &self,
+        bytes: &[u8],
+        name: &str,
+        limits: &DecodeLimits,
+    ) -> Result<DynamicImage, ImageLoaderError> {
+        if is_jpeg_xl(bytes) {
+            return decode_jpeg_xl(bytes, name, limits);
+        }
+        let open = || {
+            Reader::new(Cursor::new(bytes))
+                .with_guessed_format()
+                .map_err(|err| map_io_error(err, name))
+        };
+
+        let (width, height) = open()?
+            .into_dimensions()
+            .map_err(|err| map_image_error(err, name))?;
+        limits.check(name, width, height)?;
+
+        let mut reader = open()?;
+        let format = reader.format();
+        reader.limits(limits.to_decoder_limits());
+        debug!(decoder = "image", ?format, "Decoding {name}");
+        let image = reader.decode().map_err(|err| map_image_error(err, name))?;
+        Ok(finish_decode(image, || {
+            icc_profile(Cursor::new(bytes), format)
+        }))
+    }
+}
+
+/// Decodes JPEG and PNG with zune-jpeg and zune-png, and everything else
+/// through [`ImageCrateDecoder`]
+#[cfg(feature = "zune")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ZuneDecoder;
+
+#[cfg(feature = "zune")]
+impl ImageDecoder for ZuneDecoder {
+    fn name(&self) -> &'static str {
+        "zune"
+    }
+
+    fn decode_file(
+        &self,
+        path: &str,
+        limits: &DecodeLimits,
+    ) -> Result<DynamicImage, ImageLoaderError> {
+        let bytes = fs::read(path).map_err(|err| map_io_error(err, path))?;
+        match zune::Format::sniff(&bytes) {
+            Some(format) => {
+                debug!(decoder = "zune", ?format, "Decoding {path}");
+                zune::decode(format, &bytes, path, limits)
+            }
+            None => ImageCrateDecoder.decode_file(path, limits),
+        }
+    }
+
+    fn decode_bytes(
+        &self,
+        bytes: &[u8],
+        name: &str,
+        limits: &DecodeLimits,
+    ) -> Result<DynamicImage, ImageLoaderError> {
+        match zune::Format::sniff(bytes) {
+            Some(format) => {
+                debug!(decoder = "zune", ?format, "Decoding {name}");
+                zune::decode(format, bytes, name, limits)
+            }
+            None => ImageCrateDecoder.decode_bytes(bytes, name, limits),
+        }
+    }
+}
+
+#[cfg(feature = "zune")]
+mod zune {
+    use image::{DynamicImage, ImageBuffer};
+    use zune_jpeg::zune_core::colorspace::ColorSpace;
+    use zune_jpeg::zune_core::options::DecoderOptions;
+    use zune_jpeg::zune_core::result::DecodingResult;
+    use zune_jpeg::JpegDecoder;
+    use zune_png::PngDecoder;
+
+    use super::{finish_decode, DecodeLimits, ImageLoaderError};
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub(super) enum Format {
+        Jpeg,
+        Png,
+    }
+
+    impl Format {
+        /// Recognize the formats zune handles by their magic bytes
+        pub(super) fn sniff(bytes: &[u8]) -> Option<Format> {
+            if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+                Some(Format::Jpeg)
+            } else if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+                Some(Format::Png)
+            } else {
+                None
+            }
+        }
+    }
+
+    pub(super) fn decode(
+        format: Format,
+        bytes: &[u8],
+        name: &str,
+        limits: &DecodeLimits,
+    ) -> Result<DynamicImage, ImageLoaderError> {
+        // `limits` is checked once the header is read, with the same messages
+        // as the `image` backend, so zune's own size caps are lifted
+        let options = DecoderOptions::default()
+            .set_max_width(usize::MAX)
+            .set_max_height(usize::MAX);
+        let failed = |err: &dyn std::fmt::Display| {
+            ImageLoaderError::DecodeFailed(format!("Failed to decode image \"{name}\": {err}"))
+        };
+
+        let (width, height, colorspace, pixels, icc) = match format {
+            Format::Jpeg => {
+                let mut decoder = JpegDecoder::new_with_options(bytes, options);
+                decoder.decode_headers().map_err(|err| failed(&err))?;
+                let info = decoder.info().ok_or_else(|| failed(&"missing header"))?;
+                let (width, height) = (u32::from(info.width), u32::from(info.height));
+                limits.check(name, width, height)?;
+                let pixels = decoder.decode().map_err(|err| failed(&err))?;
+                let icc = decoder.icc_profile();
+                (
+                    width,
+                    height,
+                    decoder.get_output_colorspace(),
+                    DecodingResult::U8(pixels),
+                    icc,
+                )
+            }
+            Format::Png => {
+                let mut decoder = PngDecoder::new_with_options(bytes, options);
+                decoder.decode_headers().map_err(|err| failed(&err))?;
+                let (width, height) = decoder
+                    .get_dimensions()
+                    .ok_or_else(|| failed(&"missing header"))?;
+                let (width, height) = (width as u32, height as u32);
+                limits.check(name, width, height)?;
+                let pixels = decoder.decode().map_err(|err| failed(&err))?;
+                let icc = decoder.get_info().and_then(|info| info.icc_profile.clone());
+                (width, height, decoder.get_colorspace(), pixels, icc)
+            }
+        };
+
+        // 16-bit PNGs keep their precision until after resizing
+        let image = match (colorspace, pixels) {
+            (Some(ColorSpace::RGB), DecodingResult::U8(pixels)) => {
+                ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageRgb8)
+            }
+            (Some(ColorSpace::RGB), DecodingResult::U16(pixels)) => {
+                ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageRgb16)
+            }
+            (Some(ColorSpace::RGBA), DecodingResult::U8(pixels)) => {
+                ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageRgba8)
+            }
+            (Some(ColorSpace::RGBA), DecodingResult::U16(pixels)) => {
+                ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageRgba16)
+            }
+            (Some(ColorSpace::Luma), DecodingResult::U8(pixels)) => {
+                ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageLuma8)
+            }
+            (Some(ColorSpace::Luma), DecodingResult::U16(pixels)) => {
+                ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageLuma16)
+            }
+            (Some(ColorSpace::LumaA), DecodingResult::U8(pixels)) => {
+                ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageLumaA8)
+            }
+            (Some(ColorSpace::LumaA), DecodingResult::U16(pixels)) => {
+                ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageLumaA16)
+            }
+            _ => None,
+        };
+        let image =
+            image.ok_or_else(|| failed(&format!("unsupported color space {colorspace:?}")))?;
+        Ok(finish_decode(image, || icc))
+    }
+}
+
+/// JPEG XL signatures: a bare codestream or the ISO BMFF container
+const JXL_CODESTREAM: &[u8] = &[0xFF, 0x0A];
+const JXL_CONTAINER: &[u8] = b"\0\0\0\x0CJXL \r\n\x87\n";
+
+fn is_jpeg_xl(bytes: &[u8]) -> bool {
+    bytes.starts_with(JXL_CODESTREAM) || bytes.starts_with(JXL_CONTAINER)
+}
+
+/// Read the first bytes of `path`, enough to recognize any signature we sniff
+fn read_magic(path: &str) -> Result<Vec<u8>, ImageLoaderError> {
+    let mut magic = Vec::with_capacity(JXL_CONTAINER.len());
+    File::open(path)
+        .and_then(|file| {
+            file.take(JXL_CONTAINER.len() as u64)
+                .read_to_end(&mut magic)
+        })
+        .map_err(|err| map_io_error(err, path))?;
+    Ok(magic)
+}
+
+#[cfg(feature = "jxl")]
+fn decode_jpeg_xl(
+    bytes: &[u8],
+    name: &str,
+    limits: &DecodeLimits,
+) -> Result<DynamicImage, ImageLoaderError> {
+    debug!(decoder = "jxl-oxide", "Decoding {name}");
+    jxl::decode(bytes, name, limits)
+}
+
+#[cfg(not(feature = "jxl"))]
+fn decode_jpeg_xl(
+    _bytes: &[u8],
+    name: &str,
+    _limits: &DecodeLimits,
+) -> Result<DynamicImage, ImageLoaderError> {
+    Err(ImageLoaderError::UnsupportedFeature(format!(
+        "JPEG XL image \"{name}\" needs the 'jxl' feature; rebuild with it enabled."
+    )))
+}
+
+#[cfg(feature = "jxl")]
+mod jxl {
+    use image::{DynamicImage, GrayAlphaImage, GrayImage, RgbImage, RgbaImage};
+    use jxl_oxide::JxlImage;
+
+    use super::{DecodeLimits, ImageLoaderError};
+
+    /// Render the first keyframe of a JPEG XL image to 8-bit samples
+    pub(super) fn decode(
+        bytes: &[u8],
+        name: &str,
+        limits: &DecodeLimits,
+    ) -> Result<DynamicImage, ImageLoaderError> {
+        let failed = |err: &dyn std::fmt::Display| {
+            ImageLoaderError::DecodeFailed(format!("Failed to decode image \"{name}\": {err}"))
+        };
+
+        let image = JxlImage::builder()
+            .read(bytes)
+            .map_err(|err| failed(&err))?;
+        limits.check(name, image.width(), image.height())?;
+        if image.pixel_format().has_black() {
+            return Err(ImageLoaderError::UnsupportedFeature(format!(
+                "JPEG XL image \"{name}\" uses the CMYK color type, which cannot be decoded; \
+                 convert it to 8-bit RGB first."
+            )));
+        }
+
+        let render = image.render_frame(0).map_err(|err| failed(&err))?;
+        let mut stream = render.stream();
+        let (width, height, channels) = (stream.width(), stream.height(), stream.channels());
+        let mut pixels = vec![0u8; width as usize * height as usize * channels as usize];
+        stream.write_to_buffer(&mut pixels);
+
+        let image = match channels {
+            1 => GrayImage::from_raw(width, height, pixels).map(DynamicImage::ImageLuma8),
+            2 => GrayAlphaImage::from_raw(width, height, pixels).map(DynamicImage::ImageLumaA8),
+            3 => RgbImage::from_raw(width, height, pixels).map(DynamicImage::ImageRgb8),
+            4 => RgbaImage::from_raw(width, height, pixels).map(DynamicImage::ImageRgba8),
+            _ => None,
+        };
+        image.ok_or_else(|| failed(&format!("unsupported channel count {channels}")))
+    }
+}
+
+/// Extensions of the camera RAW formats handled with the `raw` feature
+///
+/// Most RAW files are TIFF containers, so sniffing their content would only
+/// find the embedded preview; for these the extension decides instead.
+const RAW_EXTENSIONS: &[&str] = &[
+    "arw", "cr2", "crw", "dng", "erf", "iiq", "kdc", "mef", "mos", "mrw", "nef", "nrw", "orf",
+    "pef", "raf", "rw2", "srw",
+];
+
+fn is_camera_raw(path: &str) -> bool {
+    std::path::Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| RAW_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+#[cfg(feature = "raw")]
+fn decode_camera_raw(path: &str, limits: &DecodeLimits) -> Result<DynamicImage, ImageLoaderError> {
+    debug!(decoder = "rawloader", "Decoding {path}");
+    raw::decode(path, limits)
+}
+
+#[cfg(not(feature = "raw"))]
+fn decode_camera_raw(path: &str, _limits: &DecodeLimits) -> Result<DynamicImage, ImageLoaderError> {
+    Err(ImageLoaderError::UnsupportedFeature(format!(
+        "Camera RAW image \"{path}\" needs the 'raw' feature; rebuild with it enabled."
+    )))
+}
+
+#[cfg(feature = "raw")]
+mod raw {
+    use image::{imageops, DynamicImage, ImageBuffer, Rgb};
+    use rawloader::{RawImage, RawImageData};
+
+    use super::{DecodeLimits, ImageLoaderError};
+
+    type Rgb16Image = ImageBuffer<Rgb<u16>, Vec<u16>>;
+
+    pub(super) fn decode(
+        path: &str,
+        limits: &DecodeLimits,
+    ) -> Result<DynamicImage, ImageLoaderError> {
+        let failed = |err: &dyn std::fmt::Display| {
+            ImageLoaderError::DecodeFailed(format!("Failed to decode image \"{path}\": {err}"))
+        };
+
+        // rawloader has no header-only pass, so the limits apply before the
+        // (comparatively expensive) development instead
+        let raw = rawloader::decode_file(path).map_err(|err| failed(&err))?;
+        let to_u32 = |value: usize| u32::try_from(value).unwrap_or(u32::MAX);
+        limits.check(path, to_u32(raw.width), to_u32(raw.height))?;
+
+        let image = develop(&raw).ok_or_else(|| failed(&"unsupported sensor layout"))?;
+        Ok(DynamicImage::ImageRgb16(orient(image, &raw)))
+    }
+
+    /// Turn sensor data into a displayable sRGB-ish image
+    ///
+    /// Mosaiced sensors are demosaiced by collapsing each block of the color
+    /// filter array (2x2 for Bayer, 3x3 for X-Trans) into one pixel, which
+    /// halves the resolution but needs no interpolation; a terminal never
+    /// shows that much detail anyway. Levels are normalized between the
+    /// black and white points, scaled by the camera's white balance, and
+    /// gamma-encoded at 16 bits per channel.
+    pub(super) fn develop(raw: &RawImage) -> Option<Rgb16Image> {
+        let [top, right, bottom, left] = raw.crops;
+        let width = raw.width.checked_sub(left + right)?;
+        let height = raw.height.checked_sub(top + bottom)?;
+        let block = match raw.cpp {
+            3 => 1,
+            1 if raw.cfa.is_valid() && raw.cfa.width.is_multiple_of(3) => 3,
+            1 if raw.cfa.is_valid() => 2,
+            _ => return None,
+        };
+        let (out_width, out_height) = (width / block, height / block);
+        if out_width == 0 || out_height == 0 {
+            return None;
+        }
+
+        let sample = |row: usize, col: usize, channel: usize| {
+            let index = (row * raw.width + col) * raw.cpp + channel;
+            match &raw.data {
+                RawImageData::Integer(data) => data.get(index).map(|&value| f32::from(value)),
+                RawImageData::Float(data) => data.get(index).copied(),
+            }
+        };
+        let balance = white_balance(raw.wb_coeffs);
+        let level = |value: f32, color: usize| {
+            let black = f32::from(raw.blacklevels[color]);
+            let white = f32::from(raw.whitelevels[color]).max(black + 1.0);
+            let linear = ((value - black) / (white - black) * balance[color]).clamp(0.0, 1.0);
+            (linear.powf(1.0 / 2.2) * 65_535.0).round() as u16
+        };
+
+        let mut image = Rgb16Image::new(out_width as u32, out_height as u32);
+        for (x, y, pixel) in image.enumerate_pixels_mut() {
+            let (row0, col0) = (top + y as usize * block, left + x as usize * block);
+            let mut sums = [0.0f32; 3];
+            let mut counts = [0u32; 3];
+            for row in row0..row0 + block {
+                for col in col0..col0 + block {
+                    if raw.cpp == 3 {
+                        for (channel, sum) in sums.iter_mut().enumerate() {
+                            *sum += sample(row, col, channel)?;
+                            counts[channel] += 1;
+                        }
+                    } else {
+                        // The fourth (emerald) filter color is treated as green
+                        let color = raw.cfa.color_at(row, col).min(3);
+                        let channel = if color == 3 { 1 } else { color };
+                        sums[channel] += sample(row, col, 0)?;
+                        counts[channel] += 1;
+                    }
+                }
+            }
+            if counts.contains(&0) {
+                return None;
+            }
+            *pixel = Rgb(std::array::from_fn(|channel| {
+                level(sums[channel] / counts[channel] as f32, channel)
+            }));
+        }
+        Some(image)
+    }
+
+    /// White balance multipliers relative to green, or neutral if the file
+    /// has none
+    fn white_balance(coeffs: [f32; 4]) -> [f32; 4] {
+        let green = coeffs[1];
+        let valid = |value: f32| value.is_finite() && value > 0.0;
+        if !valid(green) {
+            return [1.0; 4];
+        }
+        coeffs.map(|value| if valid(value) { value / green } else { 1.0 })
+    }
+
+    fn orient(image: Rgb16Image, raw: &RawImage) -> Rgb16Image {
+        let (transpose, flip_h, flip_v) = raw.orientation.to_flips();
+        let mut image = image;
+        if flip_h {
+            imageops::flip_horizontal_in_place(&mut image);
+        }
+        if flip_v {
+            imageops::flip_vertical_in_place(&mut image);
+        }
+        if transpose {
+            image = imageops::flip_horizontal(&imageops::rotate90(&image));
+        }
+        image
+    }
+}
+
+/// Load an image that will be shrunk to `target_width` characters
+///
+/// JPEGs much larger than the output are decoded at a reduced DCT scale
+/// (1/2, 1/4, or 1/8), skipping most of the work of a full-resolution decode.
+/// Everything else goes through the build's [`decoder`].
+pub fn load_image_for_width(
+    path: &str,
+    limits: &DecodeLimits,
+    target_width: u32,
+) -> Result<DynamicImage, ImageLoaderError> {
+    let reader = open_reader(path)?;
+    if reader.format() != Some(ImageFormat::Jpeg) || target_width == 0 {
+        return load_image_with_limits(path, limits);
+    }
+
+    let file = File::open(path).map_err(|err| map_io_error(err, path))?;
+    let mut jpeg =
+        JpegDecoder::new(BufReader::new(file)).map_err(|err| map_image_error(err, path))?;
+    let (width, height) = jpeg.dimensions();
+    limits.check(path, width, height)?;
+    jpeg.set_limits(limits.to_decoder_limits())
+        .map_err(|err| map_image_error(err, path))?;
+
+    let requested_width = target_width.saturating_mul(DCT_OVERSAMPLE).min(width);
+    if requested_width >= width {
+        return decoder().decode_file(path, limits);
+    }
+    let requested_height =
+        ((u64::from(height) * u64::from(requested_width)) / u64::from(width.max(1))).max(1) as u32;
+    jpeg.scale(
+        requested_width.min(u32::from(u16::MAX)) as u16,
+        requested_height.min(u32::from(u16::MAX)) as u16,
+    )
+    .map_err(|err| map_image_error(err, path))?;
+
+    let icc = jpeg.icc_profile();
+    let image = DynamicImage::from_decoder(jpeg).map_err(|err| map_image_error(err, path))?;
+    Ok(finish_decode(image, || icc))
+}
+
+/// Default `tolerance` of a [`ChromaKey`]
+pub const DEFAULT_CHROMA_TOLERANCE: u8 = 30;
+
+/// A solid background color to mask out, such as a green screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChromaKey {
+    pub color: [u8; 3],
+    /// How far each channel may stray from `color` and still be masked
+    pub tolerance: u8,
+}
+
+impl ChromaKey {
+    pub fn new(color: [u8; 3]) -> Self {
+        Self {
+            color,
+            tolerance: DEFAULT_CHROMA_TOLERANCE,
+        }
+    }
+
+    /// Whether `rgb` counts as the key color
+    pub fn matches(&self, rgb: [u8; 3]) -> bool {
+        rgb.iter()
+            .zip(self.color)
+            .all(|(&channel, key)| channel.abs_diff(key) <= self.tolerance)
+    }
+
+    /// Make every pixel matching the key fully transparent
+    ///
+    /// The result always has an alpha channel, so masked pixels become
+    /// transparent cells (or take the fill of [`composite_over`]).
+    pub fn mask(&self, image: &DynamicImage) -> DynamicImage {
+        if is_high_precision(image) {
+            let mut rgba = image.to_rgba16();
+            for pixel in rgba.pixels_mut() {
+                if self.matches([pixel[0], pixel[1], pixel[2]].map(|value| (value >> 8) as u8)) {
+                    pixel[3] = 0;
+                }
+            }
+            return DynamicImage::ImageRgba16(rgba);
+        }
+        let mut rgba = image.to_rgba8();
+        for pixel in rgba.pixels_mut() {
+            if self.matches([pixel[0], pixel[1], pixel[2]]) {
+                pixel[3] = 0;
+            }
+        }
+        DynamicImage::ImageRgba8(rgba)
+    }
+}
+
+/// Blend an image with transparency over a solid `background` color
+///
+/// The result is fully opaque, so no cell is left transparent. Images without
+/// an alpha channel are copied unchanged.
+pub fn composite_over(image: &DynamicImage, background: [u8; 3]) -> DynamicImage {
+    if !image.color().has_alpha() {
+        return image.clone();
+    }
+    let blend = |value: u64, background: u64, alpha: u64, max: u64| {
+        (value * alpha + background * (max - alpha) + max / 2) / max
+    };
+    if is_high_precision(image) {
+        let rgba = image.to_rgba16();
+        let rgb = ImageBuffer::from_fn(rgba.width(), rgba.height(), |x, y| {
+            let pixel = rgba.get_pixel(x, y);
+            let alpha = u64::from(pixel[3]);
+            Rgb([0, 1, 2].map(|channel| {
+                let background = u64::from(background[channel]) * 257;
+                blend(u64::from(pixel[channel]), background, alpha, 65_535) as u16
+            }))
+        });
+        return DynamicImage::ImageRgb16(rgb);
+    }
+    let rgba = image.to_rgba8();
+    let rgb = RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let pixel = rgba.get_pixel(x, y);
+        let alpha = u64::from(pixel[3]);
+        Rgb([0, 1, 2].map(|channel| {
+            let background = u64::from(background[channel]);
+            blend(u64::from(pixel[channel]), background, alpha, 255) as u8
+        }))
+    });
+    DynamicImage::ImageRgb8(rgb)
+}
+
+/// Whether `image` looks like low-resolution pixel art: small, with only a
+/// handful of distinct colors
+pub fn is_pixel_art(image: &DynamicImage) -> bool {
+    let (width, height) = image.dimensions();
+    if width.max(height) > PIXEL_ART_MAX_SIDE {
+        return false;
+    }
+    let mut colors = HashSet::new();
+    image.to_rgba8().pixels().all(|pixel| {
+        colors.insert(pixel.0);
+        colors.len() <= PIXEL_ART_MAX_COLORS
+    })
+}
+
+/// Whether `image` looks like line art: nearly colorless, mostly pure black or
+/// white, with strokes rather than dense texture
+///
+/// Transparent pixels are ignored.
+pub fn is_line_art(image: &DynamicImage) -> bool {
+    let side = LINE_ART_SAMPLE_SIDE;
+    let sample = if image.width().max(image.height()) > side {
+        image.thumbnail(side, side).to_rgba8()
+    } else {
+        image.to_rgba8()
+    };
+    let luma = |pixel: &image::Rgba<u8>| color::luma([pixel[0], pixel[1], pixel[2]]) as u32;
+    let (mut opaque, mut extremes, mut chroma, mut edges) = (0u64, 0u64, 0u64, 0u64);
+    for (x, y, pixel) in sample.enumerate_pixels() {
+        if pixel[3] < DEFAULT_ALPHA_THRESHOLD {
+            continue;
+        }
+        opaque += 1;
+        let value = luma(pixel);
+        extremes += u64::from(!(48..=208).contains(&value));
+        let channels = &pixel.0[..3];
+        let spread = channels.iter().max().unwrap_or(&0) - channels.iter().min().unwrap_or(&0);
+        chroma += u64::from(spread);
+        let right = sample.get_pixel((x + 1).min(sample.width() - 1), y);
+        let below = sample.get_pixel(x, (y + 1).min(sample.height() - 1));
+        let gradient = value.abs_diff(luma(right)) + value.abs_diff(luma(below));
+        edges += u64::from(gradient > 64);
+    }
+    if opaque == 0 {
+        return false;
+    }
+    let share = |count: u64| count as f32 / opaque as f32;
+    share(extremes) >= LINE_ART_MIN_EXTREMES
+        && share(chroma) <= LINE_ART_MAX_CHROMA
+        && share(edges) <= LINE_ART_MAX_EDGES
+}
+
+/// The grid width closest to `target_width` at an integer scale of `source_width`
+///
+/// Wider targets get each source pixel repeated a whole number of times, and
+/// narrower ones get each cell covering a whole number of source pixels, so
+/// nearest-neighbor resizing never drops or doubles single pixels unevenly.
+pub fn pixel_art_width(source_width: u32, target_width: u32) -> u32 {
+    let source_width = source_width.max(1);
+    if target_width >= source_width {
+        source_width * (target_width / source_width)
+    } else {
+        (source_width / source_width.div_ceil(target_width.max(1))).max(1)
+    }
+}
+
+/// Whether `image` has more than 8 bits per channel
+pub fn is_high_precision(image: &DynamicImage) -> bool {
+    let color = image.color();
+    color.bytes_per_pixel() > color.channel_count()
 }
 
 pub fn preprocess_image(
     img: DynamicImage,
     target_width: u32,
 ) -> Result<ProcessedImage, ImageLoaderError> {
+    preprocess_image_with_filter(img, target_width, ResizeFilter::default())
+}
+
+/// Like [`preprocess_image`], shrinking the image with `filter`
+pub fn preprocess_image_with_filter(
+    img: DynamicImage,
+    target_width: u32,
+    filter: ResizeFilter,
+) -> Result<ProcessedImage, ImageLoaderError> {
+    resize_to_grid_with_filter(&img, target_width, None, DEFAULT_CHAR_ASPECT, filter)
+        .map(ProcessedImage::new)
+}
+
+/// Resize an image to the character grid dimensions
+///
+/// When `target_height` is `None` the height is derived from the aspect ratio,
+/// halved to account for terminal characters being roughly twice as tall as wide.
+pub fn resize_to_grid(
+    img: &DynamicImage,
+    target_width: u32,
+    target_height: Option<u32>,
+) -> Result<DynamicImage, ImageLoaderError> {
+    resize_to_grid_with_aspect(img, target_width, target_height, DEFAULT_CHAR_ASPECT)
+}
+
+/// Resize an image to the character grid for cells `char_aspect` times taller than wide
+pub fn resize_to_grid_with_aspect(
+    img: &DynamicImage,
+    target_width: u32,
+    target_height: Option<u32>,
+    char_aspect: f32,
+) -> Result<DynamicImage, ImageLoaderError> {
+    let filter = ResizeFilter::default();
+    resize_to_grid_with_filter(img, target_width, target_height, char_aspect, filter)
+}
+
+/// Resize an image to the character grid, sampling with `filter`
+pub fn resize_to_grid_with_filter(
+    img: &DynamicImage,
+    target_width: u32,
+    target_height: Option<u32>,
+    char_aspect: f32,
+    filter: ResizeFilter,
+) -> Result<DynamicImage, ImageLoaderError> {
     if target_width == 0 {
         return Err(ImageLoaderError::InvalidDimensions(
             "Target width must be greater than zero.".to_string(),
         ));
     }
 
+    if target_height == Some(0) {
+        return Err(ImageLoaderError::InvalidDimensions(
+            "Target height must be greater than zero.".to_string(),
+        ));
+    }
+
+    if !(char_aspect.is_finite() && char_aspect > 0.0) {
+        return Err(ImageLoaderError::InvalidDimensions(
+            "Character aspect ratio must be a positive number.".to_string(),
+        ));
+    }
+
     let (original_width, original_height) = img.dimensions();
     if original_width == 0 || original_height == 0 {
         return Err(ImageLoaderError::InvalidDimensions(
@@ -60,29 +1255,25 @@ pub fn preprocess_image(
     }
 
     let corrected_width = target_width;
-    let aspect_ratio = original_height as f32 / original_width as f32;
-    let target_height = ((aspect_ratio * corrected_width as f32) / 2.0)
-        .round()
-        .max(1.0) as u32;
-
-    let resized = img.resize_exact(corrected_width, target_height, FilterType::Lanczos3);
-    let gray = image::imageops::grayscale(&resized);
+    let target_height = target_height.unwrap_or_else(|| {
+        let aspect_ratio = original_height as f32 / original_width as f32;
+        ((aspect_ratio * corrected_width as f32) / char_aspect)
+            .round()
+            .max(1.0) as u32
+    });
 
-    Ok(ProcessedImage {
-        original: resized,
-        gray,
-    })
+    Ok(filter.resize(img, corrected_width, target_height))
 }
 
-fn map_image_error(error: ImageError, path: &str) -> ImageLoaderError {
+pub(crate) fn map_image_error(error: ImageError, path: &str) -> ImageLoaderError {
     match error {
         ImageError::IoError(io_err) => map_io_error(io_err, path),
-        ImageError::Unsupported(_) => ImageLoaderError::UnsupportedFormat(path.to_string()),
+        ImageError::Unsupported(err) => map_unsupported_error(err, path),
         ImageError::Decoding(err) => {
             ImageLoaderError::DecodeFailed(format!("Failed to decode image \"{path}\": {err}"))
         }
         ImageError::Limits(err) => {
-            ImageLoaderError::DecodeFailed(format!("Image limits exceeded for \"{path}\": {err}"))
+            ImageLoaderError::LimitsExceeded(format!("Image limits exceeded for \"{path}\": {err}"))
         }
         other => {
             ImageLoaderError::DecodeFailed(format!("Failed to load image \"{path}\": {other}"))
@@ -90,7 +1281,49 @@ fn map_image_error(error: ImageError, path: &str) -> ImageLoaderError {
     }
 }
 
-fn map_io_error(error: io::Error, path: &str) -> ImageLoaderError {
+fn map_unsupported_error(error: UnsupportedError, path: &str) -> ImageLoaderError {
+    let format = match error.format_hint() {
+        ImageFormatHint::Exact(format) => format!("{format:?}"),
+        ImageFormatHint::Name(name) => name,
+        _ => "image".to_string(),
+    }
+    .to_uppercase();
+    match error.kind() {
+        UnsupportedErrorKind::Color(color) => {
+            // The TIFF decoder only reports the pixel size of CMYK and other
+            // exotic layouts, so name the usual culprit
+            let hint = match color {
+                ExtendedColorType::Unknown(64) if format == "TIFF" => " (likely 16-bit CMYK)",
+                _ => "",
+            };
+            ImageLoaderError::UnsupportedFeature(format!(
+                "{format} image \"{path}\" uses the {} color type{hint}, which cannot be \
+                 decoded; convert it to 8-bit RGB first.",
+                color_type_name(color)
+            ))
+        }
+        UnsupportedErrorKind::GenericFeature(feature) => ImageLoaderError::UnsupportedFeature(
+            format!("{format} image \"{path}\" uses an unsupported feature: {feature}."),
+        ),
+        #[cfg(not(feature = "avif"))]
+        UnsupportedErrorKind::Format(ImageFormatHint::Exact(ImageFormat::Avif)) => {
+            ImageLoaderError::UnsupportedFeature(format!(
+                "AVIF image \"{path}\" needs the 'avif' feature; rebuild with it enabled."
+            ))
+        }
+        _ => ImageLoaderError::UnsupportedFormat(path.to_string()),
+    }
+}
+
+fn color_type_name(color: ExtendedColorType) -> String {
+    match color {
+        ExtendedColorType::Cmyk8 => "8-bit CMYK".to_string(),
+        ExtendedColorType::Unknown(bits) => format!("unknown {bits}-bit-per-pixel"),
+        other => format!("{other:?}"),
+    }
+}
+
+pub(crate) fn map_io_error(error: io::Error, path: &str) -> ImageLoaderError {
     match error.kind() {
         io::ErrorKind::NotFound => ImageLoaderError::FileNotFound(path.to_string()),
         _ => ImageLoaderError::IoError(format!("I/O error while accessing \"{path}\": {error}")),
@@ -115,6 +1348,177 @@ mod tests {
         );
     }
 
+    #[test]
+    fn decode_limits_reject_oversized_dimensions() {
+        let limits = DecodeLimits {
+            max_pixels: 1_000,
+            max_dimension: 50,
+        };
+        assert!(limits.check("a.png", 50, 20).is_ok());
+
+        let err = limits.check("a.png", 60, 10).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Image \"a.png\" is 60x10 pixels, exceeding the maximum dimension of 50."
+        );
+
+        let err = limits.check("a.png", 40, 40).unwrap_err();
+        assert!(matches!(err, ImageLoaderError::LimitsExceeded(_)));
+    }
+
+    #[test]
+    fn load_image_with_limits_checks_header_before_decoding() {
+        let limits = DecodeLimits {
+            max_pixels: 10,
+            max_dimension: DEFAULT_MAX_DIMENSION,
+        };
+        let err = load_image_with_limits("examples/test_image_1.png", &limits).unwrap_err();
+        assert!(matches!(err, ImageLoaderError::LimitsExceeded(_)));
+    }
+
+    #[test]
+    fn load_image_from_bytes_guesses_the_format() {
+        let bytes = std::fs::read("examples/test_image_1.png").expect("read example");
+        let image = load_image_from_bytes(&bytes, &DecodeLimits::default()).expect("decode");
+        let from_file = load_image("examples/test_image_1.png").expect("decode");
+        assert_eq!(image.dimensions(), from_file.dimensions());
+
+        let err = load_image_from_bytes(b"not an image", &DecodeLimits::default()).unwrap_err();
+        assert_eq!(
+            err,
+            ImageLoaderError::UnsupportedFormat("<memory>".to_string())
+        );
+    }
+
+    #[test]
+    fn load_image_for_width_decodes_large_jpeg_at_reduced_scale() {
+        let mut file = tempfile::NamedTempFile::with_suffix(".jpg").expect("temp file");
+        DynamicImage::ImageRgb8(ImageBuffer::from_pixel(256, 128, image::Rgb([90, 120, 30])))
+            .write_to(&mut file, image::ImageOutputFormat::Jpeg(90))
+            .expect("write jpeg");
+        let path = file.path().to_str().expect("utf8 path");
+
+        let image = load_image_for_width(path, &DecodeLimits::default(), 16).expect("decode");
+        assert_eq!(image.dimensions(), (32, 16));
+
+        let full = load_image_for_width(path, &DecodeLimits::default(), 300).expect("decode");
+        assert_eq!(full.dimensions(), (256, 128));
+    }
+
+    #[test]
+    fn decoder_backends_agree_on_jpeg_and_png() {
+        let mut jpeg = Vec::new();
+        DynamicImage::ImageRgb8(ImageBuffer::from_pixel(24, 10, image::Rgb([90, 120, 30])))
+            .write_to(
+                &mut Cursor::new(&mut jpeg),
+                image::ImageOutputFormat::Jpeg(90),
+            )
+            .expect("write jpeg");
+        let png = std::fs::read("examples/test_image_1.png").expect("read example");
+
+        let limits = DecodeLimits::default();
+        for bytes in [&jpeg, &png] {
+            let reference = ImageCrateDecoder
+                .decode_bytes(bytes, "a", &limits)
+                .expect("decode");
+            let image = decoder().decode_bytes(bytes, "a", &limits).expect("decode");
+            assert_eq!(
+                image.dimensions(),
+                reference.dimensions(),
+                "{}",
+                decoder().name()
+            );
+        }
+
+        let small = DecodeLimits {
+            max_pixels: 100,
+            max_dimension: DEFAULT_MAX_DIMENSION,
+        };
+        let err = decoder().decode_bytes(&jpeg, "a", &small).unwrap_err();
+        assert!(matches!(err, ImageLoaderError::LimitsExceeded(_)));
+    }
+
+    #[cfg(feature = "zune")]
+    #[test]
+    fn zune_decoder_falls_back_for_other_formats() {
+        let mut gif = Vec::new();
+        DynamicImage::ImageRgb8(ImageBuffer::from_pixel(3, 2, image::Rgb([0, 0, 0])))
+            .write_to(&mut Cursor::new(&mut gif), image::ImageOutputFormat::Gif)
+            .expect("write gif");
+        let image = ZuneDecoder
+            .decode_bytes(&gif, "a", &DecodeLimits::default())
+            .expect("decode");
+        assert_eq!(image.dimensions(), (3, 2));
+        assert_eq!(decoder().name(), "zune");
+    }
+
+    #[cfg(feature = "jxl")]
+    #[test]
+    fn decodes_jpeg_xl() {
+        let image = load_image("tests/data/tiny.jxl").expect("decode");
+        assert!(image.width() > 0 && image.height() > 0);
+
+        let bytes = fs::read("tests/data/tiny.jxl").expect("read fixture");
+        let limits = DecodeLimits {
+            max_pixels: 1,
+            ..Default::default()
+        };
+        let err = load_image_from_bytes(&bytes, &limits).unwrap_err();
+        assert_eq!(err.kind(), "LimitsExceeded");
+    }
+
+    #[cfg(feature = "raw")]
+    #[test]
+    fn develops_bayer_sensor_data() {
+        use rawloader::{Orientation, RawImage, RawImageData, CFA};
+
+        // RGGB over a 4x2 sensor with only the red photosites lit
+        let data = (0..8u16)
+            .map(|index| if index % 2 == 0 && index < 4 { 4095 } else { 0 })
+            .collect();
+        let raw = RawImage {
+            make: String::new(),
+            model: String::new(),
+            clean_make: String::new(),
+            clean_model: String::new(),
+            width: 4,
+            height: 2,
+            cpp: 1,
+            wb_coeffs: [2.0, 1.0, 1.5, f32::NAN],
+            whitelevels: [4095; 4],
+            blacklevels: [0; 4],
+            xyz_to_cam: [[0.0; 3]; 4],
+            cfa: CFA::new("RGGB"),
+            crops: [0; 4],
+            blackareas: Vec::new(),
+            orientation: Orientation::Normal,
+            data: RawImageData::Integer(data),
+        };
+
+        let image = raw::develop(&raw).expect("develop");
+        assert_eq!(image.dimensions(), (2, 1));
+        assert_eq!(image.get_pixel(0, 0).0, [65_535, 0, 0]);
+        assert_eq!(image.get_pixel(1, 0).0, [65_535, 0, 0]);
+    }
+
+    #[test]
+    fn optional_formats_name_the_missing_feature() {
+        let jxl = fs::read("tests/data/tiny.jxl").expect("read fixture");
+        let avif = b"\0\0\0\x1cftypavif\0\0\0\0avifmif1miaf";
+        let cases: [(&[u8], &str, bool); 2] = [
+            (&jxl, "'jxl'", cfg!(feature = "jxl")),
+            (avif, "'avif'", cfg!(feature = "avif")),
+        ];
+        for (bytes, feature, enabled) in cases {
+            if enabled {
+                continue;
+            }
+            let err = load_image_from_bytes(bytes, &DecodeLimits::default()).unwrap_err();
+            assert_eq!(err.kind(), "UnsupportedFeature");
+            assert!(err.to_string().contains(feature), "{err}");
+        }
+    }
+
     #[test]
     fn preprocess_image_respects_aspect_ratio_and_width() {
         let image =
@@ -125,6 +1529,217 @@ mod tests {
         assert_eq!(processed.gray.dimensions(), (80, 40));
     }
 
+    #[test]
+    fn processed_image_keeps_alpha_only_when_it_matters() {
+        let mut rgba = ImageBuffer::from_pixel(2, 1, Rgba([10, 20, 30, 255]));
+        let opaque = ProcessedImage::new(DynamicImage::ImageRgba8(rgba.clone()));
+        assert!(opaque.alpha.is_none());
+
+        rgba.put_pixel(1, 0, Rgba([0, 0, 0, 40]));
+        let processed = ProcessedImage::new(DynamicImage::ImageRgba8(rgba));
+        let alpha = processed.alpha.expect("alpha channel");
+        assert_eq!(
+            (alpha.get_pixel(0, 0)[0], alpha.get_pixel(1, 0)[0]),
+            (255, 40)
+        );
+        assert_eq!(processed.alpha_threshold, DEFAULT_ALPHA_THRESHOLD);
+    }
+
+    #[test]
+    fn composite_over_fills_transparency_with_the_background() {
+        let mut rgba = ImageBuffer::from_pixel(3, 1, Rgba([200, 0, 0, 255]));
+        rgba.put_pixel(1, 0, Rgba([200, 0, 0, 0]));
+        rgba.put_pixel(2, 0, Rgba([200, 0, 0, 128]));
+
+        let composited = composite_over(&DynamicImage::ImageRgba8(rgba), [0, 0, 255]);
+        let rgb = composited.as_rgb8().expect("opaque RGB");
+        assert_eq!(rgb.get_pixel(0, 0).0, [200, 0, 0]);
+        assert_eq!(rgb.get_pixel(1, 0).0, [0, 0, 255]);
+        assert_eq!(rgb.get_pixel(2, 0).0, [100, 0, 127]);
+    }
+
+    #[test]
+    fn sixteen_bit_images_keep_their_precision() {
+        let mut png = Vec::new();
+        let deep = ImageBuffer::from_pixel(4, 2, Rgba([0x1234u16, 0x5678, 0x9abc, 0x8000]));
+        DynamicImage::ImageRgba16(deep)
+            .write_to(&mut Cursor::new(&mut png), image::ImageOutputFormat::Png)
+            .expect("write png");
+
+        let limits = DecodeLimits::default();
+        for image in [
+            ImageCrateDecoder
+                .decode_bytes(&png, "a", &limits)
+                .expect("decode"),
+            decoder().decode_bytes(&png, "a", &limits).expect("decode"),
+        ] {
+            let rgba = image.as_rgba16().expect("16-bit RGBA");
+            assert_eq!(rgba.get_pixel(0, 0).0, [0x1234, 0x5678, 0x9abc, 0x8000]);
+
+            let composited = composite_over(&image, [0, 0, 0]);
+            let rgb = composited.as_rgb16().expect("16-bit RGB");
+            assert_eq!(rgb.get_pixel(0, 0).0, [0x091a, 0x2b3c, 0x4d5e]);
+        }
+    }
+
+    #[cfg(feature = "icc")]
+    #[test]
+    fn icc_profiles_are_converted_to_srgb() {
+        use lcms2::{CIExyY, CIExyYTRIPLE, Profile, ToneCurve};
+
+        // sRGB primaries with a linear transfer curve: mid-gray brightens
+        let xy = |x, y| CIExyY { x, y, Y: 1.0 };
+        let primaries = CIExyYTRIPLE {
+            Red: xy(0.64, 0.33),
+            Green: xy(0.30, 0.60),
+            Blue: xy(0.15, 0.06),
+        };
+        let linear = ToneCurve::new(1.0);
+        let profile = Profile::new_rgb(&xy(0.3127, 0.3290), &primaries, &[&linear; 3])
+            .and_then(|profile| profile.icc())
+            .expect("build profile");
+
+        let gray = DynamicImage::ImageRgba8(ImageBuffer::from_pixel(1, 1, Rgba([50, 50, 50, 7])));
+        let converted = to_srgb(gray, &profile).to_rgba8();
+        let [r, g, b, a] = converted.get_pixel(0, 0).0;
+        assert!((120..=128).contains(&r), "{r}");
+        assert!(r.abs_diff(g) <= 1 && r.abs_diff(b) <= 1);
+        assert_eq!(a, 7);
+
+        let deep = DynamicImage::ImageRgb16(ImageBuffer::from_pixel(1, 1, Rgb([50 * 257; 3])));
+        let converted = to_srgb(deep, &profile);
+        assert!(converted.as_rgb16().is_some());
+        assert_eq!(converted.to_rgb8().get_pixel(0, 0).0[0], r);
+
+        let garbage = DynamicImage::ImageRgb8(ImageBuffer::from_pixel(1, 1, Rgb([50; 3])));
+        assert_eq!(to_srgb(garbage.clone(), b"not a profile"), garbage);
+    }
+
+    #[test]
+    fn chroma_key_masks_colors_within_tolerance() {
+        let colors = [[0, 250, 10], [40, 200, 40], [0, 0, 0]];
+        let rgb = ImageBuffer::from_fn(3, 1, |x, _| image::Rgb(colors[x as usize]));
+        let key = ChromaKey::new([0, 255, 0]);
+        let masked = key.mask(&DynamicImage::ImageRgb8(rgb));
+        let alpha: Vec<u8> = masked.to_rgba8().pixels().map(|pixel| pixel[3]).collect();
+        assert_eq!(alpha, [0, 255, 255]);
+
+        let loose = ChromaKey {
+            tolerance: 60,
+            ..key
+        };
+        assert!(loose.matches([40, 200, 40]));
+    }
+
+    #[test]
+    fn char_aspect_scales_derived_height() {
+        let image = DynamicImage::new_rgb8(100, 100);
+        let square = resize_to_grid_with_aspect(&image, 20, None, 1.0).expect("resize succeeds");
+        assert_eq!(square.dimensions(), (20, 20));
+        assert!(resize_to_grid_with_aspect(&image, 20, None, 0.0).is_err());
+    }
+
+    #[test]
+    fn resize_to_grid_honors_explicit_height() {
+        let image = DynamicImage::ImageRgba8(ImageBuffer::from_pixel(4, 4, Rgba([0, 0, 0, 255])));
+        let resized = resize_to_grid(&image, 30, Some(7)).expect("resize succeeds");
+        assert_eq!(resized.dimensions(), (30, 7));
+    }
+
+    #[test]
+    fn nearest_resize_keeps_hard_pixel_edges() {
+        let image = DynamicImage::ImageLuma8(ImageBuffer::from_fn(8, 8, |x, _| {
+            Luma([if x < 4 { 0 } else { 255 }])
+        }));
+        let levels = |filter| {
+            let resized = resize_to_grid_with_filter(&image, 6, Some(1), 1.0, filter)
+                .expect("resize succeeds")
+                .to_luma8();
+            let mut levels: Vec<u8> = resized.pixels().map(|pixel| pixel[0]).collect();
+            levels.sort_unstable();
+            levels.dedup();
+            levels
+        };
+        assert_eq!(levels(ResizeFilter::Nearest), [0, 255]);
+        assert!(levels(ResizeFilter::Lanczos3).len() > 2);
+
+        for filter in ["nearest", "triangle", "catmullrom", "lanczos3", "box"] {
+            assert_eq!(filter.parse::<ResizeFilter>().unwrap().to_string(), filter);
+        }
+        assert!("bicubic".parse::<ResizeFilter>().is_err());
+    }
+
+    #[test]
+    fn box_resize_averages_the_pixels_each_cell_covers() {
+        let image = DynamicImage::ImageLuma8(ImageBuffer::from_fn(6, 4, |x, y| {
+            Luma([(x * 40 + y * 10) as u8])
+        }));
+        let resized = resize_to_grid_with_filter(&image, 3, Some(2), 1.0, ResizeFilter::Box)
+            .expect("resize succeeds");
+        let DynamicImage::ImageLuma8(resized) = resized else {
+            panic!("8-bit gray stays 8-bit gray");
+        };
+        // Each cell covers 2x2 pixels: x*40 averages 20, 100, 180 and y*10 5, 25
+        assert_eq!(resized.as_raw(), &[25, 105, 185, 45, 125, 205]);
+
+        let rgba = DynamicImage::ImageRgba16(ImageBuffer::from_pixel(5, 3, Rgba([65535; 4])));
+        let grown = ResizeFilter::Box.resize(&rgba, 7, 4);
+        assert_eq!(grown.dimensions(), (7, 4));
+        assert!(grown.to_rgba8().pixels().all(|pixel| pixel.0 == [255; 4]));
+    }
+
+    #[test]
+    fn detects_pixel_art_and_snaps_to_integer_scales() {
+        let sprite = DynamicImage::ImageRgb8(ImageBuffer::from_fn(16, 16, |x, y| {
+            Rgb([(x % 4 * 60) as u8, (y % 2 * 200) as u8, 0])
+        }));
+        assert!(is_pixel_art(&sprite));
+        let photo = DynamicImage::ImageRgb8(ImageBuffer::from_fn(64, 64, |x, y| {
+            Rgb([(x * 4) as u8, (y * 4) as u8, ((x + y) * 2) as u8])
+        }));
+        assert!(!is_pixel_art(&photo));
+        assert!(!is_pixel_art(&DynamicImage::new_rgb8(
+            PIXEL_ART_MAX_SIDE + 1,
+            1
+        )));
+
+        assert_eq!(pixel_art_width(16, 80), 80);
+        assert_eq!(pixel_art_width(16, 70), 64);
+        assert_eq!(pixel_art_width(16, 12), 8);
+        assert_eq!(pixel_art_width(16, 16), 16);
+        assert_eq!(pixel_art_width(300, 100), 100);
+        assert_eq!(pixel_art_width(320, 100), 80);
+    }
+
+    #[test]
+    fn detects_line_art() {
+        let drawing = DynamicImage::ImageRgb8(ImageBuffer::from_fn(64, 64, |x, y| {
+            let stroke = x == y || x == 10 || (20..24).contains(&y);
+            Rgb(if stroke {
+                [10, 10, 10]
+            } else {
+                [250, 250, 250]
+            })
+        }));
+        assert!(is_line_art(&drawing));
+        let photo = DynamicImage::ImageRgb8(ImageBuffer::from_fn(64, 64, |x, y| {
+            Rgb([(x * 4) as u8, (y * 4) as u8, ((x + y) * 2) as u8])
+        }));
+        assert!(!is_line_art(&photo));
+        let red_drawing = DynamicImage::ImageRgb8(ImageBuffer::from_fn(64, 64, |x, _| {
+            Rgb(if x == 10 { [0, 0, 0] } else { [255, 0, 0] })
+        }));
+        assert!(!is_line_art(&red_drawing));
+        let checkerboard = DynamicImage::ImageRgb8(ImageBuffer::from_fn(64, 64, |x, y| {
+            Rgb(if (x + y) % 2 == 0 {
+                [0, 0, 0]
+            } else {
+                [255, 255, 255]
+            })
+        }));
+        assert!(!is_line_art(&checkerboard));
+    }
+
     #[test]
     fn preprocess_image_rejects_zero_width() {
         let image = DynamicImage::ImageRgba8(ImageBuffer::from_pixel(4, 4, Rgba([0, 0, 0, 255])));
@@ -0,0 +1,362 @@
+use std::fmt::{self, Write as _};
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::error::AsciiArtError;
+
+/// Columns left blank between the image and the art in a hybrid layout
+pub const GUTTER: u32 = 2;
+
+/// How the output is arranged on screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Layout {
+    /// The art alone
+    #[default]
+    Single,
+    /// The original image and the art side by side
+    Hybrid,
+}
+
+impl FromStr for Layout {
+    type Err = AsciiArtError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "single" => Ok(Layout::Single),
+            "hybrid" => Ok(Layout::Hybrid),
+            unknown => Err(AsciiArtError::InvalidArgument(format!(
+                "Unknown layout '{}'. Use 'single' or 'hybrid'.",
+                unknown
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Layout::Single => write!(f, "single"),
+            Layout::Hybrid => write!(f, "hybrid"),
+        }
+    }
+}
+
+/// Split `width` columns into image and art columns for a hybrid layout
+pub fn split_columns(width: u32) -> (u32, u32) {
+    let usable = width.saturating_sub(GUTTER).max(2);
+    let image = usable / 2;
+    (image, usable - image)
+}
+
+/// Compose a graphics sequence and rendered art into one side-by-side screen
+///
+/// `graphic` is drawn at the left edge over `graphic_rows` lines and the art
+/// starts `graphic_columns + GUTTER` columns in. Blank lines reserve the
+/// space first, so scrolling at the bottom of the screen cannot move the
+/// saved cursor. The cursor ends on the line below the taller of the two.
+pub fn side_by_side(graphic: &str, graphic_rows: u32, graphic_columns: u32, art: &str) -> String {
+    // Streamed art ends with a newline followed by the final reset
+    let (body, tail) = match art.rfind('\n') {
+        Some(end) => (&art[..end], &art[end + 1..]),
+        None => (art, ""),
+    };
+    let art_lines: Vec<&str> = if body.is_empty() {
+        Vec::new()
+    } else {
+        body.split('\n').collect()
+    };
+    let rows = graphic_rows.max(art_lines.len() as u32);
+    let offset = graphic_columns + GUTTER;
+
+    let mut out = String::with_capacity(graphic.len() + art.len() + rows as usize * 8);
+    for _ in 0..rows {
+        out.push('\n');
+    }
+    if rows > 0 {
+        let _ = write!(out, "\x1b[{rows}A");
+    }
+    out.push_str("\x1b7");
+    out.push_str(graphic);
+    out.push_str("\x1b8");
+    for line in &art_lines {
+        let _ = writeln!(out, "\x1b[{offset}C{line}");
+    }
+    for _ in art_lines.len() as u32..rows {
+        out.push('\n');
+    }
+    out.push_str(tail);
+    out
+}
+
+/// A cell on screen, counted from 1 at the top-left corner as cursor
+/// addressing does
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScreenPosition {
+    pub row: u32,
+    pub column: u32,
+}
+
+impl FromStr for ScreenPosition {
+    type Err = AsciiArtError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            AsciiArtError::InvalidArgument(format!(
+                "Invalid screen position '{}'. Use ROW,COL, counting from 1,1 at the top left.",
+                value
+            ))
+        };
+        let (row, column) = value.split_once(',').ok_or_else(invalid)?;
+        let parse = |part: &str| part.trim().parse::<u32>().ok().filter(|&n| n > 0);
+        match (parse(row), parse(column)) {
+            (Some(row), Some(column)) => Ok(Self { row, column }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for ScreenPosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{}", self.row, self.column)
+    }
+}
+
+/// A width or height in character cells, or as a percentage of the
+/// terminal's, such as `80` or `75%`
+///
+/// In config files a number of cells is written as a number and a percentage
+/// as a string.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Extent {
+    Cells(u32),
+    Percent(f32),
+}
+
+impl Extent {
+    /// The number of cells this stands for out of `available`, at least one
+    /// for a percentage
+    pub fn of(self, available: u32) -> u32 {
+        match self {
+            Extent::Cells(cells) => cells,
+            Extent::Percent(percent) => {
+                ((available as f32 * percent / 100.0).round() as u32).max(1)
+            }
+        }
+    }
+}
+
+impl FromStr for Extent {
+    type Err = AsciiArtError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            AsciiArtError::InvalidArgument(format!(
+                "Invalid size '{}'. Use a number of characters, such as 80, or a percentage \
+                 of the terminal, such as 75%.",
+                value
+            ))
+        };
+        match value.trim().strip_suffix('%') {
+            Some(percent) => percent
+                .trim()
+                .parse::<f32>()
+                .ok()
+                .filter(|percent| percent.is_finite() && *percent > 0.0)
+                .map(Extent::Percent)
+                .ok_or_else(invalid),
+            None => value
+                .trim()
+                .parse()
+                .map(Extent::Cells)
+                .map_err(|_| invalid()),
+        }
+    }
+}
+
+impl fmt::Display for Extent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Extent::Cells(cells) => write!(f, "{cells}"),
+            Extent::Percent(percent) => write!(f, "{percent}%"),
+        }
+    }
+}
+
+impl Serialize for Extent {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Extent::Cells(cells) => serializer.serialize_u32(*cells),
+            Extent::Percent(_) => serializer.collect_str(self),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Extent {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Written {
+            Cells(u32),
+            Text(String),
+        }
+        match Written::deserialize(deserializer)? {
+            Written::Cells(cells) => Ok(Extent::Cells(cells)),
+            Written::Text(text) => text.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+/// Writes output with each line moved to its own row from a [`ScreenPosition`]
+/// down, or passes it through unchanged without one
+///
+/// Lines are placed with cursor addressing rather than newlines, so nothing
+/// else on screen moves or scrolls. The cursor is saved before the first line
+/// and put back by [`Positioned::finish`], so whatever is written next goes
+/// where it would have without the art.
+pub struct Positioned<W: Write> {
+    inner: W,
+    position: Option<ScreenPosition>,
+    /// Lines already started, counting from 0
+    line: u32,
+    /// Whether the next byte begins a line, which must be placed first
+    line_start: bool,
+}
+
+impl<W: Write> Positioned<W> {
+    pub fn new(mut inner: W, position: Option<ScreenPosition>) -> io::Result<Self> {
+        if position.is_some() {
+            inner.write_all(b"\x1b7")?;
+        }
+        Ok(Self {
+            inner,
+            position,
+            line: 0,
+            line_start: true,
+        })
+    }
+
+    /// Put the cursor back where it was before the art
+    pub fn finish(&mut self) -> io::Result<()> {
+        if self.position.is_some() {
+            self.inner.write_all(b"\x1b8")?;
+        }
+        self.inner.flush()
+    }
+
+    /// The writer the lines are placed into
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+}
+
+impl<W: Write> Write for Positioned<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let Some(at) = self.position else {
+            return self.inner.write(buf);
+        };
+        let mut rest = buf;
+        while !rest.is_empty() {
+            if self.line_start {
+                let row = at.row.saturating_add(self.line);
+                write!(self.inner, "\x1b[{};{}H", row, at.column)?;
+                self.line_start = false;
+            }
+            match rest.iter().position(|&byte| byte == b'\n') {
+                Some(end) => {
+                    self.inner.write_all(&rest[..end])?;
+                    self.line += 1;
+                    self.line_start = true;
+                    rest = &rest[end + 1..];
+                }
+                None => {
+                    self.inner.write_all(rest)?;
+                    rest = &[];
+                }
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_leaves_a_gutter() {
+        assert_eq!(split_columns(80), (39, 39));
+        assert_eq!(split_columns(81), (39, 40));
+        assert_eq!(split_columns(0), (1, 1));
+    }
+
+    #[test]
+    fn art_is_shifted_past_the_graphic() {
+        let composed = side_by_side("<img>", 3, 4, "ab\ncd\n\x1b[0m");
+        assert_eq!(
+            composed,
+            "\n\n\n\x1b[3A\x1b7<img>\x1b8\x1b[6Cab\n\x1b[6Ccd\n\n\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn taller_art_reserves_its_own_rows() {
+        let composed = side_by_side("<img>", 1, 2, "a\nb\n");
+        assert_eq!(composed, "\n\n\x1b[2A\x1b7<img>\x1b8\x1b[4Ca\n\x1b[4Cb\n");
+    }
+
+    #[test]
+    fn positioned_lines_are_addressed_below_each_other() {
+        let at = "3,10".parse().ok();
+        let mut positioned = Positioned::new(Vec::new(), at).expect("saved cursor");
+        positioned.write_all(b"ab\nc").expect("written");
+        positioned.write_all(b"d\n\x1b[0m").expect("written");
+        positioned.finish().expect("restored cursor");
+        let out = String::from_utf8(positioned.inner).expect("utf8 output");
+        assert_eq!(out, "\x1b7\x1b[3;10Hab\x1b[4;10Hcd\x1b[5;10H\x1b[0m\x1b8");
+
+        let mut plain = Positioned::new(Vec::new(), None).expect("nothing written");
+        plain.write_all(b"ab\ncd\n").expect("written");
+        assert_eq!(plain.inner, b"ab\ncd\n");
+
+        let bottom = format!("{},1", u32::MAX).parse().ok();
+        let mut clamped = Positioned::new(Vec::new(), bottom).expect("saved cursor");
+        clamped.write_all(b"a\nb").expect("written");
+        let out = String::from_utf8(clamped.inner).expect("utf8 output");
+        assert_eq!(out, format!("\x1b7\x1b[{0};1Ha\x1b[{0};1Hb", u32::MAX));
+    }
+
+    #[test]
+    fn screen_positions_count_from_one() {
+        let at: ScreenPosition = "2, 7".parse().expect("valid position");
+        assert_eq!(at, ScreenPosition { row: 2, column: 7 });
+        assert_eq!(at.to_string(), "2,7");
+        assert!("0,1".parse::<ScreenPosition>().is_err());
+        assert!("4".parse::<ScreenPosition>().is_err());
+    }
+
+    #[test]
+    fn extents_are_cells_or_percentages() {
+        assert_eq!("80".parse::<Extent>().ok(), Some(Extent::Cells(80)));
+        assert_eq!(" 75% ".parse::<Extent>().ok(), Some(Extent::Percent(75.0)));
+        assert!("0%".parse::<Extent>().is_err());
+        assert!("wide".parse::<Extent>().is_err());
+        assert_eq!(Extent::Percent(75.0).of(120), 90);
+        assert_eq!(Extent::Percent(1.0).of(20), 1);
+        assert_eq!(Extent::Cells(30).of(120), 30);
+        assert_eq!(Extent::Percent(62.5).to_string(), "62.5%");
+    }
+
+    #[test]
+    fn layout_parses_names() {
+        assert_eq!("hybrid".parse::<Layout>().ok(), Some(Layout::Hybrid));
+        assert_eq!(Layout::Single.to_string(), "single");
+        assert!("grid".parse::<Layout>().is_err());
+    }
+}
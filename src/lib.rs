@@ -0,0 +1,55 @@
+//! Convert images to colorized ASCII art.
+//!
+//! The [`AsciiArtBuilder`] is the main entry point: configure it once and call
+//! [`AsciiArtBuilder::generate`] to obtain an [`AsciiArt`] value that can be
+//! rendered to the terminal or exported as often as needed.
+
+pub mod animation;
+pub mod ascii_converter;
+pub mod banner;
+pub mod builder;
+pub mod color;
+pub mod config;
+pub mod diff;
+pub mod edge_detector;
+pub mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod filters;
+pub mod font;
+pub mod graphics;
+pub mod grid;
+pub mod image_loader;
+pub mod layout;
+pub mod output;
+pub mod palette;
+pub mod parallel;
+pub mod progress;
+pub mod quantize;
+pub mod recording;
+pub mod renderer;
+pub mod score;
+#[cfg(all(feature = "server", not(target_arch = "wasm32")))]
+pub mod server;
+pub mod stats;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod terminal;
+pub mod testpattern;
+pub mod timings;
+pub mod tune;
+pub mod video;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod viewer;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod watch;
+
+pub use ascii_converter::{AsciiImage, Cell, Charset};
+pub use builder::{AsciiArt, AsciiArtBuilder, Background, Mode, StageCache};
+pub use color::ColorMetric;
+pub use error::AsciiArtError;
+pub use filters::{Clahe, ColorFilter, Denoise, Filter, Levels};
+pub use palette::{Palette, Theme};
+pub use quantize::AdaptivePalette;
+pub use renderer::{ColorDepth, RenderOptions};
@@ -1,14 +1,68 @@
-use clap::Parser;
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{self, BufWriter, IsTerminal, Read, Write};
+use std::iter;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::Duration;
 
-mod ascii_converter;
-mod edge_detector;
-mod image_loader;
-mod renderer;
-mod terminal;
+use clap::builder::PossibleValue;
+use clap::parser::ValueSource;
+use clap::{
+    ArgAction, ArgGroup, ArgMatches, Args, CommandFactory, FromArgMatches, Parser, Subcommand,
+    ValueEnum, ValueHint,
+};
+use clap_complete::Shell;
+use image::DynamicImage;
+use serde_json::json;
+use tracing::level_filters::LevelFilter;
+use tracing::{debug, info, warn};
 
-use crate::ascii_converter::{convert_to_ascii, AsciiGrid};
-use crate::image_loader::{load_image, preprocess_image, ProcessedImage};
-use terminal::WidthSource;
+use ascii_art_cli::animation::{
+    self, FrameFile, FrameFormat, FrameRange, Loop, Player, RawVideo, Timing,
+};
+use ascii_art_cli::ascii_converter::BUILTIN_CHARSETS;
+use ascii_art_cli::banner::{self, Gradient, DEFAULT_FONT_SIZE};
+use ascii_art_cli::builder::DEFAULT_STRIP_ROWS;
+use ascii_art_cli::color;
+use ascii_art_cli::config::{self, Config, Options};
+use ascii_art_cli::diff::Diff;
+use ascii_art_cli::filters::{Clahe, DEFAULT_CLAHE_CLIP_LIMIT, DEFAULT_CLAHE_TILES};
+use ascii_art_cli::graphics::{self, Protocol, RenderMode};
+use ascii_art_cli::grid::{self, GridDocument};
+use ascii_art_cli::image_loader::{
+    self, load_image_for_width, ChromaKey, DecodeLimits, ImageLoaderError, ResizeFilter,
+    DEFAULT_ALPHA_THRESHOLD, DEFAULT_CHAR_ASPECT, DEFAULT_CHROMA_TOLERANCE, DEFAULT_MAX_DIMENSION,
+    DEFAULT_MAX_PIXELS,
+};
+use ascii_art_cli::layout::{self, Extent, Layout, Positioned, ScreenPosition};
+use ascii_art_cli::output::{
+    LineEnding, OutputFields, OutputFiles, OutputFormat, OutputTemplate, TextEncoding, TextExport,
+    TextWriter,
+};
+use ascii_art_cli::parallel;
+use ascii_art_cli::progress::Progress;
+use ascii_art_cli::recording::{self, RecordFormat};
+#[cfg(feature = "server")]
+use ascii_art_cli::server;
+use ascii_art_cli::stats::Stats;
+use ascii_art_cli::terminal::{
+    self, ColorChoice, Hyperlink, PagedStdout, PagerChoice, Passthrough, TitleGuard, WidthSource,
+};
+use ascii_art_cli::testpattern::{Pattern, DEFAULT_PATTERN_SIZE, MAX_PATTERN_SIZE};
+use ascii_art_cli::timings::{time, Timings};
+use ascii_art_cli::tune;
+use ascii_art_cli::video::{self, VideoFormat};
+use ascii_art_cli::viewer;
+use ascii_art_cli::watch::{self, Watcher};
+use ascii_art_cli::{
+    AdaptivePalette, AsciiArtBuilder, AsciiArtError, Background, Charset, ColorDepth, ColorFilter,
+    ColorMetric, Denoise, Filter, Levels, Mode, Palette, Theme,
+};
 
 #[derive(Debug, Parser)]
 #[command(
@@ -16,67 +70,2706 @@ use terminal::WidthSource;
     version,
     author,
     about = "Convert images to colorized ASCII art in the terminal",
-    long_about = None
+    long_about = None,
+    subcommand_negates_reqs = true,
+    args_conflicts_with_subcommands = true
 )]
+#[command(group(ArgGroup::new("export")))]
+#[command(group(ArgGroup::new("watch_output").args(["output_template", "clear"])))]
 struct Cli {
-    /// Path to the input image file (PNG or JPEG)
-    #[arg(value_name = "IMAGE")]
-    image_path: String,
+    #[command(subcommand)]
+    command: Option<Command>,
 
-    /// Override the output width (characters)
+    /// Path to the input image (PNG, JPEG, GIF, WebP, BMP, TIFF, or ICO), or to an .aag grid
+    /// document saved with --format aag, shown at the size it was saved; several are converted
+    /// in turn
+    #[arg(
+        value_name = "IMAGE",
+        required_unless_present_any = ["files_from", "watch_dir"],
+        value_hint = ValueHint::FilePath
+    )]
+    image_paths: Vec<String>,
+
+    /// Also convert the images listed in FILE, or on stdin for '-', one path per line or
+    /// separated by NUL bytes (as `find -print0` writes them)
+    #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    files_from: Option<PathBuf>,
+
+    /// Write each image's art to a file named from this template instead of stdout, with
+    /// {stem}, {dir}, {mode}, {width}, {format}, and {ext} filled in per image
+    #[arg(long, value_name = "TEMPLATE", conflicts_with = "frames_out")]
+    output_template: Option<OutputTemplate>,
+
+    /// How lines end in art written to files (--output-template, --frames-out, or
+    /// redirected stdout); crlf for Windows tools such as Notepad
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = LineEnding::Lf,
+        conflicts_with_all = ["format", "record"]
+    )]
+    line_ending: LineEnding,
+
+    /// Encoding of art written to files: utf-8, utf-8-bom for Windows editors, or cp437 for
+    /// classic ANSI art viewers (characters it lacks become '?')
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = TextEncoding::Utf8,
+        conflicts_with_all = ["format", "record"]
+    )]
+    encoding: TextEncoding,
+
+    /// Replace files that --output-template names if they already exist
+    #[arg(long, requires = "output_template")]
+    overwrite: bool,
+
+    /// Keep converting images added to or changed in DIR, writing them where
+    /// --output-template says, or in place of the last one with --clear, until interrupted
+    #[arg(
+        long,
+        value_name = "DIR",
+        value_hint = ValueHint::DirPath,
+        requires = "watch_output"
+    )]
+    watch_dir: Option<PathBuf>,
+
+    /// Clear the screen and draw from the top-left corner, so each image replaces the one
+    /// before instead of scrolling it away
+    #[arg(long, conflicts_with_all = ["output_template", "export"])]
+    clear: bool,
+
+    /// Draw the art with its top-left corner at this terminal row and column, counting
+    /// from 1,1, leaving the rest of the screen and the cursor where they are
+    #[arg(
+        long,
+        value_name = "ROW,COL",
+        conflicts_with_all = [
+            "output_template", "export", "render", "layout", "preview_original"
+        ]
+    )]
+    at: Option<ScreenPosition>,
+
+    /// Show the art in $PAGER (or `less -R`) so its top can be scrolled back to: auto pages
+    /// art taller than the terminal, always any art; only when stdout is a terminal
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = PagerChoice::Never,
+        conflicts_with_all = ["output_template", "export", "watch_dir", "clear", "at"]
+    )]
+    pager: PagerChoice,
+
+    /// Show the art in a built-in full-screen viewer, scrolled with the arrow keys, Page
+    /// Up and Page Down, and closed with q; for when no pager keeps colors
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "pager", "compact", "output_template", "export", "watch_dir", "clear", "at",
+            "render", "layout", "preview_original"
+        ]
+    )]
+    scroll: bool,
+
+    /// Write as few bytes as possible, for slow links such as SSH: trailing blanks are
+    /// dropped and colors carry over between lines instead of being reset on each
+    #[arg(long)]
+    compact: bool,
+
+    /// Show the image being converted in the terminal title, putting the previous title
+    /// back at the end; Ctrl-C then stops after the current image
+    #[arg(long)]
+    title: bool,
+
+    /// Override the output width: characters, or a percentage of the terminal's such as 75%
+    #[arg(long, value_name = "WIDTH")]
+    width: Option<Extent>,
+
+    /// Output height: lines, or a percentage of the terminal's such as 50%; the width then
+    /// follows the image's proportions unless --width is given too
+    #[arg(long, value_name = "HEIGHT")]
+    height: Option<Extent>,
+
+    /// Only frames START..END of an animation, counting from 0 (`42..43` is frame 42);
+    /// without --frames-out, --format, or --record, the first of them is converted as a still
+    #[arg(long, value_name = "START..END")]
+    frames: Option<FrameRange>,
+
+    /// Take every Nth frame of --frames for --frames-out, --format, or --record
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u32).range(1..),
+        requires = "export"
+    )]
+    frame_step: u32,
+
+    /// Write every converted frame of an animation to its own numbered file in DIR
+    #[arg(
+        long,
+        value_name = "DIR",
+        value_hint = ValueHint::DirPath,
+        group = "export",
+        conflicts_with_all = ["render", "layout", "preview_original", "auto_tune"]
+    )]
+    frames_out: Option<PathBuf>,
+
+    /// Format of the files written by --frames-out
+    #[arg(long, value_enum, default_value_t = FrameFile::Ans, requires = "frames_out")]
+    frames_out_format: FrameFile,
+
+    /// Encode the converted animation as a looping GIF or an MP4 (which needs ffmpeg), or
+    /// save the converted cells as an .aag grid document to show again later by passing it
+    /// as the image; written to stdout or where --output-template says
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        group = "export",
+        conflicts_with_all = ["render", "layout", "preview_original", "auto_tune"]
+    )]
+    format: Option<FormatChoice>,
+
+    /// Record the converted animation playing, as an asciinema cast, a ttyrec file, or a
+    /// `script` typescript and timing file, written to stdout or where --output-template says
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        group = "export",
+        conflicts_with_all = ["render", "layout", "preview_original", "auto_tune"]
+    )]
+    record: Option<RecordFormat>,
+
+    /// How to draw the image
+    #[arg(long, value_enum, default_value_t = RenderMode::Ascii)]
+    render: RenderMode,
+
+    /// Show the original image above the art (iTerm2 or kitty inline image)
+    #[arg(long)]
+    preview_original: bool,
+
+    /// How to arrange the output
+    #[arg(long, value_enum, default_value_t = Layout::Single)]
+    layout: Layout,
+
+    /// Rendering mode
+    #[arg(long, value_enum, default_value_t = ModeChoice::Fixed(Mode::Standard))]
+    mode: ModeChoice,
+
+    /// Characters ordered from dark to light, or the name of a built-in ramp (see
+    /// `charset list`), replacing the default ramp; a ramp of double-width (CJK or
+    /// emoji) characters samples half as many columns
+    #[arg(long, value_name = "CHARS")]
+    charset: Option<String>,
+
+    /// Read the ramp, or a brightness-to-character table, from a file
+    #[arg(long, value_name = "FILE", conflicts_with = "charset")]
+    charset_file: Option<PathBuf>,
+
+    /// Height-to-width ratio of a terminal character cell
+    #[arg(long, value_name = "RATIO", default_value_t = DEFAULT_CHAR_ASPECT)]
+    char_aspect: f32,
+
+    /// Dither the character ramp (Floyd-Steinberg) for smoother gradients
+    #[arg(long)]
+    dither: bool,
+
+    /// Dither cell colors (Floyd-Steinberg) when quantizing to 8, 16, or 256 colors
+    #[arg(long)]
+    color_dither: bool,
+
+    /// Shade 8- and 16-color output with ░▒▓█ glyphs mixing foreground and background colors
+    #[arg(long)]
+    shade: bool,
+
+    /// Remap the input range before conversion, like an image editor's Levels
+    #[arg(long, value_name = "BLACK,WHITE[,GAMMA]")]
+    levels: Option<Levels>,
+
+    /// Adjust contrast before conversion (positive values increase it)
+    #[arg(
+        long,
+        value_name = "AMOUNT",
+        default_value_t = 0.0,
+        allow_negative_numbers = true,
+        value_parser = finite
+    )]
+    contrast: f32,
+
+    /// Smooth noise before resizing: gaussian[:SIGMA], median[:RADIUS], or
+    /// bilateral[:SPATIAL[:RANGE]] (edge-preserving)
+    #[arg(long, value_name = "FILTER")]
+    denoise: Option<Denoise>,
+
+    /// Equalize brightness locally (CLAHE) to bring out detail in both highlights and shadows
+    #[arg(long)]
+    clahe: bool,
+
+    /// Tiles along each side of the image for --clahe
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = DEFAULT_CLAHE_TILES,
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    clahe_tiles: u32,
+
+    /// Histogram clip limit for --clahe, as a multiple of the average (higher is stronger)
+    #[arg(
+        long,
+        value_name = "LIMIT",
+        default_value_t = DEFAULT_CLAHE_CLIP_LIMIT,
+        value_parser = positive
+    )]
+    clahe_clip: f32,
+
+    /// Sharpen brightness after resizing with an unsharp mask of this strength (e.g. 1.0)
+    #[arg(long, value_name = "AMOUNT", default_value_t = 0.0)]
+    sharpen: f32,
+
+    /// Sharpen the cell colors as well as the brightness
+    #[arg(long)]
+    sharpen_color: bool,
+
+    /// Invert brightness and colors like a photographic negative
+    #[arg(long)]
+    negate: bool,
+
+    /// Alpha (0-255) below which pixels are left blank; 0 treats every pixel as opaque
+    #[arg(long, value_name = "ALPHA", default_value_t = DEFAULT_ALPHA_THRESHOLD)]
+    alpha_threshold: u8,
+
+    /// Sampling filter for shrinking the image; nearest is fastest and keeps pixel art crisp,
+    /// box is nearly as fast and smooth (and is what play uses)
+    #[arg(long, value_enum, value_name = "FILTER", default_value_t = ResizeFilter::Lanczos3)]
+    resize_filter: ResizeFilter,
+
+    /// Treat the image as pixel art: integer nearest-neighbor scaling, no dithering, and
+    /// solid blocks when colored (auto: only if it is small with few colors)
+    #[arg(
+        long,
+        value_enum,
+        value_name = "WHEN",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "always"
+    )]
+    pixel_art: Option<PixelArtChoice>,
+
+    /// Color matching metric
+    #[arg(long, value_enum, default_value_t = ColorMetric::Oklab)]
+    color_metric: ColorMetric,
+
+    /// When to use ANSI colors
+    #[arg(long, value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+
+    /// Color depth
+    #[arg(long, value_enum, default_value_t = ColorDepthChoice::Auto)]
+    color_depth: ColorDepthChoice,
+
+    /// Make the art, or a caption line under it, a link to the source image, in terminals
+    /// known to support OSC 8 links (FORCE_HYPERLINK=1 links anywhere); elsewhere the
+    /// caption is plain text
+    #[arg(
+        long,
+        value_enum,
+        value_name = "TARGET",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "art",
+        conflicts_with = "export"
+    )]
+    hyperlink: Option<Hyperlink>,
+
+    /// Match colors against a palette file (TOML or JSON listing 16 or 256 colors), or
+    /// `adaptive:N` to extract N colors from the image and render them in truecolor
+    #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    palette: Option<PathBuf>,
+
+    /// Seed for picking the starting colors of `--palette adaptive:N`
+    #[arg(long, value_name = "SEED", default_value_t = 0)]
+    palette_seed: u64,
+
+    /// Snap output colors to a theme
+    #[arg(long, value_enum)]
+    theme: Option<Theme>,
+
+    /// Stylize cell colors; repeat to chain filters in order
+    #[arg(long = "filter", value_name = "FILTER", value_enum)]
+    color_filters: Vec<ColorFilter>,
+
+    /// Rotate the hue of cell colors by this many degrees
+    #[arg(
+        long,
+        value_name = "DEGREES",
+        default_value_t = 0.0,
+        allow_negative_numbers = true,
+        value_parser = finite
+    )]
+    hue_shift: f32,
+
+    /// Change cell color saturation by this percentage (-100 removes all color)
+    #[arg(
+        long,
+        value_name = "PERCENT",
+        default_value_t = 0.0,
+        allow_negative_numbers = true,
+        value_parser = finite
+    )]
+    saturation: f32,
+
+    /// Change cell color lightness by this percentage of the full range
+    #[arg(
+        long,
+        value_name = "PERCENT",
+        default_value_t = 0.0,
+        allow_negative_numbers = true,
+        value_parser = finite
+    )]
+    lightness: f32,
+
+    /// Apply the HSL adjustments before characters are picked, so lightness changes them too
+    #[arg(long)]
+    hsl_brightness: bool,
+
+    /// Correct color casts with a gray-world white balance of the cell colors
+    #[arg(long)]
+    auto_wb: bool,
+
+    /// Ask the terminal for its real 16-color palette (OSC 4) and match against it
+    #[arg(long)]
+    query_palette: bool,
+
+    /// Terminal background the art is shown on
+    #[arg(long, value_enum, default_value_t = BackgroundChoice::Auto)]
+    background: BackgroundChoice,
+
+    /// Fill transparent regions with this color (#rrggbb or a name) instead of leaving them blank
+    #[arg(long, value_name = "COLOR")]
+    background_color: Option<String>,
+
+    /// Mask out a solid background color (#rrggbb or a name), leaving those cells blank
+    #[arg(long, value_name = "COLOR")]
+    chroma_key: Option<String>,
+
+    /// How far each RGB channel may differ from --chroma-key and still be masked
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_CHROMA_TOLERANCE)]
+    chroma_tolerance: u8,
+
+    /// Number of worker threads (0 = one per CPU)
+    #[arg(long, default_value_t = 0)]
+    threads: usize,
+
+    /// Refuse to decode images with more pixels than this
+    #[arg(long, default_value_t = DEFAULT_MAX_PIXELS)]
+    max_pixels: u64,
+
+    /// Refuse to decode images wider or taller than this
+    #[arg(long, default_value_t = DEFAULT_MAX_DIMENSION)]
+    max_dimension: u32,
+
+    /// Report time spent in each pipeline stage on stderr
+    #[arg(long)]
+    timings: bool,
+
+    /// Report the characters, colors, brightness, and byte size of the art on stderr
+    #[arg(long)]
+    stats: bool,
+
+    /// Report how closely the art reproduces the image (SSIM and PSNR) on stderr
+    #[arg(long)]
+    score: bool,
+
+    /// Search gamma, contrast, dithering, and charset for the best --score, render
+    /// with the winner, and print the equivalent flags on stderr
+    #[arg(long, conflicts_with_all = ["levels", "contrast", "dither", "charset", "charset_file"])]
+    auto_tune: bool,
+
+    /// Log more detail on stderr (-v for progress notes, -vv for pipeline debugging)
+    #[arg(short, long, global = true, action = ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+
+    /// Print only the art and errors
+    #[arg(short, long, global = true)]
+    quiet: bool,
+
+    /// How to report errors on stderr
+    #[arg(long, global = true, value_enum, default_value_t = ErrorFormat::Text)]
+    error_format: ErrorFormat,
+
+    /// Ignore the terminal and environment so output is byte-for-byte reproducible
+    #[arg(long)]
+    deterministic: bool,
+
+    /// Apply a preset: "photo", "logo", "document", "pixel-art", or one saved with --save-preset
+    #[arg(long, value_name = "NAME")]
+    preset: Option<String>,
+
+    /// Save the options given on the command line as a preset in the config file
+    #[arg(long, value_name = "NAME")]
+    save_preset: Option<String>,
+
+    /// Read defaults from this file instead of ~/.config/ascii-art/config.toml
+    #[arg(
+        long,
+        value_name = "FILE",
+        value_hint = ValueHint::FilePath,
+        conflicts_with = "no_config"
+    )]
+    config: Option<PathBuf>,
+
+    /// Ignore the configuration file
+    #[arg(long)]
+    no_config: bool,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Print a shell completion script to stdout
+    Completions {
+        /// Shell to generate completions for
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+    /// Play an animated GIF, or a slideshow of images, in the terminal
+    Play(PlayArgs),
+    /// Stream an animated GIF, or a slideshow of images, to network clients
+    Serve(ServeArgs),
+    /// Work with character ramps
+    Charset {
+        #[command(subcommand)]
+        command: CharsetCommand,
+    },
+    /// Convert two images, or one image under two presets, and show the cells that changed
+    Diff(DiffArgs),
+    /// Render a built-in test image, to check colors, --char-aspect, and charsets
+    #[command(name = "testpattern")]
+    TestPattern(TestPatternArgs),
+    /// Draw text as a large banner, with the built-in font or a TrueType font
+    Text(TextArgs),
+}
+
+#[derive(Debug, Subcommand)]
+enum CharsetCommand {
+    /// Show every built-in ramp drawn over a gradient from black to white
+    List {
+        /// Width of the gradients (characters)
+        #[arg(long, default_value_t = 60)]
+        width: u32,
+    },
+    /// Show one built-in ramp, and its characters, over a gradient
+    Preview {
+        /// Name of the ramp
+        #[arg(value_parser = BUILTIN_CHARSETS.map(|(name, _)| name))]
+        name: String,
+
+        /// Width of the gradient (characters)
+        #[arg(long, default_value_t = 60)]
+        width: u32,
+    },
+    /// Reorder a ramp by the ink each character covers in a built-in 8x8 font
+    Calibrate(CalibrateArgs),
+}
+
+#[derive(Debug, Args)]
+#[command(group(ArgGroup::new("ramp").required(true)))]
+struct CalibrateArgs {
+    /// Characters to calibrate, in any order
+    #[arg(value_name = "CHARS", group = "ramp")]
+    chars: Option<String>,
+
+    /// Read the characters from a charset file instead
+    #[arg(long, value_name = "FILE", group = "ramp", value_hint = ValueHint::FilePath)]
+    file: Option<PathBuf>,
+
+    /// Print a table for --charset-file that spaces the characters by measured ink
+    #[arg(long)]
+    table: bool,
+}
+
+#[derive(Debug, Args)]
+struct DiffArgs {
+    /// The image converted first
+    #[arg(value_name = "IMAGE", value_hint = ValueHint::FilePath)]
+    image: String,
+
+    /// The image compared against it; IMAGE again when omitted
+    #[arg(
+        value_name = "OTHER",
+        value_hint = ValueHint::FilePath,
+        required_unless_present_any = ["preset_a", "preset_b"]
+    )]
+    other: Option<String>,
+
+    /// Preset for the first conversion, built in or saved with --save-preset
+    #[arg(long, value_name = "NAME")]
+    preset_a: Option<String>,
+
+    /// Preset for the second conversion
+    #[arg(long, value_name = "NAME")]
+    preset_b: Option<String>,
+
+    /// Output width (characters) of both conversions
+    #[arg(long, default_value_t = 80)]
+    width: u32,
+
+    /// Exit with status 1 when more than this percentage of cells changed
+    #[arg(long, value_name = "PERCENT", default_value_t = 0.0)]
+    threshold: f64,
+
+    /// When to highlight the changes with colors
+    #[arg(long, value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+
+    /// Read defaults and presets from this file instead of ~/.config/ascii-art/config.toml
+    #[arg(
+        long,
+        value_name = "FILE",
+        value_hint = ValueHint::FilePath,
+        conflicts_with = "no_config"
+    )]
+    config: Option<PathBuf>,
+
+    /// Ignore the configuration file
+    #[arg(long)]
+    no_config: bool,
+}
+
+#[derive(Debug, Args)]
+struct TestPatternArgs {
+    /// Pattern to draw
+    #[arg(value_enum, default_value_t = Pattern::Gradient)]
+    pattern: Pattern,
+
+    /// Output width (characters)
+    #[arg(long, default_value_t = 80)]
+    width: u32,
+
+    /// Color depth of the output
+    #[arg(long, value_enum, default_value_t = ColorDepth::TrueColor)]
+    color_depth: ColorDepth,
+
+    /// Characters ordered from dark to light, or the name of a built-in ramp,
+    /// replacing the default ramp
+    #[arg(long, value_name = "CHARS")]
+    charset: Option<String>,
+
+    /// Height-to-width ratio of a terminal character cell
+    #[arg(long, value_name = "RATIO", default_value_t = DEFAULT_CHAR_ASPECT)]
+    char_aspect: f32,
+
+    /// Side of the pattern image in pixels, up to 4096
+    #[arg(
+        long,
+        value_name = "PIXELS",
+        default_value_t = DEFAULT_PATTERN_SIZE,
+        value_parser = clap::value_parser!(u32).range(1..=i64::from(MAX_PATTERN_SIZE))
+    )]
+    size: u32,
+
+    /// Write the pattern image to this file (format from the extension) instead
+    #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    save: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+struct TextArgs {
+    /// Text to draw; "\n" starts a new line
+    text: String,
+
+    /// TrueType or OpenType font to draw with instead of the built-in 8x8 font
+    #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    font: Option<PathBuf>,
+
+    /// Height of a line of text drawn with --font, in pixels, up to 1024
+    #[arg(long, value_name = "PIXELS", default_value_t = DEFAULT_FONT_SIZE, requires = "font")]
+    font_size: f32,
+
+    /// Colors blended from left to right: 'rainbow', or comma-separated colors
+    #[arg(long, value_name = "COLORS", default_value = "rainbow")]
+    gradient: Gradient,
+
+    /// Output width (characters); the banner's natural width, up to the terminal's, when omitted
     #[arg(long)]
     width: Option<u32>,
 
-    /// Rendering mode: "standard" or "edge"
-    #[arg(long, default_value = "standard")]
-    mode: String,
+    /// Color depth of the output
+    #[arg(long, value_enum, default_value_t = ColorDepth::TrueColor)]
+    color_depth: ColorDepth,
+
+    /// Characters ordered from dark to light, replacing solid blocks
+    #[arg(long, value_name = "CHARS", default_value = PIXEL_ART_CHARSET)]
+    charset: String,
+}
+
+#[derive(Debug, Args)]
+struct PlayArgs {
+    /// GIF animations or still images, played in order
+    #[arg(
+        value_name = "SOURCE",
+        required_unless_present = "stdin_raw",
+        value_hint = ValueHint::FilePath
+    )]
+    sources: Vec<String>,
+
+    /// Play headerless video frames of this size and pixel format from stdin, as
+    /// `ffmpeg -f rawvideo` writes them (rgb24, rgba, or gray)
+    #[arg(
+        long,
+        value_name = "WxH:FORMAT",
+        requires = "fps",
+        conflicts_with_all = ["sources", "loops"]
+    )]
+    stdin_raw: Option<RawVideo>,
+
+    /// Output width: characters, or a percentage of the terminal's [default: terminal width]
+    #[arg(long, value_name = "WIDTH")]
+    width: Option<Extent>,
+
+    /// Rendering mode
+    #[arg(long, value_enum, default_value_t = Mode::Standard)]
+    mode: Mode,
+
+    /// Color depth of the output
+    #[arg(long, value_enum, default_value_t = ColorDepthChoice::Auto)]
+    color_depth: ColorDepthChoice,
+
+    /// Seconds each still image stays on screen
+    #[arg(long, value_name = "SECONDS", default_value_t = 3.0)]
+    slide_duration: f64,
+
+    /// Show frames at this rate instead of the source's own timing
+    #[arg(long, value_name = "FPS")]
+    fps: Option<f64>,
+
+    /// Play faster (above 1) or slower (below 1) than the source
+    #[arg(long, value_name = "FACTOR", default_value_t = 1.0)]
+    speed: f64,
+
+    /// Draw every frame even when conversion falls behind, slowing playback down
+    #[arg(long)]
+    no_frame_skip: bool,
+
+    /// Only the frames from START up to, not including, END, counting from 0
+    #[arg(long, value_name = "START..END", default_value = "0..")]
+    frames: FrameRange,
+
+    /// Take every Nth frame of the range, each shown for the frames it skips
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    frame_step: u32,
+
+    /// Times to play: a number, 'once', or 'infinite' [default: the GIF's own loop count]
+    #[arg(long = "loop", value_name = "N")]
+    loops: Option<Loop>,
+
+    /// Show the file name and frame number in the terminal title while playing
+    #[arg(long)]
+    title: bool,
+}
+
+#[derive(Debug, Args)]
+#[command(group(ArgGroup::new("protocol").required(true)))]
+struct ServeArgs {
+    /// GIF animations or still images, played in order and looped
+    #[arg(value_name = "SOURCE", required = true, value_hint = ValueHint::FilePath)]
+    sources: Vec<String>,
+
+    /// Accept raw TCP/telnet connections
+    #[arg(long, group = "protocol")]
+    telnet: bool,
+
+    /// Accept WebSocket connections, sending one text message per frame
+    #[arg(long, group = "protocol")]
+    websocket: bool,
+
+    /// How WebSocket messages encode each frame
+    #[arg(long, value_enum, default_value_t = FrameFormat::Ansi, requires = "websocket")]
+    frame_format: FrameFormat,
+
+    /// Address to listen on [default: 127.0.0.1:2323 for telnet, 127.0.0.1:8765 for WebSocket]
+    #[arg(long, value_name = "ADDR")]
+    listen: Option<SocketAddr>,
+
+    /// Output width (characters)
+    #[arg(long, default_value_t = 80)]
+    width: u32,
+
+    /// Rendering mode
+    #[arg(long, value_enum, default_value_t = Mode::Standard)]
+    mode: Mode,
+
+    /// Color depth sent to clients
+    #[arg(long, value_enum, default_value_t = ColorDepth::Ansi256)]
+    color_depth: ColorDepth,
+
+    /// Seconds each still image stays on screen
+    #[arg(long, value_name = "SECONDS", default_value_t = 3.0)]
+    slide_duration: f64,
+
+    /// Show frames at this rate instead of the source's own timing
+    #[arg(long, value_name = "FPS")]
+    fps: Option<f64>,
+
+    /// Play faster (above 1) or slower (below 1) than the source
+    #[arg(long, value_name = "FACTOR", default_value_t = 1.0)]
+    speed: f64,
+
+    /// Only the frames from START up to, not including, END, counting from 0
+    #[arg(long, value_name = "START..END", default_value = "0..")]
+    frames: FrameRange,
+
+    /// Take every Nth frame of the range, each shown for the frames it skips
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    frame_step: u32,
+}
+
+/// `--error-format`: how failures are reported on stderr
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ErrorFormat {
+    /// A human-readable message
+    Text,
+    /// One JSON object with `kind`, `message`, `exit_code`, and `path` when known
+    Json,
+}
+
+/// `--color-depth`: detect the depth from the terminal, or use a fixed one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ColorDepthChoice {
+    Auto,
+    Fixed(ColorDepth),
+}
+
+impl ValueEnum for ColorDepthChoice {
+    fn value_variants<'a>() -> &'a [Self] {
+        static VARIANTS: OnceLock<Vec<ColorDepthChoice>> = OnceLock::new();
+        VARIANTS.get_or_init(|| {
+            let fixed = ColorDepth::value_variants().iter().copied();
+            iter::once(ColorDepthChoice::Auto)
+                .chain(fixed.map(ColorDepthChoice::Fixed))
+                .collect()
+        })
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        match self {
+            ColorDepthChoice::Auto => {
+                Some(PossibleValue::new("auto").help("Detect from COLORTERM, TERM, and terminfo"))
+            }
+            ColorDepthChoice::Fixed(depth) => depth.to_possible_value(),
+        }
+    }
+}
+
+impl FromStr for ColorDepthChoice {
+    type Err = AsciiArtError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "auto" => Ok(ColorDepthChoice::Auto),
+            depth => depth.parse().map(ColorDepthChoice::Fixed),
+        }
+    }
+}
+
+/// `--mode`: pick the mode from the image, or use a fixed one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ModeChoice {
+    Auto,
+    Fixed(Mode),
+}
+
+impl ValueEnum for ModeChoice {
+    fn value_variants<'a>() -> &'a [Self] {
+        static VARIANTS: OnceLock<Vec<ModeChoice>> = OnceLock::new();
+        VARIANTS.get_or_init(|| {
+            let fixed = Mode::value_variants().iter().copied();
+            iter::once(ModeChoice::Auto)
+                .chain(fixed.map(ModeChoice::Fixed))
+                .collect()
+        })
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        match self {
+            ModeChoice::Auto => Some(PossibleValue::new("auto").help(
+                "Edge contours for line art, pixel-art blocks for small images with few \
+                 colors, standard otherwise (there is no threshold mode to pick)",
+            )),
+            ModeChoice::Fixed(mode) => mode.to_possible_value(),
+        }
+    }
+}
+
+impl FromStr for ModeChoice {
+    type Err = AsciiArtError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "auto" => Ok(ModeChoice::Auto),
+            mode => mode.parse().map(ModeChoice::Fixed),
+        }
+    }
+}
+
+/// `--format`: encode a video, or save a grid document
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FormatChoice {
+    Video(VideoFormat),
+    Grid,
+}
+
+impl ValueEnum for FormatChoice {
+    fn value_variants<'a>() -> &'a [Self] {
+        static VARIANTS: OnceLock<Vec<FormatChoice>> = OnceLock::new();
+        VARIANTS.get_or_init(|| {
+            let videos = VideoFormat::value_variants().iter().copied();
+            videos
+                .map(FormatChoice::Video)
+                .chain(iter::once(FormatChoice::Grid))
+                .collect()
+        })
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        match self {
+            FormatChoice::Video(format) => format.to_possible_value(),
+            FormatChoice::Grid => Some(
+                PossibleValue::new(grid::EXTENSION)
+                    .help("A grid document of the converted cells, with their colors"),
+            ),
+        }
+    }
+}
+
+impl FromStr for FormatChoice {
+    type Err = AsciiArtError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            grid::EXTENSION => Ok(FormatChoice::Grid),
+            format => format.parse().map(FormatChoice::Video),
+        }
+    }
+}
+
+/// `--background`: detect the background from the terminal, or use a fixed one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BackgroundChoice {
+    Auto,
+    /// Sample the exact color with OSC 11, also filling transparent regions with it
+    Terminal,
+    Fixed(Background),
+}
+
+impl ValueEnum for BackgroundChoice {
+    fn value_variants<'a>() -> &'a [Self] {
+        static VARIANTS: OnceLock<Vec<BackgroundChoice>> = OnceLock::new();
+        VARIANTS.get_or_init(|| {
+            let fixed = Background::value_variants().iter().copied();
+            [BackgroundChoice::Auto, BackgroundChoice::Terminal]
+                .into_iter()
+                .chain(fixed.map(BackgroundChoice::Fixed))
+                .collect()
+        })
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        match self {
+            BackgroundChoice::Auto => {
+                Some(PossibleValue::new("auto").help("Detect from COLORFGBG or an OSC 11 query"))
+            }
+            BackgroundChoice::Terminal => Some(
+                PossibleValue::new("terminal")
+                    .help("Query the color with OSC 11 and fill transparent regions with it"),
+            ),
+            BackgroundChoice::Fixed(background) => background.to_possible_value(),
+        }
+    }
+}
+
+impl FromStr for BackgroundChoice {
+    type Err = AsciiArtError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "auto" => Ok(BackgroundChoice::Auto),
+            "terminal" => Ok(BackgroundChoice::Terminal),
+            background => background.parse().map(BackgroundChoice::Fixed),
+        }
+    }
+}
+
+/// `--pixel-art`: always treat the image as pixel art, or only when it looks like it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum PixelArtChoice {
+    /// Detect from the image: small, with only a few distinct colors
+    Auto,
+    /// Assert that the image is pixel art
+    Always,
+}
+
+impl FromStr for PixelArtChoice {
+    type Err = AsciiArtError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "auto" => Ok(PixelArtChoice::Auto),
+            "always" => Ok(PixelArtChoice::Always),
+            unknown => Err(AsciiArtError::InvalidArgument(format!(
+                "Unknown pixel art choice '{}'. Use 'auto' or 'always'.",
+                unknown
+            ))),
+        }
+    }
+}
+
+/// Ramp used for colored pixel art: every cell a solid block of its color
+const PIXEL_ART_CHARSET: &str = "█";
+
+/// The name clap shows for a flag value, as written back to presets
+fn value_name<T: ValueEnum>(value: &T) -> Option<String> {
+    value
+        .to_possible_value()
+        .map(|value| value.get_name().to_string())
+}
+
+/// Parse an option from the config file into `field`, unless given on the command line
+fn merge<T: FromStr<Err = AsciiArtError>>(
+    field: &mut T,
+    value: Option<String>,
+    given: bool,
+) -> Result<(), AsciiArtError> {
+    if let Some(value) = value.filter(|_| !given) {
+        *field = value.parse()?;
+    }
+    Ok(())
+}
+
+/// Parse a flag value that must be a finite number
+fn finite(value: &str) -> Result<f32, String> {
+    match value.parse::<f32>() {
+        Ok(number) if number.is_finite() => Ok(number),
+        Ok(_) => Err("must be a finite number".to_string()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+/// Parse a flag value that must be a finite number above zero
+fn positive(value: &str) -> Result<f32, String> {
+    match finite(value)? {
+        number if number > 0.0 => Ok(number),
+        _ => Err("must be greater than zero".to_string()),
+    }
+}
+
+impl Cli {
+    /// The options given explicitly on the command line
+    fn given_options(&self, matches: &ArgMatches) -> Options {
+        let given = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+        let name = |value: Option<String>, id: &str| value.filter(|_| given(id));
+        Options {
+            width: self.width,
+            height: self.height,
+            render: name(value_name(&self.render), "render"),
+            layout: name(value_name(&self.layout), "layout"),
+            mode: name(value_name(&self.mode), "mode"),
+            charset: self.charset.clone(),
+            charset_file: self.charset_file.clone(),
+            char_aspect: given("char_aspect").then_some(self.char_aspect),
+            dither: given("dither").then_some(self.dither),
+            color_dither: given("color_dither").then_some(self.color_dither),
+            shade: given("shade").then_some(self.shade),
+            levels: self.levels.as_ref().map(ToString::to_string),
+            contrast: given("contrast").then_some(self.contrast),
+            denoise: self.denoise.as_ref().map(ToString::to_string),
+            clahe: given("clahe").then_some(self.clahe),
+            clahe_tiles: given("clahe_tiles").then_some(self.clahe_tiles),
+            clahe_clip: given("clahe_clip").then_some(self.clahe_clip),
+            sharpen: given("sharpen").then_some(self.sharpen),
+            sharpen_color: given("sharpen_color").then_some(self.sharpen_color),
+            negate: given("negate").then_some(self.negate),
+            alpha_threshold: given("alpha_threshold").then_some(self.alpha_threshold),
+            resize_filter: name(value_name(&self.resize_filter), "resize_filter"),
+            pixel_art: self.pixel_art.as_ref().and_then(value_name),
+            color_metric: name(value_name(&self.color_metric), "color_metric"),
+            color: name(value_name(&self.color), "color"),
+            color_depth: name(value_name(&self.color_depth), "color_depth"),
+            palette: self.palette.clone(),
+            palette_seed: given("palette_seed").then_some(self.palette_seed),
+            theme: self.theme.as_ref().and_then(value_name),
+            filter: given("color_filters")
+                .then(|| self.color_filters.iter().map(ToString::to_string).collect()),
+            hue_shift: given("hue_shift").then_some(self.hue_shift),
+            saturation: given("saturation").then_some(self.saturation),
+            lightness: given("lightness").then_some(self.lightness),
+            hsl_brightness: given("hsl_brightness").then_some(self.hsl_brightness),
+            auto_wb: given("auto_wb").then_some(self.auto_wb),
+            background: name(value_name(&self.background), "background"),
+            background_color: self.background_color.clone(),
+            chroma_key: self.chroma_key.clone(),
+            chroma_tolerance: given("chroma_tolerance").then_some(self.chroma_tolerance),
+            threads: given("threads").then_some(self.threads),
+        }
+    }
+
+    /// Fill in every option not given on the command line from `options`
+    fn apply_options(
+        &mut self,
+        matches: &ArgMatches,
+        options: Options,
+    ) -> Result<(), AsciiArtError> {
+        options.validate()?;
+        let given = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+        merge(&mut self.render, options.render, given("render"))?;
+        merge(&mut self.layout, options.layout, given("layout"))?;
+        merge(&mut self.mode, options.mode, given("mode"))?;
+        merge(
+            &mut self.resize_filter,
+            options.resize_filter,
+            given("resize_filter"),
+        )?;
+        merge(
+            &mut self.color_metric,
+            options.color_metric,
+            given("color_metric"),
+        )?;
+        merge(&mut self.color, options.color, given("color"))?;
+        merge(
+            &mut self.color_depth,
+            options.color_depth,
+            given("color_depth"),
+        )?;
+        merge(
+            &mut self.background,
+            options.background,
+            given("background"),
+        )?;
+        if self.levels.is_none() {
+            self.levels = options.levels.as_deref().map(str::parse).transpose()?;
+        }
+        if self.denoise.is_none() {
+            self.denoise = options.denoise.as_deref().map(str::parse).transpose()?;
+        }
+        if self.pixel_art.is_none() {
+            self.pixel_art = options.pixel_art.as_deref().map(str::parse).transpose()?;
+        }
+        if self.theme.is_none() {
+            self.theme = options.theme.as_deref().map(str::parse).transpose()?;
+        }
+        if let Some(filters) = options.filter.filter(|_| !given("color_filters")) {
+            self.color_filters = filters
+                .iter()
+                .map(|filter| filter.parse())
+                .collect::<Result<_, _>>()?;
+        }
+
+        self.width = self.width.or(options.width);
+        self.height = self.height.or(options.height);
+        if self.charset.is_none() && self.charset_file.is_none() {
+            self.charset = options.charset;
+            self.charset_file = options.charset_file;
+        }
+        self.palette = self.palette.take().or(options.palette);
+        if let Some(palette_seed) = options.palette_seed.filter(|_| !given("palette_seed")) {
+            self.palette_seed = palette_seed;
+        }
+        self.background_color = self.background_color.take().or(options.background_color);
+        self.chroma_key = self.chroma_key.take().or(options.chroma_key);
+        if let Some(char_aspect) = options.char_aspect.filter(|_| !given("char_aspect")) {
+            self.char_aspect = char_aspect;
+        }
+        if let Some(dither) = options.dither.filter(|_| !given("dither")) {
+            self.dither = dither;
+        }
+        if let Some(color_dither) = options.color_dither.filter(|_| !given("color_dither")) {
+            self.color_dither = color_dither;
+        }
+        if let Some(shade) = options.shade.filter(|_| !given("shade")) {
+            self.shade = shade;
+        }
+        if let Some(contrast) = options.contrast.filter(|_| !given("contrast")) {
+            self.contrast = contrast;
+        }
+        if let Some(clahe) = options.clahe.filter(|_| !given("clahe")) {
+            self.clahe = clahe;
+        }
+        if let Some(clahe_tiles) = options.clahe_tiles.filter(|_| !given("clahe_tiles")) {
+            self.clahe_tiles = clahe_tiles;
+        }
+        if let Some(clahe_clip) = options.clahe_clip.filter(|_| !given("clahe_clip")) {
+            self.clahe_clip = clahe_clip;
+        }
+        if let Some(sharpen) = options.sharpen.filter(|_| !given("sharpen")) {
+            self.sharpen = sharpen;
+        }
+        if let Some(sharpen_color) = options.sharpen_color.filter(|_| !given("sharpen_color")) {
+            self.sharpen_color = sharpen_color;
+        }
+        if let Some(negate) = options.negate.filter(|_| !given("negate")) {
+            self.negate = negate;
+        }
+        if let Some(hue_shift) = options.hue_shift.filter(|_| !given("hue_shift")) {
+            self.hue_shift = hue_shift;
+        }
+        if let Some(saturation) = options.saturation.filter(|_| !given("saturation")) {
+            self.saturation = saturation;
+        }
+        if let Some(lightness) = options.lightness.filter(|_| !given("lightness")) {
+            self.lightness = lightness;
+        }
+        if let Some(hsl_brightness) = options.hsl_brightness.filter(|_| !given("hsl_brightness")) {
+            self.hsl_brightness = hsl_brightness;
+        }
+        if let Some(auto_wb) = options.auto_wb.filter(|_| !given("auto_wb")) {
+            self.auto_wb = auto_wb;
+        }
+        if let Some(alpha_threshold) = options
+            .alpha_threshold
+            .filter(|_| !given("alpha_threshold"))
+        {
+            self.alpha_threshold = alpha_threshold;
+        }
+        if let Some(tolerance) = options
+            .chroma_tolerance
+            .filter(|_| !given("chroma_tolerance"))
+        {
+            self.chroma_tolerance = tolerance;
+        }
+        if let Some(threads) = options.threads.filter(|_| !given("threads")) {
+            self.threads = threads;
+        }
+        if self.auto_tune {
+            // The search picks these itself; clap already rejects them on the
+            // command line, so any values here came from the configuration
+            self.levels = None;
+            self.contrast = 0.0;
+            self.dither = false;
+            self.charset = None;
+            self.charset_file = None;
+        }
+        Ok(())
+    }
+}
+
+/// Parse the command line and merge in the preset and the configuration file
+///
+/// Command-line flags win over `--preset`, which wins over the file's
+/// defaults. `--config` must point at a readable file unless a preset is
+/// being saved to it; the default location is optional, and its defaults are
+/// skipped for `--deterministic` runs.
+fn parse_cli() -> Result<Cli, AsciiArtError> {
+    let matches = Cli::command()
+        .try_get_matches()
+        .unwrap_or_else(|err| exit_usage(err));
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|err| exit_usage(err));
+    init_logging(cli.verbose, cli.quiet);
+    if cli.command.is_some() {
+        return Ok(cli);
+    }
+
+    let mut config = match &cli.config {
+        // --save-preset creates the file
+        Some(path) if cli.save_preset.is_some() && !path.exists() => Config::default(),
+        Some(path) => Config::load(path)?,
+        None if cli.no_config => Config::default(),
+        None => Config::load_default()?,
+    };
+    if cli.deterministic && cli.config.is_none() {
+        config.defaults = Options::default();
+    }
+    let preset = match &cli.preset {
+        Some(name) => config.preset(name)?,
+        None => Options::default(),
+    };
+
+    if let Some(name) = &cli.save_preset {
+        let path = cli
+            .config
+            .clone()
+            .or_else(Config::default_path)
+            .ok_or_else(|| {
+                AsciiArtError::InvalidArgument(
+                    "Cannot locate the config file; pass --config <FILE> to save presets."
+                        .to_string(),
+                )
+            })?;
+        let mut options = cli.given_options(&matches).or(preset.clone());
+        // The preset may be used from another directory later
+        options.palette = options.palette.map(|palette| {
+            if AdaptivePalette::is_adaptive(&palette) {
+                palette
+            } else {
+                std::path::absolute(&palette).unwrap_or(palette)
+            }
+        });
+        options.charset_file = options
+            .charset_file
+            .map(|file| std::path::absolute(&file).unwrap_or(file));
+        config::save_preset(&path, name, &options)?;
+        if !cli.quiet {
+            eprintln!("Saved preset '{name}' to {}", path.display());
+        }
+    }
+
+    cli.apply_options(&matches, preset.or(config.defaults))?;
+    Ok(cli)
+}
+
+/// Process exit codes, so wrapper scripts can branch on the kind of failure
+mod exit_code {
+    /// Any failure without a more specific code, or `diff` over its threshold
+    pub const FAILURE: i32 = 1;
+    /// The input file does not exist or cannot be read
+    pub const FILE_NOT_FOUND: i32 = 2;
+    /// The input is not in an image format this build can decode
+    pub const UNSUPPORTED_FORMAT: i32 = 3;
+    /// The image is corrupt, empty, or over the decode limits
+    pub const DECODE_FAILED: i32 = 4;
+    /// Writing to the terminal or encoding the output failed
+    pub const RENDER_FAILED: i32 = 5;
+    /// Invalid flags, configuration, palette, or preset (`EX_USAGE`)
+    pub const USAGE: i32 = 64;
+    /// The request needs a cargo feature this build was compiled without (`EX_UNAVAILABLE`)
+    pub const UNAVAILABLE: i32 = 69;
+    /// Playback was stopped with Ctrl-C (128 + SIGINT, as shells report it)
+    pub const INTERRUPTED: i32 = 130;
+}
+
+/// The exit code reported for `err`
+fn exit_code(err: &AsciiArtError) -> i32 {
+    match err {
+        AsciiArtError::Image(err) => match err {
+            ImageLoaderError::FileNotFound(_) | ImageLoaderError::IoError(_) => {
+                exit_code::FILE_NOT_FOUND
+            }
+            ImageLoaderError::UnsupportedFormat(_) | ImageLoaderError::UnsupportedFeature(_) => {
+                exit_code::UNSUPPORTED_FORMAT
+            }
+            ImageLoaderError::DecodeFailed(_)
+            | ImageLoaderError::LimitsExceeded(_)
+            | ImageLoaderError::InvalidDimensions(_) => exit_code::DECODE_FAILED,
+        },
+        AsciiArtError::EmptyImage => exit_code::DECODE_FAILED,
+        AsciiArtError::Render(_) => exit_code::RENDER_FAILED,
+        AsciiArtError::EmptyCharset
+        | AsciiArtError::UnknownMode(_)
+        | AsciiArtError::InvalidArgument(_) => exit_code::USAGE,
+        AsciiArtError::FeatureDisabled { .. } => exit_code::UNAVAILABLE,
+        AsciiArtError::DimensionMismatch { .. } => exit_code::FAILURE,
+        AsciiArtError::EdgeDetection(err) => exit_code(err),
+    }
+}
+
+/// The `--error-format` requested, read from the raw arguments
+///
+/// Errors can happen before (or while) the arguments are parsed, so this
+/// does not rely on clap; the last valid occurrence wins.
+fn requested_error_format() -> ErrorFormat {
+    let mut format = ErrorFormat::Text;
+    // Arguments that aren't UTF-8 are left for clap to report
+    let mut args = std::env::args_os()
+        .skip(1)
+        .map(|arg| arg.into_string().ok());
+    while let Some(arg) = args.next() {
+        let value = match arg
+            .as_deref()
+            .and_then(|arg| arg.strip_prefix("--error-format"))
+        {
+            Some("") => args.next().flatten(),
+            Some(value) => value.strip_prefix('=').map(str::to_string),
+            None => None,
+        };
+        if let Some(value) = value.and_then(|value| ErrorFormat::from_str(&value, false).ok()) {
+            format = value;
+        }
+    }
+    format
+}
+
+/// Report a failure on stderr in the requested format and exit with `code`
+fn report_failure(kind: &str, message: &str, path: Option<&str>, code: i32) -> ! {
+    match requested_error_format() {
+        ErrorFormat::Text => eprintln!("{message}"),
+        ErrorFormat::Json => {
+            let mut report = json!({ "kind": kind, "message": message, "exit_code": code });
+            if let Some(path) = path {
+                report["path"] = path.into();
+            }
+            eprintln!("{report}");
+        }
+    }
+    std::process::exit(code);
+}
+
+/// Report `err` on stderr and exit with its code
+fn fail(err: AsciiArtError) -> ! {
+    let path = match &err {
+        AsciiArtError::Image(err) => err.path(),
+        _ => None,
+    };
+    report_failure(err.kind(), &err.to_string(), path, exit_code(&err));
+}
+
+/// Print a command-line parsing error and exit with [`exit_code::USAGE`]
+///
+/// `--help` and `--version` also arrive here, and still exit successfully.
+fn exit_usage(err: clap::Error) -> ! {
+    if !err.use_stderr() {
+        err.exit();
+    }
+    if requested_error_format() == ErrorFormat::Json {
+        let rendered = err.render().to_string();
+        let message = rendered.lines().next().unwrap_or_default();
+        let message = message.strip_prefix("error: ").unwrap_or(message);
+        report_failure("Usage", message, None, exit_code::USAGE);
+    }
+    let _ = err.print();
+    std::process::exit(exit_code::USAGE);
+}
+
+/// Send log events to stderr at the level chosen by `-v` and `--quiet`
+///
+/// Warnings are shown by default; `--quiet` keeps only errors.
+fn init_logging(verbose: u8, quiet: bool) {
+    let level = match (quiet, verbose) {
+        (true, _) => LevelFilter::ERROR,
+        (false, 0) => LevelFilter::WARN,
+        (false, 1) => LevelFilter::INFO,
+        (false, 2) => LevelFilter::DEBUG,
+        (false, _) => LevelFilter::TRACE,
+    };
+    tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_writer(io::stderr)
+        .with_target(false)
+        .without_time()
+        .init();
 }
 
 fn main() {
-    let cli = Cli::parse();
-    let width_resolution = terminal::resolve_output_width(cli.width);
+    let cli = parse_cli().unwrap_or_else(|err| fail(err));
+    match &cli.command {
+        Some(Command::Completions { shell }) => {
+            let mut command = Cli::command();
+            clap_complete::generate(*shell, &mut command, "ascii-art-cli", &mut io::stdout());
+            return;
+        }
+        Some(Command::Play(args)) => {
+            match run_play(args) {
+                Ok(true) => std::process::exit(exit_code::INTERRUPTED),
+                Ok(false) => {}
+                Err(err) => fail(err),
+            }
+            return;
+        }
+        Some(Command::Serve(args)) => {
+            if let Err(err) = run_server(args, cli.quiet) {
+                fail(err);
+            }
+            return;
+        }
+        Some(Command::Charset { command }) => {
+            if let Err(err) = run_charset_command(command, cli.quiet) {
+                fail(err);
+            }
+            return;
+        }
+        Some(Command::TestPattern(args)) => {
+            if let Err(err) = run_test_pattern(args) {
+                fail(err);
+            }
+            return;
+        }
+        Some(Command::Text(args)) => {
+            if let Err(err) = run_text(args) {
+                fail(err);
+            }
+            return;
+        }
+        Some(Command::Diff(args)) => {
+            match run_diff(args) {
+                Ok(true) => std::process::exit(exit_code::FAILURE),
+                Ok(false) => {}
+                Err(err) => fail(err),
+            }
+            return;
+        }
+        None => {}
+    }
+    let width_resolution = if cli.deterministic {
+        terminal::fixed_output_width(cli.width)
+    } else {
+        terminal::resolve_output_width(cli.width)
+    };
+
+    log_width(width_resolution.source, width_resolution.width);
+
+    if let Err(err) = parallel::set_thread_count(cli.threads) {
+        fail(err);
+    }
+    let mut image_paths = cli.image_paths.clone();
+    if let Some(list) = &cli.files_from {
+        match read_file_list(list) {
+            Ok(paths) => image_paths.extend(paths),
+            Err(err) => fail(err),
+        }
+    }
+    match run_batch(&cli, &image_paths, width_resolution.width) {
+        Ok(true) => std::process::exit(exit_code::INTERRUPTED),
+        Ok(false) => {}
+        Err(err) => fail(err),
+    }
+}
+
+/// Convert each image in turn, then watch `--watch-dir` if it is given, and
+/// return whether Ctrl-C stopped the run early
+///
+/// With `--title`, the terminal title names the image being converted until
+/// this returns.
+fn run_batch(cli: &Cli, image_paths: &[String], width: u32) -> Result<bool, AsciiArtError> {
+    let titles = cli.title && terminal::titles_enabled();
+    let title = TitleGuard::new(titles);
+    // The title can only be put back if Ctrl-C doesn't end the process outright
+    let stop = titles.then(terminal::catch_interrupt);
+    let mut output = cli
+        .output_template
+        .clone()
+        .map(|template| OutputFiles::new(template, cli.overwrite));
+    if let Some(files) = &output {
+        files.check_inputs(image_paths.iter().map(Path::new))?;
+    }
+    for (index, image_path) in image_paths.iter().enumerate() {
+        if stop.is_some_and(|stop| stop.load(Ordering::Relaxed)) {
+            return Ok(true);
+        }
+        let name = file_name(image_path);
+        match image_paths.len() {
+            1 => title.set(&name),
+            count => title.set(&format!("{name} \u{2014} {} of {count}", index + 1)),
+        }
+        run_pipeline(cli, image_path, width, output.as_mut())?;
+    }
+    if let Some(dir) = &cli.watch_dir {
+        run_watch(cli, dir, width, output.as_mut(), &title)?;
+    }
+    Ok(false)
+}
+
+/// The file name in `path`, or all of it if it has none
+fn file_name(path: &str) -> Cow<'_, str> {
+    Path::new(path)
+        .file_name()
+        .map_or(path.into(), |name| name.to_string_lossy())
+}
+
+/// `--watch-dir`: convert each image that settles in `dir` after a change,
+/// until Ctrl-C
+///
+/// A failed conversion is logged and the watch goes on.
+fn run_watch(
+    cli: &Cli,
+    dir: &Path,
+    width: u32,
+    mut output: Option<&mut OutputFiles>,
+    title: &TitleGuard,
+) -> Result<(), AsciiArtError> {
+    let mut watcher = Watcher::new(dir).map_err(|err| {
+        AsciiArtError::InvalidArgument(format!("Cannot watch {}: {err}", dir.display()))
+    })?;
+    let stop = terminal::catch_interrupt();
+    info!(
+        "Watching {} for images; press Ctrl-C to stop",
+        dir.display()
+    );
+    let watching = format!("watching {}", dir.display());
+    title.set(&watching);
+    while !stop.load(Ordering::Relaxed) {
+        for path in watcher.poll()? {
+            let Some(image_path) = path.to_str() else {
+                warn!("Skipping {}: the path is not valid UTF-8", path.display());
+                continue;
+            };
+            title.set(&format!("{} \u{2014} {watching}", file_name(image_path)));
+            if let Err(err) = run_pipeline(cli, image_path, width, output.as_deref_mut()) {
+                warn!("Could not convert {image_path}: {err}");
+            }
+            title.set(&watching);
+        }
+        thread::sleep(watch::POLL_INTERVAL);
+    }
+    Ok(())
+}
+
+/// The paths listed in `list`, or on stdin for `-`, for `--files-from`
+///
+/// Paths are separated by NUL bytes if there are any, else by newlines;
+/// blank entries are skipped.
+fn read_file_list(list: &Path) -> Result<Vec<String>, AsciiArtError> {
+    let data = if list == Path::new("-") {
+        let mut data = Vec::new();
+        io::stdin().lock().read_to_end(&mut data)?;
+        data
+    } else {
+        std::fs::read(list).map_err(|err| {
+            AsciiArtError::InvalidArgument(format!("Cannot read {}: {err}", list.display()))
+        })?
+    };
+    let separator = if data.contains(&0) { b'\0' } else { b'\n' };
+    data.split(|&byte| byte == separator)
+        .map(|entry| entry.strip_suffix(b"\r").unwrap_or(entry))
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            String::from_utf8(entry.to_vec()).map_err(|_| {
+                AsciiArtError::InvalidArgument(format!(
+                    "{} lists a path that is not valid UTF-8: {}",
+                    list.display(),
+                    String::from_utf8_lossy(entry)
+                ))
+            })
+        })
+        .collect()
+}
+
+/// `diff`: convert both sides with the configuration's defaults under their
+/// presets, print the second grid with its changes marked and a summary, and
+/// return whether the change exceeds `--threshold`
+fn run_diff(args: &DiffArgs) -> Result<bool, AsciiArtError> {
+    let config = match &args.config {
+        Some(path) => Config::load(path)?,
+        None if args.no_config => Config::default(),
+        None => Config::load_default()?,
+    };
+    let convert = |path: &str, preset: Option<&str>| {
+        let options = preset.map(|name| config.preset(name)).transpose()?;
+        let options = options.unwrap_or_default().or(config.defaults.clone());
+        let image = image_loader::load_image(path)?;
+        options.to_builder()?.width(args.width).generate(&image)
+    };
+    let before = convert(&args.image, args.preset_a.as_deref())?;
+    let other = args.other.as_deref().unwrap_or(&args.image);
+    let after = convert(other, args.preset_b.as_deref())?;
+
+    let diff = Diff::new(before.image(), after.image());
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(diff.render(terminal::color_enabled(args.color)).as_bytes())?;
+    writeln!(stdout, "{diff}")?;
+    Ok(diff.percentage() > args.threshold)
+}
+
+/// `testpattern`: render a built-in pattern, or save it as an image file
+fn run_test_pattern(args: &TestPatternArgs) -> Result<(), AsciiArtError> {
+    let image = args.pattern.generate(args.size);
+    if let Some(path) = &args.save {
+        return image.save(path).map_err(|err| match err {
+            image::ImageError::IoError(err) => AsciiArtError::Render(err),
+            err => AsciiArtError::InvalidArgument(format!("Cannot save {}: {err}", path.display())),
+        });
+    }
+    let mut builder = AsciiArtBuilder::new()
+        .width(args.width)
+        .char_aspect(args.char_aspect)
+        .color_depth(args.color_depth);
+    if let Some(ramp) = &args.charset {
+        builder = builder.charset(Charset::named(ramp)?);
+    }
+    builder.generate(&image)?.write_to(&mut io::stdout().lock())
+}
+
+/// Cells per pixel of the built-in font across a banner drawn by `text`
+const BANNER_CELLS_PER_PIXEL: u32 = 2;
+
+/// `text`: draw the text into an image, color it with the gradient, and
+/// render it like any other image
+fn run_text(args: &TextArgs) -> Result<(), AsciiArtError> {
+    let text = args.text.replace("\\n", "\n");
+    let (mask, natural_width) = match &args.font {
+        Some(path) => {
+            let data = std::fs::read(path).map_err(|err| {
+                AsciiArtError::InvalidArgument(format!("Cannot read {}: {err}", path.display()))
+            })?;
+            let mask = banner::rasterize_ttf(&text, data, args.font_size)?;
+            let width = mask.width();
+            (mask, width)
+        }
+        None => {
+            let mask = banner::rasterize_builtin(&text)?;
+            let width = mask.width() * BANNER_CELLS_PER_PIXEL;
+            (mask, width)
+        }
+    };
+    let width = args
+        .width
+        .unwrap_or_else(|| natural_width.min(terminal::resolve_output_width(None).width));
+    let image = banner::colorize(&mask, &args.gradient);
+    AsciiArtBuilder::new()
+        .width(width)
+        .color_depth(args.color_depth)
+        .pixel_art(args.font.is_none())
+        .charset(Charset::new(&args.charset)?)
+        .generate(&image)?
+        .write_to(&mut io::stdout().lock())
+}
 
-    emit_width_messages(width_resolution.source, width_resolution.width);
+/// Rows of gradient drawn by `charset preview`
+const PREVIEW_ROWS: usize = 3;
 
-    match run_pipeline(&cli, width_resolution.width) {
-        Ok((processed, ascii_grid)) => {
-            // Render colored ASCII art to terminal
-            if let Err(e) = renderer::render_colored(&ascii_grid, &processed.original) {
-                eprintln!("Rendering error: {}", e);
-                std::process::exit(1);
+/// `charset list` and `charset preview`: draw built-in ramps over a gradient;
+/// `charset calibrate`: print a ramp ordered, or a table spaced, by measured ink
+fn run_charset_command(command: &CharsetCommand, quiet: bool) -> Result<(), AsciiArtError> {
+    match command {
+        CharsetCommand::List { width } => {
+            let mut stdout = io::stdout().lock();
+            let name_width = BUILTIN_CHARSETS.iter().map(|(name, _)| name.len()).max();
+            let name_width = name_width.unwrap_or_default();
+            for (name, ramp) in BUILTIN_CHARSETS {
+                let gradient = Charset::new(ramp)?.gradient(*width);
+                writeln!(stdout, "{name:<name_width$}  │{gradient}│")?;
+            }
+            Ok(())
+        }
+        CharsetCommand::Preview { name, width } => {
+            let charset = Charset::builtin(name).ok_or_else(|| {
+                AsciiArtError::InvalidArgument(format!("Unknown built-in charset '{name}'."))
+            })?;
+            let ramp: String = charset.chars().iter().collect();
+            let mut stdout = io::stdout().lock();
+            writeln!(stdout, "{name}: \"{ramp}\" ({} levels)", charset.levels())?;
+            let gradient = charset.gradient(*width);
+            for _ in 0..PREVIEW_ROWS {
+                writeln!(stdout, "│{gradient}│")?;
             }
+            Ok(())
         }
-        Err(err) => {
-            eprintln!("{err}");
-            std::process::exit(1);
+        CharsetCommand::Calibrate(args) => {
+            let charset = match (&args.chars, &args.file) {
+                (Some(chars), _) => Charset::new(chars)?,
+                (None, Some(path)) => Charset::load(path)?,
+                // clap requires one of the two
+                (None, None) => Charset::default(),
+            };
+            let calibration = charset.calibrate()?;
+            let mut stdout = io::stdout().lock();
+            if args.table {
+                stdout.write_all(calibration.table().as_bytes())?;
+            } else {
+                let ramp: String = calibration.charset().chars().iter().collect();
+                writeln!(stdout, "{ramp}")?;
+            }
+            if !quiet {
+                for (ch, coverage) in &calibration.glyphs {
+                    eprintln!("'{ch}' {:5.1}% ink", coverage * 100.0);
+                }
+            }
+            Ok(())
         }
     }
 }
 
-fn emit_width_messages(source: WidthSource, width: u32) {
-    match source {
-        WidthSource::User => { /* User override already explicit. */ }
-        WidthSource::AutoDetected => {
-            println!("Using auto-detected width: {width} characters");
+/// `--slide-duration` as a [`Duration`]
+fn slide_duration(seconds: f64) -> Result<Duration, AsciiArtError> {
+    Duration::try_from_secs_f64(seconds).map_err(|_| {
+        AsciiArtError::InvalidArgument(format!(
+            "Slide duration must be a non-negative number of seconds, got {seconds}."
+        ))
+    })
+}
+
+/// `play`: convert each frame of the sources as it comes due and draw it in
+/// the terminal, dropping late frames unless `--no-frame-skip`, and return
+/// whether Ctrl-C stopped it
+fn run_play(args: &PlayArgs) -> Result<bool, AsciiArtError> {
+    let slide_duration = slide_duration(args.slide_duration)?;
+    let timing = Timing::new(args.fps, args.speed)?;
+    let color_depth = match args.color_depth {
+        ColorDepthChoice::Auto => terminal::detect_color_capability().color_depth(),
+        ColorDepthChoice::Fixed(depth) => depth,
+    };
+    // Each frame has to be converted in time to be shown, which Lanczos often isn't
+    let builder = AsciiArtBuilder::new()
+        .width(terminal::resolve_output_width(args.width).width)
+        .resize_filter(ResizeFilter::Box)
+        .mode(args.mode)
+        .color_depth(color_depth);
+    // A slideshow plays once unless told otherwise; a lone GIF as it asks
+    let loops = args.loops.unwrap_or_else(|| match args.sources.as_slice() {
+        [source] => animation::loop_count(source),
+        _ => Loop::Times(1),
+    });
+    let player = Player::new(&builder)
+        .timing(timing)
+        .skip_late(!args.no_frame_skip)
+        .loops(loops)
+        .stop_on(terminal::catch_interrupt())
+        .titles(args.title && terminal::titles_enabled());
+    let open = || {
+        let mut readers = Vec::new();
+        if let Some(video) = args.stdin_raw {
+            // clap requires --fps with --stdin-raw, and Timing checked it
+            let delay = args
+                .fps
+                .map_or(Duration::ZERO, |fps| Duration::from_secs_f64(1.0 / fps));
+            let limits = DecodeLimits::default();
+            let reader = animation::open_raw_frames(io::stdin(), "stdin", video, &limits, delay)?;
+            readers.push(reader.select(args.frames, args.frame_step as usize));
+        }
+        for source in &args.sources {
+            let reader = animation::open_frames(source, &DecodeLimits::default(), slide_duration)?;
+            readers.push(reader.select(args.frames, args.frame_step as usize));
         }
+        Ok::<_, AsciiArtError>(readers.into_iter().flatten())
+    };
+    let playback = player.play(&mut io::stdout().lock(), open)?;
+    info!(
+        shown = playback.shown,
+        dropped = playback.dropped,
+        mean = ?playback.mean_render_time(),
+        slowest = ?playback.slowest,
+        "Playback finished"
+    );
+    Ok(playback.interrupted)
+}
+
+/// Convert the sources once, then stream them to every client that connects
+#[cfg(feature = "server")]
+fn run_server(args: &ServeArgs, quiet: bool) -> Result<(), AsciiArtError> {
+    let slide_duration = slide_duration(args.slide_duration)?;
+    let timing = Timing::new(args.fps, args.speed)?;
+    let builder = AsciiArtBuilder::new()
+        .width(args.width)
+        .mode(args.mode)
+        .color_depth(args.color_depth);
+    let mut frames = Vec::new();
+    for source in &args.sources {
+        let reader = animation::open_frames(source, &DecodeLimits::default(), slide_duration)?
+            .select(args.frames, args.frame_step as usize);
+        frames.extend(animation::render_frames(reader, &builder)?);
+    }
+    for frame in &mut frames {
+        frame.delay = timing.delay(frame.delay);
+    }
+    debug!(frames = frames.len(), "Converted the animation");
+
+    let (scheme, default_port) = if args.websocket {
+        ("ws", 8765)
+    } else {
+        ("telnet", 2323)
+    };
+    let listen = args
+        .listen
+        .unwrap_or_else(|| SocketAddr::from(([127, 0, 0, 1], default_port)));
+    let listener = std::net::TcpListener::bind(listen).map_err(|err| {
+        AsciiArtError::InvalidArgument(format!("Cannot listen on {listen}: {err}"))
+    })?;
+    if !quiet {
+        let addr = listener.local_addr()?;
+        let plural = if frames.len() == 1 { "" } else { "s" };
+        eprintln!(
+            "Serving {} frame{plural} on {scheme}://{addr}",
+            frames.len()
+        );
+    }
+    if args.websocket {
+        server::serve_websocket(listener, frames.into(), args.frame_format)?;
+    } else {
+        server::serve_telnet(listener, frames.into())?;
+    }
+    Ok(())
+}
+
+/// `serve` in a build without the streaming server
+#[cfg(not(feature = "server"))]
+fn run_server(_args: &ServeArgs, _quiet: bool) -> Result<(), AsciiArtError> {
+    Err(AsciiArtError::FeatureDisabled {
+        what: "The streaming server",
+        feature: "server",
+    })
+}
+
+fn log_width(source: WidthSource, width: u32) {
+    match source {
+        WidthSource::User => debug!("Using requested width: {width} characters"),
+        WidthSource::Fixed => debug!("Using fixed width: {width} characters"),
+        WidthSource::AutoDetected => info!("Using auto-detected width: {width} characters"),
         WidthSource::Fallback => {
-            eprintln!("Warning: Unable to detect terminal size; defaulting to {width} characters.");
-            println!("Using fallback width: {width} characters");
+            warn!("Unable to detect terminal size; defaulting to {width} characters.")
+        }
+    }
+}
+
+/// Resize and encode the image for a graphics protocol, ready to write
+fn graphics_sequence(
+    cli: &Cli,
+    protocol: Protocol,
+    image: &DynamicImage,
+    width: u32,
+    cell_size: (u32, u32),
+    timings: &mut Timings,
+) -> Result<String, AsciiArtError> {
+    let resized = time(&mut timings.resize, || {
+        graphics::resize_for_cells(image, width, cell_size)
+    });
+    let sequence = time(&mut timings.render, || protocol.encode(&resized, width))?;
+    // Files and reproducible output get the sequence as is, whatever tmux is around
+    if cli.deterministic || !terminal::stdout_is_terminal() {
+        return Ok(sequence);
+    }
+    Ok(terminal::detect_passthrough()
+        .wrap(&sequence)
+        .unwrap_or(sequence))
+}
+
+/// Pick an inline-image protocol: iTerm2 by `TERM_PROGRAM`, else kitty if it answers
+fn detect_image_protocol(cli: &Cli) -> Option<Protocol> {
+    if cli.deterministic {
+        return Some(Protocol::Kitty);
+    }
+    if terminal::is_iterm2() {
+        return Some(Protocol::Iterm2);
+    }
+    if !terminal::stdout_is_terminal() {
+        return Some(Protocol::Kitty);
+    }
+    terminal::supports_kitty_graphics(terminal::QUERY_TIMEOUT).then_some(Protocol::Kitty)
+}
+
+/// Pick the protocol for `--preview-original`, or `None` to skip the preview
+fn resolve_preview_protocol(cli: &Cli) -> Option<Protocol> {
+    let interactive = !cli.deterministic && terminal::stdout_is_terminal();
+    if interactive && terminal::detect_passthrough() == Passthrough::Blocked {
+        warn!("tmux passthrough is disabled; skipping the preview.");
+        return None;
+    }
+    let protocol = detect_image_protocol(cli);
+    if protocol.is_none() {
+        warn!("Terminal does not support inline images; skipping the preview.");
+    }
+    protocol
+}
+
+/// Pick any protocol that can draw the image beside the art, or `None`
+fn resolve_hybrid_protocol(cli: &Cli) -> Option<Protocol> {
+    let interactive = !cli.deterministic && terminal::stdout_is_terminal();
+    if interactive && terminal::detect_passthrough() == Passthrough::Blocked {
+        warn!("tmux passthrough is disabled; showing the ASCII art only.");
+        return None;
+    }
+    let protocol = detect_image_protocol(cli)
+        .or_else(|| terminal::supports_sixel(terminal::QUERY_TIMEOUT).then_some(Protocol::Sixel));
+    if protocol.is_none() {
+        warn!("Terminal cannot show graphics; showing the ASCII art only.");
+    }
+    protocol
+}
+
+/// Parse `--render` into a graphics protocol, or `None` for ASCII output
+///
+/// Falls back to ASCII when the terminal cannot show the requested graphics.
+fn resolve_render_mode(cli: &Cli) -> Result<Option<Protocol>, AsciiArtError> {
+    let mode = cli.render;
+    let interactive = !cli.deterministic && terminal::stdout_is_terminal();
+    if mode == RenderMode::Ascii {
+        return Ok(None);
+    }
+
+    if interactive && terminal::detect_passthrough() == Passthrough::Blocked {
+        warn!("tmux passthrough is disabled (set -g allow-passthrough on); using ASCII output.");
+        return Ok(None);
+    }
+    match mode {
+        RenderMode::Ascii => Ok(None),
+        RenderMode::Sixel if !interactive || terminal::supports_sixel(terminal::QUERY_TIMEOUT) => {
+            Ok(Some(Protocol::Sixel))
+        }
+        RenderMode::Sixel => {
+            warn!("Terminal does not report sixel support; using ASCII output.");
+            Ok(None)
+        }
+        RenderMode::Image => {
+            let protocol = detect_image_protocol(cli);
+            if protocol.is_none() {
+                warn!("Terminal does not support inline images; using ASCII output.");
+            }
+            Ok(protocol)
+        }
+    }
+}
+
+/// Use `--background`, or detect it when interactive and left on "auto"
+///
+/// Also returns the color transparent regions are filled with: the
+/// `--background-color`, else the terminal's own with `--background terminal`.
+fn resolve_background(cli: &Cli) -> Result<(Background, Option<[u8; 3]>), AsciiArtError> {
+    let fill = cli
+        .background_color
+        .as_deref()
+        .map(color::parse_color)
+        .transpose()?;
+    let interactive = !cli.deterministic && terminal::stdout_is_terminal();
+    let background = match cli.background {
+        BackgroundChoice::Auto if !interactive => Background::default(),
+        BackgroundChoice::Auto => {
+            terminal::detect_background(terminal::QUERY_TIMEOUT).unwrap_or_default()
         }
+        BackgroundChoice::Terminal => {
+            let color = interactive
+                .then(|| terminal::query_background_color(terminal::QUERY_TIMEOUT))
+                .flatten();
+            match color {
+                Some(color) => return Ok((terminal::background_of(color), fill.or(Some(color)))),
+                None => Background::default(),
+            }
+        }
+        BackgroundChoice::Fixed(background) => background,
+    };
+    Ok((background, fill))
+}
+
+/// Pick the color depth from `--color`, `--color-depth`, and the terminal
+fn resolve_color_depth(cli: &Cli) -> Result<ColorDepth, AsciiArtError> {
+    let requested = match cli.color_depth {
+        ColorDepthChoice::Auto => None,
+        ColorDepthChoice::Fixed(depth) => Some(depth),
+    };
+    if !resolve_color(cli)? {
+        return Ok(ColorDepth::Monochrome);
+    }
+
+    Ok(match requested {
+        Some(depth) => depth,
+        None if cli.deterministic => ColorDepth::default(),
+        None => terminal::detect_color_capability().color_depth(),
+    })
+}
+
+/// The palette colors are matched against: a `--palette` file, or the
+/// terminal's own with `--query-palette`
+fn resolve_palette(cli: &Cli, color_depth: ColorDepth) -> Result<Option<Palette>, AsciiArtError> {
+    if let Some(path) = &cli.palette {
+        return Ok(Some(Palette::load(path)?));
+    }
+    let query = cli.query_palette
+        && !cli.deterministic
+        && matches!(color_depth, ColorDepth::Ansi8 | ColorDepth::Ansi16);
+    // Terminals that do not answer keep the built-in xterm values
+    Ok(query
+        .then(|| terminal::query_palette(terminal::QUERY_TIMEOUT))
+        .flatten())
+}
+
+/// Decide whether to emit colors from `--color`, the environment, and the terminal
+fn resolve_color(cli: &Cli) -> Result<bool, AsciiArtError> {
+    let colored = match cli.color {
+        // Auto mode must not depend on the environment in deterministic runs
+        ColorChoice::Auto if cli.deterministic => true,
+        ColorChoice::Auto if terminal::color_enabled(ColorChoice::Auto) => {
+            let dumb = terminal::is_dumb_terminal();
+            if dumb {
+                info!(
+                    "TERM is dumb or unset; printing plain text (use --color always to override)"
+                );
+            }
+            !dumb
+        }
+        choice => terminal::color_enabled(choice),
+    };
+
+    // Legacy Windows consoles would print escapes literally
+    Ok(colored && terminal::enable_virtual_terminal())
+}
+
+/// The first frame of `path` in `range`, for `--frames`
+fn load_frame(
+    path: &str,
+    limits: &DecodeLimits,
+    range: FrameRange,
+) -> Result<DynamicImage, AsciiArtError> {
+    let mut frames = animation::open_frames(path, limits, Duration::ZERO)?.select(range, 1);
+    match frames.next() {
+        Some(frame) => Ok(frame?.image),
+        None => Err(AsciiArtError::InvalidArgument(format!(
+            "Frame range {range} selects no frames of {path}."
+        ))),
+    }
+}
+
+/// Where `--format` or `--record` writes `fields.input`: the file the output
+/// template names, or stdout when there is none and it is not a terminal
+fn claim_export(
+    output: Option<&mut OutputFiles>,
+    fields: &OutputFields,
+) -> Result<Option<PathBuf>, AsciiArtError> {
+    match output {
+        Some(files) => Ok(Some(files.claim(fields)?)),
+        None if terminal::stdout_is_terminal() => Err(AsciiArtError::InvalidArgument(format!(
+            "Refusing to write {} output to a terminal; redirect stdout to a file or use \
+             --output-template",
+            fields.format.name()
+        ))),
+        None => Ok(None),
+    }
+}
+
+/// Convert `image_path` and print the art, or write it to the file `output`
+/// names for it
+fn run_pipeline(
+    cli: &Cli,
+    image_path: &str,
+    width: u32,
+    output: Option<&mut OutputFiles>,
+) -> Result<(), AsciiArtError> {
+    let color_metric = cli.color_metric;
+    let color_depth = resolve_color_depth(cli)?;
+    if grid::is_grid_document(Path::new(image_path)) {
+        return run_grid(cli, image_path, color_depth, output);
+    }
+    let theme = cli.theme;
+    let (background, background_color) = resolve_background(cli)?;
+    let graphics = resolve_render_mode(cli)?;
+    debug!(
+        ?color_depth,
+        ?color_metric,
+        ?background,
+        ?background_color,
+        ?graphics,
+        "Resolved settings"
+    );
+    let limits = DecodeLimits {
+        max_pixels: cli.max_pixels,
+        max_dimension: cli.max_dimension,
+    };
+    let cell_size = if cli.deterministic {
+        terminal::DEFAULT_CELL_PIXELS
+    } else {
+        terminal::cell_pixel_size().unwrap_or(terminal::DEFAULT_CELL_PIXELS)
+    };
+    let layout = cli.layout;
+    let hybrid = match layout {
+        Layout::Hybrid if graphics.is_none() => resolve_hybrid_protocol(cli),
+        _ => None,
+    };
+    let preview = if cli.preview_original && graphics.or(hybrid).is_none() {
+        resolve_preview_protocol(cli)
+    } else {
+        None
+    };
+    if output.is_some() && graphics.or(hybrid).or(preview).is_some() {
+        return Err(AsciiArtError::InvalidArgument(
+            "--output-template writes ASCII art only; drop --render, --layout hybrid, and \
+             --preview-original"
+                .to_string(),
+        ));
+    }
+    let height = cli.height.map(|height| {
+        if cli.deterministic {
+            terminal::fixed_output_height(height)
+        } else {
+            terminal::resolve_output_height(height)
+        }
+    });
+    // A height alone sets the width too, once the image's proportions are known
+    let width_from_height = height.filter(|_| cli.width.is_none());
+    let decode_width = match graphics.or(hybrid).or(preview) {
+        _ if width_from_height.is_some() => 0,
+        Some(_) => width.saturating_mul(cell_size.0),
+        None => width,
+    };
+    let mut load_time = Duration::ZERO;
+    let image = time(&mut load_time, || match cli.frames {
+        Some(range) => load_frame(image_path, &limits, range),
+        None => Ok(load_image_for_width(image_path, &limits, decode_width)?),
+    })?;
+    debug!(
+        width = image.width(),
+        height = image.height(),
+        "Decoded {image_path}"
+    );
+
+    let width = match width_from_height {
+        Some(rows) => proportional_width(&image, rows, cli.char_aspect),
+        None => width,
+    };
+
+    let mut timings = Timings {
+        load: load_time,
+        ..Timings::default()
+    };
+    if let Some(protocol) = graphics {
+        let sequence = graphics_sequence(cli, protocol, &image, width, cell_size, &mut timings)?;
+        let mut writer = BufWriter::new(io::stdout().lock());
+        writer.write_all(sequence.as_bytes())?;
+        writer.write_all(b"\n")?;
+        writer.flush()?;
+        return report_timings(cli, &timings);
+    }
+
+    let line_art = cli.mode == ModeChoice::Auto && image_loader::is_line_art(&image);
+    let pixel_art = match cli.pixel_art {
+        Some(PixelArtChoice::Always) => true,
+        Some(PixelArtChoice::Auto) => image_loader::is_pixel_art(&image),
+        // `--mode auto` draws small images with few colors as blocks
+        None => cli.mode == ModeChoice::Auto && !line_art && image_loader::is_pixel_art(&image),
+    };
+    debug!(pixel_art, "Resolved pixel art");
+    let mode = match cli.mode {
+        ModeChoice::Auto if line_art => Mode::Edge,
+        ModeChoice::Auto => Mode::Standard,
+        ModeChoice::Fixed(mode) => mode,
+    };
+    debug!(?mode, "Resolved mode");
+
+    let (image_columns, art_width) = match hybrid {
+        Some(_) => layout::split_columns(width),
+        None => (width, width),
+    };
+    let mut builder = AsciiArtBuilder::new()
+        .width(art_width)
+        .height(height)
+        .char_aspect(cli.char_aspect)
+        .resize_filter(cli.resize_filter)
+        .pixel_art(pixel_art)
+        .mode(mode)
+        .color_depth(color_depth)
+        .color_metric(color_metric)
+        .theme(theme)
+        .background(background)
+        .background_color(background_color)
+        .alpha_threshold(cli.alpha_threshold)
+        .grayscale(cli.color == ColorChoice::Gray)
+        .color_dithering(cli.color_dither)
+        .shading(cli.shade)
+        .compact(cli.compact)
+        .denoise(cli.denoise)
+        .clahe(cli.clahe.then_some(Clahe {
+            tiles: cli.clahe_tiles,
+            clip_limit: cli.clahe_clip,
+        }))
+        .sharpen(cli.sharpen)
+        .sharpen_color(cli.sharpen_color)
+        .hsl(color::HslAdjustment {
+            hue_shift: cli.hue_shift,
+            saturation: cli.saturation,
+            lightness: cli.lightness,
+        })
+        .hsl_brightness(cli.hsl_brightness)
+        .auto_white_balance(cli.auto_wb);
+    if let Some(color) = &cli.chroma_key {
+        builder = builder.chroma_key(Some(ChromaKey {
+            color: color::parse_color(color)?,
+            tolerance: cli.chroma_tolerance,
+        }));
+    }
+    for &filter in &cli.color_filters {
+        builder = builder.color_filter(filter);
+    }
+    if cli.dither {
+        builder = builder.dithering(true);
+    }
+    if let Some(levels) = cli.levels {
+        builder = builder.filter(Filter::Levels(levels));
+    }
+    if cli.contrast != 0.0 {
+        builder = builder.filter(Filter::Contrast(cli.contrast));
+    }
+    if cli.negate {
+        builder = builder.filter(Filter::Negate);
+    }
+    if let Some(ramp) = &cli.charset {
+        builder = builder.charset(Charset::named(ramp)?);
+    } else if let Some(path) = &cli.charset_file {
+        builder = builder.charset(Charset::load(path)?);
+    } else if pixel_art && color_depth != ColorDepth::Monochrome {
+        builder = builder.charset(Charset::new(PIXEL_ART_CHARSET)?);
+    }
+    let adaptive = cli
+        .palette
+        .as_deref()
+        .map(|path| AdaptivePalette::parse(path, cli.palette_seed))
+        .transpose()?
+        .flatten();
+    if let Some(adaptive) = adaptive {
+        builder = builder.adaptive_palette(Some(adaptive));
+    } else if let Some(palette) = resolve_palette(cli, color_depth)? {
+        builder = builder.palette(palette);
+    }
+
+    if cli.auto_tune {
+        if mode != Mode::Standard {
+            return Err(AsciiArtError::InvalidArgument(
+                "--auto-tune only tunes the standard mode".to_string(),
+            ));
+        }
+        let tuning = tune::auto_tune(&builder, &image)?;
+        builder = tuning.apply(builder);
+        if !cli.quiet {
+            eprint!("{tuning}");
+        }
+    }
+
+    let text = TextExport {
+        line_ending: cli.line_ending,
+        encoding: cli.encoding,
+    };
+    if let Some(dir) = &cli.frames_out {
+        let range = cli.frames.unwrap_or_default();
+        let frames = animation::open_frames(image_path, &limits, Duration::ZERO)?
+            .select(range, cli.frame_step as usize);
+        let written = time(&mut timings.render, || {
+            animation::export_frames(frames, &builder, dir, cli.frames_out_format, text)
+        })?;
+        info!(frames = written, dir = %dir.display(), "Exported frames");
+        return report_timings(cli, &timings);
+    }
+
+    if cli.format == Some(FormatChoice::Grid) {
+        let fields = OutputFields {
+            input: Path::new(image_path),
+            mode,
+            width: art_width,
+            format: OutputFormat::Grid,
+        };
+        let path = claim_export(output, &fields)?;
+        let art = time(&mut timings.conversion, || builder.generate(&image))?;
+        let document = GridDocument::new(art.image().clone())
+            .note("source", file_name(image_path))
+            .note("mode", mode.to_string());
+        time(&mut timings.render, || match &path {
+            Some(path) => document.save(path),
+            None => Ok(document.write_to(&mut BufWriter::new(io::stdout().lock()))?),
+        })?;
+        info!("Saved the grid of {image_path}");
+        return report_timings(cli, &timings);
+    }
+
+    if let Some(FormatChoice::Video(format)) = cli.format {
+        let fields = OutputFields {
+            input: Path::new(image_path),
+            mode,
+            width: art_width,
+            format: OutputFormat::Video(format),
+        };
+        let path = claim_export(output, &fields)?;
+        let range = cli.frames.unwrap_or_default();
+        let frames = animation::open_frames(image_path, &limits, Duration::ZERO)?
+            .select(range, cli.frame_step as usize);
+        let written = time(&mut timings.render, || {
+            video::export_video(frames, &builder, format, path.as_deref())
+        })?;
+        info!(frames = written, %format, "Exported video of {image_path}");
+        return report_timings(cli, &timings);
+    }
+
+    if let Some(format) = cli.record {
+        if format == RecordFormat::Script && output.is_none() {
+            return Err(AsciiArtError::InvalidArgument(
+                "--record script writes a timing file beside the typescript; name them with \
+                 --output-template"
+                    .to_string(),
+            ));
+        }
+        let fields = OutputFields {
+            input: Path::new(image_path),
+            mode,
+            width: art_width,
+            format: OutputFormat::Recording(format),
+        };
+        let path = claim_export(output, &fields)?;
+        let range = cli.frames.unwrap_or_default();
+        let frames = animation::open_frames(image_path, &limits, Duration::ZERO)?
+            .select(range, cli.frame_step as usize);
+        let written = time(&mut timings.render, || {
+            recording::record(frames, &builder, format, path.as_deref())
+        })?;
+        info!(frames = written, %format, "Recorded {image_path}");
+        return report_timings(cli, &timings);
     }
+
+    // Render colored ASCII art to terminal in strips, so tall output stays cheap
+    let stdout = io::stdout();
+    // A bar on the same terminal would interleave with the art itself
+    let mut progress = if cli.deterministic || stdout.is_terminal() {
+        Progress::hidden()
+    } else {
+        Progress::new("Converting", 0)
+    };
+    let link_url = cli
+        .hyperlink
+        .filter(|_| terminal::hyperlinks_enabled(output.is_none()))
+        .and_then(|_| terminal::file_url(Path::new(image_path)));
+    if cli.hyperlink.is_some() && link_url.is_none() {
+        debug!("Not linking {image_path}: this output may not show hyperlinks");
+    }
+    let fields = OutputFields {
+        input: Path::new(image_path),
+        mode,
+        width: art_width,
+        format: text_format(color_depth),
+    };
+    let images = hybrid.is_some() || preview.is_some();
+    let mut writer = art_writer(cli, output, &fields, text, images)?;
+    let mut stats = cli.stats.then(Stats::default);
+    if let Some(protocol) = hybrid {
+        let sequence = graphics_sequence(
+            cli,
+            protocol,
+            &image,
+            image_columns,
+            cell_size,
+            &mut timings,
+        )?;
+        let mut art = Vec::new();
+        let art_timings = builder.write_streaming_with_stats(
+            &image,
+            &mut art,
+            DEFAULT_STRIP_ROWS,
+            stats.as_mut(),
+            |done, total| {
+                progress.set_length(total as u64);
+                progress.set_position(done as u64);
+            },
+        )?;
+        progress.finish();
+
+        let image_rows = graphic_rows(&image, image_columns, cell_size);
+        let art = String::from_utf8_lossy(&art);
+        let screen = layout::side_by_side(&sequence, image_rows, image_columns, &art);
+        start_hyperlink(&mut writer, cli.hyperlink, link_url.as_deref())?;
+        writer.write_all(screen.as_bytes())?;
+        finish_hyperlink(&mut writer, cli.hyperlink, link_url.as_deref(), image_path)?;
+        writer.flush()?;
+        add_timings(&mut timings, &art_timings);
+        report_stats(stats.as_ref());
+        report_score(cli, &builder, &image)?;
+        return report_timings(cli, &timings);
+    }
+    if let Some(protocol) = preview {
+        let sequence = graphics_sequence(cli, protocol, &image, width, cell_size, &mut timings)?;
+        writer.write_all(sequence.as_bytes())?;
+        writer.write_all(b"\n")?;
+    }
+    start_hyperlink(&mut writer, cli.hyperlink, link_url.as_deref())?;
+    let art_timings = builder.write_streaming_with_stats(
+        &image,
+        &mut writer,
+        DEFAULT_STRIP_ROWS,
+        stats.as_mut(),
+        |done, total| {
+            progress.set_length(total as u64);
+            progress.set_position(done as u64);
+        },
+    )?;
+    progress.finish();
+    finish_hyperlink(&mut writer, cli.hyperlink, link_url.as_deref(), image_path)?;
+    // Wait for a pager or the viewer to be quit before reporting anything under it
+    finish_art(writer)?;
+
+    add_timings(&mut timings, &art_timings);
+    report_stats(stats.as_ref());
+    report_score(cli, &builder, &image)?;
+    report_timings(cli, &timings)
 }
 
-fn run_pipeline(cli: &Cli, width: u32) -> Result<(ProcessedImage, AsciiGrid), String> {
-    let image = load_image(&cli.image_path).map_err(|e| e.to_string())?;
-    let processed = preprocess_image(image, width).map_err(|e| e.to_string())?;
-    
-    // Select conversion mode based on CLI argument
-    let ascii_grid = match cli.mode.as_str() {
-        "edge" => edge_detector::detect_and_convert(&processed.gray)
-            .map_err(|e| format!("Edge detection failed: {}", e))?,
-        "standard" => convert_to_ascii(&processed.gray)?,
-        unknown => return Err(format!("Unknown mode '{}'. Use 'standard' or 'edge'.", unknown)),
+/// Show the art saved in the grid document `path`, or write it to the file
+/// `output` names for it
+///
+/// The cells are shown as they were converted; only how their colors are
+/// written follows the command line.
+fn run_grid(
+    cli: &Cli,
+    path: &str,
+    color_depth: ColorDepth,
+    output: Option<&mut OutputFiles>,
+) -> Result<(), AsciiArtError> {
+    let needs_image = cli.render != RenderMode::Ascii
+        || cli.layout != Layout::Single
+        || cli.preview_original
+        || cli.frames.is_some()
+        || cli.frames_out.is_some()
+        || cli.format.is_some()
+        || cli.record.is_some()
+        || cli.auto_tune
+        || cli.score;
+    if needs_image {
+        return Err(AsciiArtError::InvalidArgument(format!(
+            "{path} is a grid document, already converted; --render, --layout, \
+             --preview-original, --frames, --frames-out, --format, --record, --auto-tune, and \
+             --score need an image"
+        )));
+    }
+    if cli
+        .palette
+        .as_deref()
+        .is_some_and(|palette| palette.starts_with("adaptive:"))
+    {
+        return Err(AsciiArtError::InvalidArgument(format!(
+            "{path} is a grid document; --palette adaptive:N needs an image"
+        )));
+    }
+
+    let mut timings = Timings::default();
+    let document = time(&mut timings.load, || GridDocument::load(Path::new(path)))?;
+    let mode = match document.metadata.get("mode").map(|mode| mode.parse()) {
+        Some(Ok(mode)) => mode,
+        _ => Mode::default(),
+    };
+    debug!(
+        width = document.image.width(),
+        height = document.image.height(),
+        ?mode,
+        "Loaded the grid document {path}"
+    );
+    let mut builder = AsciiArtBuilder::new()
+        .color_depth(color_depth)
+        .color_metric(cli.color_metric)
+        .grayscale(cli.color == ColorChoice::Gray)
+        .color_dithering(cli.color_dither)
+        .shading(cli.shade)
+        .compact(cli.compact);
+    if let Some(palette) = resolve_palette(cli, color_depth)? {
+        builder = builder.palette(palette);
+    }
+    let art = builder.from_cells(document.image);
+
+    let fields = OutputFields {
+        input: Path::new(path),
+        mode,
+        width: art.width() as u32,
+        format: text_format(color_depth),
     };
-    
-    Ok((processed, ascii_grid))
+    let text = TextExport {
+        line_ending: cli.line_ending,
+        encoding: cli.encoding,
+    };
+    let link_url = cli
+        .hyperlink
+        .filter(|_| terminal::hyperlinks_enabled(output.is_none()))
+        .and_then(|_| terminal::file_url(Path::new(path)));
+    let mut writer = art_writer(cli, output, &fields, text, false)?;
+    start_hyperlink(&mut writer, cli.hyperlink, link_url.as_deref())?;
+    time(&mut timings.render, || art.write_to(&mut writer))?;
+    finish_hyperlink(&mut writer, cli.hyperlink, link_url.as_deref(), path)?;
+    // Wait for a pager or the viewer to be quit before reporting anything under it
+    finish_art(writer)?;
+
+    if cli.stats {
+        let mut stats = Stats::default();
+        stats.record(art.image(), color_depth != ColorDepth::Monochrome);
+        stats.record_bytes(art.to_ansi_string().len());
+        report_stats(Some(&stats));
+    }
+    report_timings(cli, &timings)
+}
+
+/// What art in `color_depth` is written to files as
+fn text_format(color_depth: ColorDepth) -> OutputFormat {
+    match color_depth {
+        ColorDepth::Monochrome => OutputFormat::Text,
+        _ => OutputFormat::Ansi,
+    }
+}
+
+/// Whether art has been written to stdout yet, so a byte order mark starts
+/// only the first image
+static STDOUT_STARTED: AtomicBool = AtomicBool::new(false);
+
+/// Where the art of `fields.input` goes: the file `output` names for it, the
+/// terminal (through a pager or the viewer when asked), or stdout, with the
+/// screen cleared and the art placed as `--clear` and `--at` ask
+///
+/// With `images`, the art comes with inline images, which pagers can't show.
+fn art_writer(
+    cli: &Cli,
+    output: Option<&mut OutputFiles>,
+    fields: &OutputFields,
+    text: TextExport,
+    images: bool,
+) -> Result<Positioned<BufWriter<ArtSink>>, AsciiArtError> {
+    let stdout = io::stdout();
+    let mut writer = BufWriter::new(match output {
+        Some(files) => {
+            let path = files.claim(fields)?;
+            info!("Writing {} to {}", fields.input.display(), path.display());
+            ArtSink::File(TextWriter::new(File::create(&path)?, text)?)
+        }
+        // Line endings and encoding are for files, not what a terminal shows
+        None if stdout.is_terminal() && cli.scroll => ArtSink::Viewer(Vec::new()),
+        None if stdout.is_terminal() => {
+            // Pagers show text: not inline images, nor art drawn in place
+            let in_place = cli.clear || cli.at.is_some() || cli.watch_dir.is_some();
+            let pager = if in_place || images {
+                PagerChoice::Never
+            } else {
+                cli.pager
+            };
+            ArtSink::Terminal(PagedStdout::new(pager))
+        }
+        // One byte order mark for all of stdout, not one per image
+        None if STDOUT_STARTED.swap(true, Ordering::Relaxed) => {
+            ArtSink::Stdout(TextWriter::continuing(stdout.lock(), text))
+        }
+        None => ArtSink::Stdout(TextWriter::new(stdout.lock(), text)?),
+    });
+    if cli.clear && stdout.is_terminal() {
+        // Only the visible screen: erasing scrollback (`ESC[3J`) or resetting
+        // the terminal would also reach past an alternate screen
+        writer.write_all(b"\x1b[H\x1b[2J")?;
+    }
+    Ok(Positioned::new(writer, cli.at)?)
+}
+
+/// Put the cursor back after art drawn with `--at`, show art held for the
+/// viewer, and close the output
+fn finish_art(mut writer: Positioned<BufWriter<ArtSink>>) -> io::Result<()> {
+    writer.finish()?;
+    writer.get_mut().get_mut().finish()
+}
+
+/// Where [`art_writer`] sends the art
+enum ArtSink {
+    /// A file named by `--output-template`
+    File(TextWriter<File>),
+    /// The terminal, through a pager when one is asked for
+    Terminal(PagedStdout),
+    /// Art held for the built-in viewer, shown by [`ArtSink::finish`]
+    Viewer(Vec<u8>),
+    /// Stdout that isn't a terminal
+    Stdout(TextWriter<io::StdoutLock<'static>>),
+}
+
+impl ArtSink {
+    /// Show art held for the viewer; other outputs only need a flush
+    fn finish(&mut self) -> io::Result<()> {
+        match self {
+            ArtSink::Viewer(held) => viewer::view(&String::from_utf8_lossy(&std::mem::take(held))),
+            sink => sink.flush(),
+        }
+    }
+}
+
+impl Write for ArtSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            ArtSink::File(file) => file.write(buf),
+            ArtSink::Terminal(terminal) => terminal.write(buf),
+            ArtSink::Viewer(held) => held.write(buf),
+            ArtSink::Stdout(stdout) => stdout.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            ArtSink::File(file) => file.flush(),
+            ArtSink::Terminal(terminal) => terminal.flush(),
+            ArtSink::Viewer(_) => Ok(()),
+            ArtSink::Stdout(stdout) => stdout.flush(),
+        }
+    }
+}
+
+/// Open the `--hyperlink art` link around the art, when links can be written
+fn start_hyperlink<W: Write>(
+    writer: &mut W,
+    target: Option<Hyperlink>,
+    url: Option<&str>,
+) -> io::Result<()> {
+    match (target, url) {
+        (Some(Hyperlink::Art), Some(url)) => {
+            writer.write_all(terminal::hyperlink_start(url).as_bytes())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Close the link around the art, or write the `--hyperlink caption` line,
+/// linked when links can be written and plain otherwise
+fn finish_hyperlink<W: Write>(
+    writer: &mut W,
+    target: Option<Hyperlink>,
+    url: Option<&str>,
+    image_path: &str,
+) -> io::Result<()> {
+    match (target, url) {
+        (Some(Hyperlink::Art), Some(_)) => writer.write_all(terminal::HYPERLINK_END.as_bytes()),
+        (Some(Hyperlink::Caption), Some(url)) => writeln!(
+            writer,
+            "{}{image_path}{}",
+            terminal::hyperlink_start(url),
+            terminal::HYPERLINK_END
+        ),
+        (Some(Hyperlink::Caption), None) => writeln!(writer, "{image_path}"),
+        _ => Ok(()),
+    }
+}
+
+/// Columns that keep the image's proportions when drawn `rows` lines tall
+fn proportional_width(image: &DynamicImage, rows: u32, char_aspect: f32) -> u32 {
+    let (width, height) = (image.width() as f32, image.height().max(1) as f32);
+    (rows as f32 * char_aspect * width / height)
+        .round()
+        .max(1.0) as u32
+}
+
+/// Terminal rows covered by the image drawn across `columns` cells
+fn graphic_rows(image: &DynamicImage, columns: u32, cell_size: (u32, u32)) -> u32 {
+    let pixels = columns.max(1) * cell_size.0.max(1);
+    graphics::scaled_height(image, pixels).div_ceil(cell_size.1.max(1))
+}
+
+/// Fold the art's stage timings into the totals for the run
+fn add_timings(timings: &mut Timings, art: &Timings) {
+    timings.denoise += art.denoise;
+    timings.resize += art.resize;
+    timings.grayscale += art.grayscale;
+    timings.conversion += art.conversion;
+    timings.render += art.render;
+}
+
+/// Print the usage statistics on stderr when `--stats` is set
+fn report_stats(stats: Option<&Stats>) {
+    if let Some(stats) = stats {
+        eprint!("{stats}");
+    }
+}
+
+/// Print the quality score on stderr when `--score` is set
+///
+/// The art is converted once more, so it can be compared as a whole.
+fn report_score(
+    cli: &Cli,
+    builder: &AsciiArtBuilder,
+    image: &DynamicImage,
+) -> Result<(), AsciiArtError> {
+    if cli.score {
+        eprint!("{}", builder.score(image)?);
+    }
+    Ok(())
+}
+
+/// Print the stage timings on stderr when `--timings` is set
+fn report_timings(cli: &Cli, timings: &Timings) -> Result<(), AsciiArtError> {
+    debug!(
+        load = ?timings.load,
+        denoise = ?timings.denoise,
+        resize = ?timings.resize,
+        grayscale = ?timings.grayscale,
+        conversion = ?timings.conversion,
+        render = ?timings.render,
+        "Pipeline finished"
+    );
+    if cli.timings {
+        eprint!("{timings}");
+    }
+    Ok(())
 }
@@ -0,0 +1,563 @@
+//! File names for batch conversions, built from a template such as
+//! `{stem}_{width}w.{ext}`, and the line endings and encoding of the text
+//! written to them
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::builder::Mode;
+use crate::error::AsciiArtError;
+use crate::grid;
+use crate::recording::RecordFormat;
+use crate::video::VideoFormat;
+
+/// Placeholders a template may use
+const PLACEHOLDERS: [&str; 6] = ["stem", "dir", "mode", "width", "format", "ext"];
+
+/// What an output file holds, for the `{format}` and `{ext}` placeholders
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Art with ANSI colors
+    Ansi,
+    /// Art without escape sequences
+    Text,
+    /// Pictures of the art, encoded as an animation
+    Video(VideoFormat),
+    /// A terminal session that replays the art
+    Recording(RecordFormat),
+    /// A grid document of the converted cells
+    Grid,
+}
+
+impl OutputFormat {
+    pub fn name(self) -> &'static str {
+        match self {
+            OutputFormat::Ansi => "ansi",
+            OutputFormat::Text => "text",
+            OutputFormat::Video(format) => format.extension(),
+            OutputFormat::Recording(format) => format.name(),
+            OutputFormat::Grid => grid::EXTENSION,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Ansi => "ans",
+            OutputFormat::Text => "txt",
+            OutputFormat::Video(format) => format.extension(),
+            OutputFormat::Recording(format) => format.extension(),
+            OutputFormat::Grid => grid::EXTENSION,
+        }
+    }
+}
+
+/// A path with `{placeholder}`s filled in for each input
+///
+/// `{stem}` is the input's file name without its extension, `{dir}` the
+/// directory it is in, `{mode}` the rendering mode, `{width}` the output
+/// width in characters, and `{format}` and `{ext}` the [`OutputFormat`] and
+/// its extension, such as `ansi` and `ans`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputTemplate {
+    template: String,
+}
+
+impl FromStr for OutputTemplate {
+    type Err = AsciiArtError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut rest = value;
+        while let Some(open) = rest.find(['{', '}']) {
+            let close = if rest[open..].starts_with('{') {
+                rest[open..].find('}').map(|close| open + close)
+            } else {
+                None
+            };
+            let Some(close) = close else {
+                return Err(AsciiArtError::InvalidArgument(format!(
+                    "Unmatched brace in output template '{}'.",
+                    value
+                )));
+            };
+            let name = &rest[open + 1..close];
+            if !PLACEHOLDERS.contains(&name) {
+                return Err(AsciiArtError::InvalidArgument(format!(
+                    "Unknown placeholder '{{{}}}' in output template. Use {}.",
+                    name,
+                    PLACEHOLDERS.map(|name| format!("{{{name}}}")).join(", ")
+                )));
+            }
+            rest = &rest[close + 1..];
+        }
+        if value.is_empty() {
+            return Err(AsciiArtError::InvalidArgument(
+                "Output template must not be empty.".to_string(),
+            ));
+        }
+        Ok(Self {
+            template: value.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for OutputTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.template)
+    }
+}
+
+/// What a template's placeholders are filled with for one input
+#[derive(Debug, Clone, Copy)]
+pub struct OutputFields<'a> {
+    pub input: &'a Path,
+    pub mode: Mode,
+    pub width: u32,
+    pub format: OutputFormat,
+}
+
+impl OutputTemplate {
+    /// The path `fields.input` is written to
+    pub fn expand(&self, fields: &OutputFields) -> PathBuf {
+        let path = self
+            .fill_input(fields.input)
+            .replace("{mode}", &fields.mode.to_string())
+            .replace("{width}", &fields.width.to_string())
+            .replace("{format}", fields.format.name())
+            .replace("{ext}", fields.format.extension());
+        PathBuf::from(path)
+    }
+
+    /// The template with only the placeholders taken from `input` filled in
+    fn fill_input(&self, input: &Path) -> String {
+        let stem = input.file_stem().map(|stem| stem.to_string_lossy());
+        let dir = match input.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_string_lossy(),
+            _ => ".".into(),
+        };
+        self.template
+            .replace("{stem}", stem.as_deref().unwrap_or("output"))
+            .replace("{dir}", &dir)
+    }
+
+    /// Why `earlier` and `input` would both be written to `path`, with the
+    /// placeholder that would tell them apart
+    fn collision(&self, earlier: &Path, input: &Path, path: &Path) -> AsciiArtError {
+        let uses = |name: &str| self.template.contains(&format!("{{{name}}}"));
+        let hint = match (uses("stem"), uses("dir")) {
+            (false, false) => "; add a placeholder such as {stem} or {dir} to the output \
+                               template to tell them apart"
+                .to_string(),
+            (true, true) => String::new(),
+            (stem, _) => format!(
+                "; add {{{}}} to the output template to tell them apart",
+                if stem { "dir" } else { "stem" }
+            ),
+        };
+        AsciiArtError::InvalidArgument(format!(
+            "Both {} and {} would be written to {}{hint}.",
+            earlier.display(),
+            input.display(),
+            path.display()
+        ))
+    }
+}
+
+/// Output paths handed out during a batch run, so no two inputs write the
+/// same file
+#[derive(Debug, Clone)]
+pub struct OutputFiles {
+    template: OutputTemplate,
+    overwrite: bool,
+    /// Each path claimed so far, and the input it was claimed for
+    claimed: HashMap<PathBuf, PathBuf>,
+}
+
+impl OutputFiles {
+    /// Paths from `template`, replacing existing files only with `overwrite`
+    pub fn new(template: OutputTemplate, overwrite: bool) -> Self {
+        Self {
+            template,
+            overwrite,
+            claimed: HashMap::new(),
+        }
+    }
+
+    /// Fail if any two of `inputs` would be written to the same path, before
+    /// any of them is converted
+    ///
+    /// Only `{stem}` and `{dir}` are filled in: the other placeholders are
+    /// taken to be the same for every input, which they are unless the mode
+    /// or width is picked per image.
+    pub fn check_inputs<'a>(
+        &self,
+        inputs: impl IntoIterator<Item = &'a Path>,
+    ) -> Result<(), AsciiArtError> {
+        let mut seen: HashMap<String, &Path> = HashMap::new();
+        for input in inputs {
+            let path = self.template.fill_input(input);
+            match seen.get(&path) {
+                Some(&earlier) if earlier != input => {
+                    return Err(self.template.collision(earlier, input, Path::new(&path)))
+                }
+                Some(_) => {}
+                None => {
+                    seen.insert(path, input);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The path to write `fields.input` to, with its directory created
+    ///
+    /// Fails if another input of the run was given the same path, if the
+    /// path is the input itself, or if the file already exists and
+    /// overwriting wasn't asked for. An input converted again, as when a
+    /// watched image changes, replaces its own earlier output.
+    pub fn claim(&mut self, fields: &OutputFields) -> Result<PathBuf, AsciiArtError> {
+        let path = self.template.expand(fields);
+        let earlier = self.claimed.get(&path);
+        if earlier.is_some_and(|earlier| earlier == fields.input) {
+            return Ok(path);
+        }
+        if let Some(earlier) = earlier {
+            return Err(self.template.collision(earlier, fields.input, &path));
+        }
+        if is_same_file(&path, fields.input) {
+            return Err(AsciiArtError::InvalidArgument(format!(
+                "The output template would write {} over itself.",
+                path.display()
+            )));
+        }
+        if path.exists() && !self.overwrite {
+            return Err(AsciiArtError::InvalidArgument(format!(
+                "{} already exists; pass --overwrite to replace it.",
+                path.display()
+            )));
+        }
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        self.claimed
+            .insert(path.clone(), fields.input.to_path_buf());
+        Ok(path)
+    }
+}
+
+/// Whether `a` and `b` name the same existing file, however they're spelled
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    match (std::fs::canonicalize(a), std::fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// How lines of exported text end
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum LineEnding {
+    /// `\n`, as on Unix
+    #[default]
+    Lf,
+    /// `\r\n`, as Windows tools such as Notepad expect
+    Crlf,
+}
+
+/// Character encoding of exported text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum TextEncoding {
+    #[default]
+    #[cfg_attr(feature = "cli", value(name = "utf-8"))]
+    Utf8,
+    /// UTF-8 starting with a byte order mark, which Windows editors look for
+    #[cfg_attr(feature = "cli", value(name = "utf-8-bom"))]
+    Utf8Bom,
+    /// The IBM PC character set that classic ANSI art viewers read, with any
+    /// character it lacks written as `?`
+    Cp437,
+}
+
+/// Byte order mark that starts `utf-8-bom` text
+const UTF8_BOM: &[u8] = b"\xef\xbb\xbf";
+
+/// Characters of code page 437 from 0x80 up; below that it matches ASCII
+const CP437_HIGH: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å', //
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ', //
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º', '¿', '⌐', '¬', '½', '¼', '¡', '«', '»', //
+    '░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐', //
+    '└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧', //
+    '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀', //
+    'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ', 'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩', //
+    '≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈', '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{a0}',
+];
+
+/// The code page 437 byte for `ch`, or `?` when it has none
+fn cp437_byte(ch: char) -> u8 {
+    if ch.is_ascii() {
+        return ch as u8;
+    }
+    CP437_HIGH
+        .iter()
+        .position(|&high| high == ch)
+        .map_or(b'?', |index| 0x80 + index as u8)
+}
+
+/// Line endings and encoding of text written to a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TextExport {
+    pub line_ending: LineEnding,
+    pub encoding: TextEncoding,
+}
+
+impl TextExport {
+    /// `text` as it is written to a file, byte order mark included
+    pub fn encode(self, text: &str) -> Vec<u8> {
+        let mut out = Vec::with_capacity(text.len() + UTF8_BOM.len());
+        let mut writer = TextWriter::new(&mut out, self).expect("writing to a Vec cannot fail");
+        writer
+            .write_all(text.as_bytes())
+            .expect("writing to a Vec cannot fail");
+        drop(writer);
+        out
+    }
+}
+
+/// A writer that converts the UTF-8 text written to it to a [`TextExport`]'s
+/// line endings and encoding before passing it on
+///
+/// A character split across writes is held back until the rest of it
+/// arrives; one still incomplete when the writer is flushed or dropped is
+/// written as `?`.
+#[derive(Debug)]
+pub struct TextWriter<W: Write> {
+    inner: W,
+    export: TextExport,
+    /// The start of a character whose remaining bytes haven't been written yet
+    pending: Vec<u8>,
+}
+
+impl<W: Write> TextWriter<W> {
+    /// Write to `inner` as `export` says, starting with the byte order mark
+    /// if it has one
+    pub fn new(mut inner: W, export: TextExport) -> io::Result<Self> {
+        if export.encoding == TextEncoding::Utf8Bom {
+            inner.write_all(UTF8_BOM)?;
+        }
+        Ok(Self::continuing(inner, export))
+    }
+
+    /// Write to `inner` as `export` says, without a byte order mark, for text
+    /// that continues a stream which already has one
+    pub fn continuing(inner: W, export: TextExport) -> Self {
+        Self {
+            inner,
+            export,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Stand in for a character that never got its remaining bytes
+    fn finish_pending(&mut self) -> io::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        self.pending.clear();
+        self.inner.write_all(b"?")
+    }
+
+    fn push_line_end(&self, out: &mut Vec<u8>) {
+        match self.export.line_ending {
+            LineEnding::Lf => out.push(b'\n'),
+            LineEnding::Crlf => out.extend_from_slice(b"\r\n"),
+        }
+    }
+}
+
+impl<W: Write> Write for TextWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let cp437 = self.export.encoding == TextEncoding::Cp437;
+        if !cp437 && self.export.line_ending == LineEnding::Lf {
+            return self.inner.write(buf);
+        }
+        let mut out = Vec::with_capacity(buf.len() + buf.len() / 16);
+        if !cp437 {
+            for &byte in buf {
+                match byte {
+                    b'\n' => self.push_line_end(&mut out),
+                    byte => out.push(byte),
+                }
+            }
+            self.inner.write_all(&out)?;
+            return Ok(buf.len());
+        }
+
+        self.pending.extend_from_slice(buf);
+        let mut rest = &self.pending[..];
+        while !rest.is_empty() {
+            let (valid, skip) = match std::str::from_utf8(rest) {
+                Ok(text) => (text, rest.len()),
+                Err(err) => match err.error_len() {
+                    // Not UTF-8 at all: stand in for the bad bytes with `?`
+                    Some(len) => (
+                        std::str::from_utf8(&rest[..err.valid_up_to()]).expect("checked"),
+                        err.valid_up_to() + len,
+                    ),
+                    // The start of a character whose rest comes later
+                    None if err.valid_up_to() == 0 => break,
+                    None => (
+                        std::str::from_utf8(&rest[..err.valid_up_to()]).expect("checked"),
+                        err.valid_up_to(),
+                    ),
+                },
+            };
+            for ch in valid.chars() {
+                match ch {
+                    '\n' => self.push_line_end(&mut out),
+                    ch => out.push(cp437_byte(ch)),
+                }
+            }
+            if skip > valid.len() {
+                out.push(b'?');
+            }
+            rest = &rest[skip..];
+        }
+        let held = rest.len();
+        self.pending.drain(..self.pending.len() - held);
+        self.inner.write_all(&out)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.finish_pending()?;
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Drop for TextWriter<W> {
+    fn drop(&mut self) {
+        // Nowhere to report a failure from here; flush first to see it
+        let _ = self.finish_pending();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(input: &str) -> OutputFields<'_> {
+        OutputFields {
+            input: Path::new(input),
+            mode: Mode::Standard,
+            width: 80,
+            format: OutputFormat::Ansi,
+        }
+    }
+
+    #[test]
+    fn templates_fill_every_placeholder() {
+        let template: OutputTemplate = "{dir}/{stem}_{mode}_{width}w.{format}.{ext}"
+            .parse()
+            .expect("valid template");
+        assert_eq!(
+            template.expand(&fields("shots/cat.png")),
+            Path::new("shots/cat_standard_80w.ansi.ans")
+        );
+        let plain = OutputFields {
+            format: OutputFormat::Text,
+            ..fields("cat.png")
+        };
+        assert_eq!(
+            template.expand(&plain),
+            Path::new("./cat_standard_80w.text.txt")
+        );
+        let video = OutputFields {
+            format: OutputFormat::Video(VideoFormat::Mp4),
+            ..fields("cat.gif")
+        };
+        assert_eq!(
+            template.expand(&video),
+            Path::new("./cat_standard_80w.mp4.mp4")
+        );
+    }
+
+    #[test]
+    fn templates_reject_unknown_placeholders_and_stray_braces() {
+        assert!("{stem}.txt".parse::<OutputTemplate>().is_ok());
+        assert!("{name}.txt".parse::<OutputTemplate>().is_err());
+        assert!("{stem.txt".parse::<OutputTemplate>().is_err());
+        assert!("stem}.txt".parse::<OutputTemplate>().is_err());
+        assert!("".parse::<OutputTemplate>().is_err());
+    }
+
+    #[test]
+    fn claims_refuse_collisions_and_existing_files() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let template = format!("{}/out/{{stem}}.{{ext}}", dir.path().display());
+        let mut files = OutputFiles::new(template.parse().expect("valid template"), false);
+        let path = files.claim(&fields("a/cat.png")).expect("first claim");
+        assert!(path.parent().expect("out dir").is_dir());
+        let err = files.claim(&fields("b/cat.png")).unwrap_err();
+        assert!(err.to_string().contains("a/cat.png"), "{err}");
+        assert!(err.to_string().contains("add {dir}"), "{err}");
+        std::fs::write(&path, "art").expect("write output");
+        assert_eq!(files.claim(&fields("a/cat.png")).ok().as_ref(), Some(&path));
+
+        let mut again = OutputFiles::new(template.parse().expect("valid template"), false);
+        assert!(again.claim(&fields("a/cat.png")).is_err());
+        let mut overwrite = OutputFiles::new(template.parse().expect("valid template"), true);
+        assert_eq!(overwrite.claim(&fields("a/cat.png")).ok(), Some(path));
+    }
+
+    #[test]
+    fn colliding_inputs_are_found_before_any_is_claimed() {
+        let files = OutputFiles::new("out/{stem}.txt".parse().expect("valid template"), false);
+        let inputs = ["a/x.png", "a/y.png", "a/x.png"].map(Path::new);
+        assert!(files.check_inputs(inputs).is_ok());
+        let inputs = ["a/x.png", "b/x.png"].map(Path::new);
+        let err = files.check_inputs(inputs).unwrap_err();
+        assert!(err.to_string().contains("out/x.txt; add {dir}"), "{err}");
+
+        let fixed = OutputFiles::new("out.txt".parse().expect("valid template"), false);
+        let err = fixed.check_inputs(inputs).unwrap_err();
+        assert!(err.to_string().contains("such as {stem} or {dir}"), "{err}");
+    }
+
+    #[test]
+    fn text_exports_convert_line_endings_and_encoding() {
+        let windows = TextExport {
+            line_ending: LineEnding::Crlf,
+            encoding: TextEncoding::Utf8Bom,
+        };
+        assert_eq!(windows.encode("é\n#\n"), b"\xef\xbb\xbf\xc3\xa9\r\n#\r\n");
+        assert_eq!(TextExport::default().encode("é\n"), "é\n".as_bytes());
+
+        let dos = TextExport {
+            line_ending: LineEnding::Crlf,
+            encoding: TextEncoding::Cp437,
+        };
+        assert_eq!(dos.encode("\x1b[31m░▓█⣿\n"), b"\x1b[31m\xb0\xb2\xdb?\r\n");
+    }
+
+    #[test]
+    fn cp437_holds_back_characters_split_across_writes() {
+        let export = TextExport {
+            encoding: TextEncoding::Cp437,
+            ..TextExport::default()
+        };
+        let mut out = Vec::new();
+        let mut writer = TextWriter::new(&mut out, export).expect("writer");
+        let block = "a█b".as_bytes();
+        writer.write_all(&block[..2]).expect("write");
+        writer.write_all(&block[2..]).expect("write");
+        writer.write_all(b"\xff").expect("write");
+        writer.write_all(&block[..2]).expect("write");
+        writer.flush().expect("flush");
+        drop(writer);
+        assert_eq!(out, b"a\xdbb?a?");
+    }
+}
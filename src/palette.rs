@@ -0,0 +1,431 @@
+use std::fmt;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, OnceLock};
+
+use serde::Deserialize;
+
+use crate::ascii_converter::AsciiImage;
+use crate::color::{oklab_distance, srgb_to_oklab, ColorMetric};
+use crate::error::AsciiArtError;
+use crate::renderer::ansi16_rgb;
+
+/// RGB values the terminal actually displays for its palette indices
+///
+/// Holds either the 16 basic ANSI colors or a full 256-color table. Only the
+/// nearest-color search uses these values; the escape codes written are the
+/// same whatever the palette.
+#[derive(Debug, Clone)]
+pub struct Palette {
+    colors: Arc<[[u8; 3]]>,
+    /// `colors` in OKLab, converted on first use and shared between clones
+    lab: Arc<OnceLock<Vec<[f32; 3]>>>,
+}
+
+impl PartialEq for Palette {
+    fn eq(&self, other: &Self) -> bool {
+        self.colors == other.colors
+    }
+}
+
+impl Eq for Palette {}
+
+impl Hash for Palette {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.colors.hash(state);
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::xterm()
+    }
+}
+
+impl Palette {
+    pub fn new(colors: [[u8; 3]; 16]) -> Self {
+        Self {
+            colors: Arc::new(colors),
+            lab: Arc::default(),
+        }
+    }
+
+    /// Build a palette from 16 or 256 colors
+    pub fn from_colors(colors: Vec<[u8; 3]>) -> Result<Self, AsciiArtError> {
+        match colors.len() {
+            16 | 256 => Ok(Self {
+                colors: colors.into(),
+                lab: Arc::default(),
+            }),
+            count => Err(AsciiArtError::InvalidArgument(format!(
+                "Palette must list 16 or 256 colors, found {count}."
+            ))),
+        }
+    }
+
+    /// The nominal xterm values assumed when the terminal's palette is unknown
+    pub fn xterm() -> Self {
+        Self::new(std::array::from_fn(ansi16_rgb))
+    }
+
+    /// Load a palette file: JSON when the extension is `.json`, TOML otherwise
+    ///
+    /// Both formats hold a `colors` list whose entries are `"#rrggbb"` strings
+    /// or `[r, g, b]` arrays.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, AsciiArtError> {
+        let path = path.as_ref();
+        let invalid = |reason: String| {
+            AsciiArtError::InvalidArgument(format!(
+                "Invalid palette file \"{}\": {reason}",
+                path.display()
+            ))
+        };
+
+        let source = fs::read_to_string(path).map_err(|err| invalid(err.to_string()))?;
+        let is_json = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+        let result = if is_json {
+            Self::from_json(&source)
+        } else {
+            Self::from_toml(&source)
+        };
+        result.map_err(|err| invalid(err.to_string()))
+    }
+
+    /// Parse a palette from a TOML document with a `colors` list
+    pub fn from_toml(source: &str) -> Result<Self, AsciiArtError> {
+        let file: PaletteFile = toml::from_str(source)
+            .map_err(|err| AsciiArtError::InvalidArgument(err.message().to_string()))?;
+        file.into_palette()
+    }
+
+    /// Parse a palette from a JSON object with a `colors` list
+    pub fn from_json(source: &str) -> Result<Self, AsciiArtError> {
+        let file: PaletteFile = serde_json::from_str(source)
+            .map_err(|err| AsciiArtError::InvalidArgument(err.to_string()))?;
+        file.into_palette()
+    }
+
+    /// Every color in the palette, in index order
+    pub fn colors(&self) -> &[[u8; 3]] {
+        &self.colors
+    }
+
+    /// [`colors`](Self::colors) converted to OKLab, in the same order
+    pub fn oklab(&self) -> &[[f32; 3]] {
+        self.lab
+            .get_or_init(|| self.colors.iter().copied().map(srgb_to_oklab).collect())
+    }
+
+    /// The 16 basic ANSI colors
+    pub fn ansi16(&self) -> &[[u8; 3]] {
+        &self.colors[..16]
+    }
+
+    /// The first 8 entries: the standard colors, leaving out their bright variants
+    pub fn ansi8(&self) -> &[[u8; 3]] {
+        &self.colors[..8]
+    }
+
+    /// The full 256-color table, when the palette defines one
+    pub fn ansi256(&self) -> Option<&[[u8; 3]]> {
+        (self.colors.len() == 256).then_some(&self.colors[..])
+    }
+
+    /// Index (0-15) of the basic ANSI color closest to `rgb` under `metric`
+    pub fn nearest(&self, rgb: [u8; 3], metric: ColorMetric) -> usize {
+        nearest_color(rgb, self.ansi16(), &self.oklab()[..16], metric)
+    }
+
+    /// Index (0-7) of the standard ANSI color closest to `rgb` under `metric`
+    pub fn nearest_standard(&self, rgb: [u8; 3], metric: ColorMetric) -> usize {
+        nearest_color(rgb, self.ansi8(), &self.oklab()[..8], metric)
+    }
+}
+
+/// Index of the candidate closest to `rgb`; `lab` holds the candidates in OKLab
+pub(crate) fn nearest_color(
+    rgb: [u8; 3],
+    candidates: &[[u8; 3]],
+    lab: &[[f32; 3]],
+    metric: ColorMetric,
+) -> usize {
+    let target = srgb_to_oklab(rgb);
+    let score = |index: usize| match metric {
+        ColorMetric::Rgb => metric.distance(rgb, candidates[index]),
+        ColorMetric::Oklab => oklab_distance(target, lab[index]),
+    };
+
+    (0..candidates.len())
+        .min_by(|&a, &b| score(a).total_cmp(&score(b)))
+        .unwrap_or(0)
+}
+
+/// A built-in color scheme that output colors are snapped to
+///
+/// Unlike a [`Palette`], which describes what the terminal displays, a theme
+/// replaces each cell's color with the nearest scheme color, so the art looks
+/// cohesive whatever the colors of the source image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Theme {
+    /// Arctic blues and frosty grays
+    Nord,
+    /// Dark purple with vivid pinks and greens
+    Dracula,
+    /// Ethan Schoonover's dark Solarized scheme
+    SolarizedDark,
+    /// Warm retro browns, yellows, and greens
+    Gruvbox,
+}
+
+impl FromStr for Theme {
+    type Err = AsciiArtError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "nord" => Ok(Theme::Nord),
+            "dracula" => Ok(Theme::Dracula),
+            "solarized-dark" => Ok(Theme::SolarizedDark),
+            "gruvbox" => Ok(Theme::Gruvbox),
+            unknown => Err(AsciiArtError::InvalidArgument(format!(
+                "Unknown theme '{}'. Use 'nord', 'dracula', 'solarized-dark', or 'gruvbox'.",
+                unknown
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Theme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Theme::Nord => write!(f, "nord"),
+            Theme::Dracula => write!(f, "dracula"),
+            Theme::SolarizedDark => write!(f, "solarized-dark"),
+            Theme::Gruvbox => write!(f, "gruvbox"),
+        }
+    }
+}
+
+impl Theme {
+    /// The scheme's colors
+    pub fn colors(self) -> &'static [[u8; 3]] {
+        match self {
+            Theme::Nord => &NORD,
+            Theme::Dracula => &DRACULA,
+            Theme::SolarizedDark => &SOLARIZED_DARK,
+            Theme::Gruvbox => &GRUVBOX,
+        }
+    }
+
+    /// Replace every cell color with the nearest scheme color under `metric`
+    pub fn quantize(self, image: &mut AsciiImage, metric: ColorMetric) {
+        let colors = self.colors();
+        let lab: Vec<[f32; 3]> = colors.iter().copied().map(srgb_to_oklab).collect();
+        image.map_colors(|rgb| colors[nearest_color(rgb, colors, &lab, metric)]);
+    }
+}
+
+/// Nord (nordtheme.com): polar night, snow storm, frost, and aurora
+const NORD: [[u8; 3]; 16] = [
+    [0x2e, 0x34, 0x40],
+    [0x3b, 0x42, 0x52],
+    [0x43, 0x4c, 0x5e],
+    [0x4c, 0x56, 0x6a],
+    [0xd8, 0xde, 0xe9],
+    [0xe5, 0xe9, 0xf0],
+    [0xec, 0xef, 0xf4],
+    [0x8f, 0xbc, 0xbb],
+    [0x88, 0xc0, 0xd0],
+    [0x81, 0xa1, 0xc1],
+    [0x5e, 0x81, 0xac],
+    [0xbf, 0x61, 0x6a],
+    [0xd0, 0x87, 0x70],
+    [0xeb, 0xcb, 0x8b],
+    [0xa3, 0xbe, 0x8c],
+    [0xb4, 0x8e, 0xad],
+];
+
+/// Dracula (draculatheme.com)
+const DRACULA: [[u8; 3]; 11] = [
+    [0x28, 0x2a, 0x36],
+    [0x44, 0x47, 0x5a],
+    [0xf8, 0xf8, 0xf2],
+    [0x62, 0x72, 0xa4],
+    [0x8b, 0xe9, 0xfd],
+    [0x50, 0xfa, 0x7b],
+    [0xff, 0xb8, 0x6c],
+    [0xff, 0x79, 0xc6],
+    [0xbd, 0x93, 0xf9],
+    [0xff, 0x55, 0x55],
+    [0xf1, 0xfa, 0x8c],
+];
+
+/// Solarized (ethanschoonover.com/solarized): base tones and accents
+const SOLARIZED_DARK: [[u8; 3]; 16] = [
+    [0x00, 0x2b, 0x36],
+    [0x07, 0x36, 0x42],
+    [0x58, 0x6e, 0x75],
+    [0x65, 0x7b, 0x83],
+    [0x83, 0x94, 0x96],
+    [0x93, 0xa1, 0xa1],
+    [0xee, 0xe8, 0xd5],
+    [0xfd, 0xf6, 0xe3],
+    [0xb5, 0x89, 0x00],
+    [0xcb, 0x4b, 0x16],
+    [0xdc, 0x32, 0x2f],
+    [0xd3, 0x36, 0x82],
+    [0x6c, 0x71, 0xc4],
+    [0x26, 0x8b, 0xd2],
+    [0x2a, 0xa1, 0x98],
+    [0x85, 0x99, 0x00],
+];
+
+/// Gruvbox dark: background, normal, and bright accents
+const GRUVBOX: [[u8; 3]; 16] = [
+    [0x28, 0x28, 0x28],
+    [0xcc, 0x24, 0x1d],
+    [0x98, 0x97, 0x1a],
+    [0xd7, 0x99, 0x21],
+    [0x45, 0x85, 0x88],
+    [0xb1, 0x62, 0x86],
+    [0x68, 0x9d, 0x6a],
+    [0xa8, 0x99, 0x84],
+    [0x92, 0x83, 0x74],
+    [0xfb, 0x49, 0x34],
+    [0xb8, 0xbb, 0x26],
+    [0xfa, 0xbd, 0x2f],
+    [0x83, 0xa5, 0x98],
+    [0xd3, 0x86, 0x9b],
+    [0x8e, 0xc0, 0x7c],
+    [0xeb, 0xdb, 0xb2],
+];
+
+#[derive(Debug, Deserialize)]
+struct PaletteFile {
+    colors: Vec<ColorSpec>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ColorSpec {
+    Hex(String),
+    Rgb([u8; 3]),
+}
+
+impl PaletteFile {
+    fn into_palette(self) -> Result<Palette, AsciiArtError> {
+        let colors = self
+            .colors
+            .into_iter()
+            .map(|spec| match spec {
+                ColorSpec::Rgb(rgb) => Ok(rgb),
+                ColorSpec::Hex(hex) => parse_hex_color(&hex),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Palette::from_colors(colors)
+    }
+}
+
+/// Parse a `#rrggbb` (or `rrggbb`) color
+pub fn parse_hex_color(value: &str) -> Result<[u8; 3], AsciiArtError> {
+    let hex = value.strip_prefix('#').unwrap_or(value);
+    let invalid =
+        || AsciiArtError::InvalidArgument(format!("Invalid color '{value}'. Use '#rrggbb'."));
+    if hex.len() != 6 || !hex.is_ascii() {
+        return Err(invalid());
+    }
+
+    let mut rgb = [0u8; 3];
+    for (channel, index) in rgb.iter_mut().zip([0, 2, 4]) {
+        *channel = u8::from_str_radix(&hex[index..index + 2], 16).map_err(|_| invalid())?;
+    }
+    Ok(rgb)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::ansi16_index;
+
+    #[test]
+    fn xterm_palette_matches_builtin_lookup() {
+        let palette = Palette::xterm();
+        for rgb in [[0, 0, 0], [250, 10, 10], [198, 134, 66], [90, 200, 180]] {
+            for metric in [ColorMetric::Rgb, ColorMetric::Oklab] {
+                let [r, g, b] = rgb;
+                assert_eq!(palette.nearest(rgb, metric), ansi16_index(r, g, b, metric));
+            }
+        }
+    }
+
+    #[test]
+    fn parses_toml_and_json_files() {
+        let hex: Vec<String> = (0..16)
+            .map(|i| format!("\"#{:02x}0000\"", i * 16))
+            .collect();
+        let toml = format!("colors = [{}]", hex.join(", "));
+        let palette = Palette::from_toml(&toml).expect("valid toml palette");
+        assert_eq!(palette.colors()[15], [240, 0, 0]);
+        assert!(palette.ansi256().is_none());
+
+        let arrays: Vec<String> = (0..256).map(|i| format!("[{i}, {i}, 0]")).collect();
+        let json = format!("{{\"colors\": [{}]}}", arrays.join(","));
+        let palette = Palette::from_json(&json).expect("valid json palette");
+        assert_eq!(palette.ansi256().map(<[_]>::len), Some(256));
+    }
+
+    #[test]
+    fn oklab_colors_are_converted_once_per_palette() {
+        let palette = Palette::xterm();
+        let copy = palette.clone();
+        assert_eq!(palette.oklab().len(), 16);
+        assert_eq!(palette.oklab()[15], srgb_to_oklab(palette.colors()[15]));
+        assert!(std::ptr::eq(palette.oklab(), copy.oklab()));
+        assert_eq!(palette, copy);
+    }
+
+    #[test]
+    fn theme_quantize_snaps_cells_to_scheme() {
+        use crate::ascii_converter::Cell;
+
+        let cells = vec![
+            Cell::opaque('#', [250, 20, 20], 80),
+            Cell::opaque('.', [10, 10, 10], 10),
+        ];
+        let mut image = AsciiImage::new(2, 1, cells).expect("valid image");
+        Theme::Gruvbox.quantize(&mut image, ColorMetric::Oklab);
+
+        let colors = Theme::Gruvbox.colors();
+        assert!(image.cells().iter().all(|cell| colors.contains(&cell.rgb)));
+        assert_eq!(image.cells()[1].rgb, [0x28, 0x28, 0x28]);
+        assert_eq!(image.to_text(), "#.\n");
+    }
+
+    #[test]
+    fn theme_names_round_trip() {
+        for theme in [
+            Theme::Nord,
+            Theme::Dracula,
+            Theme::SolarizedDark,
+            Theme::Gruvbox,
+        ] {
+            assert_eq!(theme.to_string().parse::<Theme>().ok(), Some(theme));
+        }
+        assert!("monokai".parse::<Theme>().is_err());
+    }
+
+    #[test]
+    fn rejects_wrong_color_counts_and_bad_hex() {
+        let err = Palette::from_toml("colors = [\"#000000\"]").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Palette must list 16 or 256 colors, found 1."
+        );
+        assert!(parse_hex_color("#12345g").is_err());
+        assert_eq!(parse_hex_color("ff8000").ok(), Some([255, 128, 0]));
+    }
+}
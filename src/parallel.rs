@@ -0,0 +1,63 @@
+use crate::error::AsciiArtError;
+
+/// Map `f` over `0..count`, in parallel when the `parallel` feature is enabled
+///
+/// Results are returned in index order regardless of how the work was scheduled.
+pub fn map_rows<T, F>(count: usize, f: F) -> Vec<T>
+where
+    T: Send,
+    F: Fn(usize) -> T + Sync + Send,
+{
+    #[cfg(feature = "parallel")]
+    {
+        use rayon::prelude::*;
+        (0..count).into_par_iter().map(f).collect()
+    }
+
+    #[cfg(not(feature = "parallel"))]
+    {
+        (0..count).map(f).collect()
+    }
+}
+
+/// Limit the number of worker threads used for conversion and rendering
+///
+/// Must be called before any parallel work starts; `0` keeps rayon's default
+/// of one thread per logical CPU.
+pub fn set_thread_count(threads: usize) -> Result<(), AsciiArtError> {
+    #[cfg(feature = "parallel")]
+    {
+        if threads == 0 {
+            return Ok(());
+        }
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .map_err(|err| {
+                AsciiArtError::InvalidArgument(format!("Could not configure threads: {err}"))
+            })
+    }
+
+    #[cfg(not(feature = "parallel"))]
+    {
+        if threads > 1 {
+            return Err(AsciiArtError::InvalidArgument(
+                "This build does not include the 'parallel' feature; --threads is unavailable."
+                    .to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn map_rows_preserves_order() {
+        let squares = map_rows(100, |i| i * i);
+        assert_eq!(squares.len(), 100);
+        assert!(squares.iter().enumerate().all(|(i, &v)| v == i * i));
+    }
+}
@@ -0,0 +1,117 @@
+use std::io::{self, IsTerminal, Write};
+
+/// Width of the bar itself, excluding the label and counters
+const BAR_WIDTH: usize = 30;
+
+/// A single-line progress bar drawn on stderr
+///
+/// The bar is only drawn when stderr is a terminal, so redirected or piped
+/// diagnostics never receive carriage returns and partial lines.
+#[derive(Debug)]
+pub struct Progress {
+    label: String,
+    total: u64,
+    done: u64,
+    enabled: bool,
+}
+
+impl Progress {
+    /// Create a bar that draws only when stderr is a terminal
+    pub fn new(label: impl Into<String>, total: u64) -> Self {
+        Self::with_enabled(label, total, io::stderr().is_terminal())
+    }
+
+    /// A bar that never draws anything
+    pub fn hidden() -> Self {
+        Self::with_enabled("", 0, false)
+    }
+
+    fn with_enabled(label: impl Into<String>, total: u64, enabled: bool) -> Self {
+        Self {
+            label: label.into(),
+            total,
+            done: 0,
+            enabled,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Change the total amount of work, e.g. once it becomes known
+    pub fn set_length(&mut self, total: u64) {
+        self.total = total;
+    }
+
+    /// Record `amount` more units of completed work and redraw
+    pub fn inc(&mut self, amount: u64) {
+        self.set_position(self.done.saturating_add(amount));
+    }
+
+    /// Set the number of completed units and redraw
+    pub fn set_position(&mut self, done: u64) {
+        self.done = done.min(self.total);
+        if self.enabled {
+            let mut stderr = io::stderr().lock();
+            let _ = write!(stderr, "\r{}", self.line());
+            let _ = stderr.flush();
+        }
+    }
+
+    /// Erase the bar so following output starts on a clean line
+    pub fn finish(&mut self) {
+        if self.enabled {
+            let mut stderr = io::stderr().lock();
+            let _ = write!(stderr, "\r\x1b[2K");
+            let _ = stderr.flush();
+            self.enabled = false;
+        }
+    }
+
+    /// The text of the bar at its current position
+    fn line(&self) -> String {
+        let filled = if self.total == 0 {
+            BAR_WIDTH
+        } else {
+            (self.done as u128 * BAR_WIDTH as u128 / self.total as u128) as usize
+        };
+        format!(
+            "{} [{}{}] {}/{}",
+            self.label,
+            "#".repeat(filled),
+            "-".repeat(BAR_WIDTH - filled),
+            self.done,
+            self.total
+        )
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_reflects_position() {
+        let mut progress = Progress::with_enabled("Converting", 4, false);
+        progress.inc(1);
+        assert_eq!(
+            progress.line(),
+            format!("Converting [{}{}] 1/4", "#".repeat(7), "-".repeat(23))
+        );
+
+        progress.inc(10);
+        assert!(progress.line().ends_with("4/4"));
+    }
+
+    #[test]
+    fn hidden_bar_is_disabled() {
+        assert!(!Progress::hidden().is_enabled());
+    }
+}
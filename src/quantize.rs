@@ -0,0 +1,235 @@
+//! Adaptive palettes: the most representative colors of an image, found by
+//! k-means clustering
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::error::AsciiArtError;
+
+/// Prefix of a `--palette` value that asks for an adaptive palette
+pub const ADAPTIVE_PREFIX: &str = "adaptive:";
+
+/// Largest number of colors an adaptive palette can have
+pub const MAX_ADAPTIVE_COLORS: usize = 256;
+
+/// Rounds of k-means refinement after seeding
+const ITERATIONS: usize = 12;
+
+/// An N-color palette extracted from each image and applied to its cells
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AdaptivePalette {
+    /// Number of colors to extract
+    pub colors: usize,
+    /// Seed for choosing the initial cluster centers; the same seed always
+    /// gives the same palette for the same image
+    pub seed: u64,
+}
+
+impl AdaptivePalette {
+    /// Parse an `adaptive:N` palette value
+    ///
+    /// Returns `Ok(None)` for anything else, which names a palette file.
+    pub fn parse(value: &Path, seed: u64) -> Result<Option<Self>, AsciiArtError> {
+        let Some(count) = value
+            .to_str()
+            .and_then(|value| value.strip_prefix(ADAPTIVE_PREFIX))
+        else {
+            return Ok(None);
+        };
+        match count.parse::<usize>() {
+            Ok(colors) if (1..=MAX_ADAPTIVE_COLORS).contains(&colors) => {
+                Ok(Some(Self { colors, seed }))
+            }
+            _ => Err(AsciiArtError::InvalidArgument(format!(
+                "Invalid adaptive palette '{}'. Use 'adaptive:N' with N from 1 to {}.",
+                value.display(),
+                MAX_ADAPTIVE_COLORS
+            ))),
+        }
+    }
+
+    /// Whether a palette value names an adaptive palette rather than a file
+    pub fn is_adaptive(value: &Path) -> bool {
+        value
+            .to_str()
+            .is_some_and(|value| value.starts_with(ADAPTIVE_PREFIX))
+    }
+
+    /// Cluster `pixels` into at most [`colors`](Self::colors) representative colors
+    pub fn extract(&self, pixels: &[[u8; 3]]) -> Vec<[u8; 3]> {
+        kmeans(pixels, self.colors, self.seed)
+    }
+}
+
+/// The `k` centers that k-means settles on for `pixels`, seeded with k-means++
+///
+/// Identical colors are clustered once with their count as weight, and when an
+/// image has no more than `k` distinct colors they are returned as they are.
+pub fn kmeans(pixels: &[[u8; 3]], k: usize, seed: u64) -> Vec<[u8; 3]> {
+    let mut counts: HashMap<[u8; 3], u32> = HashMap::new();
+    for &pixel in pixels {
+        *counts.entry(pixel).or_default() += 1;
+    }
+    let mut colors: Vec<([u8; 3], f32)> = counts
+        .into_iter()
+        .map(|(color, count)| (color, count as f32))
+        .collect();
+    // HashMap order varies between runs, so sort for reproducible seeding
+    colors.sort_unstable_by_key(|&(color, _)| color);
+    if colors.len() <= k {
+        return colors.into_iter().map(|(color, _)| color).collect();
+    }
+
+    let points: Vec<[f32; 3]> = colors
+        .iter()
+        .map(|(color, _)| color.map(f32::from))
+        .collect();
+    let weights: Vec<f32> = colors.iter().map(|&(_, weight)| weight).collect();
+    let mut centers = seed_centers(&points, &weights, k, seed);
+    let mut assignment = vec![usize::MAX; points.len()];
+
+    for _ in 0..ITERATIONS {
+        let mut changed = false;
+        for (point, assigned) in points.iter().zip(&mut assignment) {
+            let nearest = nearest_center(*point, &centers);
+            changed |= nearest != *assigned;
+            *assigned = nearest;
+        }
+        if !changed {
+            break;
+        }
+
+        let mut sums = vec![([0f32; 3], 0f32); centers.len()];
+        for ((point, &weight), &assigned) in points.iter().zip(&weights).zip(&assignment) {
+            let (sum, total) = &mut sums[assigned];
+            for c in 0..3 {
+                sum[c] += point[c] * weight;
+            }
+            *total += weight;
+        }
+        for (center, (sum, total)) in centers.iter_mut().zip(sums) {
+            // A center that lost all its points stays where it was
+            if total > 0.0 {
+                *center = sum.map(|value| value / total);
+            }
+        }
+    }
+
+    centers
+        .into_iter()
+        .map(|center| center.map(|value| value.round().clamp(0.0, 255.0) as u8))
+        .collect()
+}
+
+/// Pick `k` initial centers, each new one with probability proportional to
+/// its weighted squared distance from the centers chosen so far (k-means++)
+fn seed_centers(points: &[[f32; 3]], weights: &[f32], k: usize, seed: u64) -> Vec<[f32; 3]> {
+    let mut rng = SplitMix64(seed);
+    let first = weighted_choice(weights, rng.next_f32());
+    let mut centers = vec![points[first]];
+    let mut distances: Vec<f32> = points
+        .iter()
+        .map(|&point| distance(point, points[first]))
+        .collect();
+
+    while centers.len() < k {
+        let scores: Vec<f32> = distances.iter().zip(weights).map(|(d, w)| d * w).collect();
+        let next = points[weighted_choice(&scores, rng.next_f32())];
+        centers.push(next);
+        for (point, nearest) in points.iter().zip(&mut distances) {
+            *nearest = nearest.min(distance(*point, next));
+        }
+    }
+    centers
+}
+
+/// Index into `weights` at fraction `at` (0-1) of their running total
+fn weighted_choice(weights: &[f32], at: f32) -> usize {
+    let total: f32 = weights.iter().sum();
+    let mut target = at * total;
+    for (index, &weight) in weights.iter().enumerate() {
+        if target < weight {
+            return index;
+        }
+        target -= weight;
+    }
+    // Rounding can leave a sliver past the end; fall back to the last candidate
+    weights
+        .iter()
+        .rposition(|&weight| weight > 0.0)
+        .unwrap_or(0)
+}
+
+fn nearest_center(point: [f32; 3], centers: &[[f32; 3]]) -> usize {
+    centers
+        .iter()
+        .enumerate()
+        .min_by(|(_, a), (_, b)| distance(point, **a).total_cmp(&distance(point, **b)))
+        .map_or(0, |(index, _)| index)
+}
+
+fn distance(a: [f32; 3], b: [f32; 3]) -> f32 {
+    (0..3).map(|c| (a[c] - b[c]).powi(2)).sum()
+}
+
+/// Small, fast generator for reproducible seeding (splitmix64)
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A uniform value in 0..1
+    fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_adaptive_palette_values() {
+        let parse = |value: &str| AdaptivePalette::parse(Path::new(value), 7);
+        assert_eq!(
+            parse("adaptive:8").unwrap(),
+            Some(AdaptivePalette { colors: 8, seed: 7 })
+        );
+        assert_eq!(parse("c64.toml").unwrap(), None);
+        assert!(parse("adaptive:0").is_err());
+        assert!(parse("adaptive:300").is_err());
+        assert!(parse("adaptive:many").is_err());
+        assert!(AdaptivePalette::is_adaptive(Path::new("adaptive:4")));
+        assert!(!AdaptivePalette::is_adaptive(Path::new(
+            "palettes/c64.toml"
+        )));
+    }
+
+    #[test]
+    fn kmeans_finds_the_clusters() {
+        let mut pixels = vec![[250, 10, 10]; 40];
+        pixels.extend(vec![[240, 20, 0]; 40]);
+        pixels.extend(vec![[10, 10, 240]; 40]);
+        pixels.extend(vec![[0, 30, 250]; 40]);
+
+        let mut palette = kmeans(&pixels, 2, 0);
+        palette.sort_unstable();
+        assert_eq!(palette, vec![[5, 20, 245], [245, 15, 5]]);
+    }
+
+    #[test]
+    fn kmeans_is_reproducible_and_keeps_few_colors() {
+        let pixels: Vec<[u8; 3]> = (0..=255u8).map(|v| [v, v / 2, 255 - v]).collect();
+        assert_eq!(kmeans(&pixels, 5, 42), kmeans(&pixels, 5, 42));
+        assert_eq!(kmeans(&pixels, 5, 42).len(), 5);
+
+        let few = [[1, 2, 3], [4, 5, 6], [1, 2, 3]];
+        assert_eq!(kmeans(&few, 8, 0), vec![[1, 2, 3], [4, 5, 6]]);
+    }
+}
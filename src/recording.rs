@@ -0,0 +1,369 @@
+//! Terminal recordings of the converted animation, which replay the art
+//! with its timing in tools such as `asciinema play`, `ttyplay`, and
+//! `scriptreplay`
+//!
+//! Every format is written from the same [`Timeline`]: the bytes a terminal
+//! would have received while playing the frames, and when. Only how those
+//! moments are encoded differs.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde_json::json;
+
+use crate::animation::Frame;
+use crate::builder::AsciiArtBuilder;
+use crate::error::AsciiArtError;
+use crate::image_loader::ImageLoaderError;
+
+/// First line of a `script` typescript, which `scriptreplay` skips
+const SCRIPT_HEADER: &[u8] = b"Script started by ascii-art-cli\n";
+
+/// A terminal recording format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum RecordFormat {
+    /// An asciinema v2 cast, for `asciinema play` and the web player
+    Cast,
+    /// A ttyrec file, for `ttyplay`
+    Ttyrec,
+    /// A `script` typescript, with its timing in a `.timing` file beside it,
+    /// for `scriptreplay`
+    Script,
+}
+
+impl RecordFormat {
+    pub fn name(self) -> &'static str {
+        match self {
+            RecordFormat::Cast => "cast",
+            RecordFormat::Ttyrec => "ttyrec",
+            RecordFormat::Script => "script",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            RecordFormat::Cast => "cast",
+            RecordFormat::Ttyrec => "ttyrec",
+            RecordFormat::Script => "typescript",
+        }
+    }
+}
+
+impl FromStr for RecordFormat {
+    type Err = AsciiArtError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "cast" => Ok(RecordFormat::Cast),
+            "ttyrec" => Ok(RecordFormat::Ttyrec),
+            "script" => Ok(RecordFormat::Script),
+            unknown => Err(AsciiArtError::InvalidArgument(format!(
+                "Unknown recording format '{}'. Use 'cast', 'ttyrec', or 'script'.",
+                unknown
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for RecordFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Where a `script` recording's timing goes: the typescript's path with
+/// `.timing` appended
+pub fn timing_path(path: &Path) -> PathBuf {
+    let mut timing = path.as_os_str().to_owned();
+    timing.push(".timing");
+    PathBuf::from(timing)
+}
+
+/// Output sent to the terminal at one moment of a recording
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    /// Time since the recording started
+    pub at: Duration,
+    pub data: Vec<u8>,
+}
+
+/// The terminal output of playing converted frames, as timed [`Event`]s
+///
+/// The screen is cleared and the cursor hidden before the first frame, each
+/// frame is drawn from the top-left corner when the previous one's delay has
+/// passed, and the colors and cursor are restored once the last one has
+/// been shown, as `play` does. Lines end in `\r\n`, as a terminal receives
+/// them.
+#[derive(Debug, Clone, Default)]
+pub struct Timeline {
+    elapsed: Duration,
+    started: bool,
+}
+
+impl Timeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The event that draws `text`, shown for `delay` before the next one
+    pub fn frame(&mut self, text: &str, delay: Duration) -> Event {
+        let mut data = Vec::with_capacity(text.len() + text.len() / 16 + 16);
+        if !self.started {
+            data.extend_from_slice(b"\x1b[2J\x1b[?25l");
+            self.started = true;
+        }
+        data.extend_from_slice(b"\x1b[H");
+        for (index, line) in text.split('\n').enumerate() {
+            if index > 0 {
+                data.extend_from_slice(b"\r\n");
+            }
+            data.extend_from_slice(line.as_bytes());
+        }
+        let event = Event {
+            at: self.elapsed,
+            data,
+        };
+        self.elapsed += delay;
+        event
+    }
+
+    /// The event that restores the terminal after the last frame
+    pub fn finish(&self) -> Event {
+        Event {
+            at: self.elapsed,
+            data: b"\x1b[0m\x1b[?25h\r\n".to_vec(),
+        }
+    }
+}
+
+/// Convert every frame with `builder` and record playing them as `format`,
+/// written to `path` or to stdout when it is `None`, and return how many
+/// frames were recorded
+///
+/// `script` recordings need a `path`, since their timing is written to a
+/// second file, [`timing_path`].
+pub fn record(
+    frames: impl IntoIterator<Item = Result<Frame, ImageLoaderError>>,
+    builder: &AsciiArtBuilder,
+    format: RecordFormat,
+    path: Option<&Path>,
+) -> Result<usize, AsciiArtError> {
+    if format == RecordFormat::Script && path.is_none() {
+        return Err(AsciiArtError::InvalidArgument(
+            "script recordings are written to a file, with their timing beside it".to_string(),
+        ));
+    }
+    let create = |path: &Path| -> io::Result<Box<dyn Write>> {
+        Ok(Box::new(BufWriter::new(File::create(path)?)))
+    };
+    let writer = match path {
+        Some(path) => create(path)?,
+        None => Box::new(BufWriter::new(io::stdout().lock())),
+    };
+    let mut encoder = match format {
+        RecordFormat::Cast => Encoder::Cast { writer },
+        RecordFormat::Ttyrec => Encoder::Ttyrec { writer },
+        RecordFormat::Script => Encoder::Script {
+            typescript: writer,
+            timing: create(&timing_path(path.expect("checked above")))?,
+            last: Duration::ZERO,
+        },
+    };
+
+    let mut timeline = Timeline::new();
+    let mut written = 0;
+    for frame in frames {
+        let frame = frame?;
+        let art = builder.generate(&frame.image)?;
+        let event = timeline.frame(&art.to_ansi_string(), frame.delay);
+        if written == 0 {
+            encoder.start((art.image().width(), art.image().height()))?;
+        }
+        encoder.event(&event)?;
+        written += 1;
+    }
+    if written > 0 {
+        encoder.event(&timeline.finish())?;
+    }
+    encoder.flush()?;
+    Ok(written)
+}
+
+/// How each recording format stores the events of a [`Timeline`]
+enum Encoder {
+    /// A JSON header line, then one `[seconds, "o", text]` line per event
+    Cast { writer: Box<dyn Write> },
+    /// Per event, seconds and microseconds since the start and the length of
+    /// the data, each a little-endian `u32`, then the data
+    Ttyrec { writer: Box<dyn Write> },
+    /// The data alone, while each line of the timing file holds the seconds
+    /// since the previous event and how many bytes it wrote
+    Script {
+        typescript: Box<dyn Write>,
+        timing: Box<dyn Write>,
+        last: Duration,
+    },
+}
+
+impl Encoder {
+    fn start(&mut self, (width, height): (usize, usize)) -> io::Result<()> {
+        match self {
+            Encoder::Cast { writer } => {
+                let header = json!({
+                    "version": 2,
+                    "width": width,
+                    "height": height,
+                    "env": {"TERM": "xterm-256color"},
+                });
+                writeln!(writer, "{header}")
+            }
+            Encoder::Ttyrec { .. } => Ok(()),
+            Encoder::Script { typescript, .. } => typescript.write_all(SCRIPT_HEADER),
+        }
+    }
+
+    fn event(&mut self, event: &Event) -> io::Result<()> {
+        match self {
+            Encoder::Cast { writer } => {
+                let text = String::from_utf8_lossy(&event.data);
+                let line = json!([event.at.as_secs_f64(), "o", text]);
+                writeln!(writer, "{line}")
+            }
+            Encoder::Ttyrec { writer } => {
+                let header = [
+                    event.at.as_secs() as u32,
+                    event.at.subsec_micros(),
+                    event.data.len() as u32,
+                ];
+                for field in header {
+                    writer.write_all(&field.to_le_bytes())?;
+                }
+                writer.write_all(&event.data)
+            }
+            Encoder::Script {
+                typescript,
+                timing,
+                last,
+            } => {
+                let since = event.at.saturating_sub(*last);
+                *last = event.at;
+                writeln!(timing, "{:.6} {}", since.as_secs_f64(), event.data.len())?;
+                typescript.write_all(&event.data)
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Encoder::Cast { writer } | Encoder::Ttyrec { writer } => writer.flush(),
+            Encoder::Script {
+                typescript, timing, ..
+            } => {
+                typescript.flush()?;
+                timing.flush()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{DynamicImage, RgbImage};
+
+    fn frames() -> impl Iterator<Item = Result<Frame, ImageLoaderError>> {
+        [40u64, 60].into_iter().enumerate().map(|(index, delay)| {
+            let shade = index as u8 * 200;
+            Ok(Frame {
+                image: DynamicImage::ImageRgb8(RgbImage::from_pixel(8, 8, [shade; 3].into())),
+                delay: Duration::from_millis(delay),
+                source: None,
+            })
+        })
+    }
+
+    fn builder() -> AsciiArtBuilder {
+        AsciiArtBuilder::new().width(3)
+    }
+
+    #[test]
+    fn timeline_times_each_frame_after_the_previous_delay() {
+        let mut timeline = Timeline::new();
+        let first = timeline.frame("ab\ncd", Duration::from_millis(40));
+        let second = timeline.frame("ef\ngh", Duration::from_millis(60));
+        assert_eq!(first.at, Duration::ZERO);
+        assert_eq!(first.data, b"\x1b[2J\x1b[?25l\x1b[Hab\r\ncd");
+        assert_eq!(second.at, Duration::from_millis(40));
+        assert_eq!(second.data, b"\x1b[Hef\r\ngh");
+        assert_eq!(timeline.finish().at, Duration::from_millis(100));
+    }
+
+    #[test]
+    fn casts_have_a_header_and_one_line_per_event() {
+        let file = tempfile::NamedTempFile::with_suffix(".cast").expect("temp file");
+        let written =
+            record(frames(), &builder(), RecordFormat::Cast, Some(file.path())).expect("recorded");
+        assert_eq!(written, 2);
+
+        let cast = std::fs::read_to_string(file.path()).expect("read cast");
+        let lines: Vec<serde_json::Value> = cast
+            .lines()
+            .map(|line| serde_json::from_str(line).expect("json line"))
+            .collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0]["version"], 2);
+        assert_eq!(lines[0]["width"], 3);
+        assert_eq!(lines[2][0], 0.04);
+        assert_eq!(lines[3][0], 0.1);
+        assert_eq!(lines[1][1], "o");
+    }
+
+    #[test]
+    fn ttyrec_headers_hold_time_and_length() {
+        let file = tempfile::NamedTempFile::with_suffix(".ttyrec").expect("temp file");
+        record(
+            frames(),
+            &builder(),
+            RecordFormat::Ttyrec,
+            Some(file.path()),
+        )
+        .expect("recorded");
+
+        let bytes = std::fs::read(file.path()).expect("read ttyrec");
+        let mut rest = &bytes[..];
+        let mut times = Vec::new();
+        while !rest.is_empty() {
+            let field = |at: usize| u32::from_le_bytes(rest[at..at + 4].try_into().unwrap());
+            times.push((field(0), field(4)));
+            let len = field(8) as usize;
+            rest = &rest[12 + len..];
+        }
+        assert_eq!(times, [(0, 0), (0, 40_000), (0, 100_000)]);
+    }
+
+    #[test]
+    fn script_timing_counts_the_bytes_of_the_typescript() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("art.typescript");
+        record(frames(), &builder(), RecordFormat::Script, Some(&path)).expect("recorded");
+        assert!(record(frames(), &builder(), RecordFormat::Script, None).is_err());
+
+        let typescript = std::fs::read(&path).expect("read typescript");
+        let timing = std::fs::read_to_string(timing_path(&path)).expect("read timing");
+        let mut delays = Vec::new();
+        let mut total = 0;
+        for line in timing.lines() {
+            let (delay, len) = line.split_once(' ').expect("delay and length");
+            delays.push(delay.to_string());
+            total += len.parse::<usize>().expect("length");
+        }
+        assert_eq!(delays, ["0.000000", "0.040000", "0.060000"]);
+        assert_eq!(SCRIPT_HEADER.len() + total, typescript.len());
+        assert!(typescript.starts_with(SCRIPT_HEADER));
+    }
+}
@@ -1,5 +1,17 @@
-use image::{DynamicImage, GenericImageView};
-use crate::ascii_converter::AsciiGrid;
+use std::collections::HashMap;
+use std::fmt::{self, Write as _};
+use std::io::{self, BufWriter, Write};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+
+use image::{Rgb, RgbImage};
+
+use crate::ascii_converter::{AsciiImage, Cell};
+use crate::color::{luma, oklab_distance, srgb_to_oklab, ColorMetric};
+use crate::error::AsciiArtError;
+use crate::font::{self, GLYPH_SIZE};
+use crate::palette::{nearest_color, Palette};
+use crate::parallel::map_rows;
 
 // 16 basic ANSI colors (foreground codes)
 const ANSI_COLORS: [(u8, u8, u8, &str); 16] = [
@@ -21,10 +33,104 @@ const ANSI_COLORS: [(u8, u8, u8, &str); 16] = [
     (255, 255, 255, "\x1b[97m"),   // Bright White
 ];
 
-const RESET: &str = "\x1b[0m";
+/// Escape sequence restoring the terminal's default attributes
+pub const RESET: &str = "\x1b[0m";
+
+/// Escape sequence saving the window title on the terminal's title stack
+pub const SAVE_TITLE: &str = "\x1b[22;0t";
+
+/// Escape sequence restoring the title last saved with [`SAVE_TITLE`]
+pub const RESTORE_TITLE: &str = "\x1b[23;0t";
+
+/// Escape sequence setting the window and icon title (OSC 0) to `title`,
+/// without control characters, which could end the sequence early
+pub fn title_sequence(title: &str) -> String {
+    let title: String = title.chars().filter(|ch| !ch.is_control()).collect();
+    format!("\x1b]0;{title}\x07")
+}
+
+/// Upper bound on the length of one foreground escape (`\x1b[38;2;255;255;255m`)
+const MAX_ESCAPE_LEN: usize = 19;
+
+/// Intensity levels of the 6x6x6 color cube in the 256-color palette
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// Number of colors the terminal output is quantized to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum ColorDepth {
+    /// Foreground codes 30-37 only, never the bright 90-97
+    #[cfg_attr(feature = "cli", value(name = "8"))]
+    Ansi8,
+    /// The 16 basic ANSI colors
+    #[default]
+    #[cfg_attr(feature = "cli", value(name = "16"))]
+    Ansi16,
+    /// The xterm 256-color palette (color cube plus grayscale ramp)
+    #[cfg_attr(feature = "cli", value(name = "256"))]
+    Ansi256,
+    /// 24-bit RGB escapes
+    #[cfg_attr(feature = "cli", value(name = "truecolor", alias = "24bit"))]
+    TrueColor,
+    /// No escapes at all, only the character grid
+    #[cfg_attr(feature = "cli", value(name = "mono", alias = "none"))]
+    Monochrome,
+}
+
+impl FromStr for ColorDepth {
+    type Err = AsciiArtError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "8" => Ok(ColorDepth::Ansi8),
+            "16" => Ok(ColorDepth::Ansi16),
+            "256" => Ok(ColorDepth::Ansi256),
+            "truecolor" | "24bit" => Ok(ColorDepth::TrueColor),
+            "mono" | "none" => Ok(ColorDepth::Monochrome),
+            unknown => Err(AsciiArtError::InvalidArgument(format!(
+                "Unknown color depth '{}'. Use '8', '16', '256', 'truecolor', or 'mono'.",
+                unknown
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for ColorDepth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ColorDepth::Ansi8 => write!(f, "8"),
+            ColorDepth::Ansi16 => write!(f, "16"),
+            ColorDepth::Ansi256 => write!(f, "256"),
+            ColorDepth::TrueColor => write!(f, "truecolor"),
+            ColorDepth::Monochrome => write!(f, "mono"),
+        }
+    }
+}
+
+/// Options controlling how cell colors are turned into escape sequences
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct RenderOptions {
+    pub color_depth: ColorDepth,
+    pub color_metric: ColorMetric,
+    /// Colors the 8-, 16-, and 256-color output is matched against
+    pub palette: Palette,
+    /// Replace each cell color with the gray of equal luma before it is matched
+    /// to the palette
+    pub grayscale: bool,
+    /// Diffuse the quantization error of 8-, 16-, and 256-color output to
+    /// neighboring cells (Floyd-Steinberg) instead of banding smooth areas
+    pub color_dither: bool,
+    /// Draw 8- and 16-color output with shade glyphs (░▒▓█) over a background
+    /// color, mixing two palette colors per cell for in-between shades
+    pub shade: bool,
+    /// Emit as few bytes as possible: trailing blanks are dropped and colors
+    /// carry over from one row to the next instead of being reset at the end
+    /// of each (shaded rows keep their resets, which end the background)
+    pub compact: bool,
+}
 
 /// Map RGB values to the closest ANSI color code using Euclidean distance
-pub fn rgb_to_ansi(r: u8, g: u8, b: u8) -> String {
+pub fn rgb_to_ansi(r: u8, g: u8, b: u8) -> &'static str {
     let mut min_distance = f32::MAX;
     let mut closest_code = ANSI_COLORS[0].3;
     
@@ -40,41 +146,869 @@ pub fn rgb_to_ansi(r: u8, g: u8, b: u8) -> String {
             closest_code = code;
         }
     }
-    
-    closest_code.to_string()
-}
-
-/// Render ASCII grid to terminal with colors from original image
-pub fn render_colored(
-    grid: &AsciiGrid,
-    original: &DynamicImage,
-) -> Result<(), String> {
-    for (y, row) in grid.iter().enumerate() {
-        for (x, &ch) in row.iter().enumerate() {
-            // Sample original pixel color
-            let pixel = original.get_pixel(x as u32, y as u32);
-            let (r, g, b) = (pixel[0], pixel[1], pixel[2]);
-            
-            // Get ANSI color code
-            let color_code = rgb_to_ansi(r, g, b);
-            
-            // Print colored character
-            print!("{}{}", color_code, ch);
-        }
-        // Reset color at end of line
-        println!("{}", RESET);
+
+    closest_code
+}
+
+/// Map RGB values to the closest of the 16 ANSI color codes under `metric`
+pub fn rgb_to_ansi_with_metric(r: u8, g: u8, b: u8, metric: ColorMetric) -> &'static str {
+    ANSI_COLORS[ansi16_index(r, g, b, metric)].3
+}
+
+/// Index (0-15) of the closest basic ANSI color under `metric`
+pub fn ansi16_index(r: u8, g: u8, b: u8, metric: ColorMetric) -> usize {
+    match metric {
+        ColorMetric::Rgb => {
+            let code = rgb_to_ansi(r, g, b);
+            ANSI_COLORS.iter().position(|c| c.3 == code).unwrap_or(0)
+        }
+        ColorMetric::Oklab => nearest_oklab(srgb_to_oklab([r, g, b]), ansi16_oklab()),
     }
-    
+}
+
+/// Nominal RGB value of one of the 16 basic ANSI colors
+pub fn ansi16_rgb(index: usize) -> [u8; 3] {
+    let (r, g, b, _) = ANSI_COLORS[index.min(15)];
+    [r, g, b]
+}
+
+/// Map RGB values to the closest index of the xterm 256-color palette
+///
+/// Both the 6x6x6 color cube and the 24-step grayscale ramp are considered,
+/// and whichever candidate is nearer in RGB space wins.
+pub fn rgb_to_ansi256_index(r: u8, g: u8, b: u8) -> u8 {
+    let nearest_level = |value: u8| {
+        CUBE_LEVELS
+            .iter()
+            .enumerate()
+            .min_by_key(|&(_, &level)| (level as i32 - value as i32).abs())
+            .map(|(index, _)| index)
+            .unwrap_or(0)
+    };
+    let (ri, gi, bi) = (nearest_level(r), nearest_level(g), nearest_level(b));
+    let cube = (CUBE_LEVELS[ri], CUBE_LEVELS[gi], CUBE_LEVELS[bi]);
+    let cube_index = 16 + 36 * ri + 6 * gi + bi;
+
+    let average = (r as u32 + g as u32 + b as u32) / 3;
+    let gray_step = (average.saturating_sub(3) / 10).min(23);
+    let gray_value = (8 + gray_step * 10) as u8;
+    let gray_index = 232 + gray_step as usize;
+
+    let distance = |(cr, cg, cb): (u8, u8, u8)| {
+        (r as i32 - cr as i32).pow(2)
+            + (g as i32 - cg as i32).pow(2)
+            + (b as i32 - cb as i32).pow(2)
+    };
+
+    if distance((gray_value, gray_value, gray_value)) < distance(cube) {
+        gray_index as u8
+    } else {
+        cube_index as u8
+    }
+}
+
+/// Map RGB values to the closest xterm 256-color index under `metric`
+///
+/// The perceptual metric searches the full color cube and grayscale ramp;
+/// the 16 theme-dependent system colors are never selected.
+pub fn rgb_to_ansi256_index_with_metric(r: u8, g: u8, b: u8, metric: ColorMetric) -> u8 {
+    match metric {
+        ColorMetric::Rgb => rgb_to_ansi256_index(r, g, b),
+        ColorMetric::Oklab => {
+            let target = srgb_to_oklab([r, g, b]);
+            (16 + nearest_oklab(target, ansi256_oklab())) as u8
+        }
+    }
+}
+
+/// RGB value of an xterm 256-color palette entry in the range 16..=255
+pub fn ansi256_rgb(index: u8) -> [u8; 3] {
+    if index >= 232 {
+        let value = 8 + (index - 232) * 10;
+        [value, value, value]
+    } else {
+        let offset = index.saturating_sub(16) as usize;
+        [
+            CUBE_LEVELS[offset / 36],
+            CUBE_LEVELS[(offset / 6) % 6],
+            CUBE_LEVELS[offset % 6],
+        ]
+    }
+}
+
+fn ansi16_oklab() -> &'static [[f32; 3]] {
+    static TABLE: OnceLock<Vec<[f32; 3]>> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        ANSI_COLORS
+            .iter()
+            .map(|&(r, g, b, _)| srgb_to_oklab([r, g, b]))
+            .collect()
+    })
+}
+
+fn ansi256_oklab() -> &'static [[f32; 3]] {
+    static TABLE: OnceLock<Vec<[f32; 3]>> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        (16..=255u8)
+            .map(|i| srgb_to_oklab(ansi256_rgb(i)))
+            .collect()
+    })
+}
+
+fn nearest_oklab(target: [f32; 3], candidates: &[[f32; 3]]) -> usize {
+    candidates
+        .iter()
+        .enumerate()
+        .min_by(|(_, a), (_, b)| {
+            oklab_distance(target, **a).total_cmp(&oklab_distance(target, **b))
+        })
+        .map(|(index, _)| index)
+        .unwrap_or(0)
+}
+
+/// Bits kept per channel when indexing the color lookup table
+const LUT_BITS: u32 = 5;
+const LUT_SIDE: usize = 1 << LUT_BITS;
+
+/// Distinct option sets whose tables [`ColorLut::shared`] keeps at once
+const MAX_SHARED_TABLES: usize = 8;
+
+/// Precomputed mapping from RGB to palette index for one depth and metric
+///
+/// Colors are bucketed to 5 bits per channel (32x32x32 entries), turning the
+/// per-cell nearest-color search into a single table lookup. Truecolor output
+/// needs no quantization, so its table is empty and colors pass through.
+#[derive(Debug, Clone)]
+pub struct ColorLut {
+    depth: ColorDepth,
+    /// With grayscale output the table is indexed by luma alone
+    grayscale: bool,
+    indices: Vec<u8>,
+}
+
+impl ColorLut {
+    /// Build a lookup table for the given options
+    pub fn new(options: &RenderOptions) -> Self {
+        let depth = options.color_depth;
+        let metric = options.color_metric;
+        let ansi16 = options.palette.ansi16();
+        let ansi8 = options.palette.ansi8();
+        let ansi256 = options.palette.ansi256();
+        let lab = options.palette.oklab();
+        let expand = |v: usize| ((v << 3) | (v >> 2)) as u8;
+
+        let indices = match depth {
+            ColorDepth::TrueColor | ColorDepth::Monochrome => Vec::new(),
+            _ if options.grayscale => gray_indices(options),
+            _ => map_rows(LUT_SIDE, |ri| {
+                let mut slice = Vec::with_capacity(LUT_SIDE * LUT_SIDE);
+                for gi in 0..LUT_SIDE {
+                    for bi in 0..LUT_SIDE {
+                        let (r, g, b) = (expand(ri), expand(gi), expand(bi));
+                        slice.push(match (depth, ansi256) {
+                            (ColorDepth::Ansi8, _) => {
+                                nearest_color([r, g, b], ansi8, &lab[..8], metric) as u8
+                            }
+                            (ColorDepth::Ansi16, _) => {
+                                nearest_color([r, g, b], ansi16, &lab[..16], metric) as u8
+                            }
+                            (_, Some(table)) => nearest_color([r, g, b], table, lab, metric) as u8,
+                            _ => rgb_to_ansi256_index_with_metric(r, g, b, metric),
+                        });
+                    }
+                }
+                slice
+            })
+            .concat(),
+        };
+
+        Self {
+            depth,
+            grayscale: options.grayscale,
+            indices,
+        }
+    }
+
+    /// Shared, lazily built table for the given options
+    ///
+    /// Tables for the last few distinct sets of options are kept, so repeated
+    /// renders reuse them while a long session cycling through settings
+    /// doesn't hold on to every table it ever built.
+    pub fn shared(options: &RenderOptions) -> Arc<ColorLut> {
+        static TABLES: OnceLock<Mutex<HashMap<RenderOptions, Arc<ColorLut>>>> = OnceLock::new();
+        let mut tables = TABLES
+            .get_or_init(Default::default)
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if let Some(table) = tables.get(options) {
+            return Arc::clone(table);
+        }
+        if tables.len() >= MAX_SHARED_TABLES {
+            tables.clear();
+        }
+        let table = Arc::new(ColorLut::new(options));
+        tables.insert(options.clone(), Arc::clone(&table));
+        table
+    }
+
+    /// Palette index for a color: 0-7 for 8-color, 0-15 for 16-color, 16-255 for
+    /// 256-color output
+    ///
+    /// Returns `None` for truecolor output, which is not quantized.
+    pub fn index(&self, r: u8, g: u8, b: u8) -> Option<u8> {
+        if self.indices.is_empty() {
+            return None;
+        }
+        if self.grayscale {
+            return Some(self.indices[gray_level([r, g, b]) as usize]);
+        }
+        let shift = 8 - LUT_BITS;
+        let key = ((r as usize >> shift) * LUT_SIDE + (g as usize >> shift)) * LUT_SIDE
+            + (b as usize >> shift);
+        Some(self.indices[key])
+    }
+
+    /// Append the escape sequence for a palette index returned by [`index`](Self::index)
+    pub fn push_index_code(&self, out: &mut String, index: u8) {
+        match self.depth {
+            ColorDepth::Ansi8 | ColorDepth::Ansi16 => out.push_str(ANSI_COLORS[index as usize].3),
+            ColorDepth::Ansi256 => out.push_str(&ansi256_codes()[index as usize]),
+            ColorDepth::TrueColor | ColorDepth::Monochrome => {}
+        }
+    }
+
+    /// Append the escape sequence for a color using the table
+    pub fn push_code(&self, out: &mut String, r: u8, g: u8, b: u8) {
+        let [r, g, b] = if self.grayscale {
+            gray([r, g, b])
+        } else {
+            [r, g, b]
+        };
+        match (self.depth, self.index(r, g, b)) {
+            (ColorDepth::Ansi8 | ColorDepth::Ansi16, Some(index)) => {
+                out.push_str(ANSI_COLORS[index as usize].3)
+            }
+            (ColorDepth::Ansi256, Some(index)) => out.push_str(&ansi256_codes()[index as usize]),
+            (ColorDepth::Monochrome, _) => {}
+            _ => {
+                let _ = write!(out, "\x1b[38;2;{};{};{}m", r, g, b);
+            }
+        }
+    }
+}
+
+/// Shades of gray among the 8 standard colors: black and white
+const GRAY_ANSI8: [u8; 2] = [0, 7];
+/// Shades of gray among the 16 basic colors: black, both grays, and bright white
+const GRAY_ANSI16: [u8; 4] = [0, 8, 7, 15];
+
+/// Luma of a color, rounded to the 0-255 range used for gray shades
+pub fn gray_level(rgb: [u8; 3]) -> u8 {
+    luma(rgb).round().clamp(0.0, 255.0) as u8
+}
+
+/// The gray with the same luma as `rgb`
+pub fn gray(rgb: [u8; 3]) -> [u8; 3] {
+    [gray_level(rgb); 3]
+}
+
+/// For every luma level, the palette index of the nearest gray shade
+///
+/// 256-color output picks from black, the 24-step gray ramp, and white.
+fn gray_indices(options: &RenderOptions) -> Vec<u8> {
+    let candidates: Vec<(u8, u8)> = match options.color_depth {
+        depth @ (ColorDepth::Ansi8 | ColorDepth::Ansi16) => {
+            let colors = options.palette.ansi16();
+            let shades: &[u8] = match depth {
+                ColorDepth::Ansi8 => &GRAY_ANSI8,
+                _ => &GRAY_ANSI16,
+            };
+            shades
+                .iter()
+                .map(|&index| (index, gray_level(colors[index as usize])))
+                .collect()
+        }
+        _ => {
+            let rgb = |index: u8| match options.palette.ansi256() {
+                Some(table) => table[index as usize],
+                None => ansi256_rgb(index),
+            };
+            std::iter::once(16)
+                .chain(232..=255)
+                .chain(std::iter::once(231))
+                .map(|index| (index, gray_level(rgb(index))))
+                .collect()
+        }
+    };
+    (0..=255u8)
+        .map(|level| {
+            candidates
+                .iter()
+                .min_by_key(|&&(_, shade)| shade.abs_diff(level))
+                .map_or(0, |&(index, _)| index)
+        })
+        .collect()
+}
+
+fn ansi256_codes() -> &'static [String] {
+    static CODES: OnceLock<Vec<String>> = OnceLock::new();
+    CODES.get_or_init(|| (0..=255u8).map(|i| format!("\x1b[38;5;{}m", i)).collect())
+}
+
+/// Build the foreground escape sequence for a color with the given options
+pub fn color_code(r: u8, g: u8, b: u8, options: &RenderOptions) -> String {
+    let mut code = String::with_capacity(MAX_ESCAPE_LEN);
+    push_color_code(&mut code, r, g, b, options);
+    code
+}
+
+/// Append the foreground escape sequence for a color without allocating
+pub fn push_color_code(out: &mut String, r: u8, g: u8, b: u8, options: &RenderOptions) {
+    if options.grayscale {
+        ColorLut::shared(options).push_code(out, r, g, b);
+        return;
+    }
+    let metric = options.color_metric;
+    // Writing into a String cannot fail
+    let _ = match options.color_depth {
+        ColorDepth::Ansi8 => {
+            out.push_str(ANSI_COLORS[options.palette.nearest_standard([r, g, b], metric)].3);
+            Ok(())
+        }
+        ColorDepth::Ansi16 => {
+            out.push_str(ANSI_COLORS[options.palette.nearest([r, g, b], metric)].3);
+            Ok(())
+        }
+        ColorDepth::Ansi256 => {
+            let index = match options.palette.ansi256() {
+                Some(table) => {
+                    nearest_color([r, g, b], table, options.palette.oklab(), metric) as u8
+                }
+                None => rgb_to_ansi256_index_with_metric(r, g, b, metric),
+            };
+            write!(out, "\x1b[38;5;{}m", index)
+        }
+        ColorDepth::TrueColor => write!(out, "\x1b[38;2;{};{};{}m", r, g, b),
+        ColorDepth::Monochrome => Ok(()),
+    };
+}
+
+/// Assemble the colored output for an ASCII image into a single string
+///
+/// Escapes are only emitted when the color actually changes, and spaces
+/// (including transparent cells) are written without any escape since their
+/// foreground color is invisible.
+/// Every row starts from a reset state, so rows are assembled independently.
+pub fn render_to_string(image: &AsciiImage, options: &RenderOptions) -> String {
+    let mut out = render_rows(image, options);
+
     // Final reset for terminal state safety
-    print!("{}", RESET);
-    
+    out.push_str(final_reset(options));
+    out
+}
+
+/// The sequence written after the last row: [`RESET`], or nothing for monochrome
+pub fn final_reset(options: &RenderOptions) -> &'static str {
+    match options.color_depth {
+        ColorDepth::Monochrome => "",
+        _ => RESET,
+    }
+}
+
+/// Assemble the colored rows of an image without the trailing reset
+///
+/// Used for streaming output, where several strips are written back to back
+/// and a single [`RESET`] follows the last one. With color dithering the
+/// error is diffused within the image only, so each strip starts afresh.
+pub fn render_rows(image: &AsciiImage, options: &RenderOptions) -> String {
+    if options.shade && matches!(options.color_depth, ColorDepth::Ansi8 | ColorDepth::Ansi16) {
+        return render_shaded_rows(image, options);
+    }
+    let lut = ColorLut::shared(options);
+    let lut = &*lut;
+    let indices = options
+        .color_dither
+        .then(|| dither_indices(image, options, lut))
+        .flatten();
+    if options.compact {
+        return render_compact_rows(image, indices.as_deref(), lut);
+    }
+    let rows: Vec<&[Cell]> = image.rows().collect();
+    let width = image.width();
+    let lines = map_rows(rows.len(), |y| {
+        let row_indices = indices
+            .as_ref()
+            .map(|indices| &indices[y * width..(y + 1) * width]);
+        render_row(rows[y], row_indices, lut)
+    });
+
+    let total: usize = lines.iter().map(String::len).sum();
+    let mut out = String::with_capacity(total + RESET.len());
+    for line in &lines {
+        out.push_str(line);
+    }
+    out
+}
+
+/// Palette indices for every cell, with the quantization error of each cell
+/// spread over its unvisited neighbors (Floyd-Steinberg)
+///
+/// Blank and transparent cells show no color, so they neither take nor pass on
+/// any error. Returns `None` for output that is not quantized.
+fn dither_indices(image: &AsciiImage, options: &RenderOptions, lut: &ColorLut) -> Option<Vec<u8>> {
+    lut.index(0, 0, 0)?;
+    let width = image.width();
+    let rgb = |index: u8| match (options.color_depth, options.palette.ansi256()) {
+        (ColorDepth::Ansi8 | ColorDepth::Ansi16, _) => options.palette.ansi16()[index as usize],
+        (_, Some(table)) => table[index as usize],
+        _ => ansi256_rgb(index),
+    };
+
+    let mut indices = vec![0u8; image.cells().len()];
+    // Error carried into the current and the next row, with a cell of padding on each side
+    let mut current = vec![[0f32; 3]; width + 2];
+    let mut next = vec![[0f32; 3]; width + 2];
+    for (y, row) in image.rows().enumerate() {
+        for (x, cell) in row.iter().enumerate() {
+            if cell.transparent || cell.ch == ' ' {
+                continue;
+            }
+            let source = if options.grayscale {
+                gray(cell.rgb)
+            } else {
+                cell.rgb
+            };
+            let target: [f32; 3] = std::array::from_fn(|c| {
+                (f32::from(source[c]) + current[x + 1][c]).clamp(0.0, 255.0)
+            });
+            let [r, g, b] = target.map(|value| value.round() as u8);
+            let index = lut.index(r, g, b)?;
+            indices[y * width + x] = index;
+
+            let chosen = rgb(index);
+            for c in 0..3 {
+                let error = target[c] - f32::from(chosen[c]);
+                current[x + 2][c] += error * 7.0 / 16.0;
+                next[x][c] += error * 3.0 / 16.0;
+                next[x + 1][c] += error * 5.0 / 16.0;
+                next[x + 2][c] += error / 16.0;
+            }
+        }
+        std::mem::swap(&mut current, &mut next);
+        next.fill([0.0; 3]);
+    }
+    Some(indices)
+}
+
+/// Shade glyphs and the fraction of the cell their foreground covers
+const SHADE_GLYPHS: [(char, f32); 3] = [('░', 0.25), ('▒', 0.5), ('▓', 0.75)];
+
+/// A cell drawn as `glyph` in palette color `fg` over palette color `bg`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Shade {
+    fg: u8,
+    bg: u8,
+    glyph: char,
+}
+
+/// SGR parameter of a basic ANSI color: 30-37 and 90-97 for the foreground,
+/// plus 10 for the background
+fn ansi16_sgr(index: u8, background: bool) -> u8 {
+    let base = if index < 8 {
+        30 + index
+    } else {
+        90 + index - 8
+    };
+    if background {
+        base + 10
+    } else {
+        base
+    }
+}
+
+/// Every (foreground, background, glyph) combination the palette can show,
+/// with the color each one appears as from a distance
+fn shade_candidates(options: &RenderOptions) -> (Vec<Shade>, Vec<[u8; 3]>) {
+    let colors = match options.color_depth {
+        ColorDepth::Ansi8 => options.palette.ansi8(),
+        _ => options.palette.ansi16(),
+    };
+    let mut shades = Vec::new();
+    let mut mixed = Vec::new();
+    for (fg, &fg_rgb) in colors.iter().enumerate() {
+        // A full block looks the same over any background
+        shades.push(Shade {
+            fg: fg as u8,
+            bg: fg as u8,
+            glyph: '█',
+        });
+        mixed.push(fg_rgb);
+        for (bg, &bg_rgb) in colors.iter().enumerate().filter(|&(bg, _)| bg != fg) {
+            for (glyph, coverage) in SHADE_GLYPHS {
+                shades.push(Shade {
+                    fg: fg as u8,
+                    bg: bg as u8,
+                    glyph,
+                });
+                mixed.push(std::array::from_fn(|c| {
+                    let value =
+                        f32::from(fg_rgb[c]) * coverage + f32::from(bg_rgb[c]) * (1.0 - coverage);
+                    value.round() as u8
+                }));
+            }
+        }
+    }
+    (shades, mixed)
+}
+
+/// Assemble rows where every visible cell is the shade nearest its color
+///
+/// The characters of the image are replaced: the glyph only sets how much of
+/// the foreground color shows over the background. Transparent cells stay
+/// blank with the default background.
+fn render_shaded_rows(image: &AsciiImage, options: &RenderOptions) -> String {
+    let (shades, mixed) = shade_candidates(options);
+    let lab: Vec<[f32; 3]> = mixed.iter().copied().map(srgb_to_oklab).collect();
+    let metric = options.color_metric;
+    let rows: Vec<&[Cell]> = image.rows().collect();
+
+    let lines = map_rows(rows.len(), |y| {
+        let row = rows[y];
+        let mut out = String::with_capacity(row.len() * 16 + RESET.len() + 1);
+        let mut current: Option<(u8, u8)> = None;
+        for cell in row {
+            if cell.transparent {
+                if current.take().is_some() {
+                    out.push_str(RESET);
+                }
+                out.push(' ');
+                continue;
+            }
+            let rgb = if options.grayscale {
+                gray(cell.rgb)
+            } else {
+                cell.rgb
+            };
+            let shade = shades[nearest_color(rgb, &mixed, &lab, metric)];
+            if current != Some((shade.fg, shade.bg)) {
+                let fg = ansi16_sgr(shade.fg, false);
+                let bg = ansi16_sgr(shade.bg, true);
+                let _ = write!(out, "\x1b[{fg};{bg}m");
+                current = Some((shade.fg, shade.bg));
+            }
+            out.push(shade.glyph);
+        }
+        if current.is_some() {
+            out.push_str(RESET);
+        }
+        out.push('\n');
+        out
+    });
+    lines.concat()
+}
+
+/// Render one row of cells, terminated by a newline
+///
+/// `indices` holds precomputed palette indices for the row, from dithering.
+fn render_row(row: &[Cell], indices: Option<&[u8]>, lut: &ColorLut) -> String {
+    let mut out = String::with_capacity(row.len() * (MAX_ESCAPE_LEN + 4) + RESET.len() + 1);
+    let mut current = String::with_capacity(MAX_ESCAPE_LEN);
+    push_row(&mut out, row, indices, lut, &mut current);
+
+    // Reset color at end of line, unless the row never set one
+    if !current.is_empty() {
+        out.push_str(RESET);
+    }
+    out.push('\n');
+    out
+}
+
+/// Assemble rows for [`RenderOptions::compact`]: one pass over the image,
+/// so each row can pick up the color the one before it left set
+fn render_compact_rows(image: &AsciiImage, indices: Option<&[u8]>, lut: &ColorLut) -> String {
+    let width = image.width();
+    let mut out = String::with_capacity(image.cells().len() * 4);
+    let mut current = String::with_capacity(MAX_ESCAPE_LEN);
+    for (y, row) in image.rows().enumerate() {
+        let visible = row
+            .iter()
+            .rposition(|cell| !(cell.transparent || cell.ch == ' '))
+            .map_or(0, |last| last + 1);
+        let row_indices = indices.map(|indices| &indices[y * width..y * width + visible]);
+        push_row(&mut out, &row[..visible], row_indices, lut, &mut current);
+        out.push('\n');
+    }
+    out
+}
+
+/// Append the cells of `row`, switching colors only where they differ from
+/// `current`, the color last set, which is updated as the row goes
+fn push_row(
+    out: &mut String,
+    row: &[Cell],
+    indices: Option<&[u8]>,
+    lut: &ColorLut,
+    current: &mut String,
+) {
+    let mut code = String::with_capacity(MAX_ESCAPE_LEN);
+    for (x, cell) in row.iter().enumerate() {
+        if cell.transparent || cell.ch == ' ' {
+            out.push(' ');
+            continue;
+        }
+
+        let [r, g, b] = cell.rgb;
+        code.clear();
+        match indices {
+            Some(indices) => lut.push_index_code(&mut code, indices[x]),
+            None => lut.push_code(&mut code, r, g, b),
+        }
+        if code != *current {
+            out.push_str(&code);
+            std::mem::swap(&mut code, current);
+        }
+        out.push(cell.ch);
+    }
+}
+
+/// Write the colored output for an ASCII image to any writer in a single call
+pub fn render_to_writer<W: Write>(
+    image: &AsciiImage,
+    options: &RenderOptions,
+    writer: &mut W,
+) -> Result<(), AsciiArtError> {
+    writer.write_all(render_to_string(image, options).as_bytes())?;
+    writer.flush()?;
     Ok(())
 }
 
+/// Render ASCII image to terminal using the colors stored in its cells
+pub fn render_colored(image: &AsciiImage) -> Result<(), AsciiArtError> {
+    render_colored_with_options(image, &RenderOptions::default())
+}
+
+/// Render ASCII image to terminal, quantizing colors with the given options
+pub fn render_colored_with_options(
+    image: &AsciiImage,
+    options: &RenderOptions,
+) -> Result<(), AsciiArtError> {
+    let stdout = io::stdout();
+    let mut writer = BufWriter::new(stdout.lock());
+    render_to_writer(image, options, &mut writer)
+}
+
+/// Render an ASCII image as an HTML `<pre>` block for web pages
+///
+/// Cells keep their exact colors as `<span style="color:#rrggbb">` runs, one
+/// span per change of color; spaces never open a span. Grayscale output uses
+/// the gray of each cell's luma. Monochrome output is the escaped text alone.
+pub fn render_html(image: &AsciiImage, options: &RenderOptions) -> String {
+    let mut out = String::with_capacity(image.cells().len() * 8 + 32);
+    out.push_str("<pre class=\"ascii-art\">");
+    for row in image.rows() {
+        let mut open: Option<[u8; 3]> = None;
+        for cell in row {
+            let rgb = if options.grayscale {
+                gray(cell.rgb)
+            } else {
+                cell.rgb
+            };
+            if options.color_depth != ColorDepth::Monochrome
+                && !cell.transparent
+                && cell.ch != ' '
+                && open != Some(rgb)
+            {
+                if open.is_some() {
+                    out.push_str("</span>");
+                }
+                let [r, g, b] = rgb;
+                let _ = write!(out, "<span style=\"color:#{r:02x}{g:02x}{b:02x}\">");
+                open = Some(rgb);
+            }
+            match cell.ch {
+                '&' => out.push_str("&amp;"),
+                '<' => out.push_str("&lt;"),
+                '>' => out.push_str("&gt;"),
+                ch => out.push(ch),
+            }
+        }
+        if open.is_some() {
+            out.push_str("</span>");
+        }
+        out.push('\n');
+    }
+    out.push_str("</pre>\n");
+    out
+}
+
+/// Pixel rows each row of a glyph is drawn across by [`render_image`], so
+/// cells are twice as tall as they are wide, as in a terminal
+const IMAGE_ROW_SCALE: u32 = 2;
+
+/// Draw an ASCII image with the built-in 8x8 font, for tools that take
+/// pictures rather than text
+///
+/// Each cell is `GLYPH_SIZE` pixels wide and twice that tall, with the glyph
+/// in the cell's exact color on black, as [`render_html`] colors it.
+/// Characters the font lacks, and transparent cells, are left black.
+pub fn render_image(image: &AsciiImage, options: &RenderOptions) -> RgbImage {
+    let cell_height = GLYPH_SIZE * IMAGE_ROW_SCALE;
+    let mut out = RgbImage::new(
+        image.width() as u32 * GLYPH_SIZE,
+        image.height() as u32 * cell_height,
+    );
+    for (y, row) in image.rows().enumerate() {
+        for (x, cell) in row.iter().enumerate() {
+            let Some(glyph) = font::glyph(cell.ch).filter(|_| !cell.transparent) else {
+                continue;
+            };
+            let rgb = match options.color_depth {
+                ColorDepth::Monochrome => [255; 3],
+                _ if options.grayscale => gray(cell.rgb),
+                _ => cell.rgb,
+            };
+            let (left, top) = (x as u32 * GLYPH_SIZE, y as u32 * cell_height);
+            for dy in 0..cell_height {
+                let bits = glyph[(dy / IMAGE_ROW_SCALE) as usize];
+                for dx in (0..GLYPH_SIZE).filter(|dx| bits & (1 << dx) != 0) {
+                    out.put_pixel(left + dx, top + dy, Rgb(rgb));
+                }
+            }
+        }
+    }
+    out
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn two_cell_image() -> AsciiImage {
+        let cells = vec![
+            Cell::opaque('#', [255, 0, 0], 0),
+            Cell::opaque('.', [0, 0, 0], 0),
+        ];
+        AsciiImage::new(2, 1, cells).expect("valid image")
+    }
+
+    #[test]
+    fn image_output_draws_glyphs_in_cell_colors() {
+        let image = render_image(&two_cell_image(), &RenderOptions::default());
+        assert_eq!(image.dimensions(), (16, 16));
+        let inked = |x0: u32| {
+            (x0..x0 + 8)
+                .flat_map(|x| (0..16).map(move |y| (x, y)))
+                .filter(|&(x, y)| image.get_pixel(x, y).0 != [0, 0, 0])
+                .map(|(x, y)| image.get_pixel(x, y).0)
+                .collect::<Vec<_>>()
+        };
+        let hash = inked(0);
+        assert!(!hash.is_empty());
+        assert!(hash.iter().all(|&rgb| rgb == [255, 0, 0]));
+        // The '.' cell is black, so its ink is too
+        assert!(inked(8).is_empty());
+
+        let mono = RenderOptions {
+            color_depth: ColorDepth::Monochrome,
+            ..RenderOptions::default()
+        };
+        let image = render_image(&two_cell_image(), &mono);
+        assert_eq!(image.get_pixel(1, 2).0, [255, 255, 255]);
+    }
+
+    #[test]
+    fn titles_drop_control_characters() {
+        assert_eq!(
+            title_sequence("cat.gif \u{2014} frame 3"),
+            "\x1b]0;cat.gif \u{2014} frame 3\x07"
+        );
+        assert_eq!(title_sequence("evil\x07\x1b]0;x"), "\x1b]0;evil]0;x\x07");
+    }
+
+    #[test]
+    fn html_output_escapes_text_and_groups_colors() {
+        let image = AsciiImage::new(
+            4,
+            1,
+            vec![
+                Cell::opaque('<', [255, 0, 0], 0),
+                Cell::opaque('&', [255, 0, 0], 0),
+                Cell::opaque(' ', [0, 0, 0], 0),
+                Cell::opaque('#', [0, 0, 255], 0),
+            ],
+        )
+        .expect("valid image");
+        assert_eq!(
+            render_html(&image, &RenderOptions::default()),
+            "<pre class=\"ascii-art\"><span style=\"color:#ff0000\">&lt;&amp; </span>\
+             <span style=\"color:#0000ff\">#</span>\n</pre>\n"
+        );
+        let mono = RenderOptions {
+            color_depth: ColorDepth::Monochrome,
+            ..RenderOptions::default()
+        };
+        assert_eq!(
+            render_html(&image, &mono),
+            "<pre class=\"ascii-art\">&lt;&amp; #\n</pre>\n"
+        );
+    }
+
+    #[test]
+    fn color_depth_round_trips_through_strings() {
+        for depth in [
+            ColorDepth::Ansi8,
+            ColorDepth::Ansi16,
+            ColorDepth::Ansi256,
+            ColorDepth::TrueColor,
+            ColorDepth::Monochrome,
+        ] {
+            assert_eq!(depth.to_string().parse::<ColorDepth>().ok(), Some(depth));
+        }
+        assert!("12".parse::<ColorDepth>().is_err());
+    }
+
+    #[test]
+    fn custom_palette_changes_16_color_matching() {
+        // A theme whose "red" slot is actually a dark blue
+        let mut colors: [[u8; 3]; 16] = std::array::from_fn(ansi16_rgb);
+        colors[1] = [0, 0, 90];
+        let options = RenderOptions {
+            palette: Palette::new(colors),
+            ..RenderOptions::default()
+        };
+
+        assert_eq!(color_code(0, 0, 90, &options), "\x1b[31m");
+        let mut out = String::new();
+        ColorLut::new(&options).push_code(&mut out, 0, 0, 90);
+        assert_eq!(out, "\x1b[31m");
+    }
+
+    #[test]
+    fn full_palette_drives_256_color_matching() {
+        // Remap every entry to black except index 200, which becomes orange
+        let mut colors = vec![[0, 0, 0]; 256];
+        colors[200] = [255, 128, 0];
+        let options = RenderOptions {
+            color_depth: ColorDepth::Ansi256,
+            palette: Palette::from_colors(colors).expect("256 colors"),
+            ..RenderOptions::default()
+        };
+
+        assert_eq!(color_code(250, 120, 10, &options), "\x1b[38;5;200m");
+        let mut out = String::new();
+        ColorLut::new(&options).push_code(&mut out, 250, 120, 10);
+        assert_eq!(out, "\x1b[38;5;200m");
+    }
+
+    #[test]
+    fn monochrome_output_has_no_escapes() {
+        let options = RenderOptions {
+            color_depth: ColorDepth::Monochrome,
+            ..RenderOptions::default()
+        };
+        assert_eq!(render_to_string(&two_cell_image(), &options), "#.\n");
+    }
+
     #[test]
     fn test_rgb_to_ansi_black() {
         assert_eq!(rgb_to_ansi(0, 0, 0), "\x1b[30m");
@@ -98,4 +1032,291 @@ mod tests {
         // Test that (130, 0, 0) maps to red (closest to 128,0,0)
         assert_eq!(rgb_to_ansi(130, 0, 0), "\x1b[31m");
     }
+
+    #[test]
+    fn test_rgb_to_ansi256_cube_and_gray() {
+        assert_eq!(rgb_to_ansi256_index(255, 0, 0), 196);
+        assert_eq!(rgb_to_ansi256_index(0, 0, 0), 16);
+        assert_eq!(rgb_to_ansi256_index(128, 128, 128), 244);
+    }
+
+    #[test]
+    fn test_color_code_per_depth() {
+        let with_depth = |color_depth| RenderOptions {
+            color_depth,
+            ..RenderOptions::default()
+        };
+        assert_eq!(
+            color_code(255, 0, 0, &with_depth(ColorDepth::Ansi16)),
+            "\x1b[91m"
+        );
+        assert_eq!(
+            color_code(255, 0, 0, &with_depth(ColorDepth::Ansi256)),
+            "\x1b[38;5;196m"
+        );
+        assert_eq!(
+            color_code(1, 2, 3, &with_depth(ColorDepth::TrueColor)),
+            "\x1b[38;2;1;2;3m"
+        );
+    }
+
+    #[test]
+    fn test_oklab_metric_matches_exact_palette_entries() {
+        for &(r, g, b, code) in &ANSI_COLORS {
+            assert_eq!(rgb_to_ansi_with_metric(r, g, b, ColorMetric::Oklab), code);
+        }
+        for index in [16u8, 100, 196, 231, 232, 255] {
+            let [r, g, b] = ansi256_rgb(index);
+            assert_eq!(
+                rgb_to_ansi256_index_with_metric(r, g, b, ColorMetric::Oklab),
+                index
+            );
+        }
+    }
+
+    #[test]
+    fn test_oklab_metric_keeps_skin_tones_warm() {
+        // A tan skin tone lands on gray under RGB distance but stays warm in OKLab
+        assert_eq!(rgb_to_ansi(198, 134, 66), "\x1b[90m");
+        assert_eq!(
+            rgb_to_ansi_with_metric(198, 134, 66, ColorMetric::Oklab),
+            "\x1b[33m"
+        );
+    }
+
+    #[test]
+    fn test_color_lut_agrees_with_direct_search_on_palette() {
+        let options = RenderOptions::default();
+        let lut = ColorLut::new(&options);
+        for index in 0..16 {
+            let [r, g, b] = ansi16_rgb(index);
+            assert_eq!(lut.index(r, g, b), Some(index as u8));
+        }
+
+        let mut code = String::new();
+        lut.push_code(&mut code, 255, 0, 0);
+        assert_eq!(code, color_code(255, 0, 0, &options));
+    }
+
+    #[test]
+    fn test_8_color_output_never_uses_bright_colors() {
+        let options = RenderOptions {
+            color_depth: ColorDepth::Ansi8,
+            ..RenderOptions::default()
+        };
+        assert_eq!(color_code(255, 0, 0, &options), "\x1b[31m");
+        assert_eq!(color_code(255, 255, 255, &options), "\x1b[37m");
+        assert_eq!(color_code(20, 20, 20, &options), "\x1b[30m");
+
+        let lut = ColorLut::new(&options);
+        for index in 0..16 {
+            let [r, g, b] = ansi16_rgb(index);
+            let standard = lut.index(r, g, b).expect("8-color output is quantized");
+            assert!(standard < 8, "color {index} mapped to {standard}");
+            let mut code = String::new();
+            lut.push_code(&mut code, r, g, b);
+            assert_eq!(code, ANSI_COLORS[standard as usize].3);
+        }
+        for index in 0..8 {
+            let [r, g, b] = ansi16_rgb(index);
+            assert_eq!(lut.index(r, g, b), Some(index as u8));
+        }
+    }
+
+    #[test]
+    fn test_color_dithering_mixes_palette_colors() {
+        // A dark red between the palette's black and red
+        let row = vec![Cell::opaque('#', [100, 0, 0], 0); 32];
+        let image = AsciiImage::new(32, 2, [row.clone(), row].concat()).expect("valid image");
+        let codes = |color_dither| {
+            let options = RenderOptions {
+                color_dither,
+                ..RenderOptions::default()
+            };
+            let out = render_rows(&image, &options);
+            let mut codes: Vec<&str> = out
+                .split('\x1b')
+                .filter_map(|part| part.split('m').next())
+                .filter(|code| code.starts_with('[') && *code != "[0")
+                .collect();
+            codes.sort_unstable();
+            codes.dedup();
+            codes.len()
+        };
+        // The row is a single run without dithering, but alternates colors with it
+        assert_eq!(codes(false), 1);
+        assert!(codes(true) > 1);
+
+        let mono = RenderOptions {
+            color_depth: ColorDepth::Monochrome,
+            color_dither: true,
+            ..RenderOptions::default()
+        };
+        let plain = RenderOptions {
+            color_dither: false,
+            ..mono.clone()
+        };
+        assert_eq!(render_rows(&image, &mono), render_rows(&image, &plain));
+    }
+
+    #[test]
+    fn test_shading_mixes_foreground_and_background() {
+        let options = RenderOptions {
+            shade: true,
+            ..RenderOptions::default()
+        };
+        // Halfway between the palette's black and dark red
+        let mut transparent = Cell::opaque('#', [0, 0, 0], 0);
+        transparent.transparent = true;
+        let image = AsciiImage::new(
+            4,
+            1,
+            vec![
+                Cell::opaque(' ', [64, 0, 0], 0),
+                Cell::opaque('@', [64, 0, 0], 0),
+                transparent,
+                Cell::opaque('.', [255, 255, 255], 0),
+            ],
+        )
+        .expect("valid image");
+        assert_eq!(
+            render_rows(&image, &options),
+            "\x1b[30;41m▒▒\x1b[0m \x1b[97;107m█\x1b[0m\n"
+        );
+
+        // Other depths keep the character ramp
+        let truecolor = RenderOptions {
+            color_depth: ColorDepth::TrueColor,
+            ..options.clone()
+        };
+        assert!(!render_rows(&image, &truecolor).contains('▒'));
+        assert_eq!(ansi16_sgr(7, true), 47);
+        assert_eq!(ansi16_sgr(9, false), 91);
+    }
+
+    #[test]
+    fn test_color_lut_passes_truecolor_through() {
+        let options = RenderOptions {
+            color_depth: ColorDepth::TrueColor,
+            ..RenderOptions::default()
+        };
+        let lut = ColorLut::shared(&options);
+        assert_eq!(lut.index(1, 2, 3), None);
+
+        let mut code = String::new();
+        lut.push_code(&mut code, 1, 2, 3);
+        assert_eq!(code, "\x1b[38;2;1;2;3m");
+    }
+
+    #[test]
+    fn test_grayscale_output_uses_gray_shades() {
+        let gray = |color_depth| RenderOptions {
+            color_depth,
+            grayscale: true,
+            ..RenderOptions::default()
+        };
+        // Pure red has a luma of 76
+        assert_eq!(
+            color_code(255, 0, 0, &gray(ColorDepth::TrueColor)),
+            "\x1b[38;2;76;76;76m"
+        );
+        assert_eq!(
+            color_code(255, 0, 0, &gray(ColorDepth::Ansi256)),
+            "\x1b[38;5;239m"
+        );
+        assert_eq!(color_code(255, 0, 0, &gray(ColorDepth::Ansi16)), "\x1b[90m");
+        assert_eq!(
+            color_code(0, 0, 0, &gray(ColorDepth::Ansi256)),
+            "\x1b[38;5;16m"
+        );
+        assert_eq!(
+            color_code(255, 255, 255, &gray(ColorDepth::Ansi16)),
+            "\x1b[97m"
+        );
+        assert_eq!(
+            color_code(255, 255, 255, &gray(ColorDepth::Ansi8)),
+            "\x1b[37m"
+        );
+
+        let html = render_html(&two_cell_image(), &gray(ColorDepth::TrueColor));
+        assert!(
+            html.contains("<span style=\"color:#4c4c4c\">#</span>"),
+            "{html}"
+        );
+    }
+
+    #[test]
+    fn test_render_to_string_layout() {
+        let rendered = render_to_string(&two_cell_image(), &RenderOptions::default());
+        assert_eq!(rendered, "\x1b[91m#\x1b[30m.\x1b[0m\n\x1b[0m");
+    }
+
+    #[test]
+    fn test_render_to_writer_matches_string() {
+        let image = two_cell_image();
+        let mut buffer = Vec::new();
+        let options = RenderOptions {
+            color_depth: ColorDepth::TrueColor,
+            ..RenderOptions::default()
+        };
+        render_to_writer(&image, &options, &mut buffer).expect("write succeeds");
+        assert_eq!(
+            String::from_utf8(buffer).expect("utf8"),
+            render_to_string(&image, &options)
+        );
+    }
+
+    #[test]
+    fn test_render_reuses_current_color() {
+        let red = [255, 0, 0];
+        let cells = vec![
+            Cell::opaque('#', red, 0),
+            Cell::opaque('@', red, 0),
+            Cell::opaque('%', red, 0),
+        ];
+        let image = AsciiImage::new(3, 1, cells).expect("valid image");
+        let rendered = render_to_string(&image, &RenderOptions::default());
+        assert_eq!(rendered, "\x1b[91m#@%\x1b[0m\n\x1b[0m");
+    }
+
+    #[test]
+    fn test_compact_render_carries_colors_across_rows() {
+        let red = [255, 0, 0];
+        let blank = Cell::opaque(' ', red, 0);
+        let cells = vec![
+            Cell::opaque('#', red, 0),
+            blank,
+            blank,
+            Cell::opaque('@', red, 0),
+            Cell::opaque('%', [0, 0, 0], 0),
+            blank,
+        ];
+        let image = AsciiImage::new(3, 2, cells).expect("valid image");
+        let options = RenderOptions {
+            compact: true,
+            ..RenderOptions::default()
+        };
+        let rendered = render_to_string(&image, &options);
+        assert_eq!(rendered, "\x1b[91m#\n@\x1b[30m%\n\x1b[0m");
+        assert!(rendered.len() < render_to_string(&image, &RenderOptions::default()).len());
+    }
+
+    #[test]
+    fn test_render_skips_escapes_for_spaces() {
+        let image = AsciiImage::new(
+            3,
+            2,
+            vec![
+                Cell::opaque('#', [255, 0, 0], 0),
+                Cell::opaque(' ', [0, 0, 255], 0),
+                Cell::opaque('#', [255, 0, 0], 0),
+                Cell::opaque(' ', [0, 255, 0], 0),
+                Cell::opaque(' ', [0, 255, 0], 0),
+                Cell::opaque(' ', [0, 255, 0], 0),
+            ],
+        )
+        .expect("valid image");
+        let rendered = render_to_string(&image, &RenderOptions::default());
+        assert_eq!(rendered, "\x1b[91m# #\x1b[0m\n   \n\x1b[0m");
+    }
 }
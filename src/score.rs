@@ -0,0 +1,208 @@
+//! Objective quality of the art against its source, as reported by `--score`
+//!
+//! The art is turned back into an image with one pixel per cell, whose
+//! brightness is the ink coverage of the cell's glyph in the built-in font
+//! times the brightness of its color. That is compared with the source
+//! downsampled to the same grid.
+
+use std::fmt;
+
+use image::imageops::FilterType;
+use image::{DynamicImage, GrayImage, Luma};
+
+use crate::ascii_converter::AsciiImage;
+use crate::builder::Background;
+use crate::color::luma;
+use crate::font;
+
+/// Side of the square SSIM windows, in cells
+const SSIM_WINDOW: u32 = 7;
+
+/// SSIM stabilizers for 8-bit values, from Wang et al. (2004)
+const SSIM_C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+const SSIM_C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+
+/// How closely the art reproduces the brightness of its source
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Score {
+    /// Mean structural similarity, up to 1 for identical images
+    pub ssim: f64,
+    /// Peak signal-to-noise ratio in dB; infinite for identical images
+    pub psnr: f64,
+}
+
+impl Score {
+    /// Compare the art's reconstruction with `reference`, both one pixel per cell
+    pub fn compare(reference: &GrayImage, reconstructed: &GrayImage) -> Self {
+        Self {
+            ssim: ssim(reference, reconstructed),
+            psnr: psnr(reference, reconstructed),
+        }
+    }
+}
+
+impl fmt::Display for Score {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Score:")?;
+        writeln!(f, "  ssim  {:>8.4}", self.ssim)?;
+        writeln!(f, "  psnr  {:>8.2} dB", self.psnr)
+    }
+}
+
+/// The source's brightness at the art's grid resolution, with transparent
+/// areas showing the terminal background
+pub fn reference(
+    image: &DynamicImage,
+    width: u32,
+    height: u32,
+    background: Background,
+) -> GrayImage {
+    let resized = image
+        .resize_exact(width.max(1), height.max(1), FilterType::Triangle)
+        .to_luma_alpha8();
+    let backdrop = backdrop(background);
+    GrayImage::from_fn(resized.width(), resized.height(), |x, y| {
+        let [value, alpha] = resized.get_pixel(x, y).0;
+        let alpha = f32::from(alpha) / 255.0;
+        Luma([(f32::from(value) * alpha + backdrop * (1.0 - alpha)).round() as u8])
+    })
+}
+
+/// The brightness each cell of `art` shows on screen
+///
+/// Colored cells ink in their own color; monochrome ones in the terminal's
+/// foreground, taken as white on dark backgrounds and black on light ones.
+/// Characters the built-in font lacks are assumed to show their cell's
+/// brightness.
+pub fn reconstruct(art: &AsciiImage, colored: bool, background: Background) -> GrayImage {
+    let backdrop = backdrop(background);
+    let foreground = 255.0 - backdrop;
+    let mut image = GrayImage::new(art.width() as u32, art.height() as u32);
+    for (y, row) in art.rows().enumerate() {
+        for (x, cell) in row.iter().enumerate() {
+            let shown = match font::ink_coverage(cell.ch) {
+                _ if cell.transparent => backdrop,
+                Some(coverage) => {
+                    let ink = if colored { luma(cell.rgb) } else { foreground };
+                    backdrop + (ink - backdrop) * coverage
+                }
+                None => f32::from(cell.luma),
+            };
+            image.put_pixel(x as u32, y as u32, Luma([shown.round() as u8]));
+        }
+    }
+    image
+}
+
+/// Brightness of the terminal background
+fn backdrop(background: Background) -> f32 {
+    match background {
+        Background::Dark => 0.0,
+        Background::Light => 255.0,
+    }
+}
+
+/// Peak signal-to-noise ratio of `b` against `a`, in dB
+pub fn psnr(a: &GrayImage, b: &GrayImage) -> f64 {
+    let squared: f64 = a
+        .pixels()
+        .zip(b.pixels())
+        .map(|(p, q)| (f64::from(p[0]) - f64::from(q[0])).powi(2))
+        .sum();
+    let mse = squared / (a.width() as f64 * a.height() as f64).max(1.0);
+    if mse == 0.0 {
+        return f64::INFINITY;
+    }
+    10.0 * (255.0 * 255.0 / mse).log10()
+}
+
+/// Mean structural similarity of `a` and `b` over square windows, shrunk to
+/// fit images smaller than a window
+pub fn ssim(a: &GrayImage, b: &GrayImage) -> f64 {
+    let (width, height) = (a.width().min(b.width()), a.height().min(b.height()));
+    if width == 0 || height == 0 {
+        return 1.0;
+    }
+    let (window_x, window_y) = (SSIM_WINDOW.min(width), SSIM_WINDOW.min(height));
+    let mut total = 0.0;
+    let mut windows = 0;
+    for top in 0..=height - window_y {
+        for left in 0..=width - window_x {
+            let (mut sum_a, mut sum_b) = (0.0, 0.0);
+            let (mut sum_aa, mut sum_bb, mut sum_ab) = (0.0, 0.0, 0.0);
+            for y in top..top + window_y {
+                for x in left..left + window_x {
+                    let (p, q) = (
+                        f64::from(a.get_pixel(x, y)[0]),
+                        f64::from(b.get_pixel(x, y)[0]),
+                    );
+                    sum_a += p;
+                    sum_b += q;
+                    sum_aa += p * p;
+                    sum_bb += q * q;
+                    sum_ab += p * q;
+                }
+            }
+            let n = f64::from(window_x * window_y);
+            let (mean_a, mean_b) = (sum_a / n, sum_b / n);
+            let var_a = sum_aa / n - mean_a * mean_a;
+            let var_b = sum_bb / n - mean_b * mean_b;
+            let covariance = sum_ab / n - mean_a * mean_b;
+            total += ((2.0 * mean_a * mean_b + SSIM_C1) * (2.0 * covariance + SSIM_C2))
+                / ((mean_a * mean_a + mean_b * mean_b + SSIM_C1) * (var_a + var_b + SSIM_C2));
+            windows += 1;
+        }
+    }
+    total / f64::from(windows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ascii_converter::Cell;
+
+    fn gradient(width: u32, height: u32) -> GrayImage {
+        GrayImage::from_fn(width, height, |x, y| Luma([((x * 13 + y * 7) % 256) as u8]))
+    }
+
+    #[test]
+    fn identical_images_score_perfectly() {
+        let image = gradient(20, 10);
+        let score = Score::compare(&image, &image);
+        assert!((score.ssim - 1.0).abs() < 1e-9, "{score:?}");
+        assert!(score.psnr.is_infinite());
+
+        let mut noisy = image.clone();
+        for pixel in noisy.pixels_mut().step_by(3) {
+            pixel[0] = pixel[0].wrapping_add(40);
+        }
+        let worse = Score::compare(&image, &noisy);
+        assert!(worse.ssim < 0.99 && worse.psnr < 30.0, "{worse:?}");
+        assert!(worse.to_string().contains("psnr"));
+    }
+
+    #[test]
+    fn reconstruction_follows_glyph_coverage_and_background() {
+        let white = [255, 255, 255];
+        let mut blank = Cell::opaque(' ', white, 200);
+        blank.transparent = true;
+        let cells = vec![
+            Cell::opaque('█', white, 200),
+            Cell::opaque('.', white, 200),
+            blank,
+            Cell::opaque('⠿', white, 200),
+        ];
+        let art = AsciiImage::new(4, 1, cells).expect("valid grid");
+
+        let dark = reconstruct(&art, false, Background::Dark);
+        assert_eq!(dark.get_pixel(0, 0)[0], 255);
+        assert!(dark.get_pixel(1, 0)[0] < 64);
+        assert_eq!(dark.get_pixel(2, 0)[0], 0);
+        // No glyph in the built-in font: the cell's own brightness
+        assert_eq!(dark.get_pixel(3, 0)[0], 200);
+
+        let light = reconstruct(&art, false, Background::Light);
+        assert_eq!(light.get_pixel(0, 0)[0], 0);
+        assert_eq!(light.get_pixel(2, 0)[0], 255);
+    }
+}
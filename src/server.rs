@@ -0,0 +1,216 @@
+use std::io::{self, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use tracing::{info, warn};
+use tungstenite::Message;
+
+use crate::animation::{frame_message, pace, FrameFormat, RenderedFrame};
+
+/// Telnet negotiation sent on connect: IAC WILL ECHO, IAC WILL SUPPRESS-GO-AHEAD
+///
+/// This puts clients in character mode, so typed keys are not echoed over
+/// the animation.
+const TELNET_NEGOTIATION: [u8; 6] = [255, 251, 1, 255, 251, 3];
+
+/// How long a write may block before a stalled client is dropped
+pub const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a WebSocket client may take to send its handshake request
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Most clients served at once; each has a thread, so later connections are
+/// turned away until one leaves
+pub const MAX_CLIENTS: usize = 64;
+
+/// Stream `frames` to every telnet client that connects, looping until they leave
+///
+/// Each client gets its own thread and its own clock, so a slow connection
+/// never holds back the others. Up to [`MAX_CLIENTS`] are served at once.
+pub fn serve_telnet(listener: TcpListener, frames: Arc<[RenderedFrame]>) -> io::Result<()> {
+    accept_clients(listener, move |mut stream, peer| {
+        report(&peer, play(&mut stream, &frames, None));
+    })
+}
+
+/// Stream `frames` to every WebSocket client that connects, one message per frame
+///
+/// Messages are encoded with [`frame_message`]. Clients are served like [`serve_telnet`] clients, each on its own thread.
+pub fn serve_websocket(
+    listener: TcpListener,
+    frames: Arc<[RenderedFrame]>,
+    format: FrameFormat,
+) -> io::Result<()> {
+    accept_clients(listener, move |stream, peer| {
+        let _ = stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT));
+        let mut socket = match tungstenite::accept(stream) {
+            Ok(socket) => socket,
+            Err(err) => {
+                warn!("WebSocket handshake with {peer} failed: {err}");
+                return;
+            }
+        };
+        let _ = socket.get_ref().set_read_timeout(None);
+        let result = pace(&frames, None, |frame| {
+            socket
+                .send(Message::Text(frame_message(frame, format)))
+                .map_err(websocket_io_error)
+        });
+        if result.is_ok() {
+            let _ = socket.close(None);
+        }
+        report(&peer, result);
+    })
+}
+
+fn accept_clients<F>(listener: TcpListener, serve_client: F) -> io::Result<()>
+where
+    F: Fn(TcpStream, String) + Clone + Send + 'static,
+{
+    let active = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                warn!("Failed to accept a connection: {err}");
+                continue;
+            }
+        };
+        let peer = stream
+            .peer_addr()
+            .map_or_else(|_| "unknown peer".to_string(), |addr| addr.to_string());
+        let Some(slot) = ClientSlot::take(&active) else {
+            warn!("Turning away {peer}: already serving {MAX_CLIENTS} clients");
+            continue;
+        };
+        info!("{peer} connected");
+        let _ = stream.set_nodelay(true);
+        let _ = stream.set_write_timeout(Some(WRITE_TIMEOUT));
+        let serve_client = serve_client.clone();
+        thread::spawn(move || {
+            let _slot = slot;
+            serve_client(stream, peer);
+        });
+    }
+    Ok(())
+}
+
+/// A client's place among the [`MAX_CLIENTS`], given back when dropped
+struct ClientSlot(Arc<AtomicUsize>);
+
+impl ClientSlot {
+    /// Take a place, unless all of them are in use
+    fn take(active: &Arc<AtomicUsize>) -> Option<Self> {
+        if active.fetch_add(1, Ordering::AcqRel) < MAX_CLIENTS {
+            Some(Self(Arc::clone(active)))
+        } else {
+            active.fetch_sub(1, Ordering::AcqRel);
+            None
+        }
+    }
+}
+
+impl Drop for ClientSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+fn report(peer: &str, result: io::Result<()>) {
+    match result {
+        Ok(()) => info!("{peer} finished"),
+        Err(err) if is_disconnect(&err) => info!("{peer} disconnected"),
+        Err(err) => warn!("Dropping {peer}: {err}"),
+    }
+}
+
+/// Whether `err` means the client went away rather than something failing
+fn is_disconnect(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::BrokenPipe
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::UnexpectedEof
+    )
+}
+
+fn websocket_io_error(err: tungstenite::Error) -> io::Error {
+    match err {
+        tungstenite::Error::Io(err) => err,
+        tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed => {
+            io::ErrorKind::ConnectionAborted.into()
+        }
+        other => io::Error::other(other),
+    }
+}
+
+/// Play `frames` on a telnet client, `passes` times or until writing fails
+///
+/// Each frame is drawn from the home position with CRLF line endings, as
+/// network virtual terminals expect. The cursor is hidden while playing and
+/// restored at the end.
+pub fn play<W: Write>(
+    writer: &mut W,
+    frames: &[RenderedFrame],
+    passes: Option<u32>,
+) -> io::Result<()> {
+    writer.write_all(&TELNET_NEGOTIATION)?;
+    writer.write_all(b"\x1b[2J\x1b[?25l")?;
+
+    let mut buffer = Vec::new();
+    pace(frames, passes, |frame| {
+        buffer.clear();
+        buffer.extend_from_slice(b"\x1b[H");
+        buffer.extend_from_slice(frame.text.replace('\n', "\r\n").as_bytes());
+        writer.write_all(&buffer)?;
+        writer.flush()
+    })?;
+
+    writer.write_all(b"\x1b[0m\x1b[?25h\r\n")?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clients_beyond_the_cap_are_turned_away_until_one_leaves() {
+        let active = Arc::new(AtomicUsize::new(0));
+        let mut slots: Vec<_> = (0..MAX_CLIENTS)
+            .map(|_| ClientSlot::take(&active).expect("free slot"))
+            .collect();
+        assert!(ClientSlot::take(&active).is_none());
+        assert_eq!(active.load(Ordering::Acquire), MAX_CLIENTS);
+        slots.pop();
+        assert!(ClientSlot::take(&active).is_some());
+    }
+
+    #[test]
+    fn frames_are_drawn_from_home_with_crlf() {
+        let mut out = Vec::new();
+        let frames = [
+            RenderedFrame::two_cells("ab\ncd\n"),
+            RenderedFrame::two_cells("ef\n"),
+        ];
+        play(&mut out, &frames, Some(2)).expect("played");
+        let out = String::from_utf8_lossy(&out);
+        assert!(out.starts_with("\u{fffd}\u{fffd}\u{1}\u{fffd}\u{fffd}\u{3}\x1b[2J\x1b[?25l"));
+        assert_eq!(out.matches("\x1b[Hab\r\ncd\r\n").count(), 2);
+        assert_eq!(out.matches("\x1b[Hef\r\n").count(), 2);
+        assert!(out.ends_with("\x1b[?25h\r\n"));
+    }
+
+    #[test]
+    fn closed_connections_count_as_disconnects() {
+        assert!(is_disconnect(&io::ErrorKind::BrokenPipe.into()));
+        assert!(!is_disconnect(&io::ErrorKind::PermissionDenied.into()));
+        assert!(is_disconnect(&websocket_io_error(
+            tungstenite::Error::ConnectionClosed
+        )));
+    }
+}
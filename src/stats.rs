@@ -0,0 +1,171 @@
+//! Usage statistics of the rendered art, as reported by `--stats`
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::ascii_converter::AsciiImage;
+
+/// Brightness histogram buckets, each covering 32 levels
+pub const BRIGHTNESS_BUCKETS: usize = 8;
+
+/// Most-used colors listed in the report
+const TOP_COLORS: usize = 8;
+
+/// Width of the longest bar in the report
+const BAR_WIDTH: usize = 20;
+
+/// Characters, colors, and brightness of the cells written so far, and the
+/// size of the output
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Cells drawn, not counting transparent ones
+    pub cells: u64,
+    /// Transparent cells, left blank
+    pub transparent: u64,
+    /// How often each character was drawn
+    pub chars: HashMap<char, u64>,
+    /// How often each cell color was used, before quantizing to the output
+    /// color depth; empty for monochrome output
+    pub colors: HashMap<[u8; 3], u64>,
+    /// Cells by brightness, darkest bucket first
+    pub brightness: [u64; BRIGHTNESS_BUCKETS],
+    /// Bytes of output, escape sequences included
+    pub bytes: u64,
+}
+
+impl Stats {
+    /// Count the cells of `image`, and their colors if `colors` is set
+    pub fn record(&mut self, image: &AsciiImage, colors: bool) {
+        for cell in image.cells() {
+            if cell.transparent {
+                self.transparent += 1;
+                continue;
+            }
+            self.cells += 1;
+            *self.chars.entry(cell.ch).or_default() += 1;
+            if colors {
+                *self.colors.entry(cell.rgb).or_default() += 1;
+            }
+            self.brightness[usize::from(cell.luma) * BRIGHTNESS_BUCKETS / 256] += 1;
+        }
+    }
+
+    /// Count `bytes` more bytes of output
+    pub fn record_bytes(&mut self, bytes: usize) {
+        self.bytes += bytes as u64;
+    }
+
+    /// `count` as a percentage of the drawn cells
+    fn share(&self, count: u64) -> f64 {
+        count as f64 * 100.0 / self.cells.max(1) as f64
+    }
+}
+
+/// Entries of `counts`, most frequent first
+fn by_count<K: Copy + Ord>(counts: &HashMap<K, u64>) -> Vec<(K, u64)> {
+    let mut entries: Vec<(K, u64)> = counts.iter().map(|(&key, &count)| (key, count)).collect();
+    entries.sort_unstable_by_key(|&(key, count)| (Reverse(count), key));
+    entries
+}
+
+fn bar(count: u64, max: u64) -> String {
+    "#".repeat((count * BAR_WIDTH as u64).div_ceil(max.max(1)) as usize)
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Stats:")?;
+        writeln!(
+            f,
+            "  cells       {} ({} transparent)",
+            self.cells, self.transparent
+        )?;
+        writeln!(
+            f,
+            "  output      {:.1} KiB ({} bytes)",
+            self.bytes as f64 / 1024.0,
+            self.bytes
+        )?;
+
+        let chars = by_count(&self.chars);
+        let max = chars.first().map_or(0, |&(_, count)| count);
+        writeln!(f, "  characters  {} distinct", chars.len())?;
+        for (ch, count) in &chars {
+            let share = self.share(*count);
+            writeln!(f, "    '{ch}' {share:5.1}% {}", bar(*count, max))?;
+        }
+
+        if !self.colors.is_empty() {
+            let colors = by_count(&self.colors);
+            writeln!(
+                f,
+                "  colors      {} distinct (before quantizing)",
+                colors.len()
+            )?;
+            for ([r, g, b], count) in colors.iter().take(TOP_COLORS) {
+                let share = self.share(*count);
+                writeln!(f, "    #{r:02x}{g:02x}{b:02x} {share:5.1}%")?;
+            }
+        }
+
+        let max = self.brightness.iter().copied().max().unwrap_or(0);
+        writeln!(f, "  brightness")?;
+        for (bucket, &count) in self.brightness.iter().enumerate() {
+            let low = bucket * 256 / BRIGHTNESS_BUCKETS;
+            let high = low + 256 / BRIGHTNESS_BUCKETS - 1;
+            let share = self.share(count);
+            writeln!(f, "    {low:>3}-{high:<3} {share:5.1}% {}", bar(count, max))?;
+        }
+
+        if self.cells > 0 && chars.len() <= 2 {
+            writeln!(
+                f,
+                "  note: the art uses only {} character(s); try --levels, --contrast, or --clahe",
+                chars.len()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ascii_converter::Cell;
+
+    #[test]
+    fn record_counts_characters_colors_and_brightness() {
+        let mut blank = Cell::opaque(' ', [0, 0, 0], 0);
+        blank.transparent = true;
+        let cells = vec![
+            Cell::opaque('@', [255, 0, 0], 250),
+            Cell::opaque('@', [255, 0, 0], 240),
+            Cell::opaque('.', [0, 0, 255], 10),
+            blank,
+        ];
+        let image = AsciiImage::new(4, 1, cells).expect("valid grid");
+        let mut stats = Stats::default();
+        stats.record(&image, true);
+        stats.record_bytes(120);
+
+        assert_eq!((stats.cells, stats.transparent, stats.bytes), (3, 1, 120));
+        assert_eq!(stats.chars[&'@'], 2);
+        assert_eq!(stats.colors[&[255, 0, 0]], 2);
+        assert_eq!(stats.brightness, [1, 0, 0, 0, 0, 0, 0, 2]);
+
+        let report = stats.to_string();
+        assert!(
+            report.contains("'@'  66.7% ####################"),
+            "{report}"
+        );
+        assert!(report.contains("#ff0000  66.7%"), "{report}");
+        assert!(report.contains("224-255  66.7%"), "{report}");
+        assert!(report.contains("only 2 character(s)"), "{report}");
+
+        let mut mono = Stats::default();
+        mono.record(&image, false);
+        assert!(mono.colors.is_empty());
+        assert!(!mono.to_string().contains("colors"));
+    }
+}
@@ -1,11 +1,39 @@
+use std::env;
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::str::FromStr;
+use std::sync::atomic::AtomicBool;
+use std::sync::Once;
+use std::time::Duration;
+
 use terminal_size::{terminal_size, Height, Width};
 
+use crate::builder::Background;
+use crate::color::luma;
+use crate::error::AsciiArtError;
+use crate::layout::Extent;
+use crate::palette::Palette;
+use crate::renderer::{title_sequence, ColorDepth, RESTORE_TITLE, SAVE_TITLE};
+
+/// Width used when the terminal size is unknown or deliberately ignored.
+const FALLBACK_WIDTH: u32 = 80;
+
+/// Rows that percentage heights are of when the terminal size is unknown or
+/// deliberately ignored.
+const FALLBACK_HEIGHT: u32 = 24;
+
 /// Indicates how the final output width was decided.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum WidthSource {
     User,
     AutoDetected,
     Fallback,
+    /// Fixed default chosen without consulting the terminal.
+    Fixed,
 }
 
 /// Result of resolving the output width, including provenance information.
@@ -20,28 +48,50 @@ pub fn get_terminal_size() -> Option<(u32, u32)> {
     terminal_size().map(|(Width(w), Height(h))| (u32::from(w), u32::from(h)))
 }
 
-pub fn resolve_output_width(user_width: Option<u32>) -> WidthResolution {
+pub fn resolve_output_width(user_width: Option<Extent>) -> WidthResolution {
     let detected_width = get_terminal_size().map(|(w, _)| w);
     compute_output_width(user_width, detected_width)
 }
 
-fn compute_output_width(user_width: Option<u32>, detected_width: Option<u32>) -> WidthResolution {
+/// Resolve the output width without terminal detection, for reproducible output.
+///
+/// A percentage is of the fallback width.
+pub fn fixed_output_width(user_width: Option<Extent>) -> WidthResolution {
     match user_width {
         Some(width) => WidthResolution {
+            width: width.of(FALLBACK_WIDTH),
+            source: WidthSource::User,
+        },
+        None => WidthResolution {
+            width: FALLBACK_WIDTH,
+            source: WidthSource::Fixed,
+        },
+    }
+}
+
+fn compute_output_width(
+    user_width: Option<Extent>,
+    detected_width: Option<u32>,
+) -> WidthResolution {
+    let source = if detected_width.is_some() {
+        WidthSource::AutoDetected
+    } else {
+        WidthSource::Fallback
+    };
+    match user_width {
+        Some(Extent::Cells(width)) => WidthResolution {
             width,
             source: WidthSource::User,
         },
+        Some(percent) => WidthResolution {
+            width: percent.of(detected_width.unwrap_or(FALLBACK_WIDTH)),
+            source,
+        },
         None => {
             let width_with_margin = detected_width
                 .map(apply_margin)
                 .map(|width| width.max(40))
-                .unwrap_or(80);
-
-            let source = if detected_width.is_some() {
-                WidthSource::AutoDetected
-            } else {
-                WidthSource::Fallback
-            };
+                .unwrap_or(FALLBACK_WIDTH);
 
             WidthResolution {
                 width: width_with_margin,
@@ -51,21 +101,1136 @@ fn compute_output_width(user_width: Option<u32>, detected_width: Option<u32>) ->
     }
 }
 
+/// Resolve a requested output height against the terminal's.
+///
+/// A percentage is of the rows above the prompt, or of the fallback height
+/// when the terminal size is unknown.
+pub fn resolve_output_height(user_height: Extent) -> u32 {
+    let detected_height = get_terminal_size().map(|(_, h)| h);
+    compute_output_height(user_height, detected_height)
+}
+
+/// Resolve a requested output height without terminal detection.
+pub fn fixed_output_height(user_height: Extent) -> u32 {
+    compute_output_height(user_height, None)
+}
+
+fn compute_output_height(user_height: Extent, detected_height: Option<u32>) -> u32 {
+    let available = detected_height.map_or(FALLBACK_HEIGHT, |height| height.saturating_sub(1));
+    user_height.of(available.max(1))
+}
+
 fn apply_margin(width: u32) -> u32 {
     width.saturating_sub(2)
 }
 
+/// Return true when `TERM` names a terminal that cannot interpret escapes.
+///
+/// Covers `TERM=dumb`, used by editor-embedded terminals and CI logs, and a
+/// missing `TERM` outside Windows, whose consoles never set it.
+pub fn is_dumb_terminal() -> bool {
+    term_is_dumb(env::var_os("TERM").as_deref())
+}
+
+fn term_is_dumb(term: Option<&OsStr>) -> bool {
+    match term {
+        Some(term) => term.is_empty() || term == "dumb",
+        None => !cfg!(windows),
+    }
+}
+
+/// Richest color output the terminal is known to support.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorCapability {
+    Ansi16,
+    Ansi256,
+    TrueColor,
+}
+
+impl ColorCapability {
+    /// The renderer color depth matching this capability.
+    pub fn color_depth(self) -> ColorDepth {
+        match self {
+            ColorCapability::Ansi16 => ColorDepth::Ansi16,
+            ColorCapability::Ansi256 => ColorDepth::Ansi256,
+            ColorCapability::TrueColor => ColorDepth::TrueColor,
+        }
+    }
+}
+
+/// Detect the terminal's color support from `COLORTERM`, `TERM`, and terminfo.
+pub fn detect_color_capability() -> ColorCapability {
+    let term = env::var("TERM").ok();
+    let terminfo = term.as_deref().and_then(terminfo_colors);
+    classify_color_capability(
+        env::var("COLORTERM").ok().as_deref(),
+        term.as_deref(),
+        terminfo,
+    )
+}
+
+fn classify_color_capability(
+    colorterm: Option<&str>,
+    term: Option<&str>,
+    terminfo_colors: Option<u32>,
+) -> ColorCapability {
+    if matches!(colorterm, Some("truecolor" | "24bit")) {
+        return ColorCapability::TrueColor;
+    }
+    if let Some(term) = term {
+        if term.ends_with("-direct") || term.contains("truecolor") {
+            return ColorCapability::TrueColor;
+        }
+        if term.contains("256color") {
+            return ColorCapability::Ansi256;
+        }
+    }
+    match terminfo_colors {
+        Some(colors) if colors >= 1 << 24 => ColorCapability::TrueColor,
+        Some(colors) if colors >= 256 => ColorCapability::Ansi256,
+        _ => ColorCapability::Ansi16,
+    }
+}
+
+/// Position of the `colors` capability among terminfo numeric capabilities.
+const TERMINFO_COLORS_INDEX: usize = 13;
+
+/// Look up the `colors` capability in the compiled terminfo entry for `term`.
+fn terminfo_colors(term: &str) -> Option<u32> {
+    let first = term.chars().next()?;
+    terminfo_dirs()
+        .into_iter()
+        .flat_map(|dir| {
+            // ncurses uses the first letter as directory name; macOS uses its hex code
+            [
+                dir.join(first.to_string()).join(term),
+                dir.join(format!("{:x}", first as u32)).join(term),
+            ]
+        })
+        .find_map(|path| fs::read(path).ok())
+        .and_then(|data| parse_terminfo_colors(&data))
+}
+
+fn terminfo_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Some(dir) = env::var_os("TERMINFO") {
+        dirs.push(PathBuf::from(dir));
+    }
+    if let Some(home) = env::var_os("HOME") {
+        dirs.push(PathBuf::from(home).join(".terminfo"));
+    }
+    if let Some(list) = env::var_os("TERMINFO_DIRS") {
+        dirs.extend(env::split_paths(&list).filter(|dir| !dir.as_os_str().is_empty()));
+    }
+    dirs.extend(
+        ["/etc/terminfo", "/lib/terminfo", "/usr/share/terminfo"]
+            .into_iter()
+            .map(PathBuf::from),
+    );
+    dirs
+}
+
+/// Read the `colors` number from a compiled terminfo file (legacy or 32-bit format).
+fn parse_terminfo_colors(data: &[u8]) -> Option<u32> {
+    let short = |index: usize| {
+        data.get(index * 2..index * 2 + 2)
+            .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]) as usize)
+    };
+
+    let number_size = match short(0)? {
+        0o432 => 2,
+        0o1036 => 4,
+        _ => return None,
+    };
+    let (names, bools, numbers) = (short(1)?, short(2)?, short(3)?);
+    if numbers <= TERMINFO_COLORS_INDEX {
+        return None;
+    }
+
+    // Numbers start on an even offset after the header, names, and booleans
+    let numbers_start = (12 + names + bools + 1) & !1;
+    let start = numbers_start + TERMINFO_COLORS_INDEX * number_size;
+    let bytes = data.get(start..start + number_size)?;
+    let value = match number_size {
+        2 => i16::from_le_bytes([bytes[0], bytes[1]]) as i32,
+        _ => i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+    };
+    u32::try_from(value).ok().filter(|&colors| colors > 0)
+}
+
+/// How long to wait for the terminal to answer a query before giving up.
+pub const QUERY_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// Ask the terminal for its actual 16-color palette using OSC 4.
+///
+/// Returns `None` when there is no controlling terminal, it does not answer
+/// within `timeout`, or it reports fewer than all 16 colors.
+pub fn query_palette(timeout: Duration) -> Option<Palette> {
+    let mut request: String = (0..16)
+        .map(|index| format!("\x1b]4;{index};?\x07"))
+        .collect();
+    // Primary device attributes: answered by virtually every terminal, so a
+    // reply without palette entries ends the wait early
+    request.push_str("\x1b[c");
+
+    let reply = query_tty(&request, timeout)?;
+    parse_palette_reply(&reply)
+}
+
+/// Cell size assumed when the terminal does not report its pixel dimensions.
+pub const DEFAULT_CELL_PIXELS: (u32, u32) = (10, 20);
+
+/// Size of one character cell in pixels, from the terminal's window size.
+pub fn cell_pixel_size() -> Option<(u32, u32)> {
+    platform::cell_pixel_size()
+}
+
+/// Ask the terminal whether it can display sixel graphics.
+///
+/// Terminals list their features in the primary device attributes reply
+/// (`ESC [ ? 62 ; 4 ; ... c`), where attribute 4 means sixel support.
+pub fn supports_sixel(timeout: Duration) -> bool {
+    query_tty("\x1b[c", timeout)
+        .map(|reply| device_attributes(&reply).contains(&4))
+        .unwrap_or(false)
+}
+
+/// Ask the terminal whether it implements the kitty graphics protocol.
+///
+/// Sends a query-only image command (`a=q`), which supporting terminals
+/// acknowledge with `OK` without drawing anything.
+pub fn supports_kitty_graphics(timeout: Duration) -> bool {
+    query_tty("\x1b_Gi=31,s=1,v=1,a=q,t=d,f=24;AAAA\x1b\\\x1b[c", timeout)
+        .is_some_and(|reply| kitty_reply_ok(&reply))
+}
+
+fn kitty_reply_ok(reply: &[u8]) -> bool {
+    String::from_utf8_lossy(reply).contains("\x1b_Gi=31;OK")
+}
+
+/// Whether the program is running inside iTerm2, which shows inline images.
+///
+/// iTerm2 exports `TERM_PROGRAM=iTerm.app`; there is no escape to query.
+pub fn is_iterm2() -> bool {
+    term_program_is_iterm2(env::var_os("TERM_PROGRAM").as_deref())
+}
+
+fn term_program_is_iterm2(term_program: Option<&OsStr>) -> bool {
+    term_program.is_some_and(|program| program == "iTerm.app")
+}
+
+/// Parse the attribute list from a primary device attributes reply.
+fn device_attributes(reply: &[u8]) -> Vec<u32> {
+    let text = String::from_utf8_lossy(reply);
+    let Some(start) = text.rfind("\x1b[?") else {
+        return Vec::new();
+    };
+    let body = &text[start + 3..];
+    let Some(end) = body.find('c') else {
+        return Vec::new();
+    };
+    body[..end]
+        .split(';')
+        .filter_map(|value| value.parse().ok())
+        .collect()
+}
+
+/// Send a query to the outer terminal, through tmux when needed.
+fn query_tty(request: &str, timeout: Duration) -> Option<Vec<u8>> {
+    let request = detect_passthrough().wrap(request)?;
+    platform::query_tty(request.as_bytes(), timeout)
+}
+
+/// How escape sequences reach the real terminal from this process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Passthrough {
+    /// Not inside a multiplexer: sequences are written as-is.
+    Direct,
+    /// Inside tmux with passthrough allowed: sequences need a DCS envelope.
+    Tmux,
+    /// Inside tmux with `allow-passthrough` off: sequences would be dropped.
+    Blocked,
+}
+
+impl Passthrough {
+    /// Prepare `sequence` for the outer terminal, or `None` when it cannot get there.
+    pub fn wrap(self, sequence: &str) -> Option<String> {
+        match self {
+            Passthrough::Direct => Some(sequence.to_string()),
+            Passthrough::Tmux => Some(tmux_wrap(sequence)),
+            Passthrough::Blocked => None,
+        }
+    }
+}
+
+/// Detect tmux from `$TMUX` and check whether it lets sequences through.
+pub fn detect_passthrough() -> Passthrough {
+    let inside_tmux = env::var_os("TMUX").is_some_and(|value| !value.is_empty());
+    if !inside_tmux {
+        return Passthrough::Direct;
+    }
+
+    // The pane's own value, or the window's or global one it inherits
+    let mut show = Command::new("tmux");
+    show.args(["show-options", "-Apqv"]);
+    if let Some(pane) = env::var_os("TMUX_PANE") {
+        show.arg("-t").arg(pane);
+    }
+    let setting = show
+        .arg("allow-passthrough")
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string());
+    passthrough_from_setting(setting.as_deref())
+}
+
+fn passthrough_from_setting(setting: Option<&str>) -> Passthrough {
+    match setting {
+        Some("on" | "all") => Passthrough::Tmux,
+        // tmux before 3.3 has no such option and always passes sequences through
+        Some("") | None => Passthrough::Tmux,
+        Some(_) => Passthrough::Blocked,
+    }
+}
+
+/// Wrap a sequence in tmux's DCS passthrough envelope, doubling inner escapes.
+pub fn tmux_wrap(sequence: &str) -> String {
+    let mut wrapped = String::with_capacity(sequence.len() + 16);
+    wrapped.push_str("\x1bPtmux;");
+    for ch in sequence.chars() {
+        if ch == '\x1b' {
+            wrapped.push('\x1b');
+        }
+        wrapped.push(ch);
+    }
+    wrapped.push_str("\x1b\\");
+    wrapped
+}
+
+/// Detect whether the terminal background is light or dark.
+///
+/// Checks `COLORFGBG` first, then asks the terminal for its background color
+/// with OSC 11. Returns `None` when neither gives an answer within `timeout`.
+pub fn detect_background(timeout: Duration) -> Option<Background> {
+    if let Some(background) = env::var("COLORFGBG")
+        .ok()
+        .and_then(|value| background_from_colorfgbg(&value))
+    {
+        return Some(background);
+    }
+
+    query_background_color(timeout).map(background_of)
+}
+
+/// Ask the terminal for its exact background color with OSC 11.
+///
+/// Returns `None` when the terminal does not answer within `timeout`.
+pub fn query_background_color(timeout: Duration) -> Option<[u8; 3]> {
+    let reply = query_tty("\x1b]11;?\x07\x1b[c", timeout)?;
+    parse_background_reply(&reply)
+}
+
+/// Classify a background color by its brightness.
+pub fn background_of(rgb: [u8; 3]) -> Background {
+    if luma(rgb) >= 128.0 {
+        Background::Light
+    } else {
+        Background::Dark
+    }
+}
+
+/// Interpret `COLORFGBG` (`fg;bg` or `fg;default;bg`) using ANSI color indices.
+fn background_from_colorfgbg(value: &str) -> Option<Background> {
+    let index: u8 = value.rsplit(';').next()?.parse().ok()?;
+    match index {
+        7 | 9..=15 => Some(Background::Light),
+        0..=6 | 8 => Some(Background::Dark),
+        _ => None,
+    }
+}
+
+/// Extract the color from an `OSC 11 ; rgb:R/G/B` reply.
+fn parse_background_reply(reply: &[u8]) -> Option<[u8; 3]> {
+    let text = String::from_utf8_lossy(reply);
+    let spec = text.split("\x1b]11;").nth(1)?;
+    parse_rgb_spec(spec.split(['\x07', '\x1b']).next()?)
+}
+
+/// Extract the colors from a sequence of `OSC 4 ; index ; rgb:R/G/B` replies.
+fn parse_palette_reply(reply: &[u8]) -> Option<Palette> {
+    let text = String::from_utf8_lossy(reply);
+    let mut colors = [None; 16];
+
+    for entry in text.split("\x1b]4;").skip(1) {
+        let entry = entry.split(['\x07', '\x1b']).next().unwrap_or_default();
+        let Some((index, spec)) = entry.split_once(';') else {
+            continue;
+        };
+        let (Ok(index), Some(rgb)) = (index.parse::<usize>(), parse_rgb_spec(spec)) else {
+            continue;
+        };
+        if let Some(slot) = colors.get_mut(index) {
+            *slot = Some(rgb);
+        }
+    }
+
+    let mut palette = [[0; 3]; 16];
+    for (slot, color) in palette.iter_mut().zip(colors) {
+        *slot = color?;
+    }
+    Some(Palette::new(palette))
+}
+
+/// Parse an X11 color spec of the form `rgb:RRRR/GGGG/BBBB` (1-4 hex digits each).
+fn parse_rgb_spec(spec: &str) -> Option<[u8; 3]> {
+    let mut channels = spec.strip_prefix("rgb:")?.split('/');
+    let mut rgb = [0u8; 3];
+    for channel in rgb.iter_mut() {
+        let hex = channels.next()?;
+        if hex.is_empty() || hex.len() > 4 {
+            return None;
+        }
+        let value = u32::from_str_radix(hex, 16).ok()?;
+        let max = (1u32 << (4 * hex.len())) - 1;
+        *channel = ((value * 255 + max / 2) / max) as u8;
+    }
+    channels.next().is_none().then_some(rgb)
+}
+
+/// Make sure the console interprets ANSI escape sequences.
+///
+/// On Windows this enables virtual terminal processing for stdout, which legacy
+/// consoles leave off. Returns false when escapes would be shown as raw text, so
+/// callers can fall back to monochrome output. Other platforms always succeed.
+pub fn enable_virtual_terminal() -> bool {
+    platform::enable_virtual_terminal()
+}
+
+/// Set by Ctrl-C once [`catch_interrupt`] has installed its handler
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Catch Ctrl-C instead of letting it kill the process, and return the flag
+/// it sets
+///
+/// Long-running output such as animation playback checks the flag, so it can
+/// stop cleanly and restore the cursor and colors. Without a handler (on
+/// platforms other than Unix and Windows), the flag is never set.
+pub fn catch_interrupt() -> &'static AtomicBool {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(platform::catch_interrupt);
+    &INTERRUPTED
+}
+
+/// Keyboard input from the controlling terminal, a key at a time and
+/// without echo, until dropped.
+///
+/// Ctrl-C still interrupts; use [`catch_interrupt`] to notice it instead.
+pub struct KeyInput {
+    raw: platform::RawMode,
+}
+
+impl KeyInput {
+    /// Take input from the terminal, or `None` without one, or on platforms
+    /// other than Unix.
+    pub fn open() -> Option<Self> {
+        platform::RawMode::open().map(|raw| Self { raw })
+    }
+
+    /// Read the bytes of the keys pressed, or nothing after about 100ms
+    /// without input.
+    pub fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        self.raw.read(buffer)
+    }
+}
+
+/// Raw mode where it is not implemented, which can never be opened.
+#[cfg(not(unix))]
+struct UnsupportedRawMode(std::convert::Infallible);
+
+#[cfg(not(unix))]
+impl UnsupportedRawMode {
+    fn open() -> Option<Self> {
+        None
+    }
+
+    fn read(&self, _buffer: &mut [u8]) -> io::Result<usize> {
+        match self.0 {}
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::sync::atomic::Ordering;
+    use std::time::Duration;
+
+    use windows_sys::Win32::Foundation::{BOOL, INVALID_HANDLE_VALUE};
+    use windows_sys::Win32::System::Console::{
+        GetConsoleMode, GetStdHandle, SetConsoleCtrlHandler, SetConsoleMode, CONSOLE_MODE,
+        CTRL_C_EVENT, ENABLE_VIRTUAL_TERMINAL_PROCESSING, STD_OUTPUT_HANDLE,
+    };
+
+    unsafe extern "system" fn on_ctrl(ctrl_type: u32) -> BOOL {
+        if ctrl_type != CTRL_C_EVENT {
+            return 0;
+        }
+        super::INTERRUPTED.store(true, Ordering::Relaxed);
+        1
+    }
+
+    pub fn catch_interrupt() {
+        // SAFETY: the handler only stores to an atomic
+        unsafe {
+            SetConsoleCtrlHandler(Some(on_ctrl), 1);
+        }
+    }
+
+    pub fn enable_virtual_terminal() -> bool {
+        // SAFETY: plain Win32 calls on the process's own stdout handle
+        unsafe {
+            let handle = GetStdHandle(STD_OUTPUT_HANDLE);
+            if handle == INVALID_HANDLE_VALUE || handle.is_null() {
+                return false;
+            }
+
+            let mut mode: CONSOLE_MODE = 0;
+            if GetConsoleMode(handle, &mut mode) == 0 {
+                // Not a console (redirected to a file or pipe): escapes pass through untouched
+                return true;
+            }
+            if mode & ENABLE_VIRTUAL_TERMINAL_PROCESSING != 0 {
+                return true;
+            }
+            SetConsoleMode(handle, mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING) != 0
+        }
+    }
+
+    /// Console input would need separate handling; queries are not attempted.
+    pub fn query_tty(_request: &[u8], _timeout: Duration) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// The console API only reports sizes in cells.
+    pub fn cell_pixel_size() -> Option<(u32, u32)> {
+        None
+    }
+
+    // Keys would need the console input API; they are not read
+    pub(super) use super::UnsupportedRawMode as RawMode;
+}
+
+#[cfg(not(windows))]
+mod platform {
+    pub fn enable_virtual_terminal() -> bool {
+        true
+    }
+
+    #[cfg(unix)]
+    pub use super::unix::{catch_interrupt, cell_pixel_size, query_tty, RawMode};
+
+    #[cfg(not(unix))]
+    pub(super) use super::UnsupportedRawMode as RawMode;
+
+    #[cfg(not(unix))]
+    pub fn catch_interrupt() {}
+
+    #[cfg(not(unix))]
+    pub fn query_tty(_request: &[u8], _timeout: std::time::Duration) -> Option<Vec<u8>> {
+        None
+    }
+
+    #[cfg(not(unix))]
+    pub fn cell_pixel_size() -> Option<(u32, u32)> {
+        None
+    }
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::fs::{File, OpenOptions};
+    use std::io::{Read, Write};
+    use std::mem::MaybeUninit;
+    use std::os::unix::io::AsRawFd;
+    use std::sync::atomic::Ordering;
+    use std::time::{Duration, Instant};
+
+    extern "C" fn on_interrupt(_signal: libc::c_int) {
+        super::INTERRUPTED.store(true, Ordering::Relaxed);
+    }
+
+    pub fn catch_interrupt() {
+        // SAFETY: the handler only stores to an atomic, which is async-signal-safe
+        unsafe {
+            libc::signal(
+                libc::SIGINT,
+                on_interrupt as *const () as libc::sighandler_t,
+            );
+        }
+    }
+
+    /// The controlling terminal, in non-canonical, no-echo mode until dropped.
+    pub struct RawMode {
+        tty: File,
+        saved: libc::termios,
+    }
+
+    impl RawMode {
+        pub fn open() -> Option<Self> {
+            let tty = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open("/dev/tty")
+                .ok()?;
+            let fd = tty.as_raw_fd();
+            let mut termios = MaybeUninit::<libc::termios>::uninit();
+            // SAFETY: tcgetattr fully initializes `termios` when it returns 0
+            let saved = unsafe {
+                if libc::tcgetattr(fd, termios.as_mut_ptr()) != 0 {
+                    return None;
+                }
+                termios.assume_init()
+            };
+
+            let mut raw = saved;
+            raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+            // Return from read() after at most 100ms without input
+            raw.c_cc[libc::VMIN] = 0;
+            raw.c_cc[libc::VTIME] = 1;
+            // SAFETY: `raw` is a valid termios derived from the current settings
+            if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &raw) } != 0 {
+                return None;
+            }
+            Some(Self { tty, saved })
+        }
+
+        /// Read what has been typed, or nothing after 100ms without input.
+        pub fn read(&self, buffer: &mut [u8]) -> std::io::Result<usize> {
+            (&self.tty).read(buffer)
+        }
+    }
+
+    impl Drop for RawMode {
+        fn drop(&mut self) {
+            // SAFETY: restores the settings read in `open`
+            unsafe {
+                libc::tcsetattr(self.tty.as_raw_fd(), libc::TCSANOW, &self.saved);
+            }
+        }
+    }
+
+    /// Write `request` to the controlling terminal and collect the reply.
+    ///
+    /// Reading stops at the reply to a trailing device attributes query
+    /// (`ESC [ ? ... c`) or once `timeout` has passed.
+    pub fn query_tty(request: &[u8], timeout: Duration) -> Option<Vec<u8>> {
+        let raw = RawMode::open()?;
+        let mut tty = &raw.tty;
+        tty.write_all(request).ok()?;
+        tty.flush().ok()?;
+
+        let deadline = Instant::now() + timeout;
+        let mut reply = Vec::new();
+        let mut buffer = [0u8; 256];
+        while Instant::now() < deadline && !ends_with_device_attributes(&reply) {
+            match raw.read(&mut buffer) {
+                Ok(read) => reply.extend_from_slice(&buffer[..read]),
+                Err(_) => break,
+            }
+        }
+        Some(reply)
+    }
+
+    /// Pixel size of a cell, from the window size reported for stdout.
+    pub fn cell_pixel_size() -> Option<(u32, u32)> {
+        let mut size = MaybeUninit::<libc::winsize>::zeroed();
+        // SAFETY: TIOCGWINSZ fills a winsize struct for a terminal file descriptor
+        let size = unsafe {
+            if libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, size.as_mut_ptr()) != 0 {
+                return None;
+            }
+            size.assume_init()
+        };
+        if size.ws_col == 0 || size.ws_row == 0 || size.ws_xpixel == 0 || size.ws_ypixel == 0 {
+            return None;
+        }
+        Some((
+            u32::from(size.ws_xpixel / size.ws_col),
+            u32::from(size.ws_ypixel / size.ws_row),
+        ))
+    }
+
+    fn ends_with_device_attributes(reply: &[u8]) -> bool {
+        reply.ends_with(b"c")
+            && reply
+                .windows(3)
+                .rposition(|window| window == b"\x1b[?")
+                .is_some_and(|start| {
+                    reply[start + 3..reply.len() - 1]
+                        .iter()
+                        .all(|&byte| byte.is_ascii_digit() || byte == b';')
+                })
+    }
+}
+
+/// Whether to emit ANSI colors, as chosen with `--color`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum ColorChoice {
+    /// Color only when stdout is a terminal and `NO_COLOR` is not set.
+    #[default]
+    Auto,
+    /// Color even when redirected.
+    Always,
+    /// Plain text.
+    Never,
+    /// Shades of gray by luminance, even when redirected.
+    Gray,
+}
+
+impl FromStr for ColorChoice {
+    type Err = AsciiArtError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            "gray" => Ok(ColorChoice::Gray),
+            unknown => Err(AsciiArtError::InvalidArgument(format!(
+                "Unknown color choice '{}'. Use 'auto', 'always', 'never', or 'gray'.",
+                unknown
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for ColorChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ColorChoice::Auto => write!(f, "auto"),
+            ColorChoice::Always => write!(f, "always"),
+            ColorChoice::Never => write!(f, "never"),
+            ColorChoice::Gray => write!(f, "gray"),
+        }
+    }
+}
+
+/// What `--hyperlink` makes clickable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Hyperlink {
+    /// The art itself.
+    #[default]
+    Art,
+    /// A line under the art naming the source image.
+    Caption,
+}
+
+impl FromStr for Hyperlink {
+    type Err = AsciiArtError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "art" => Ok(Hyperlink::Art),
+            "caption" => Ok(Hyperlink::Caption),
+            unknown => Err(AsciiArtError::InvalidArgument(format!(
+                "Unknown hyperlink target '{}'. Use 'art' or 'caption'.",
+                unknown
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Hyperlink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Hyperlink::Art => write!(f, "art"),
+            Hyperlink::Caption => write!(f, "caption"),
+        }
+    }
+}
+
+/// Sequence that ends an OSC 8 hyperlink.
+pub const HYPERLINK_END: &str = "\x1b]8;;\x1b\\";
+
+/// Sequence that starts an OSC 8 hyperlink to `url`; text up to
+/// [`HYPERLINK_END`] is clickable.
+pub fn hyperlink_start(url: &str) -> String {
+    format!("\x1b]8;;{url}\x1b\\")
+}
+
+/// Whether OSC 8 hyperlinks should be written to the output.
+///
+/// Terminals that do not know the sequence may print it, so links are only
+/// written to a terminal known to support them, and never when redirected.
+/// `FORCE_HYPERLINK=1` or `FORCE_HYPERLINK=0` settles it either way, as for
+/// other tools that emit links.
+pub fn hyperlinks_enabled(to_stdout: bool) -> bool {
+    decide_hyperlinks(
+        |name| env::var(name).ok(),
+        to_stdout && stdout_is_terminal(),
+    )
+}
+
+fn decide_hyperlinks(var: impl Fn(&str) -> Option<String>, to_terminal: bool) -> bool {
+    if let Some(force) = var("FORCE_HYPERLINK").filter(|value| !value.is_empty()) {
+        return force != "0";
+    }
+    if !to_terminal {
+        return false;
+    }
+    // Multiplexers only forward links when configured to, which can't be seen from here
+    if var("TMUX").is_some_and(|value| !value.is_empty()) {
+        return false;
+    }
+    let term = var("TERM").unwrap_or_default();
+    if term.starts_with("screen") || term_is_dumb(Some(OsStr::new(&term))) {
+        return false;
+    }
+    if [
+        "xterm-kitty",
+        "foot",
+        "alacritty",
+        "wezterm",
+        "xterm-ghostty",
+    ]
+    .iter()
+    .any(|prefix| term.starts_with(prefix))
+    {
+        return true;
+    }
+    if matches!(
+        var("TERM_PROGRAM").as_deref(),
+        Some("iTerm.app" | "WezTerm" | "vscode" | "ghostty")
+    ) {
+        return true;
+    }
+    // VTE, behind GNOME Terminal and others, has links from 0.50
+    if var("VTE_VERSION").and_then(|version| version.parse::<u32>().ok()) >= Some(5000) {
+        return true;
+    }
+    ["WT_SESSION", "KONSOLE_VERSION", "DOMTERM"]
+        .iter()
+        .any(|name| var(name).is_some())
+}
+
+/// A `file://` URL for `path`, made absolute, or `None` if it does not exist.
+pub fn file_url(path: &Path) -> Option<String> {
+    let path = fs::canonicalize(path).ok()?;
+    let path = path.to_string_lossy();
+    // Windows paths come back as `\\?\C:\dir`
+    let path = path
+        .strip_prefix(r"\\?\")
+        .unwrap_or(&path)
+        .replace('\\', "/");
+    let mut url = String::from("file://");
+    if !path.starts_with('/') {
+        url.push('/');
+    }
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' | b':' => {
+                url.push(byte as char)
+            }
+            byte => url.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    Some(url)
+}
+
+/// Whether `--title` can set the title: stdout must be a terminal that
+/// interprets escapes.
+pub fn titles_enabled() -> bool {
+    stdout_is_terminal() && !is_dumb_terminal()
+}
+
+/// Sets the terminal title on stdout while it lives, and puts the previous
+/// title back when dropped.
+///
+/// The previous title is kept on the title stack that xterm, VTE, kitty,
+/// WezTerm, and Windows Terminal share; a terminal without the stack keeps
+/// the last title set. A disabled guard writes nothing.
+#[derive(Debug)]
+pub struct TitleGuard {
+    enabled: bool,
+}
+
+impl TitleGuard {
+    /// Save the current title, if `enabled`.
+    pub fn new(enabled: bool) -> Self {
+        let guard = Self { enabled };
+        guard.write(SAVE_TITLE);
+        guard
+    }
+
+    pub fn set(&self, title: &str) {
+        self.write(&title_sequence(title));
+    }
+
+    fn write(&self, sequence: &str) {
+        if self.enabled {
+            let mut stdout = io::stdout().lock();
+            // Titles are cosmetic; a closed stdout shows up when the art is written
+            let _ = stdout
+                .write_all(sequence.as_bytes())
+                .and_then(|()| stdout.flush());
+        }
+    }
+}
+
+impl Drop for TitleGuard {
+    fn drop(&mut self) {
+        self.write(RESTORE_TITLE);
+    }
+}
+
+/// When to show the art in a pager, as chosen with `--pager`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum PagerChoice {
+    /// Only output taller than the terminal, when stdout is one.
+    Auto,
+    /// Any output, when stdout is a terminal.
+    Always,
+    /// Write straight to stdout.
+    #[default]
+    Never,
+}
+
+impl FromStr for PagerChoice {
+    type Err = AsciiArtError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "auto" => Ok(PagerChoice::Auto),
+            "always" => Ok(PagerChoice::Always),
+            "never" => Ok(PagerChoice::Never),
+            unknown => Err(AsciiArtError::InvalidArgument(format!(
+                "Unknown pager choice '{}'. Use 'auto', 'always', or 'never'.",
+                unknown
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for PagerChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PagerChoice::Auto => write!(f, "auto"),
+            PagerChoice::Always => write!(f, "always"),
+            PagerChoice::Never => write!(f, "never"),
+        }
+    }
+}
+
+/// The pager to run: `$PAGER` split on whitespace, or `less -R` when it is
+/// unset or empty.
+fn pager_command(pager: Option<String>) -> Vec<String> {
+    let command: Vec<String> = pager
+        .iter()
+        .flat_map(|pager| pager.split_whitespace())
+        .map(str::to_string)
+        .collect();
+    if command.is_empty() {
+        vec!["less".to_string(), "-R".to_string()]
+    } else {
+        command
+    }
+}
+
+/// Stdout, or a pager reading what would have gone there when
+/// [`PagerChoice`] calls for one.
+///
+/// In `auto` mode output is held back until it either ends, and goes to
+/// stdout, or fills the terminal, and the pager is started with everything
+/// so far. Dropping the output writes out what is still held back, or waits
+/// for the pager to exit; whatever is written after the pager has been quit
+/// is discarded.
+#[derive(Debug)]
+pub struct PagedStdout {
+    state: PagerState,
+}
+
+#[derive(Debug)]
+enum PagerState {
+    /// Output so far and the lines it ends, until they reach `rows`.
+    Holding {
+        held: Vec<u8>,
+        lines: usize,
+        rows: usize,
+    },
+    Stdout,
+    /// A running pager, with its input until it stops reading.
+    Pager {
+        child: Child,
+        input: Option<ChildStdin>,
+    },
+}
+
+impl PagedStdout {
+    pub fn new(choice: PagerChoice) -> Self {
+        let rows = match choice {
+            _ if !stdout_is_terminal() => None,
+            PagerChoice::Never => None,
+            PagerChoice::Always => Some(0),
+            PagerChoice::Auto => get_terminal_size().map(|(_, rows)| rows as usize),
+        };
+        let state = match rows {
+            Some(rows) => PagerState::Holding {
+                held: Vec::new(),
+                lines: 0,
+                rows,
+            },
+            None => PagerState::Stdout,
+        };
+        Self { state }
+    }
+
+    /// Start the pager with the output held so far, or write it to stdout
+    /// if the pager cannot be run.
+    fn start_pager(&mut self) -> io::Result<()> {
+        let PagerState::Holding { held, .. } = &mut self.state else {
+            return Ok(());
+        };
+        let held = std::mem::take(held);
+        let command = pager_command(env::var("PAGER").ok());
+        let mut pager = Command::new(&command[0]);
+        pager.args(&command[1..]).stdin(Stdio::piped());
+        if env::var_os("LESS").is_none() {
+            // As git does: keep colors, skip the pager for output that fits,
+            // and leave the art on screen after quitting
+            pager.env("LESS", "FRX");
+        }
+        match pager.spawn() {
+            Ok(mut child) => {
+                // Ctrl-C is for the pager; stopping here would cut its input short
+                catch_interrupt();
+                let input = child.stdin.take();
+                self.state = PagerState::Pager { child, input };
+                self.write_all(&held)
+            }
+            Err(_) => {
+                self.state = PagerState::Stdout;
+                self.write_all(&held)
+            }
+        }
+    }
+}
+
+impl Write for PagedStdout {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.state {
+            PagerState::Holding { held, lines, rows } => {
+                held.extend_from_slice(buf);
+                *lines += buf.iter().filter(|&&byte| byte == b'\n').count();
+                if *lines >= *rows {
+                    self.start_pager()?;
+                }
+            }
+            PagerState::Stdout => return io::stdout().write(buf),
+            PagerState::Pager { input, .. } => {
+                let quit = match input {
+                    Some(pipe) => match pipe.write_all(buf) {
+                        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => true,
+                        result => result.map(|()| false)?,
+                    },
+                    None => false,
+                };
+                if quit {
+                    *input = None;
+                }
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.state {
+            // Held back until the output ends or fills the terminal
+            PagerState::Holding { .. } => Ok(()),
+            PagerState::Stdout => io::stdout().flush(),
+            PagerState::Pager { input, .. } => match input.as_mut().map(Write::flush) {
+                Some(Err(err)) if err.kind() != io::ErrorKind::BrokenPipe => Err(err),
+                _ => Ok(()),
+            },
+        }
+    }
+}
+
+impl Drop for PagedStdout {
+    fn drop(&mut self) {
+        match &mut self.state {
+            PagerState::Holding { held, .. } => {
+                let mut stdout = io::stdout().lock();
+                // A terminal that stopped taking output has nowhere to show the error
+                let _ = stdout.write_all(held).and_then(|()| stdout.flush());
+            }
+            PagerState::Stdout => {}
+            PagerState::Pager { child, input } => {
+                // Closing the pipe tells the pager the output is complete
+                *input = None;
+                let _ = child.wait();
+            }
+        }
+    }
+}
+
+/// Return true when stdout is attached to a terminal rather than a pipe or file.
+pub fn stdout_is_terminal() -> bool {
+    io::stdout().is_terminal()
+}
+
+/// Decide whether output should be colored, honoring `NO_COLOR` and piping in auto mode.
+pub fn color_enabled(choice: ColorChoice) -> bool {
+    decide_color(
+        choice,
+        env::var_os("NO_COLOR").as_deref(),
+        stdout_is_terminal(),
+    )
+}
+
+fn decide_color(choice: ColorChoice, no_color: Option<&OsStr>, stdout_is_tty: bool) -> bool {
+    match choice {
+        ColorChoice::Always | ColorChoice::Gray => true,
+        ColorChoice::Never => false,
+        // https://no-color.org: any non-empty value disables color
+        ColorChoice::Auto => {
+            stdout_is_tty
+                && match no_color {
+                    Some(value) => value.is_empty(),
+                    None => true,
+                }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn test_user_width_override() {
-        let resolution = compute_output_width(Some(120), Some(100));
+        let resolution = compute_output_width(Some(Extent::Cells(120)), Some(100));
         assert_eq!(resolution.width, 120);
         assert_eq!(resolution.source, WidthSource::User);
     }
 
+    #[test]
+    fn test_percentages_are_of_the_terminal() {
+        let resolution = compute_output_width(Some(Extent::Percent(75.0)), Some(200));
+        assert_eq!(resolution.width, 150);
+        assert_eq!(resolution.source, WidthSource::AutoDetected);
+        let fallback = compute_output_width(Some(Extent::Percent(50.0)), None);
+        assert_eq!(
+            (fallback.width, fallback.source),
+            (40, WidthSource::Fallback)
+        );
+
+        assert_eq!(compute_output_height(Extent::Percent(50.0), Some(41)), 20);
+        assert_eq!(compute_output_height(Extent::Percent(50.0), None), 12);
+        assert_eq!(compute_output_height(Extent::Cells(30), Some(41)), 30);
+    }
+
     #[test]
     fn test_minimum_width_enforced() {
         let resolution = compute_output_width(None, Some(30));
@@ -76,4 +1241,230 @@ mod tests {
         assert_eq!(fallback.width, 80);
         assert_eq!(fallback.source, WidthSource::Fallback);
     }
+
+    #[test]
+    fn test_fixed_width_ignores_terminal() {
+        let fixed = fixed_output_width(None);
+        assert_eq!(fixed.width, 80);
+        assert_eq!(fixed.source, WidthSource::Fixed);
+        assert_eq!(
+            fixed_output_width(Some(Extent::Cells(33))).source,
+            WidthSource::User
+        );
+    }
+
+    #[test]
+    fn test_no_color_only_affects_auto() {
+        let set = Some(OsStr::new("1"));
+        assert!(!decide_color(ColorChoice::Auto, set, true));
+        assert!(decide_color(ColorChoice::Auto, Some(OsStr::new("")), true));
+        assert!(decide_color(ColorChoice::Auto, None, true));
+        assert!(decide_color(ColorChoice::Always, set, true));
+        assert!(decide_color(ColorChoice::Gray, set, false));
+        assert!(!decide_color(ColorChoice::Never, None, true));
+    }
+
+    #[test]
+    fn test_hyperlinks_need_a_known_terminal_unless_forced() {
+        let decide = |vars: &[(&str, &str)], to_terminal| {
+            let var = |name: &str| {
+                vars.iter()
+                    .find(|(key, _)| *key == name)
+                    .map(|(_, value)| value.to_string())
+            };
+            decide_hyperlinks(var, to_terminal)
+        };
+        assert!(decide(&[("TERM", "xterm-kitty")], true));
+        assert!(decide(
+            &[("TERM", "xterm-256color"), ("VTE_VERSION", "6800")],
+            true
+        ));
+        assert!(!decide(
+            &[("TERM", "xterm-256color"), ("VTE_VERSION", "4601")],
+            true
+        ));
+        assert!(!decide(&[("TERM", "xterm-256color")], true));
+        assert!(!decide(&[("TERM_PROGRAM", "iTerm.app")], false));
+        assert!(!decide(
+            &[("TERM", "xterm-kitty"), ("TMUX", "/tmp/tmux-0/default")],
+            true
+        ));
+        assert!(decide(&[("FORCE_HYPERLINK", "1")], false));
+        assert!(!decide(&[("TERM", "foot"), ("FORCE_HYPERLINK", "0")], true));
+    }
+
+    #[test]
+    fn test_file_urls_are_absolute_and_escaped() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("my cat%.png");
+        std::fs::write(&path, b"").expect("write");
+        let url = file_url(&path).expect("url");
+        assert!(url.starts_with("file:///"), "{url}");
+        assert!(url.ends_with("/my%20cat%25.png"), "{url}");
+        assert_eq!(file_url(&dir.path().join("missing.png")), None);
+    }
+
+    #[test]
+    fn test_pager_defaults_to_less_keeping_colors() {
+        assert_eq!(pager_command(None), ["less", "-R"]);
+        assert_eq!(pager_command(Some(" ".to_string())), ["less", "-R"]);
+        assert_eq!(pager_command(Some("most -s".to_string())), ["most", "-s"]);
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn test_virtual_terminal_is_always_available() {
+        assert!(enable_virtual_terminal());
+    }
+
+    #[test]
+    fn test_dumb_terminal_detection() {
+        assert!(term_is_dumb(Some(OsStr::new("dumb"))));
+        assert!(term_is_dumb(Some(OsStr::new(""))));
+        assert!(!term_is_dumb(Some(OsStr::new("xterm-256color"))));
+        assert_eq!(term_is_dumb(None), !cfg!(windows));
+    }
+
+    #[test]
+    fn test_color_capability_prefers_explicit_hints() {
+        use ColorCapability::*;
+        assert_eq!(
+            classify_color_capability(Some("truecolor"), Some("xterm"), None),
+            TrueColor
+        );
+        assert_eq!(
+            classify_color_capability(None, Some("xterm-256color"), None),
+            Ansi256
+        );
+        assert_eq!(
+            classify_color_capability(None, Some("xterm-direct"), None),
+            TrueColor
+        );
+        assert_eq!(
+            classify_color_capability(None, Some("xterm"), Some(256)),
+            Ansi256
+        );
+        assert_eq!(
+            classify_color_capability(None, Some("xterm"), Some(8)),
+            Ansi16
+        );
+        assert_eq!(classify_color_capability(None, None, None), Ansi16);
+    }
+
+    #[test]
+    fn test_parse_terminfo_colors() {
+        // Header: magic, 3 name bytes, 1 boolean, 14 numbers, no strings
+        let mut data = Vec::new();
+        for value in [0o432u16, 3, 1, 14, 0, 0] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend_from_slice(b"xt\0");
+        data.push(1);
+        for index in 0..14u16 {
+            let value: u16 = if index == 13 { 256 } else { 0xffff };
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+
+        assert_eq!(parse_terminfo_colors(&data), Some(256));
+        assert_eq!(parse_terminfo_colors(&data[..20]), None);
+        assert_eq!(parse_terminfo_colors(b"not terminfo"), None);
+    }
+
+    #[test]
+    fn test_parse_palette_reply() {
+        let mut reply = String::new();
+        for index in 0..16 {
+            let terminator = if index % 2 == 0 { "\x07" } else { "\x1b\\" };
+            reply.push_str(&format!(
+                "\x1b]4;{index};rgb:{index:x}{index:x}/0000/ffff{terminator}"
+            ));
+        }
+        reply.push_str("\x1b[?62;22c");
+
+        let palette = parse_palette_reply(reply.as_bytes()).expect("full palette");
+        assert_eq!(palette.colors()[0], [0, 0, 255]);
+        assert_eq!(palette.colors()[15], [255, 0, 255]);
+    }
+
+    #[test]
+    fn test_partial_palette_reply_is_ignored() {
+        let reply = b"\x1b]4;0;rgb:00/00/00\x07\x1b[?1;2c";
+        assert_eq!(parse_palette_reply(reply), None);
+        assert_eq!(parse_palette_reply(b""), None);
+    }
+
+    #[test]
+    fn test_background_detection_sources() {
+        assert_eq!(background_from_colorfgbg("0;15"), Some(Background::Light));
+        assert_eq!(
+            background_from_colorfgbg("15;default;0"),
+            Some(Background::Dark)
+        );
+        assert_eq!(background_from_colorfgbg("garbage"), None);
+
+        let light = b"\x1b]11;rgb:fdfd/f6f6/e3e3\x1b\\\x1b[?62c";
+        assert_eq!(
+            parse_background_reply(light).map(background_of),
+            Some(Background::Light)
+        );
+        let dark = b"\x1b]11;rgb:0000/2b2b/3636\x07";
+        assert_eq!(parse_background_reply(dark), Some([0, 0x2b, 0x36]));
+        assert_eq!(
+            parse_background_reply(dark).map(background_of),
+            Some(Background::Dark)
+        );
+        assert_eq!(parse_background_reply(b"\x1b[?62c"), None);
+    }
+
+    #[test]
+    fn test_tmux_wrap_doubles_escapes() {
+        assert_eq!(
+            tmux_wrap("\x1b]11;?\x07"),
+            "\x1bPtmux;\x1b\x1b]11;?\x07\x1b\\"
+        );
+        assert_eq!(Passthrough::Direct.wrap("x").as_deref(), Some("x"));
+        assert_eq!(Passthrough::Blocked.wrap("x"), None);
+    }
+
+    #[test]
+    fn test_passthrough_setting() {
+        assert_eq!(passthrough_from_setting(Some("on")), Passthrough::Tmux);
+        assert_eq!(passthrough_from_setting(Some("all")), Passthrough::Tmux);
+        assert_eq!(passthrough_from_setting(Some("off")), Passthrough::Blocked);
+        assert_eq!(passthrough_from_setting(None), Passthrough::Tmux);
+    }
+
+    #[test]
+    fn test_device_attributes_reply() {
+        assert_eq!(device_attributes(b"\x1b[?62;4;22c"), vec![62, 4, 22]);
+        assert_eq!(device_attributes(b"\x1b[?1;2c"), vec![1, 2]);
+        assert!(device_attributes(b"").is_empty());
+    }
+
+    #[test]
+    fn test_kitty_reply_detection() {
+        assert!(kitty_reply_ok(b"\x1b_Gi=31;OK\x1b\\\x1b[?62;c"));
+        assert!(!kitty_reply_ok(b"\x1b[?62;4c"));
+    }
+
+    #[test]
+    fn test_iterm2_detection() {
+        assert!(term_program_is_iterm2(Some(OsStr::new("iTerm.app"))));
+        assert!(!term_program_is_iterm2(Some(OsStr::new("Apple_Terminal"))));
+        assert!(!term_program_is_iterm2(None));
+    }
+
+    #[test]
+    fn test_parse_rgb_spec_scales_channels() {
+        assert_eq!(parse_rgb_spec("rgb:ffff/8080/0000"), Some([255, 128, 0]));
+        assert_eq!(parse_rgb_spec("rgb:f/8/0"), Some([255, 136, 0]));
+        assert_eq!(parse_rgb_spec("rgb:ff/ff"), None);
+        assert_eq!(parse_rgb_spec("#ffffff"), None);
+    }
+
+    #[test]
+    fn test_piped_output_is_plain_unless_forced() {
+        assert!(!decide_color(ColorChoice::Auto, None, false));
+        assert!(decide_color(ColorChoice::Always, None, false));
+    }
 }
@@ -0,0 +1,149 @@
+//! Built-in test images for checking color support, character aspect, and
+//! ramps without an input file
+//!
+//! Patterns are drawn procedurally, so the same size always gives the same
+//! pixels.
+
+use std::fmt;
+use std::str::FromStr;
+
+use image::{DynamicImage, Rgb, RgbImage};
+
+use crate::color::hsl_to_rgb;
+use crate::error::AsciiArtError;
+
+/// Default side of a pattern image, in pixels
+pub const DEFAULT_PATTERN_SIZE: u32 = 512;
+
+/// Largest side `testpattern --size` accepts, far more than any terminal shows
+pub const MAX_PATTERN_SIZE: u32 = 4096;
+
+/// Squares along each side of the checkerboard
+const CHECKERBOARD_SQUARES: u32 = 8;
+
+/// Rings from the center to the edge of the circles pattern
+const CIRCLE_RINGS: f32 = 4.0;
+
+/// Color bars, left to right, in the order of SMPTE bars at full intensity
+const BARS: [[u8; 3]; 8] = [
+    [255, 255, 255],
+    [255, 255, 0],
+    [0, 255, 255],
+    [0, 255, 0],
+    [255, 0, 255],
+    [255, 0, 0],
+    [0, 0, 255],
+    [0, 0, 0],
+];
+
+/// A built-in test image
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Pattern {
+    /// A gray ramp from black to white above a sweep through all hues
+    #[default]
+    Gradient,
+    /// Eight color bars: white, the primaries and secondaries, and black
+    Bars,
+    /// Concentric rings, which look round only with the right --char-aspect
+    Circles,
+    /// Black and white squares, which look square only with the right --char-aspect
+    Checkerboard,
+}
+
+impl FromStr for Pattern {
+    type Err = AsciiArtError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "gradient" => Ok(Pattern::Gradient),
+            "bars" => Ok(Pattern::Bars),
+            "circles" => Ok(Pattern::Circles),
+            "checkerboard" => Ok(Pattern::Checkerboard),
+            unknown => Err(AsciiArtError::InvalidArgument(format!(
+                "Unknown test pattern '{}'. Use 'gradient', 'bars', 'circles', or 'checkerboard'.",
+                unknown
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pattern::Gradient => write!(f, "gradient"),
+            Pattern::Bars => write!(f, "bars"),
+            Pattern::Circles => write!(f, "circles"),
+            Pattern::Checkerboard => write!(f, "checkerboard"),
+        }
+    }
+}
+
+impl Pattern {
+    /// Draw the pattern on a `size` by `size` square
+    pub fn generate(self, size: u32) -> DynamicImage {
+        let size = size.max(1);
+        let fraction = |value: u32| value as f32 / (size - 1).max(1) as f32;
+        let image = RgbImage::from_fn(size, size, |x, y| {
+            Rgb(match self {
+                Pattern::Gradient if y < size / 2 => [(fraction(x) * 255.0).round() as u8; 3],
+                Pattern::Gradient => hsl_to_rgb([fraction(x) * 360.0, 1.0, 0.5]),
+                Pattern::Bars => BARS[(x * BARS.len() as u32 / size) as usize],
+                Pattern::Circles => {
+                    let center = (size - 1) as f32 / 2.0;
+                    let distance = (x as f32 - center).hypot(y as f32 - center);
+                    let ring = (distance / (center.max(1.0) / CIRCLE_RINGS)) as u32;
+                    [if ring.is_multiple_of(2) { 255 } else { 0 }; 3]
+                }
+                Pattern::Checkerboard => {
+                    let square = |value: u32| value * CHECKERBOARD_SQUARES / size;
+                    let white = (square(x) + square(y)).is_multiple_of(2);
+                    [if white { 255 } else { 0 }; 3]
+                }
+            })
+        });
+        DynamicImage::ImageRgb8(image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "cli")]
+    #[test]
+    fn parses_and_displays_every_pattern() {
+        use clap::ValueEnum;
+
+        for pattern in Pattern::value_variants() {
+            assert_eq!(pattern.to_string().parse::<Pattern>().unwrap(), *pattern);
+        }
+        assert!("smpte".parse::<Pattern>().is_err());
+    }
+
+    #[test]
+    fn patterns_are_drawn_as_described() {
+        let gradient = Pattern::Gradient.generate(64).to_rgb8();
+        assert_eq!(gradient.get_pixel(0, 0).0, [0, 0, 0]);
+        assert_eq!(gradient.get_pixel(63, 0).0, [255, 255, 255]);
+        assert_eq!(gradient.get_pixel(0, 63).0, [255, 0, 0]);
+
+        let bars = Pattern::Bars.generate(64).to_rgb8();
+        let colors: Vec<[u8; 3]> = (0..8)
+            .map(|bar| bars.get_pixel(bar * 8 + 4, 32).0)
+            .collect();
+        assert_eq!(colors, BARS);
+
+        let circles = Pattern::Circles.generate(64).to_rgb8();
+        assert_eq!(circles.get_pixel(32, 32).0, [255; 3]);
+        // Rings are round: the same distance gives the same color either way
+        assert_eq!(circles.get_pixel(32, 10).0, circles.get_pixel(10, 32).0);
+
+        let board = Pattern::Checkerboard.generate(64).to_rgb8();
+        assert_eq!(board.get_pixel(0, 0).0, [255; 3]);
+        assert_eq!(board.get_pixel(8, 0).0, [0; 3]);
+        assert_eq!(board.get_pixel(8, 8).0, [255; 3]);
+
+        assert_eq!(Pattern::Bars.generate(64), Pattern::Bars.generate(64));
+    }
+}
@@ -0,0 +1,81 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Wall-clock time spent in each pipeline stage
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Timings {
+    pub load: Duration,
+    pub denoise: Duration,
+    pub resize: Duration,
+    pub grayscale: Duration,
+    pub conversion: Duration,
+    pub render: Duration,
+}
+
+impl Timings {
+    pub fn total(&self) -> Duration {
+        self.load + self.denoise + self.resize + self.grayscale + self.conversion + self.render
+    }
+}
+
+impl fmt::Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let stages = [
+            ("load", self.load),
+            ("denoise", self.denoise),
+            ("resize", self.resize),
+            ("grayscale", self.grayscale),
+            ("conversion", self.conversion),
+            ("render", self.render),
+            ("total", self.total()),
+        ];
+
+        writeln!(f, "Timings:")?;
+        for (name, duration) in stages {
+            writeln!(f, "  {name:<11}{:>9.2} ms", duration.as_secs_f64() * 1000.0)?;
+        }
+        Ok(())
+    }
+}
+
+/// Run `f`, adding the time it took to `slot`
+pub fn time<T>(slot: &mut Duration, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let result = f();
+    *slot += start.elapsed();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn total_sums_all_stages() {
+        let timings = Timings {
+            load: Duration::from_millis(1),
+            denoise: Duration::from_millis(6),
+            resize: Duration::from_millis(2),
+            grayscale: Duration::from_millis(3),
+            conversion: Duration::from_millis(4),
+            render: Duration::from_millis(5),
+        };
+        assert_eq!(timings.total(), Duration::from_millis(21));
+    }
+
+    #[test]
+    fn report_lists_every_stage() {
+        let report = Timings::default().to_string();
+        for stage in [
+            "load",
+            "denoise",
+            "resize",
+            "grayscale",
+            "conversion",
+            "render",
+            "total",
+        ] {
+            assert!(report.contains(stage), "missing {stage}");
+        }
+    }
+}
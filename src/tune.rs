@@ -0,0 +1,226 @@
+//! Search for the adjustments that score best on an image, as done by
+//! `--auto-tune`
+
+use std::cmp::Ordering;
+use std::fmt;
+
+use image::DynamicImage;
+use tracing::debug;
+
+use crate::ascii_converter::{builtin_ramp, Charset, BUILTIN_CHARSETS};
+use crate::builder::{AsciiArtBuilder, StageCache};
+use crate::error::AsciiArtError;
+use crate::filters::{Filter, Levels};
+use crate::font;
+use crate::score::Score;
+
+/// Midtone gammas tried, applied as `--levels 0,255,GAMMA`
+pub const GAMMAS: [f32; 5] = [0.6, 0.8, 1.0, 1.25, 1.6];
+
+/// Contrast amounts tried
+pub const CONTRASTS: [f32; 5] = [-20.0, 0.0, 20.0, 40.0, 60.0];
+
+/// Passes over all the settings; the search stops early once a pass finds
+/// nothing better
+pub const MAX_ROUNDS: usize = 3;
+
+/// The best adjustments found for an image, and how they scored
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tuning {
+    pub gamma: f32,
+    pub contrast: f32,
+    pub dithering: bool,
+    /// Name of the built-in charset
+    pub charset: &'static str,
+    pub score: Score,
+    /// Configurations converted and scored during the search
+    pub evaluations: usize,
+}
+
+impl Tuning {
+    /// Apply the adjustments to `builder`
+    pub fn apply(&self, builder: AsciiArtBuilder) -> AsciiArtBuilder {
+        let mut builder = builder.dithering(self.dithering);
+        if let Some(charset) = Charset::builtin(self.charset) {
+            builder = builder.charset(charset);
+        }
+        if self.gamma != 1.0 {
+            builder = builder.filter(Filter::Levels(Levels {
+                black: 0,
+                white: 255,
+                gamma: self.gamma,
+            }));
+        }
+        if self.contrast != 0.0 {
+            builder = builder.filter(Filter::Contrast(self.contrast));
+        }
+        builder
+    }
+
+    /// Command-line flags that reproduce the adjustments
+    pub fn flags(&self) -> String {
+        let mut flags = Vec::new();
+        if self.gamma != 1.0 {
+            flags.push(format!("--levels 0,255,{}", self.gamma));
+        }
+        if self.contrast != 0.0 {
+            flags.push(format!("--contrast {}", self.contrast));
+        }
+        if self.dithering {
+            flags.push("--dither".to_string());
+        }
+        if let Some(ramp) = builtin_ramp(self.charset).filter(|_| self.charset != "standard") {
+            flags.push(format!("--charset '{}'", ramp.replace('\'', r"'\''")));
+        }
+        flags.join(" ")
+    }
+}
+
+impl fmt::Display for Tuning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let flags = self.flags();
+        writeln!(f, "Auto-tune ({} configurations tried):", self.evaluations)?;
+        writeln!(
+            f,
+            "  ssim {:.4}, psnr {:.2} dB",
+            self.score.ssim, self.score.psnr
+        )?;
+        if flags.is_empty() {
+            writeln!(f, "  flags: (defaults)")
+        } else {
+            writeln!(f, "  flags: {flags}")
+        }
+    }
+}
+
+/// Built-in charsets the score can judge: those drawn entirely with glyphs
+/// of the built-in font
+fn candidate_charsets() -> Vec<&'static str> {
+    BUILTIN_CHARSETS
+        .iter()
+        .filter(|(_, ramp)| ramp.chars().all(|ch| font::glyph(ch).is_some()))
+        .map(|&(name, _)| name)
+        .collect()
+}
+
+/// Whether `a` scores better than `b`: higher SSIM, then higher PSNR
+fn better(a: &Score, b: &Score) -> bool {
+    let key = |score: &Score| (score.ssim, score.psnr);
+    key(a).partial_cmp(&key(b)) == Some(Ordering::Greater)
+}
+
+/// Search gamma, contrast, dithering, and charset for the best score on
+/// `image`, starting from `base`
+///
+/// Each setting is swept in turn with the others held at their best so far,
+/// for at most [`MAX_ROUNDS`] passes.
+pub fn auto_tune(base: &AsciiArtBuilder, image: &DynamicImage) -> Result<Tuning, AsciiArtError> {
+    let mut best = Tuning {
+        gamma: 1.0,
+        contrast: 0.0,
+        dithering: false,
+        charset: "standard",
+        score: Score {
+            ssim: f64::NEG_INFINITY,
+            psnr: f64::NEG_INFINITY,
+        },
+        evaluations: 0,
+    };
+    let mut evaluations = 0;
+    // Candidates differing only in charset or dithering reuse the resized and
+    // filtered image
+    let mut cache = StageCache::new(image);
+    let mut evaluate = |candidate: Tuning, best: &mut Tuning| {
+        evaluations += 1;
+        let score = candidate.apply(base.clone()).score_cached(&mut cache)?;
+        if better(&score, &best.score) {
+            *best = Tuning { score, ..candidate };
+            debug!(
+                flags = best.flags(),
+                ssim = score.ssim,
+                "Auto-tune improved"
+            );
+            return Ok(true);
+        }
+        Ok::<_, AsciiArtError>(false)
+    };
+
+    evaluate(best, &mut best)?;
+    let charsets = candidate_charsets();
+    for _ in 0..MAX_ROUNDS {
+        let mut improved = false;
+        for gamma in GAMMAS {
+            improved |= evaluate(Tuning { gamma, ..best }, &mut best)?;
+        }
+        for contrast in CONTRASTS {
+            improved |= evaluate(Tuning { contrast, ..best }, &mut best)?;
+        }
+        for dithering in [false, true] {
+            improved |= evaluate(Tuning { dithering, ..best }, &mut best)?;
+        }
+        for &charset in &charsets {
+            improved |= evaluate(Tuning { charset, ..best }, &mut best)?;
+        }
+        if !improved {
+            break;
+        }
+    }
+    best.evaluations = evaluations;
+    Ok(best)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn flags_reproduce_the_tuning() {
+        let tuning = Tuning {
+            gamma: 1.25,
+            contrast: 20.0,
+            dithering: true,
+            charset: "blocks",
+            score: Score {
+                ssim: 0.5,
+                psnr: 20.0,
+            },
+            evaluations: 1,
+        };
+        assert_eq!(
+            tuning.flags(),
+            "--levels 0,255,1.25 --contrast 20 --dither --charset ' ░▒▓█'"
+        );
+        let defaults = Tuning {
+            gamma: 1.0,
+            contrast: 0.0,
+            dithering: false,
+            charset: "standard",
+            ..tuning
+        };
+        assert_eq!(defaults.flags(), "");
+        assert!(defaults.to_string().contains("(defaults)"));
+    }
+
+    #[test]
+    fn braille_is_not_a_candidate() {
+        let charsets = candidate_charsets();
+        assert!(charsets.contains(&"blocks"), "{charsets:?}");
+        assert!(!charsets.contains(&"braille"), "{charsets:?}");
+    }
+
+    #[test]
+    fn tuning_never_scores_worse_than_the_defaults() {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_fn(48, 24, |x, y| {
+            let value = (x * 3 + y * 2) as u8;
+            Rgb([value, value, value])
+        }));
+        let base = AsciiArtBuilder::new().width(24);
+        let plain = base.score(&image).expect("score");
+        let tuning = auto_tune(&base, &image).expect("tune");
+        assert!(!better(&plain, &tuning.score), "{plain:?} {tuning:?}");
+        assert!(tuning.evaluations > 1);
+        let applied = tuning.apply(base).score(&image).expect("score");
+        assert_eq!(applied, tuning.score);
+    }
+}
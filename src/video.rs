@@ -0,0 +1,246 @@
+//! Animated exports of the converted art, for sharing where text can't go
+//!
+//! Each frame is converted, drawn with the built-in font by
+//! [`AsciiArt::to_image`](crate::AsciiArt::to_image), and encoded: GIFs with
+//! the `image` crate, MP4s by piping raw frames to `ffmpeg`, which must be on
+//! the `PATH`.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::str::FromStr;
+use std::time::Duration;
+
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, DynamicImage, RgbImage};
+
+use crate::animation::Frame;
+use crate::builder::AsciiArtBuilder;
+use crate::error::AsciiArtError;
+use crate::image_loader::ImageLoaderError;
+
+/// Frame rate of MP4 exports; every GIF delay is a whole number of frames
+const MP4_FPS: u32 = 50;
+
+/// Speed of GIF color quantization, from 1 (best) to 30 (fastest); art with
+/// 256 colors or fewer keeps them exactly at any speed
+const GIF_SPEED: i32 = 10;
+
+/// An animated file format for the converted art
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum VideoFormat {
+    /// An animated GIF that loops forever
+    Gif,
+    /// H.264 video in an MP4 container, encoded by ffmpeg
+    Mp4,
+}
+
+impl VideoFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            VideoFormat::Gif => "gif",
+            VideoFormat::Mp4 => "mp4",
+        }
+    }
+}
+
+impl FromStr for VideoFormat {
+    type Err = AsciiArtError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "gif" => Ok(VideoFormat::Gif),
+            "mp4" => Ok(VideoFormat::Mp4),
+            unknown => Err(AsciiArtError::InvalidArgument(format!(
+                "Unknown video format '{}'. Use 'gif' or 'mp4'.",
+                unknown
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for VideoFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.extension())
+    }
+}
+
+/// Convert every frame with `builder` and encode the pictures of the art as
+/// `format`, written to `path` or to stdout when it is `None`, and return
+/// how many frames were encoded
+///
+/// Frames keep their delays. MP4 video runs at a fixed rate, so each frame
+/// is repeated for as long as it stays on screen; MP4 written to stdout is
+/// fragmented, since a pipe can't be rewound to finish the file's index.
+pub fn export_video(
+    frames: impl IntoIterator<Item = Result<Frame, ImageLoaderError>>,
+    builder: &AsciiArtBuilder,
+    format: VideoFormat,
+    path: Option<&Path>,
+) -> Result<usize, AsciiArtError> {
+    let pictures = frames.into_iter().map(|frame| {
+        let frame = frame?;
+        let picture = builder.generate(&frame.image)?.to_image();
+        Ok::<_, AsciiArtError>((picture, frame.delay))
+    });
+    match format {
+        VideoFormat::Gif => {
+            let writer: Box<dyn Write> = match path {
+                Some(path) => Box::new(File::create(path)?),
+                None => Box::new(io::stdout().lock()),
+            };
+            encode_gif(pictures, BufWriter::new(writer))
+        }
+        VideoFormat::Mp4 => encode_mp4(pictures, path),
+    }
+}
+
+fn encode_gif<W: Write>(
+    pictures: impl Iterator<Item = Result<(RgbImage, Duration), AsciiArtError>>,
+    writer: W,
+) -> Result<usize, AsciiArtError> {
+    let mut encoder = GifEncoder::new_with_speed(writer, GIF_SPEED);
+    encoder.set_repeat(Repeat::Infinite).map_err(gif_error)?;
+    let mut written = 0;
+    for picture in pictures {
+        let (picture, delay) = picture?;
+        let rgba = DynamicImage::ImageRgb8(picture).into_rgba8();
+        let delay = Delay::from_saturating_duration(delay);
+        encoder
+            .encode_frame(image::Frame::from_parts(rgba, 0, 0, delay))
+            .map_err(gif_error)?;
+        written += 1;
+    }
+    Ok(written)
+}
+
+fn gif_error(err: image::ImageError) -> AsciiArtError {
+    match err {
+        image::ImageError::IoError(err) => AsciiArtError::Render(err),
+        err => AsciiArtError::InvalidArgument(format!("Cannot encode the GIF: {err}")),
+    }
+}
+
+/// Video frames a picture shown for `delay` is repeated across at [`MP4_FPS`]
+fn repeats(delay: Duration) -> u32 {
+    ((delay.as_secs_f64() * f64::from(MP4_FPS)).round() as u32).max(1)
+}
+
+fn encode_mp4(
+    mut pictures: impl Iterator<Item = Result<(RgbImage, Duration), AsciiArtError>>,
+    path: Option<&Path>,
+) -> Result<usize, AsciiArtError> {
+    // ffmpeg is started with the first frame, once the size is known
+    let mut ffmpeg: Option<(Child, ChildStdin)> = None;
+    let mut written = 0;
+    let result = pictures.try_for_each(|picture| {
+        let (picture, delay) = picture?;
+        let stdin = match &mut ffmpeg {
+            Some((_, stdin)) => stdin,
+            None => &mut ffmpeg.insert(spawn_ffmpeg(picture.dimensions(), path)?).1,
+        };
+        for _ in 0..repeats(delay) {
+            stdin.write_all(picture.as_raw())?;
+        }
+        written += 1;
+        Ok(())
+    });
+    let Some((mut child, stdin)) = ffmpeg else {
+        return result.map(|()| written);
+    };
+    // Closing stdin tells ffmpeg the video is over
+    drop(stdin);
+    let status = child.wait()?;
+    match result {
+        // A write fails with a broken pipe when ffmpeg gives up; its exit
+        // status says more
+        Err(AsciiArtError::Render(_)) | Ok(()) if !status.success() => {
+            Err(AsciiArtError::InvalidArgument(format!(
+                "ffmpeg could not encode the video ({status})."
+            )))
+        }
+        result => result.map(|()| written),
+    }
+}
+
+fn spawn_ffmpeg(
+    (width, height): (u32, u32),
+    path: Option<&Path>,
+) -> Result<(Child, ChildStdin), AsciiArtError> {
+    let mut command = Command::new("ffmpeg");
+    command
+        .args(["-hide_banner", "-loglevel", "error", "-y"])
+        .args(["-f", "rawvideo", "-pix_fmt", "rgb24"])
+        .args(["-s", &format!("{width}x{height}")])
+        .args(["-r", &MP4_FPS.to_string(), "-i", "-"])
+        .args(["-c:v", "libx264", "-pix_fmt", "yuv420p"]);
+    match path {
+        Some(path) => command.args(["-movflags", "+faststart"]).arg(path),
+        None => command.args([
+            "-movflags",
+            "frag_keyframe+empty_moov",
+            "-f",
+            "mp4",
+            "pipe:1",
+        ]),
+    };
+    let mut child = command.stdin(Stdio::piped()).spawn().map_err(|err| {
+        AsciiArtError::InvalidArgument(format!(
+            "MP4 export needs ffmpeg on the PATH, and it could not be started: {err}"
+        ))
+    })?;
+    let stdin = child.stdin.take().expect("stdin is piped");
+    Ok((child, stdin))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::animation::{open_frames, DEFAULT_FRAME_DELAY};
+    use crate::image_loader::DecodeLimits;
+
+    #[test]
+    fn frames_repeat_for_their_delay_at_the_video_rate() {
+        assert_eq!(repeats(Duration::from_millis(20)), 1);
+        assert_eq!(repeats(DEFAULT_FRAME_DELAY), 5);
+        assert_eq!(repeats(Duration::ZERO), 1);
+    }
+
+    #[test]
+    fn gif_export_keeps_frames_and_delays() {
+        let frames = (0..2u8).map(|shade| {
+            let image = RgbImage::from_pixel(8, 8, image::Rgb([shade * 200; 3]));
+            Ok(Frame {
+                image: DynamicImage::ImageRgb8(image),
+                delay: Duration::from_millis(50),
+                source: None,
+            })
+        });
+        let file = tempfile::NamedTempFile::with_suffix(".gif").expect("temp file");
+        let builder = AsciiArtBuilder::new().width(3);
+        let written =
+            export_video(frames, &builder, VideoFormat::Gif, Some(file.path())).expect("exported");
+        assert_eq!(written, 2);
+
+        let path = file.path().to_str().expect("utf8 path");
+        let decoded: Vec<Frame> = open_frames(path, &DecodeLimits::default(), Duration::ZERO)
+            .expect("opened")
+            .collect::<Result<_, _>>()
+            .expect("decoded");
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(decoded[0].delay, Duration::from_millis(50));
+        // Three cells of the built-in font across
+        assert_eq!(decoded[0].image.width(), 3 * crate::font::GLYPH_SIZE);
+    }
+
+    #[test]
+    fn video_format_parses_names() {
+        assert_eq!("gif".parse::<VideoFormat>().ok(), Some(VideoFormat::Gif));
+        assert_eq!("mp4".parse::<VideoFormat>().ok(), Some(VideoFormat::Mp4));
+        assert!("webm".parse::<VideoFormat>().is_err());
+        assert_eq!(VideoFormat::Mp4.to_string(), "mp4");
+    }
+}
@@ -0,0 +1,230 @@
+//! A minimal full-screen viewer for art taller than the terminal, for when
+//! no pager that keeps colors is at hand
+//!
+//! The art is shown on the alternate screen, scrolled with the arrow keys,
+//! Page Up and Page Down, Home and End (or `j`, `k`, space, `b`, `g`, and `G`,
+//! as in `less`), and closed with `q`, Escape, or Ctrl-C.
+
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::renderer::RESET;
+use crate::terminal::{self, KeyInput};
+
+/// Size assumed when the terminal does not report one
+const FALLBACK_SIZE: (u32, u32) = (80, 24);
+
+/// A key the viewer acts on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    Quit,
+}
+
+/// The keys in a chunk of terminal input, skipping any the viewer ignores
+///
+/// An Escape on its own quits; one that starts a sequence the viewer does not
+/// know is dropped along with the sequence.
+pub fn parse_keys(input: &[u8]) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let mut rest = input;
+    while let Some((&byte, tail)) = rest.split_first() {
+        rest = tail;
+        let key = match byte {
+            b'q' | b'Q' => Some(Key::Quit),
+            b'k' => Some(Key::Up),
+            b'j' | b'\r' | b'\n' => Some(Key::Down),
+            b'b' => Some(Key::PageUp),
+            b' ' => Some(Key::PageDown),
+            b'g' => Some(Key::Home),
+            b'G' => Some(Key::End),
+            0x1b if rest.is_empty() => Some(Key::Quit),
+            0x1b => {
+                // CSI (`ESC [`) or SS3 (`ESC O`): parameters, then a final byte
+                let body = &rest[1..];
+                let end = body.iter().position(|byte| (0x40..=0x7e).contains(byte));
+                let Some(end) = end.filter(|_| matches!(rest[0], b'[' | b'O')) else {
+                    continue;
+                };
+                rest = &body[end + 1..];
+                match &body[..=end] {
+                    b"A" => Some(Key::Up),
+                    b"B" => Some(Key::Down),
+                    b"5~" => Some(Key::PageUp),
+                    b"6~" => Some(Key::PageDown),
+                    b"H" | b"1~" | b"7~" => Some(Key::Home),
+                    b"F" | b"4~" | b"8~" => Some(Key::End),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        keys.extend(key);
+    }
+    keys
+}
+
+/// The first line shown after `key`, from `top`, with `rows` of `lines`
+/// on screen
+pub fn scroll(top: usize, key: Key, lines: usize, rows: usize) -> usize {
+    let last = lines.saturating_sub(rows);
+    let top = match key {
+        Key::Up => top.saturating_sub(1),
+        Key::Down => top + 1,
+        Key::PageUp => top.saturating_sub(rows),
+        Key::PageDown => top + rows,
+        Key::Home => 0,
+        Key::End => last,
+        Key::Quit => top,
+    };
+    top.min(last)
+}
+
+/// The lines of rendered art, without the reset that follows the last one
+fn art_lines(art: &str) -> Vec<&str> {
+    let mut lines: Vec<&str> = art.split('\n').collect();
+    if lines
+        .last()
+        .is_some_and(|last| last.is_empty() || *last == RESET)
+    {
+        lines.pop();
+    }
+    lines
+}
+
+/// Show `art` in the viewer until it is closed
+///
+/// Without a terminal to read keys from, the art is written to stdout as it
+/// is.
+pub fn view(art: &str) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    let Some(mut input) = KeyInput::open() else {
+        stdout.write_all(art.as_bytes())?;
+        return stdout.flush();
+    };
+    let interrupted = terminal::catch_interrupt();
+    let result = browse(&mut input, stdout, &art_lines(art), interrupted);
+    // Ctrl-C closes this viewer only; a later one starts with the flag clear
+    interrupted.store(false, Ordering::Relaxed);
+    result
+}
+
+/// Scroll through `lines` on the alternate screen until `q` is pressed or
+/// `interrupted` is set
+fn browse(
+    input: &mut KeyInput,
+    stdout: impl Write,
+    lines: &[&str],
+    interrupted: &AtomicBool,
+) -> io::Result<()> {
+    let mut screen = Screen::enter(stdout)?;
+    let mut top = 0;
+    let mut drawn = None;
+    let mut buffer = [0u8; 64];
+    while !interrupted.load(Ordering::Relaxed) {
+        let (_, height) = terminal::get_terminal_size().unwrap_or(FALLBACK_SIZE);
+        // The bottom row holds the status line
+        let rows = (height as usize).saturating_sub(1).max(1);
+        top = top.min(lines.len().saturating_sub(rows));
+        if drawn != Some((top, rows)) {
+            screen.draw(lines, top, rows)?;
+            drawn = Some((top, rows));
+        }
+
+        let read = input.read(&mut buffer)?;
+        for key in parse_keys(&buffer[..read]) {
+            if key == Key::Quit {
+                return Ok(());
+            }
+            top = scroll(top, key, lines.len(), rows);
+        }
+    }
+    Ok(())
+}
+
+/// The alternate screen, with the cursor hidden and lines cut off at the
+/// right edge instead of wrapping, until dropped
+struct Screen<W: Write> {
+    out: W,
+}
+
+impl<W: Write> Screen<W> {
+    fn enter(mut out: W) -> io::Result<Self> {
+        out.write_all(b"\x1b[?1049h\x1b[?25l\x1b[?7l")?;
+        Ok(Self { out })
+    }
+
+    /// Draw `rows` lines from `top`, and the status line below them
+    fn draw(&mut self, lines: &[&str], top: usize, rows: usize) -> io::Result<()> {
+        let shown = &lines[top.min(lines.len())..(top + rows).min(lines.len())];
+        let mut frame = String::from("\x1b[H");
+        for line in shown {
+            frame.push_str(line);
+            frame.push_str(RESET);
+            frame.push_str("\x1b[K\r\n");
+        }
+        frame.push_str("\x1b[J");
+        let status = match lines.len() {
+            0 => "no lines".to_string(),
+            count => format!("lines {}-{} of {count}", top + 1, top + shown.len()),
+        };
+        frame.push_str(&format!(
+            "\x1b[{};1H\x1b[7m {status}  \u{2191}\u{2193} PgUp PgDn Home End  q quits {RESET}",
+            rows + 1
+        ));
+        self.out.write_all(frame.as_bytes())?;
+        self.out.flush()
+    }
+}
+
+impl<W: Write> Drop for Screen<W> {
+    fn drop(&mut self) {
+        // Nothing is left to show an error on once the screen is closing
+        let _ = self
+            .out
+            .write_all(b"\x1b[?7h\x1b[?25h\x1b[?1049l")
+            .and_then(|()| self.out.flush());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_are_read_from_escape_sequences_and_letters() {
+        assert_eq!(
+            parse_keys(b"\x1b[A\x1bOB\x1b[5~\x1b[6~ jkq"),
+            [
+                Key::Up,
+                Key::Down,
+                Key::PageUp,
+                Key::PageDown,
+                Key::PageDown,
+                Key::Down,
+                Key::Up,
+                Key::Quit
+            ]
+        );
+        assert_eq!(
+            parse_keys(b"\x1b[H\x1b[4~\x1b[1;5Cx"),
+            [Key::Home, Key::End]
+        );
+        assert_eq!(parse_keys(b"\x1b"), [Key::Quit]);
+    }
+
+    #[test]
+    fn scrolling_stays_within_the_art() {
+        assert_eq!(scroll(0, Key::Up, 100, 20), 0);
+        assert_eq!(scroll(0, Key::PageDown, 100, 20), 20);
+        assert_eq!(scroll(75, Key::PageDown, 100, 20), 80);
+        assert_eq!(scroll(10, Key::End, 100, 20), 80);
+        assert_eq!(scroll(0, Key::Down, 10, 20), 0);
+        assert_eq!(art_lines("ab\ncd\n\x1b[0m"), ["ab", "cd"]);
+    }
+}
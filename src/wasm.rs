@@ -0,0 +1,33 @@
+use wasm_bindgen::prelude::*;
+
+use crate::config::Options;
+use crate::error::AsciiArtError;
+use crate::image_loader::{load_image_from_bytes, DecodeLimits};
+
+/// Convert an encoded image (PNG, JPEG, GIF, ...) to ASCII art
+///
+/// `options` is a JSON object with the keys of the configuration file, such
+/// as `{"width": 80, "mode": "edge", "color-depth": "truecolor"}`; pass `"{}"`
+/// for the defaults. `output` is `"html"` for a colored `<pre>` block,
+/// `"ansi"` for escape sequences (for terminal emulators such as xterm.js),
+/// or `"text"` for the bare characters. Failures are thrown as `Error`s.
+#[wasm_bindgen]
+pub fn convert_bytes(image_bytes: &[u8], options: &str, output: &str) -> Result<String, JsError> {
+    convert(image_bytes, options, output).map_err(|err| JsError::new(&err.to_string()))
+}
+
+fn convert(image_bytes: &[u8], options: &str, output: &str) -> Result<String, AsciiArtError> {
+    let options: Options = serde_json::from_str(options)
+        .map_err(|err| AsciiArtError::InvalidArgument(format!("Invalid options: {err}")))?;
+    let image = load_image_from_bytes(image_bytes, &DecodeLimits::default())?;
+    let art = options.to_builder()?.generate(&image)?;
+    match output {
+        "html" => Ok(art.to_html()),
+        "ansi" => Ok(art.to_ansi_string()),
+        "text" => Ok(art.to_text()),
+        unknown => Err(AsciiArtError::InvalidArgument(format!(
+            "Unknown output '{}'. Use 'html', 'ansi', or 'text'.",
+            unknown
+        ))),
+    }
+}
@@ -0,0 +1,125 @@
+//! Hot-folder mode: noticing images that are added to or changed in a
+//! directory, for `--watch-dir`
+//!
+//! The directory is polled rather than subscribed to, so it works the same
+//! on every platform and over network shares. A file is reported once its
+//! size and modification time have held still between two polls, so images
+//! that are still being copied in are not converted half-written.
+
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use image::ImageFormat;
+
+/// How often the directory is checked for changes
+pub const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// What a file looked like when it was last checked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Stamp {
+    modified: SystemTime,
+    len: u64,
+}
+
+/// Images in a directory, and which of them have changed since they were
+/// last reported
+#[derive(Debug, Clone)]
+pub struct Watcher {
+    dir: PathBuf,
+    /// Each image as of the latest poll
+    observed: HashMap<PathBuf, Stamp>,
+    /// Each image as it was when last reported, or when watching began
+    reported: HashMap<PathBuf, Stamp>,
+}
+
+impl Watcher {
+    /// Watch `dir`, which must exist; images already in it are not reported
+    /// until they change
+    pub fn new(dir: &Path) -> io::Result<Self> {
+        let observed = scan(dir)?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            reported: observed.clone(),
+            observed,
+        })
+    }
+
+    /// Images that are new or changed, and have stayed the same since the
+    /// previous poll, in name order
+    ///
+    /// Subdirectories are not watched, and files that are not images by
+    /// their extension are ignored.
+    pub fn poll(&mut self) -> io::Result<Vec<PathBuf>> {
+        let current = scan(&self.dir)?;
+        let mut ready: Vec<PathBuf> = current
+            .iter()
+            .filter(|&(path, stamp)| {
+                self.observed.get(path) == Some(stamp) && self.reported.get(path) != Some(stamp)
+            })
+            .map(|(path, _)| path.clone())
+            .collect();
+        ready.sort();
+        for path in &ready {
+            self.reported.insert(path.clone(), current[path]);
+        }
+        self.reported.retain(|path, _| current.contains_key(path));
+        self.observed = current;
+        Ok(ready)
+    }
+}
+
+/// The images directly inside `dir`, by path
+fn scan(dir: &Path) -> io::Result<HashMap<PathBuf, Stamp>> {
+    let mut images = HashMap::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if ImageFormat::from_path(&path).is_err() {
+            continue;
+        }
+        // Files can vanish between listing and inspecting them
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        if !metadata.is_file() {
+            continue;
+        }
+        let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+        let stamp = Stamp {
+            modified,
+            len: metadata.len(),
+        };
+        images.insert(path, stamp);
+    }
+    Ok(images)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn images_are_reported_once_they_settle() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        std::fs::write(dir.path().join("before.png"), b"old").expect("write");
+        let mut watcher = Watcher::new(dir.path()).expect("watching");
+        assert!(watcher.poll().expect("polled").is_empty());
+
+        let added = dir.path().join("added.png");
+        std::fs::write(&added, b"new").expect("write");
+        std::fs::write(dir.path().join("notes.txt"), b"text").expect("write");
+        // Seen for the first time: it may still be being written
+        assert!(watcher.poll().expect("polled").is_empty());
+        assert_eq!(
+            watcher.poll().expect("polled"),
+            std::slice::from_ref(&added)
+        );
+        assert!(watcher.poll().expect("polled").is_empty());
+
+        std::fs::write(&added, b"changed").expect("rewrite");
+        watcher.poll().expect("polled");
+        assert_eq!(watcher.poll().expect("polled"), [added]);
+    }
+}
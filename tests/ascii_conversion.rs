@@ -9,6 +9,8 @@ fn ascii_conversion_produces_colored_output() {
         .arg("examples/test_image_1.png")
         .arg("--width")
         .arg("40")
+        .arg("--color")
+        .arg("always")
         .assert()
         .success()
         .stdout(contains("\x1b["))  // Contains ANSI escape codes
@@ -23,8 +25,25 @@ fn ascii_conversion_handles_different_widths() {
         .arg("examples/test_image_1.png")
         .arg("--width")
         .arg("80")
+        .arg("--color")
+        .arg("always")
         .assert()
         .success()
         .stdout(contains("\x1b["))  // Contains ANSI escape codes
         .stdout(contains("\x1b[0m"));  // Contains ANSI reset code
 }
+
+#[test]
+fn ascii_conversion_rejects_unknown_color_metric() {
+    Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .arg("examples/test_image_1.png")
+        .arg("--color-metric")
+        .arg("lab")
+        .assert()
+        .failure()
+        .stderr(contains(
+            "invalid value 'lab' for '--color-metric <COLOR_METRIC>'",
+        ))
+        .stderr(contains("[possible values: rgb, oklab]"));
+}
@@ -1,5 +1,6 @@
 use assert_cmd::Command;
 use image::{DynamicImage, ImageBuffer, ImageOutputFormat, Rgba};
+use predicates::prelude::*;
 use predicates::str::contains;
 use tempfile::NamedTempFile;
 
@@ -19,18 +20,49 @@ fn run_with_width_override_succeeds() {
 
     Command::cargo_bin("ascii-art-cli")
         .expect("binary exists")
+        .arg("--no-config")
         .arg(image_file.path())
         .arg("--width")
         .arg("80")
+        .arg("--color")
+        .arg("always")
         .assert()
         .success()
         .stdout(contains("\x1b[")); // Check for ANSI escape codes
 }
 
+#[test]
+fn sizes_can_be_relative_or_follow_the_height() {
+    let image_file = create_sample_image();
+    let run = |args: &[&str]| {
+        let output = Command::cargo_bin("ascii-art-cli")
+            .expect("binary exists")
+            .arg("--no-config")
+            .arg(image_file.path())
+            .args(["--color", "never", "--deterministic"])
+            .args(args)
+            .assert()
+            .success()
+            .get_output()
+            .stdout
+            .clone();
+        let text = String::from_utf8(output).expect("utf8 output");
+        let widths: Vec<usize> = text.lines().map(|line| line.chars().count()).collect();
+        (widths.len(), widths[0])
+    };
+
+    // Without a terminal, percentages are of an 80x24 screen
+    assert_eq!(run(&["--width", "50%"]).1, 40);
+    // A square image in cells half as wide as they are tall
+    assert_eq!(run(&["--height", "6", "--char-aspect", "2"]), (6, 12));
+    assert_eq!(run(&["--height", "6", "--width", "5"]), (6, 5));
+}
+
 #[test]
 fn missing_image_reports_user_friendly_error() {
     Command::cargo_bin("ascii-art-cli")
         .expect("binary exists")
+        .arg("--no-config")
         .arg("tests/data/does-not-exist.png")
         .assert()
         .failure()
@@ -54,3 +86,1779 @@ fn create_sample_image() -> NamedTempFile {
 
     file
 }
+
+#[test]
+fn oversized_image_is_rejected_before_decoding() {
+    let image_file = create_sample_image();
+
+    Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .arg("--no-config")
+        .arg(image_file.path())
+        .arg("--max-dimension")
+        .arg("2")
+        .assert()
+        .failure()
+        .stderr(contains("exceeding the maximum dimension of 2"));
+}
+
+#[test]
+fn timings_flag_reports_stages_on_stderr() {
+    let image_file = create_sample_image();
+
+    Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .arg("--no-config")
+        .arg(image_file.path())
+        .arg("--width")
+        .arg("20")
+        .arg("--timings")
+        .assert()
+        .success()
+        .stderr(contains("Timings:"))
+        .stderr(contains("conversion"));
+}
+
+#[test]
+fn stats_flag_reports_histograms_on_stderr() {
+    let image_file = create_sample_image();
+
+    Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .arg(image_file.path())
+        .args(["--width", "20", "--no-config", "--stats"])
+        .assert()
+        .success()
+        .stderr(contains("Stats:"))
+        .stderr(contains("characters"))
+        .stderr(contains("brightness"))
+        .stderr(contains("bytes)"));
+
+    Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .arg(image_file.path())
+        .args(["--width", "20", "--no-config", "--stats", "--charset", " @"])
+        .assert()
+        .success()
+        .stderr(contains("uses only 2 character(s)"));
+}
+
+#[test]
+fn score_flag_reports_similarity_on_stderr() {
+    let image_file = create_sample_image();
+
+    Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .arg(image_file.path())
+        .args(["--width", "20", "--no-config", "--score"])
+        .assert()
+        .success()
+        .stderr(contains("Score:"))
+        .stderr(contains("ssim"))
+        .stderr(contains("dB"));
+}
+
+#[test]
+fn auto_tune_prints_flags_that_reproduce_its_score() {
+    let image_file = create_sample_image();
+    let output = Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .arg(image_file.path())
+        .args(["--width", "20", "--no-config", "--auto-tune", "--score"])
+        .output()
+        .expect("run binary");
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).expect("utf8 stderr");
+    assert!(stderr.contains("Auto-tune ("), "{stderr}");
+    let flags = stderr
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("flags: "))
+        .expect("flags line");
+    let score = |stderr: &str| {
+        let line = stderr
+            .lines()
+            .rfind(|line| line.trim_start().starts_with("ssim"));
+        line.map(str::to_owned)
+    };
+
+    // Flags look like `--levels 0,255,1.6 --dither --charset ' ░▒▓█'`
+    let mut args = vec![
+        "--width".to_owned(),
+        "20".to_owned(),
+        "--no-config".to_owned(),
+    ];
+    args.push("--score".to_owned());
+    for flag in flags.split(" --").filter(|flag| !flag.starts_with('(')) {
+        let (name, value) = flag.split_once(' ').unwrap_or((flag, ""));
+        args.push(format!("--{}", name.trim_start_matches("--")));
+        if !value.is_empty() {
+            args.push(value.trim_matches('\'').to_owned());
+        }
+    }
+    let rerun = Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .arg(image_file.path())
+        .args(&args)
+        .output()
+        .expect("run binary");
+    let rerun = String::from_utf8(rerun.stderr).expect("utf8 stderr");
+    assert_eq!(score(&rerun), score(&stderr), "{args:?}");
+
+    Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .arg(image_file.path())
+        .args(["--no-config", "--auto-tune", "--contrast", "10"])
+        .assert()
+        .code(64);
+}
+
+#[test]
+fn auto_mode_traces_line_art_blocks_pixel_art_and_shades_photos() {
+    let drawing = NamedTempFile::with_suffix(".png").expect("create temp image file");
+    let strokes = ImageBuffer::from_fn(64, 64, |x, y| {
+        let stroke = x == y || x == 10 || (20..24).contains(&y);
+        Rgba(if stroke {
+            [0, 0, 0, 255]
+        } else {
+            [255, 255, 255, 255]
+        })
+    });
+    DynamicImage::ImageRgba8(strokes)
+        .save(drawing.path())
+        .expect("write png");
+    // Too many colors to pass for pixel art
+    let photo = NamedTempFile::with_suffix(".png").expect("create temp image file");
+    let gradient = ImageBuffer::from_fn(64, 64, |x, y| Rgba([x as u8 * 4, y as u8 * 4, 150, 255]));
+    DynamicImage::ImageRgba8(gradient)
+        .save(photo.path())
+        .expect("write png");
+    let sprite = create_sample_image();
+
+    let render = |image: &NamedTempFile, args: &[&str]| {
+        Command::cargo_bin("ascii-art-cli")
+            .expect("binary exists")
+            .arg(image.path())
+            .args(["--width", "20", "--no-config"])
+            .args(args)
+            .assert()
+            .success()
+            .get_output()
+            .stdout
+            .clone()
+    };
+    let auto = ["--mode", "auto"];
+    assert_eq!(
+        render(&drawing, &auto),
+        render(&drawing, &["--mode", "edge"])
+    );
+    assert_eq!(
+        render(&photo, &auto),
+        render(&photo, &["--mode", "standard"])
+    );
+    assert_eq!(render(&sprite, &auto), render(&sprite, &["--pixel-art"]));
+}
+
+#[test]
+fn diff_reports_changed_cells_and_fails_above_threshold() {
+    let before = create_sample_image();
+    let after = NamedTempFile::with_suffix(".png").expect("create temp image file");
+    let image = ImageBuffer::from_fn(4, 4, |x, y| {
+        let value = if x < 2 { 255 } else { (y * 60) as u8 };
+        Rgba([value, value, value, 255])
+    });
+    DynamicImage::ImageRgba8(image)
+        .save(after.path())
+        .expect("write png");
+
+    let diff = |args: &[&str]| {
+        let mut command = Command::cargo_bin("ascii-art-cli").expect("binary exists");
+        command
+            .args(["diff", "--no-config", "--width", "8"])
+            .args(args);
+        command.assert()
+    };
+    let (before, after) = (
+        before.path().to_str().expect("utf8 path"),
+        after.path().to_str().expect("utf8 path"),
+    );
+    diff(&[before, after])
+        .code(1)
+        .stdout(contains("% of cells changed"))
+        .stdout(contains("#"));
+    diff(&[before, after, "--threshold", "100"]).success();
+    diff(&[before, before])
+        .success()
+        .stdout(contains("0.00% of cells changed"));
+    diff(&[before, after, "--color", "always"])
+        .code(1)
+        .stdout(contains("\x1b[97;41m"));
+    diff(&[before, "--preset-a", "photo", "--preset-b", "photo"]).success();
+    diff(&[before]).code(64);
+}
+
+#[test]
+fn testpattern_renders_without_an_input_file() {
+    let render = |args: &[&str]| {
+        let mut command = Command::cargo_bin("ascii-art-cli").expect("binary exists");
+        command.arg("testpattern").args(args);
+        command.assert().success().get_output().stdout.clone()
+    };
+    let board = render(&["checkerboard", "--width", "32", "--color-depth", "mono"]);
+    let board = String::from_utf8(board).expect("utf8 output");
+    assert_eq!(board.lines().count(), 16, "{board}");
+    assert!(board.starts_with("@@@@    @@@@    "), "{board}");
+
+    let bars = render(&["bars", "--width", "16", "--color-depth", "256"]);
+    assert!(String::from_utf8_lossy(&bars).contains("\x1b[38;5;"));
+
+    let saved = NamedTempFile::with_suffix(".png").expect("create temp image file");
+    render(&[
+        "circles",
+        "--size",
+        "32",
+        "--save",
+        saved.path().to_str().expect("utf8 path"),
+    ]);
+    let image = image::open(saved.path()).expect("saved pattern decodes");
+    assert_eq!((image.width(), image.height()), (32, 32));
+
+    Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .args(["testpattern", "--size", "100000"])
+        .assert()
+        .code(64);
+}
+
+#[test]
+fn text_draws_a_banner_with_the_builtin_font() {
+    let mut command = Command::cargo_bin("ascii-art-cli").expect("binary exists");
+    command.args(["text", "Hi", "--width", "32", "--color-depth", "mono"]);
+    let output = command.assert().success().get_output().stdout.clone();
+    let banner = String::from_utf8(output).expect("utf8 output");
+    assert_eq!(banner.lines().count(), 8, "{banner}");
+    assert!(banner.starts_with("████    ████"), "{banner}");
+
+    let mut command = Command::cargo_bin("ascii-art-cli").expect("binary exists");
+    command.args(["text", "Hi", "--width", "32", "--gradient", "red"]);
+    command
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\x1b[38;2;255;0;0m"));
+
+    let mut command = Command::cargo_bin("ascii-art-cli").expect("binary exists");
+    command.args(["text", "日本"]);
+    command
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--font"));
+}
+
+#[test]
+fn play_draws_each_slide_and_restores_the_cursor() {
+    let image_file = create_sample_image();
+    let path = image_file.path().to_str().expect("utf8 path");
+    let mut command = Command::cargo_bin("ascii-art-cli").expect("binary exists");
+    command.args([
+        "play",
+        path,
+        path,
+        "--width",
+        "8",
+        "--color-depth",
+        "mono",
+        "--fps",
+        "100",
+    ]);
+    let output = command.assert().success().get_output().stdout.clone();
+    let output = String::from_utf8(output).expect("utf8 output");
+    assert_eq!(output.matches("\x1b[H").count(), 2, "{output:?}");
+    assert!(output.ends_with("\x1b[?25h"), "{output:?}");
+
+    let mut command = Command::cargo_bin("ascii-art-cli").expect("binary exists");
+    command.args(["play", path, "--speed", "0"]);
+    command.assert().failure().stderr(predicate::str::contains(
+        "Speed must be a factor of at least",
+    ));
+}
+
+#[test]
+fn play_reads_raw_video_frames_from_stdin() {
+    // Three 4x2 gray frames, then half of a fourth
+    let frames: Vec<u8> = (0..28).map(|byte| byte * 9).collect();
+    let mut command = Command::cargo_bin("ascii-art-cli").expect("binary exists");
+    command
+        .args([
+            "play",
+            "--stdin-raw",
+            "4x2:gray",
+            "--fps",
+            "100",
+            "--width",
+            "4",
+        ])
+        .args(["--color-depth", "mono", "--no-frame-skip"])
+        .write_stdin(frames[..24].to_vec());
+    let output = command.assert().success().get_output().stdout.clone();
+    let output = String::from_utf8(output).expect("utf8 output");
+    assert_eq!(output.matches("\x1b[H").count(), 3, "{output:?}");
+
+    let mut command = Command::cargo_bin("ascii-art-cli").expect("binary exists");
+    command
+        .args(["play", "--stdin-raw", "4x2:gray", "--fps", "100"])
+        .write_stdin(frames);
+    command
+        .assert()
+        .failure()
+        .stderr(contains("ended partway through a 4x2:gray frame"));
+}
+
+#[test]
+fn files_from_stdin_feed_templated_outputs() {
+    let (first, second) = (create_sample_image(), create_sample_image());
+    let dir = tempfile::tempdir().expect("temp dir");
+    let template = format!("{}/{{stem}}_{{width}}w.{{ext}}", dir.path().display());
+    let list = format!("{}\0{}\0", first.path().display(), second.path().display());
+    let run = |extra: &[&str]| {
+        let mut command = Command::cargo_bin("ascii-art-cli").expect("binary exists");
+        command
+            .args(["--files-from", "-", "--width", "6", "--color", "never"])
+            .args(["--output-template", &template])
+            .args(extra)
+            .write_stdin(list.clone());
+        command.assert()
+    };
+
+    run(&[]).success().stdout("");
+    for image in [&first, &second] {
+        let stem = image.path().file_stem().expect("stem").to_string_lossy();
+        let art = std::fs::read_to_string(dir.path().join(format!("{stem}_6w.txt")))
+            .expect("art written");
+        assert_eq!(art.lines().next().map(str::len), Some(6), "{art:?}");
+    }
+
+    run(&[]).failure().stderr(contains("--overwrite"));
+    run(&["--overwrite"]).success();
+}
+
+#[test]
+fn output_templates_never_write_over_the_input() {
+    let dir = tempfile::tempdir().expect("temp dir");
+    let input = dir.path().join("cat.png");
+    std::fs::copy(create_sample_image().path(), &input).expect("copy image");
+    let image = std::fs::read(&input).expect("read image");
+
+    Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .current_dir(dir.path())
+        .args([
+            "cat.png",
+            "--no-config",
+            "--output-template",
+            "{dir}/{stem}.png",
+        ])
+        .arg("--overwrite")
+        .assert()
+        .failure()
+        .stderr(contains("over itself"));
+    assert_eq!(std::fs::read(&input).expect("read image"), image);
+}
+
+#[test]
+fn colliding_output_paths_fail_before_anything_is_written() {
+    let dir = tempfile::tempdir().expect("temp dir");
+    for sub in ["a", "b"] {
+        let input = dir.path().join(sub).join("x.png");
+        std::fs::create_dir(dir.path().join(sub)).expect("create dir");
+        std::fs::copy(create_sample_image().path(), &input).expect("copy image");
+    }
+
+    Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .current_dir(dir.path())
+        .args(["--no-config", "--output-template", "out/{stem}.txt"])
+        .args(["a/x.png", "b/x.png"])
+        .assert()
+        .code(64)
+        .stderr(contains("add {dir}"));
+    assert!(!dir.path().join("out/x.txt").exists());
+}
+
+#[test]
+fn deterministic_output_is_reproducible() {
+    let image_file = create_sample_image();
+    let run = || {
+        Command::cargo_bin("ascii-art-cli")
+            .expect("binary exists")
+            .arg("--no-config")
+            .arg(image_file.path())
+            .arg("--deterministic")
+            .env("COLUMNS", "37")
+            .assert()
+            .success()
+            .get_output()
+            .stdout
+            .clone()
+    };
+
+    let first = run();
+    assert_eq!(first, run());
+
+    let text = String::from_utf8(first).expect("utf8 output");
+    assert!(!text.contains("Using"), "width messages leaked: {text}");
+    assert!(text.contains("\x1b["), "expected colored art: {text}");
+}
+
+#[test]
+fn no_color_env_and_flag_control_escapes() {
+    let image_file = create_sample_image();
+    let run = |args: &[&str], no_color: Option<&str>| {
+        let mut command = Command::cargo_bin("ascii-art-cli").expect("binary exists");
+        command
+            .arg(image_file.path())
+            .args(["--width", "10"])
+            .args(args);
+        match no_color {
+            Some(value) => command.env("NO_COLOR", value),
+            None => command.env_remove("NO_COLOR"),
+        };
+        let output = command.assert().success().get_output().stdout.clone();
+        String::from_utf8(output).expect("utf8 output")
+    };
+
+    assert!(!run(&[], Some("1")).contains('\x1b'));
+    assert!(!run(&["--color", "never"], None).contains('\x1b'));
+    assert!(run(&["--color", "always"], Some("1")).contains("\x1b["));
+}
+
+#[test]
+fn piped_output_is_plain_text_without_banner() {
+    let image_file = create_sample_image();
+
+    Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .arg("--no-config")
+        .arg(image_file.path())
+        .env_remove("NO_COLOR")
+        .assert()
+        .success()
+        .stdout(contains("\x1b").not())
+        .stdout(contains("Using").not());
+}
+
+#[test]
+fn hyperlinks_are_only_written_where_they_can_be_shown() {
+    let image_file = create_sample_image();
+    let run = |target: &str, force: Option<&str>| {
+        let mut command = Command::cargo_bin("ascii-art-cli").expect("binary exists");
+        command
+            .arg(image_file.path())
+            .args(["--width", "8", &format!("--hyperlink={target}")])
+            .env_remove("FORCE_HYPERLINK");
+        if let Some(force) = force {
+            command.env("FORCE_HYPERLINK", force);
+        }
+        let output = command.assert().success().get_output().stdout.clone();
+        String::from_utf8(output).expect("utf8 output")
+    };
+
+    let linked = run("art", Some("1"));
+    assert!(linked.starts_with("\x1b]8;;file://"), "{linked:?}");
+    assert!(linked.ends_with("\x1b]8;;\x1b\\"), "{linked:?}");
+    // Piped output has no links, but the caption stays as plain text
+    let name = image_file.path().to_str().expect("utf8 path");
+    let caption = run("caption", None);
+    assert!(!caption.contains("\x1b]8"));
+    assert!(caption.ends_with(&format!("{name}\n")), "{caption:?}");
+
+    // The bare flag must not take the path after it as its value
+    Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .args(["--no-config", "--width", "8", "--hyperlink"])
+        .arg(image_file.path())
+        .env("FORCE_HYPERLINK", "1")
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("\x1b]8;;file://"));
+}
+
+#[test]
+fn exports_use_the_chosen_line_endings_and_encoding() {
+    let image_file = create_sample_image();
+    let output = Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .arg("--no-config")
+        .arg(image_file.path())
+        .args(["--width", "8", "--charset", " █", "--color", "never"])
+        .args(["--line-ending", "crlf", "--encoding", "cp437"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let lines: Vec<&[u8]> = output.split_inclusive(|&byte| byte == b'\n').collect();
+    assert!(
+        lines.iter().all(|line| line.ends_with(b"\r\n")),
+        "{output:?}"
+    );
+    assert!(output.contains(&0xdb), "{output:?}");
+    assert!(std::str::from_utf8(&output).is_err());
+}
+
+#[test]
+fn grid_documents_show_the_saved_art_again() {
+    let image_file = create_sample_image();
+    let run = |args: &[&str]| {
+        Command::cargo_bin("ascii-art-cli")
+            .expect("binary exists")
+            .arg("--no-config")
+            .args(args)
+            .assert()
+            .success()
+            .get_output()
+            .stdout
+            .clone()
+    };
+    let image = image_file.path().to_str().expect("utf-8 path");
+    let colors = ["--color", "always", "--color-depth", "256"];
+    let document = NamedTempFile::with_suffix(".aag").expect("temp file");
+    let saved = run(&[image, "--width", "12", "--format", "aag"]);
+    assert!(saved.starts_with(b"AAG 1\nwidth 12\n"));
+    std::fs::write(document.path(), saved).expect("write document");
+
+    let path = document.path().to_str().expect("utf-8 path");
+    let direct = run(&[&[image, "--width", "12"][..], &colors].concat());
+    assert_eq!(run(&[&[path][..], &colors].concat()), direct);
+
+    Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .arg("--no-config")
+        .arg(document.path())
+        .arg("--auto-tune")
+        .assert()
+        .failure()
+        .stderr(contains("is a grid document"));
+}
+
+#[test]
+fn color_depth_flag_selects_escape_format() {
+    let image_file = create_sample_image();
+
+    Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .arg("--no-config")
+        .arg(image_file.path())
+        .args([
+            "--width",
+            "10",
+            "--color",
+            "always",
+            "--color-depth",
+            "truecolor",
+        ])
+        .assert()
+        .success()
+        .stdout(contains("\x1b[38;2;"));
+
+    Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .arg("--no-config")
+        .arg(image_file.path())
+        .args(["--width", "10", "--color", "always", "--color-depth", "8"])
+        .assert()
+        .success()
+        .stdout(contains("\x1b[3"))
+        .stdout(contains("\x1b[9").not());
+
+    Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .arg("--no-config")
+        .arg(image_file.path())
+        .args(["--color-depth", "12"])
+        .assert()
+        .failure()
+        .stderr(contains(
+            "invalid value '12' for '--color-depth <COLOR_DEPTH>'",
+        ));
+}
+
+/// Run with truecolor output and check that every escape is a shade of gray
+fn assert_only_gray_escapes(args: &[&str]) {
+    let image_file = create_sample_image();
+
+    let output = Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .arg("--no-config")
+        .arg(image_file.path())
+        .args(["--width", "10", "--color-depth", "truecolor"])
+        .args(args)
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let text = String::from_utf8(output).expect("utf8 output");
+    let escapes: Vec<&str> = text
+        .split("\x1b[38;2;")
+        .skip(1)
+        .map(|rest| rest.split('m').next().unwrap_or_default())
+        .collect();
+    assert!(!escapes.is_empty(), "{text:?}");
+    for escape in escapes {
+        let channels: Vec<&str> = escape.split(';').collect();
+        assert!(
+            channels.iter().all(|channel| *channel == channels[0]),
+            "{escape}"
+        );
+    }
+}
+
+#[test]
+fn gray_color_choice_emits_only_gray_escapes() {
+    assert_only_gray_escapes(&["--color", "gray"]);
+}
+
+#[test]
+fn color_filters_restyle_cell_colors() {
+    assert_only_gray_escapes(&["--color", "always", "--filter", "noir"]);
+    assert_only_gray_escapes(&["--color", "always", "--filter", "sepia", "--filter", "noir"]);
+
+    Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .arg("--no-config")
+        .args(["--filter", "vintage", "image.png"])
+        .assert()
+        .failure()
+        .stderr(contains("invalid value 'vintage' for '--filter <FILTER>'"));
+}
+
+#[test]
+fn saturation_adjusts_cell_colors() {
+    assert_only_gray_escapes(&["--color", "always", "--saturation", "-100"]);
+    assert_only_gray_escapes(&[
+        "--color",
+        "always",
+        "--hue-shift",
+        "90",
+        "--saturation",
+        "-100",
+    ]);
+
+    for flag in ["--hue-shift", "--saturation", "--lightness"] {
+        Command::cargo_bin("ascii-art-cli")
+            .expect("binary exists")
+            .args(["--no-config", flag, "NaN", "image.png"])
+            .assert()
+            .code(64)
+            .stderr(contains("finite number"));
+    }
+}
+
+#[test]
+fn hsl_brightness_changes_the_characters() {
+    let image_file = create_sample_image();
+    let convert = |args: &[&str]| {
+        let output = Command::cargo_bin("ascii-art-cli")
+            .expect("binary exists")
+            .arg("--no-config")
+            .arg(image_file.path())
+            .args(["--width", "10", "--color", "never", "--deterministic"])
+            .args(args)
+            .assert()
+            .success()
+            .get_output()
+            .stdout
+            .clone();
+        String::from_utf8(output).expect("utf8 output")
+    };
+
+    let plain = convert(&[]);
+    assert_eq!(convert(&["--lightness", "100"]), plain);
+    let lightened = convert(&["--lightness", "100", "--hsl-brightness"]);
+    assert_ne!(lightened, plain);
+    assert!(
+        lightened.lines().all(|line| line.chars().all(|c| c == '@')),
+        "{lightened}"
+    );
+}
+
+#[test]
+fn negate_inverts_characters_and_colors() {
+    let mut file = NamedTempFile::with_suffix(".png").expect("create temp image file");
+    DynamicImage::ImageRgba8(ImageBuffer::from_pixel(4, 4, Rgba([10, 200, 30, 255])))
+        .write_to(&mut file, ImageOutputFormat::Png)
+        .expect("write png");
+    let convert = |args: &[&str]| {
+        let output = Command::cargo_bin("ascii-art-cli")
+            .expect("binary exists")
+            .arg("--no-config")
+            .arg(file.path())
+            .args([
+                "--width",
+                "4",
+                "--color-depth",
+                "truecolor",
+                "--deterministic",
+            ])
+            .args(args)
+            .assert()
+            .success()
+            .get_output()
+            .stdout
+            .clone();
+        String::from_utf8(output).expect("utf8 output")
+    };
+
+    assert!(convert(&["--color", "always"]).contains("\x1b[38;2;10;200;30m"));
+    assert!(convert(&["--color", "always", "--negate"]).contains("\x1b[38;2;245;55;225m"));
+    assert_ne!(
+        convert(&["--color", "never"]),
+        convert(&["--color", "never", "--negate"])
+    );
+}
+
+#[test]
+fn levels_stretch_a_narrow_input_range() {
+    let mut file = NamedTempFile::with_suffix(".png").expect("create temp image file");
+    DynamicImage::ImageRgba8(ImageBuffer::from_pixel(4, 4, Rgba([40, 40, 40, 255])))
+        .write_to(&mut file, ImageOutputFormat::Png)
+        .expect("write png");
+    let convert = |levels: &str| {
+        Command::cargo_bin("ascii-art-cli")
+            .expect("binary exists")
+            .arg("--no-config")
+            .arg(file.path())
+            .args(["--width", "4", "--color", "never", "--deterministic"])
+            .args(["--levels", levels])
+            .assert()
+    };
+
+    let output = convert("0,40").success().get_output().stdout.clone();
+    let output = String::from_utf8(output).expect("utf8 output");
+    assert!(
+        output.lines().all(|line| line.chars().all(|c| c == '@')),
+        "{output}"
+    );
+
+    convert("200,100")
+        .failure()
+        .stderr(predicate::str::contains("Invalid levels '200,100'"));
+}
+
+#[test]
+fn nearest_resize_filter_keeps_hard_edges() {
+    let mut file = NamedTempFile::with_suffix(".png").expect("create temp image file");
+    let halves = ImageBuffer::from_fn(8, 8, |x, _| {
+        let level = if x < 4 { 0 } else { 255 };
+        Rgba([level, level, level, 255])
+    });
+    DynamicImage::ImageRgba8(halves)
+        .write_to(&mut file, ImageOutputFormat::Png)
+        .expect("write png");
+    let distinct_chars = |filter: &str| {
+        let output = Command::cargo_bin("ascii-art-cli")
+            .expect("binary exists")
+            .arg("--no-config")
+            .arg(file.path())
+            .args(["--width", "6", "--color", "never", "--deterministic"])
+            .args(["--resize-filter", filter])
+            .assert()
+            .success()
+            .get_output()
+            .stdout
+            .clone();
+        let mut chars: Vec<char> = String::from_utf8(output)
+            .expect("utf8 output")
+            .chars()
+            .filter(|c| *c != '\n')
+            .collect();
+        chars.sort_unstable();
+        chars.dedup();
+        chars.len()
+    };
+
+    assert_eq!(distinct_chars("nearest"), 2);
+    assert!(distinct_chars("triangle") > 2);
+}
+
+#[test]
+fn pixel_art_draws_crisp_blocks() {
+    let mut file = NamedTempFile::with_suffix(".png").expect("create temp image file");
+    let checker = ImageBuffer::from_fn(8, 8, |x, y| match (x + y) % 2 {
+        0 => Rgba([255, 0, 0, 255]),
+        _ => Rgba([0, 0, 255, 255]),
+    });
+    DynamicImage::ImageRgba8(checker)
+        .write_to(&mut file, ImageOutputFormat::Png)
+        .expect("write png");
+    let convert = |args: &[&str]| {
+        let output = Command::cargo_bin("ascii-art-cli")
+            .expect("binary exists")
+            .arg("--no-config")
+            .arg(file.path())
+            .args(["--width", "20", "--deterministic"])
+            .args(args)
+            .assert()
+            .success()
+            .get_output()
+            .stdout
+            .clone();
+        String::from_utf8(output).expect("utf8 output")
+    };
+
+    let colored = convert(&[
+        "--pixel-art",
+        "--color",
+        "always",
+        "--color-depth",
+        "truecolor",
+    ]);
+    assert!(colored.contains("\x1b[38;2;255;0;0m██"), "{colored}");
+    assert!(!colored.contains("\x1b[38;2;127"), "{colored}");
+    let plain = convert(&["--pixel-art=auto", "--color", "never"]);
+    assert!(
+        plain.lines().all(|line| line.chars().count() == 16),
+        "{plain}"
+    );
+
+    // The bare flag must not take the path after it as its value
+    Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .args(["--no-config", "--pixel-art"])
+        .arg(file.path())
+        .args(["--width", "20", "--deterministic"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn auto_wb_neutralizes_a_color_cast() {
+    let mut file = NamedTempFile::with_suffix(".png").expect("create temp image file");
+    let warm = ImageBuffer::from_fn(4, 4, |x, _| match x % 2 {
+        0 => Rgba([200, 150, 100, 255]),
+        _ => Rgba([100, 75, 50, 255]),
+    });
+    DynamicImage::ImageRgba8(warm)
+        .write_to(&mut file, ImageOutputFormat::Png)
+        .expect("write png");
+
+    let output = Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .arg("--no-config")
+        .arg(file.path())
+        .args([
+            "--width",
+            "4",
+            "--color",
+            "always",
+            "--color-depth",
+            "truecolor",
+        ])
+        .args(["--deterministic", "--auto-wb"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let output = String::from_utf8(output).expect("utf8 output");
+    assert!(output.contains("\x1b[38;2;150;150;150m"), "{output}");
+    assert!(!output.contains("\x1b[38;2;200;150;100m"), "{output}");
+}
+
+#[test]
+fn charset_file_supplies_the_ramp() {
+    let image_file = create_sample_image();
+    let ramp = NamedTempFile::new().expect("create ramp file");
+    std::fs::write(ramp.path(), "0 ' '\n128 o\n200 O\n").expect("write ramp");
+
+    let output = Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .arg("--no-config")
+        .arg(image_file.path())
+        .args(["--width", "10", "--color", "never", "--deterministic"])
+        .arg("--charset-file")
+        .arg(ramp.path())
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let output = String::from_utf8(output).expect("utf8 output");
+    assert!(
+        output.chars().all(|c| matches!(c, ' ' | 'o' | 'O' | '\n')),
+        "{output}"
+    );
+
+    let broken = NamedTempFile::new().expect("create ramp file");
+    std::fs::write(broken.path(), "0 ' '\n128 oo\n").expect("write ramp");
+    Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .arg("--no-config")
+        .arg(image_file.path())
+        .arg("--charset-file")
+        .arg(broken.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "line 2, column 5: expected one character, found 'oo'",
+        ));
+}
+
+#[test]
+fn wide_charsets_keep_the_output_width() {
+    let image_file = create_sample_image();
+    let convert = |charset: &str| {
+        Command::cargo_bin("ascii-art-cli")
+            .expect("binary exists")
+            .arg("--no-config")
+            .arg(image_file.path())
+            .args(["--width", "20", "--color", "never", "--deterministic"])
+            .args(["--charset", charset])
+            .assert()
+    };
+
+    let output = convert("\u{3000}・口回")
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let output = String::from_utf8(output).expect("utf8 output");
+    assert!(
+        output.lines().all(|line| line.chars().count() == 10),
+        "{output}"
+    );
+
+    convert(" .\u{200b}#")
+        .failure()
+        .stderr(contains("U+200B takes no columns"));
+    convert(" 口")
+        .failure()
+        .stderr(contains("mixes single- and double-width characters"));
+}
+
+#[test]
+fn adaptive_palette_limits_the_colors() {
+    let image_file = create_sample_image();
+
+    let output = Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .arg("--no-config")
+        .arg(image_file.path())
+        .args(["--width", "10", "--color", "always", "--color-depth", "16"])
+        .args(["--palette", "adaptive:2", "--palette-seed", "3"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let text = String::from_utf8(output).expect("utf8 output");
+    let mut colors: Vec<&str> = text
+        .split("\x1b[38;2;")
+        .skip(1)
+        .map(|rest| rest.split('m').next().unwrap_or_default())
+        .collect();
+    colors.sort_unstable();
+    colors.dedup();
+    assert!((1..=2).contains(&colors.len()), "{text:?}");
+
+    Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .arg("--no-config")
+        .arg(image_file.path())
+        .args(["--palette", "adaptive:0"])
+        .assert()
+        .failure()
+        .stderr(contains("Invalid adaptive palette 'adaptive:0'"));
+}
+
+#[test]
+fn shade_draws_glyphs_over_background_colors() {
+    let image_file = create_sample_image();
+
+    Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .arg("--no-config")
+        .arg(image_file.path())
+        .args([
+            "--width",
+            "10",
+            "--color",
+            "always",
+            "--color-depth",
+            "16",
+            "--shade",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::is_match("\x1b\\[(3|9)[0-7];(4|10)[0-7]m[░▒▓█]").unwrap());
+}
+
+#[test]
+fn sharpen_recovers_edge_contrast() {
+    let mut file = NamedTempFile::with_suffix(".png").expect("create temp image file");
+    let step = ImageBuffer::from_fn(16, 16, |x, _| {
+        let level = if x < 8 { 100 } else { 150 };
+        Rgba([level, level, level, 255])
+    });
+    DynamicImage::ImageRgba8(step)
+        .write_to(&mut file, ImageOutputFormat::Png)
+        .expect("write png");
+    let convert = |args: &[&str]| {
+        let output = Command::cargo_bin("ascii-art-cli")
+            .expect("binary exists")
+            .arg("--no-config")
+            .arg(file.path())
+            .args(["--width", "16", "--color", "never", "--deterministic"])
+            .args(args)
+            .assert()
+            .success()
+            .get_output()
+            .stdout
+            .clone();
+        String::from_utf8(output).expect("utf8 output")
+    };
+
+    assert_ne!(convert(&["--sharpen", "2"]), convert(&[]));
+
+    Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .arg("--no-config")
+        .arg(file.path())
+        .args(["--sharpen", "-1"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn denoise_removes_speckles_before_conversion() {
+    let mut file = NamedTempFile::with_suffix(".png").expect("create temp image file");
+    // Dark noise-free image sprinkled with single bright pixels
+    let speckled = ImageBuffer::from_fn(64, 64, |x, y| {
+        if (x * 7 + y * 13) % 11 == 0 {
+            Rgba([255, 255, 255, 255])
+        } else {
+            Rgba([20, 20, 20, 255])
+        }
+    });
+    DynamicImage::ImageRgba8(speckled)
+        .write_to(&mut file, ImageOutputFormat::Png)
+        .expect("write png");
+    let convert = |args: &[&str]| {
+        let output = Command::cargo_bin("ascii-art-cli")
+            .expect("binary exists")
+            .arg("--no-config")
+            .arg(file.path())
+            .args(["--width", "16", "--color", "never", "--deterministic"])
+            .args(args)
+            .assert()
+            .success()
+            .get_output()
+            .stdout
+            .clone();
+        String::from_utf8(output).expect("utf8 output")
+    };
+
+    assert_ne!(convert(&["--denoise", "gaussian:3"]), convert(&[]));
+    assert_ne!(convert(&["--denoise", "bilateral:2:200"]), convert(&[]));
+    if cfg!(feature = "edge") {
+        let denoised = convert(&["--denoise", "median:2"]);
+        let first = denoised.chars().next();
+        assert!(
+            denoised
+                .lines()
+                .all(|line| line.chars().all(|c| Some(c) == first)),
+            "{denoised}"
+        );
+    }
+
+    Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .arg("--no-config")
+        .arg(file.path())
+        .args(["--denoise", "sharpen"])
+        .assert()
+        .failure()
+        .stderr(contains("Unknown denoise filter 'sharpen'"));
+}
+
+#[test]
+fn clahe_brings_out_detail_in_dark_regions() {
+    let mut file = NamedTempFile::with_suffix(".png").expect("create temp image file");
+    // Faint stripes in a dark image map to one character without equalization
+    let dark = ImageBuffer::from_fn(32, 32, |x, _| {
+        let level = 4 + (x % 4) as u8 * 2;
+        Rgba([level, level, level, 255])
+    });
+    DynamicImage::ImageRgba8(dark)
+        .write_to(&mut file, ImageOutputFormat::Png)
+        .expect("write png");
+    let distinct_chars = |args: &[&str]| {
+        let output = Command::cargo_bin("ascii-art-cli")
+            .expect("binary exists")
+            .arg("--no-config")
+            .arg(file.path())
+            .args(["--width", "32", "--color", "never", "--deterministic"])
+            .args(args)
+            .assert()
+            .success()
+            .get_output()
+            .stdout
+            .clone();
+        let mut chars: Vec<char> = String::from_utf8(output)
+            .expect("utf8 output")
+            .chars()
+            .filter(|c| *c != '\n')
+            .collect();
+        chars.sort_unstable();
+        chars.dedup();
+        chars.len()
+    };
+
+    let plain = distinct_chars(&[]);
+    assert!(distinct_chars(&["--clahe", "--clahe-tiles", "2", "--clahe-clip", "40"]) > plain + 1);
+
+    for clip in ["0", "NaN"] {
+        Command::cargo_bin("ascii-art-cli")
+            .expect("binary exists")
+            .arg("--no-config")
+            .arg(file.path())
+            .args(["--clahe", "--clahe-clip", clip])
+            .assert()
+            .code(64);
+    }
+}
+
+#[test]
+fn query_palette_without_terminal_falls_back_silently() {
+    let image_file = create_sample_image();
+
+    Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .arg("--no-config")
+        .arg(image_file.path())
+        .args(["--width", "10", "--color", "always", "--color-depth", "16"])
+        .arg("--query-palette")
+        .assert()
+        .success()
+        .stdout(contains("\x1b["));
+}
+
+#[test]
+fn palette_file_constrains_16_color_matching() {
+    let image_file = create_sample_image();
+    let mut palette_file = NamedTempFile::with_suffix(".toml").expect("create palette file");
+    let colors = vec!["\"#000000\""; 16].join(", ");
+    std::io::Write::write_all(&mut palette_file, format!("colors = [{colors}]").as_bytes())
+        .expect("write palette");
+
+    let output = Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .arg("--no-config")
+        .arg(image_file.path())
+        .args(["--width", "10", "--color", "always", "--color-depth", "16"])
+        .arg("--palette")
+        .arg(palette_file.path())
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let text = String::from_utf8(output).expect("utf8 output");
+    // Every slot is black, so black is the only foreground ever selected
+    assert!(text.contains("\x1b[30m"));
+    assert_eq!(
+        text.matches("\x1b[3").count(),
+        text.matches("\x1b[30m").count()
+    );
+    assert!(!text.contains("\x1b[9"));
+}
+
+#[test]
+fn invalid_palette_file_is_reported() {
+    let image_file = create_sample_image();
+    let mut palette_file = NamedTempFile::with_suffix(".json").expect("create palette file");
+    std::io::Write::write_all(&mut palette_file, br##"{"colors": ["#000000"]}"##)
+        .expect("write palette");
+
+    Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .arg("--no-config")
+        .arg(image_file.path())
+        .arg("--palette")
+        .arg(palette_file.path())
+        .assert()
+        .failure()
+        .stderr(contains("Palette must list 16 or 256 colors, found 1."));
+}
+
+#[test]
+fn theme_restricts_truecolor_output_to_scheme() {
+    let image_file = create_sample_image();
+    let nord = [
+        "46;52;64",
+        "59;66;82",
+        "67;76;94",
+        "76;86;106",
+        "216;222;233",
+        "229;233;240",
+        "236;239;244",
+        "143;188;187",
+        "136;192;208",
+        "129;161;193",
+        "94;129;172",
+        "191;97;106",
+        "208;135;112",
+        "235;203;139",
+        "163;190;140",
+        "180;142;173",
+    ];
+
+    let output = Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .arg("--no-config")
+        .arg(image_file.path())
+        .args([
+            "--width",
+            "10",
+            "--color",
+            "always",
+            "--color-depth",
+            "truecolor",
+        ])
+        .args(["--theme", "nord"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let text = String::from_utf8(output).expect("utf8 output");
+
+    let used: Vec<&str> = text
+        .split("\x1b[38;2;")
+        .skip(1)
+        .filter_map(|rest| rest.split('m').next())
+        .collect();
+    assert!(!used.is_empty());
+    assert!(used.iter().all(|rgb| nord.contains(rgb)), "{used:?}");
+}
+
+#[test]
+fn light_background_inverts_character_ramp() {
+    let white = NamedTempFile::with_suffix(".png").expect("create temp image file");
+    DynamicImage::ImageRgba8(ImageBuffer::from_pixel(4, 4, Rgba([255, 255, 255, 255])))
+        .save(white.path())
+        .expect("write png");
+    let run = |background: &str| {
+        let output = Command::cargo_bin("ascii-art-cli")
+            .expect("binary exists")
+            .arg("--no-config")
+            .arg(white.path())
+            .args(["--width", "4", "--background", background])
+            .assert()
+            .success()
+            .get_output()
+            .stdout
+            .clone();
+        String::from_utf8(output).expect("utf8 output")
+    };
+
+    assert!(run("dark").starts_with("@@@@"));
+    assert!(run("light").starts_with("    "));
+}
+
+#[test]
+fn transparent_regions_are_blank_or_filled_with_background_color() {
+    let logo = NamedTempFile::with_suffix(".png").expect("create temp image file");
+    DynamicImage::ImageRgba8(ImageBuffer::from_pixel(4, 4, Rgba([255, 255, 255, 0])))
+        .save(logo.path())
+        .expect("write png");
+    let run = |args: &[&str]| {
+        let output = Command::cargo_bin("ascii-art-cli")
+            .expect("binary exists")
+            .arg("--no-config")
+            .arg(logo.path())
+            .args(["--width", "4", "--background", "dark"])
+            .args(args)
+            .assert()
+            .success()
+            .get_output()
+            .stdout
+            .clone();
+        String::from_utf8(output).expect("utf8 output")
+    };
+
+    assert!(run(&[]).starts_with("    \n"));
+    assert!(run(&["--background-color", "white"]).starts_with("@@@@"));
+    assert!(run(&["--alpha-threshold", "0"]).starts_with("@@@@"));
+
+    Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .arg("--no-config")
+        .arg(logo.path())
+        .args(["--background-color", "teal"])
+        .assert()
+        .code(64)
+        .stderr(contains("Invalid color 'teal'"));
+}
+
+#[test]
+fn sixel_render_mode_emits_sixel_sequence() {
+    let image_file = create_sample_image();
+
+    Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .arg("--no-config")
+        .arg(image_file.path())
+        .args(["--width", "4", "--render", "sixel", "--deterministic"])
+        .assert()
+        .success()
+        .stdout(predicates::str::starts_with("\x1bPq\"1;1;40;40"))
+        .stdout(contains("\x1b\\"));
+}
+
+#[test]
+fn preview_original_precedes_ascii_art() {
+    let image_file = create_sample_image();
+
+    let output = Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .arg("--no-config")
+        .arg(image_file.path())
+        .args(["--width", "6", "--preview-original", "--deterministic"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let text = String::from_utf8(output).expect("utf8 output");
+
+    assert!(text.starts_with("\x1b_Ga=T,f=100,c=6,"), "{text:?}");
+
+    let plain = Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .arg("--no-config")
+        .arg(image_file.path())
+        .args(["--width", "6", "--deterministic"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let plain = String::from_utf8(plain).expect("utf8 output");
+    assert!(text.ends_with(&format!("\x1b\\\n{plain}")));
+}
+
+#[test]
+fn image_render_mode_uses_iterm2_inside_iterm() {
+    let image_file = create_sample_image();
+
+    Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .arg("--no-config")
+        .arg(image_file.path())
+        .args(["--width", "4", "--render", "image"])
+        .env("TERM_PROGRAM", "iTerm.app")
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("\x1b]1337;File=inline=1;size="))
+        .stdout(contains(";width=4;"));
+}
+
+#[test]
+fn hybrid_layout_places_art_beside_the_image() {
+    let image_file = create_sample_image();
+
+    Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .arg("--no-config")
+        .arg(image_file.path())
+        .args(["--width", "12", "--layout", "hybrid", "--deterministic"])
+        .assert()
+        .success()
+        .stdout(contains("\x1b7\x1b_Ga=T,f=100,c=5,"))
+        .stdout(contains("\x1b8\x1b[7C"));
+}
+
+#[test]
+fn config_file_supplies_defaults_that_flags_override() {
+    let image_file = create_sample_image();
+    let config_dir = tempfile::tempdir().expect("create config dir");
+    let app_dir = config_dir.path().join("ascii-art");
+    std::fs::create_dir(&app_dir).expect("create app dir");
+    std::fs::write(
+        app_dir.join("config.toml"),
+        "color = \"always\"\ncolor-depth = \"truecolor\"\ncharset = \"ab\"\n",
+    )
+    .expect("write config");
+    let run = |args: &[&str]| {
+        let output = Command::cargo_bin("ascii-art-cli")
+            .expect("binary exists")
+            .arg(image_file.path())
+            .args(["--width", "6"])
+            .args(args)
+            .env("XDG_CONFIG_HOME", config_dir.path())
+            .env_remove("NO_COLOR")
+            .assert()
+            .success()
+            .get_output()
+            .stdout
+            .clone();
+        String::from_utf8(output).expect("utf8 output")
+    };
+
+    let configured = run(&[]);
+    assert!(configured.contains("\x1b[38;2;"), "{configured:?}");
+
+    let overridden = run(&["--color", "never"]);
+    assert!(!overridden.contains('\x1b'));
+    assert!(overridden
+        .lines()
+        .all(|line| line.chars().all(|ch| ch == 'a' || ch == 'b')));
+
+    let ignored = run(&["--no-config", "--color", "never"]);
+    assert!(ignored
+        .lines()
+        .any(|line| line.chars().any(|ch| ch != 'a' && ch != 'b')));
+}
+
+#[test]
+fn missing_config_file_is_reported() {
+    let image_file = create_sample_image();
+
+    Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .arg(image_file.path())
+        .args(["--config", "tests/data/does-not-exist.toml"])
+        .assert()
+        .failure()
+        .stderr(contains(
+            "Invalid config file \"tests/data/does-not-exist.toml\"",
+        ));
+}
+
+#[test]
+fn preset_sets_charset_and_flags_override_it() {
+    let image_file = create_sample_image();
+    let run = |args: &[&str]| {
+        let output = Command::cargo_bin("ascii-art-cli")
+            .expect("binary exists")
+            .arg(image_file.path())
+            .args(["--width", "6", "--color", "never", "--no-config"])
+            .args(args)
+            .assert()
+            .success()
+            .get_output()
+            .stdout
+            .clone();
+        String::from_utf8(output).expect("utf8 output")
+    };
+
+    let blocks = run(&["--preset", "pixel-art"]);
+    assert!(
+        blocks.chars().all(|ch| " ░▒▓█\n".contains(ch)),
+        "{blocks:?}"
+    );
+    assert!(blocks.chars().any(|ch| "░▒▓█".contains(ch)));
+
+    let overridden = run(&["--preset", "pixel-art", "--charset", "xy"]);
+    assert!(
+        overridden.chars().all(|ch| "xy\n".contains(ch)),
+        "{overridden:?}"
+    );
+}
+
+#[test]
+fn saved_preset_is_reused_from_config_file() {
+    let image_file = create_sample_image();
+    let config_dir = tempfile::tempdir().expect("create config dir");
+    let config_path = config_dir.path().join("config.toml");
+
+    Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .arg(image_file.path())
+        .args([
+            "--width",
+            "6",
+            "--charset",
+            "xy",
+            "--save-preset",
+            "mine",
+            "--config",
+        ])
+        .arg(&config_path)
+        .assert()
+        .success()
+        .stderr(contains("Saved preset 'mine'"));
+
+    let saved = std::fs::read_to_string(&config_path).expect("config written");
+    assert!(saved.contains("[presets.mine]"), "{saved}");
+
+    Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .arg(image_file.path())
+        .args([
+            "--width", "6", "--color", "never", "--preset", "mine", "--config",
+        ])
+        .arg(&config_path)
+        .assert()
+        .success()
+        .stdout(predicate::function(|out: &str| {
+            out.chars().all(|ch| "xy\n".contains(ch))
+        }));
+}
+
+#[test]
+fn unknown_preset_is_reported() {
+    let image_file = create_sample_image();
+
+    Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .arg(image_file.path())
+        .args(["--preset", "poster", "--no-config"])
+        .assert()
+        .failure()
+        .stderr(contains("Unknown preset 'poster'."));
+}
+
+#[test]
+fn completions_subcommand_lists_flag_values() {
+    Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .args(["completions", "bash"])
+        .assert()
+        .success()
+        .stdout(contains("--color-depth"))
+        .stdout(contains("auto 8 16 256 truecolor mono"))
+        .stdout(contains("auto standard edge"));
+
+    Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .args(["completions", "fish"])
+        .assert()
+        .success()
+        .stdout(contains("-l mode"));
+}
+
+#[test]
+fn charset_calibrate_orders_by_ink() {
+    Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .args(["charset", "calibrate", "--quiet", "█.░ "])
+        .assert()
+        .success()
+        .stdout(" .░█\n");
+
+    Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .args(["charset", "calibrate", "--table", "█ "])
+        .assert()
+        .success()
+        .stdout("# brightness  character\n0     ' '\n128   '█'\n")
+        .stderr(contains("100.0% ink"));
+
+    Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .args(["charset", "calibrate", " .Ж"])
+        .assert()
+        .failure()
+        .stderr(contains("no glyph for 'Ж'"));
+}
+
+#[test]
+fn charset_list_and_preview_draw_gradients() {
+    Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .args(["charset", "list", "--width", "10"])
+        .assert()
+        .success()
+        .stdout(contains("blocks    │  ░░▒▒▓▓██│\n"))
+        .stdout(contains("standard  │ .:-=+*#%@│\n"));
+
+    Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .args(["charset", "preview", "simple", "--width", "4"])
+        .assert()
+        .success()
+        .stdout("simple: \" .:#\" (4 levels)\n│ .:#│\n│ .:#│\n│ .:#│\n");
+
+    Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .args(["charset", "preview", "fancy"])
+        .assert()
+        .failure()
+        .stderr(contains("possible values: standard, detailed"));
+
+    let image_file = create_sample_image();
+    let output = Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .arg(image_file.path())
+        .args(["--width", "8", "--no-config", "--color", "never"])
+        .args(["--charset", "blocks"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let output = String::from_utf8(output).expect("utf8 output");
+    assert!(
+        output.chars().all(|ch| " ░▒▓█\n".contains(ch)),
+        "{output:?}"
+    );
+}
+
+#[test]
+fn config_value_errors_keep_the_detailed_message() {
+    let image_file = create_sample_image();
+    let mut config_file = NamedTempFile::with_suffix(".toml").expect("create config file");
+    std::io::Write::write_all(&mut config_file, b"mode = \"sketch\"\n").expect("write config");
+
+    Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .arg(image_file.path())
+        .arg("--config")
+        .arg(config_file.path())
+        .assert()
+        .failure()
+        .stderr(contains("Unknown mode 'sketch'"));
+}
+
+#[test]
+fn non_finite_contrast_is_rejected() {
+    let image_file = create_sample_image();
+    Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .arg(image_file.path())
+        .args(["--contrast", "NaN", "--no-config"])
+        .assert()
+        .code(64)
+        .stderr(contains("finite number"));
+
+    let mut config_file = NamedTempFile::with_suffix(".toml").expect("create config file");
+    std::io::Write::write_all(&mut config_file, b"contrast = nan\n").expect("write config");
+    Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .arg(image_file.path())
+        .arg("--config")
+        .arg(config_file.path())
+        .assert()
+        .code(64)
+        .stderr(contains("contrast = NaN is not a finite number"));
+}
+
+#[test]
+fn quiet_mode_prints_only_the_art() {
+    let image_file = create_sample_image();
+
+    let output = Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .arg(image_file.path())
+        .args(["--quiet", "--color", "never", "--no-config"])
+        .env_remove("COLUMNS")
+        .assert()
+        .success()
+        .stderr("")
+        .get_output()
+        .stdout
+        .clone();
+    let text = String::from_utf8(output).expect("utf8 output");
+    assert!(!text.contains("width"), "{text:?}");
+}
+
+#[test]
+fn verbose_flags_log_pipeline_details() {
+    let image_file = create_sample_image();
+
+    Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .arg(image_file.path())
+        .args(["--width", "8", "--mode", "edge", "-vv", "--no-config"])
+        .assert()
+        .success()
+        .stderr(contains("Resolved settings"))
+        .stderr(contains("Canny thresholds"))
+        .stderr(contains("Pipeline finished"));
+
+    Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .arg(image_file.path())
+        .args(["--width", "8", "-v", "--no-config"])
+        .assert()
+        .success()
+        .stderr(contains("Canny thresholds").not());
+}
+
+#[test]
+fn exit_codes_distinguish_failures() {
+    let run = |args: &[&str]| {
+        Command::cargo_bin("ascii-art-cli")
+            .expect("binary exists")
+            .args(args)
+            .arg("--no-config")
+            .assert()
+    };
+    let mut unsupported = NamedTempFile::with_suffix(".txt").expect("create text file");
+    std::io::Write::write_all(&mut unsupported, b"not an image").expect("write text");
+    let mut corrupt = NamedTempFile::with_suffix(".png").expect("create png file");
+    std::io::Write::write_all(&mut corrupt, b"\x89PNG\r\n\x1a\ntruncated").expect("write png");
+    let image_file = create_sample_image();
+    let image = image_file.path().to_str().expect("utf8 path");
+
+    run(&["tests/data/does-not-exist.png"]).code(2);
+    run(&[unsupported.path().to_str().expect("utf8 path")]).code(3);
+    run(&[corrupt.path().to_str().expect("utf8 path")]).code(4);
+    run(&[image, "--mode", "sketch"]).code(64);
+    run(&[image, "--charset", ""]).code(64);
+    run(&["--help"]).code(0);
+}
+
+#[test]
+fn json_error_format_reports_structured_failures() {
+    let run = |args: &[&str]| {
+        let output = Command::cargo_bin("ascii-art-cli")
+            .expect("binary exists")
+            .args(args)
+            .args(["--no-config", "--error-format", "json"])
+            .output()
+            .expect("run binary");
+        let stderr = String::from_utf8(output.stderr).expect("utf8 stderr");
+        let last_line = stderr.lines().last().unwrap_or_default();
+        let report: serde_json::Value =
+            serde_json::from_str(last_line).expect("failure is reported as JSON");
+        (output.status.code(), report)
+    };
+
+    let (code, report) = run(&["tests/data/does-not-exist.png"]);
+    assert_eq!(code, Some(2));
+    assert_eq!(report["kind"], "FileNotFound");
+    assert_eq!(report["path"], "tests/data/does-not-exist.png");
+    assert_eq!(report["exit_code"], 2);
+
+    let image_file = create_sample_image();
+    let image = image_file.path().to_str().expect("utf8 path");
+    let (code, report) = run(&[image, "--mode", "sketch"]);
+    assert_eq!(code, Some(64));
+    assert_eq!(report["kind"], "Usage");
+    assert!(
+        report["message"]
+            .as_str()
+            .is_some_and(|m| m.contains("sketch")),
+        "{report}"
+    );
+    assert!(report.get("path").is_none());
+}
+
+#[cfg(unix)]
+#[test]
+fn non_utf8_arguments_are_reported_not_panicked_on() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let output = Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .arg(OsStr::from_bytes(b"--charset=\xff"))
+        .args(["--no-config", "--error-format", "json", "image.png"])
+        .output()
+        .expect("run binary");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(64), "{stderr}");
+    assert!(!stderr.contains("panicked"), "{stderr}");
+}
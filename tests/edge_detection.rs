@@ -11,6 +11,8 @@ fn test_edge_mode_produces_output() {
         .arg("edge")
         .arg("--width")
         .arg("40")
+        .arg("--color")
+        .arg("always")
         .assert()
         .success()
         .stdout(predicate::str::contains("\x1b[")); // Check for ANSI escape codes
@@ -26,7 +28,9 @@ fn test_edge_mode_invalid_mode() {
         .arg("invalid")
         .assert()
         .failure()
-        .stderr(predicate::str::contains("Unknown mode 'invalid'"));
+        .stderr(predicate::str::contains(
+            "invalid value 'invalid' for '--mode <MODE>'",
+        ));
 }
 
 #[test]
@@ -54,3 +58,15 @@ fn test_default_mode_is_standard() {
         .assert()
         .success();
 }
+
+#[test]
+fn test_mode_typo_suggests_valid_value() {
+    Command::cargo_bin("ascii-art-cli")
+        .unwrap()
+        .arg("examples/test_image_1.png")
+        .arg("--mode")
+        .arg("edgee")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("a similar value exists: 'edge'"));
+}
@@ -0,0 +1,135 @@
+use std::path::Path;
+
+use assert_cmd::Command;
+use image::codecs::gif::GifEncoder;
+use image::{DynamicImage, ImageBuffer, ImageOutputFormat, Rgba};
+use predicates::str::contains;
+use tempfile::NamedTempFile;
+
+/// An 8x8 image with a bright left half and a dark right half
+fn sample() -> DynamicImage {
+    DynamicImage::ImageRgba8(ImageBuffer::from_fn(8, 8, |x, _| {
+        if x < 4 {
+            Rgba([250, 250, 250, 255])
+        } else {
+            Rgba([10, 10, 10, 255])
+        }
+    }))
+}
+
+fn write_sample(suffix: &str, format: ImageOutputFormat) -> NamedTempFile {
+    let mut file = NamedTempFile::with_suffix(suffix).expect("create temp image file");
+    sample().write_to(&mut file, format).expect("encode sample");
+    file
+}
+
+/// Convert `path` at 8 columns without colors
+fn convert(path: &Path) -> assert_cmd::assert::Assert {
+    Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .arg(path)
+        .args(["--width", "8", "--color", "never", "--deterministic"])
+        .assert()
+}
+
+fn assert_converts_sample(path: &Path) {
+    let output = convert(path).success().get_output().stdout.clone();
+    let text = String::from_utf8(output).expect("utf8 output");
+    assert_eq!(text.lines().count(), 4, "{text}");
+    assert!(text.lines().all(|line| line.starts_with("@@@@")), "{text}");
+}
+
+#[test]
+fn bmp_input_converts() {
+    assert_converts_sample(write_sample(".bmp", ImageOutputFormat::Bmp).path());
+}
+
+#[test]
+fn tiff_input_converts() {
+    assert_converts_sample(write_sample(".tiff", ImageOutputFormat::Tiff).path());
+}
+
+#[test]
+fn ico_input_converts() {
+    assert_converts_sample(write_sample(".ico", ImageOutputFormat::Ico).path());
+}
+
+#[test]
+fn static_gif_input_converts() {
+    assert_converts_sample(write_sample(".gif", ImageOutputFormat::Gif).path());
+}
+
+#[test]
+fn animated_gif_input_uses_the_first_frame() {
+    let file = NamedTempFile::with_suffix(".gif").expect("create temp image file");
+    let mut encoder = GifEncoder::new(file.reopen().expect("reopen"));
+    for frame in [
+        sample().to_rgba8(),
+        ImageBuffer::from_pixel(8, 8, Rgba([0, 0, 0, 255])),
+    ] {
+        encoder
+            .encode_frame(image::Frame::new(frame))
+            .expect("encode frame");
+    }
+    drop(encoder);
+
+    assert_converts_sample(file.path());
+}
+
+#[test]
+fn webp_input_converts() {
+    let output = convert(Path::new("tests/data/simple-rgb.webp"))
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    assert!(!output.is_empty());
+}
+
+#[test]
+fn files_are_decoded_by_content_not_extension() {
+    assert_converts_sample(write_sample(".jpg", ImageOutputFormat::Png).path());
+}
+
+#[test]
+fn cmyk16_tiff_reports_the_unsupported_color_type() {
+    let file = NamedTempFile::with_suffix(".tiff").expect("create temp image file");
+    let mut encoder =
+        tiff::encoder::TiffEncoder::new(file.reopen().expect("reopen")).expect("tiff encoder");
+    encoder
+        .write_image::<tiff::encoder::colortype::CMYK16>(2, 2, &[0u16; 2 * 2 * 4])
+        .expect("encode cmyk tiff");
+
+    convert(file.path())
+        .code(3)
+        .stderr(contains("TIFF image"))
+        .stderr(contains("16-bit CMYK"))
+        .stderr(contains("convert it to 8-bit RGB first"));
+}
+
+#[test]
+fn unknown_format_is_reported() {
+    let mut file = NamedTempFile::with_suffix(".dat").expect("create temp file");
+    std::io::Write::write_all(&mut file, b"definitely not an image").expect("write");
+
+    convert(file.path())
+        .code(3)
+        .stderr(contains("Unsupported image format"));
+}
+
+#[test]
+fn camera_raw_is_chosen_by_extension() {
+    // A plain TIFF is not a camera RAW, even named like one
+    let file = write_sample(".NEF", ImageOutputFormat::Tiff);
+
+    if cfg!(feature = "raw") {
+        convert(file.path())
+            .code(4)
+            .stderr(contains("Failed to decode image"));
+    } else {
+        convert(file.path())
+            .code(3)
+            .stderr(contains("Camera RAW image"))
+            .stderr(contains("'raw' feature"));
+    }
+}
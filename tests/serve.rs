@@ -0,0 +1,78 @@
+#![cfg(feature = "server")]
+
+use std::io::{BufRead, BufReader, Read};
+use std::net::TcpStream;
+use std::process::{Child, Command, Stdio};
+
+use assert_cmd::cargo::CommandCargoExt;
+
+/// Start `serve` on a free port, returning the server and its startup banner
+fn start_server(args: &[&str]) -> (Child, String) {
+    let mut server = Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .args(["serve", "--listen", "127.0.0.1:0", "--width", "20"])
+        .args(args)
+        .arg("examples/test_image_1.png")
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("server starts");
+
+    let mut banner = String::new();
+    BufReader::new(server.stderr.take().expect("stderr"))
+        .read_line(&mut banner)
+        .expect("read banner");
+    (server, banner.trim().to_string())
+}
+
+#[test]
+fn telnet_clients_receive_the_animation() {
+    let (mut server, banner) = start_server(&["--telnet"]);
+    let addr = banner
+        .rsplit("telnet://")
+        .next()
+        .expect("address in banner");
+
+    let mut client = TcpStream::connect(addr).expect("connect");
+    let mut received = vec![0; 64];
+    client.read_exact(&mut received).expect("read frame");
+    drop(client);
+    server.kill().expect("stop server");
+    let _ = server.wait();
+
+    assert!(
+        banner.starts_with("Serving 1 frame on telnet://"),
+        "{banner}"
+    );
+    assert_eq!(&received[..6], &[255, 251, 1, 255, 251, 3]);
+    let text = String::from_utf8_lossy(&received);
+    assert!(text.contains("\x1b[2J\x1b[?25l\x1b[H"), "{text:?}");
+}
+
+#[test]
+fn websocket_clients_receive_json_frames() {
+    let (mut server, banner) = start_server(&["--websocket", "--frame-format", "json"]);
+    let addr = banner.rsplit("ws://").next().expect("address in banner");
+
+    let (mut socket, _) = tungstenite::connect(format!("ws://{addr}")).expect("connect");
+    let message = socket.read().expect("read frame");
+    let _ = socket.close(None);
+    server.kill().expect("stop server");
+    let _ = server.wait();
+
+    let frame: serde_json::Value =
+        serde_json::from_str(message.to_text().expect("text message")).expect("JSON frame");
+    assert_eq!(frame["width"], 20);
+    let lines = frame["lines"].as_array().expect("lines");
+    assert_eq!(Some(lines.len() as u64), frame["height"].as_u64());
+    assert_eq!(frame["colors"][0].as_array().map(Vec::len), Some(20));
+}
+
+#[test]
+fn serve_requires_a_protocol() {
+    assert_cmd::Command::cargo_bin("ascii-art-cli")
+        .expect("binary exists")
+        .args(["serve", "examples/test_image_1.png"])
+        .assert()
+        .code(64)
+        .stderr(predicates::str::contains("--telnet"));
+}